@@ -0,0 +1,65 @@
+// compares matching keys the way SCAN MATCH used to, re-reading the pattern for every key,
+// against compiling it for every call and against compiling it once and caching it on the
+// connection, over a full SCAN of batches of COUNT keys. run it with
+//
+//     cargo run --release --example glob_bench
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+use redis_starter_rust::glob::{ glob_match, Pattern, PatternCache };
+
+const KEYS: usize = 10_000;
+const ROUNDS: usize = 50;
+// SCAN's default COUNT, every batch is one call with its own MATCH.
+const BATCH: usize = 10;
+
+fn time(name: &str, mut run: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    let mut matched = 0;
+    for _ in 0..ROUNDS {
+        matched += run();
+    }
+    let elapsed = start.elapsed();
+    println!("{:<28} {:>10.2?} per round, {} matches", name, elapsed / ROUNDS as u32, matched / ROUNDS);
+    elapsed
+}
+
+fn main() {
+    let keys: Vec<Vec<u8>> = (0..KEYS)
+        .map(|i| format!("user:{}:session:{}", i % 97, i).into_bytes())
+        .collect();
+
+    for pattern in [&b"user:1*:session:*9"[..], b"user:[1-3]?:*:[^0]*", b"*:*:*:*5"] {
+        println!("{}", String::from_utf8_lossy(pattern));
+
+        let interpreted = time("glob_match per key", || {
+            keys.iter().filter(|key| glob_match(black_box(pattern), key, false)).count()
+        });
+
+        let compiled = time("compiled once per call", || {
+            keys.chunks(BATCH)
+                .map(|batch| {
+                    let compiled = Pattern::new(black_box(pattern), false);
+                    batch.iter().filter(|key| compiled.matches(key)).count()
+                })
+                .sum()
+        });
+
+        // a SCAN loop calls back with the same pattern for every cursor.
+        let mut cache = PatternCache::default();
+        let cached = time("cached across calls", || {
+            keys.chunks(BATCH)
+                .map(|batch| {
+                    let compiled = cache.get(black_box(pattern), false);
+                    batch.iter().filter(|key| compiled.matches(key)).count()
+                })
+                .sum()
+        });
+
+        println!(
+            "{:<28} {:.2}x compiled, {:.2}x cached\n",
+            "speedup",
+            interpreted.as_secs_f64() / compiled.as_secs_f64(),
+            interpreted.as_secs_f64() / cached.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs::File;
+use std::hash::{ BuildHasher, RandomState };
+use std::io::Read;
+
+// the only user there is, it takes the password from requirepass like redis does for configs
+// written before ACLs existed.
+pub const DEFAULT_USER: &str = "default";
+
+// the length of an ACL GENPASS password when no size was asked for.
+pub const DEFAULT_GENPASS_BITS: usize = 256;
+pub const MAX_GENPASS_BITS: usize = 4096;
+
+// every category redis knows about, in the order ACL CAT lists them.
+pub const CATEGORIES: [&str; 21] = [
+    "keyspace", "read", "write", "set", "sortedset", "list", "hash", "string", "bitmap",
+    "hyperloglog", "geo", "stream", "pubsub", "admin", "fast", "slow", "blocking", "dangerous",
+    "connection", "transaction", "scripting",
+];
+
+// a command as the server declares it. the flags describe how it behaves and most of its
+// categories follow from them, the rest are listed explicitly.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    // redis' convention, a positive arity is exactly how many arguments the command takes
+    // counting its name and a negative one the least it takes.
+    pub arity: i32,
+    pub flags: &'static [&'static str],
+    pub categories: &'static [&'static str],
+}
+
+impl CommandSpec {
+    const fn new(name: &'static str, arity: i32, flags: &'static [&'static str], categories: &'static [&'static str]) -> Self {
+        Self { name, arity, flags, categories }
+    }
+
+    // whether argc arguments, the name included, is a count the command can be called with.
+    pub fn accepts(&self, argc: usize) -> bool {
+        let arity = self.arity.unsigned_abs() as usize;
+        if self.arity < 0 { argc >= arity } else { argc == arity }
+    }
+
+    // write and readonly map onto @write and @read, admin commands are also @dangerous, and
+    // anything that isn't fast is @slow.
+    pub fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        for flag in self.flags {
+            match *flag {
+                "write" => categories.push("write"),
+                "readonly" => categories.push("read"),
+                "admin" => categories.extend(["admin", "dangerous"]),
+                "fast" => categories.push("fast"),
+                _ => {},
+            }
+        }
+
+        if !self.flags.contains(&"fast") {
+            categories.push("slow");
+        }
+
+        for category in self.categories {
+            if !categories.contains(category) {
+                categories.push(category);
+            }
+        }
+        categories
+    }
+}
+
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec::new("ping", -1, &["fast"], &["connection"]),
+    CommandSpec::new("echo", 2, &["fast"], &["connection"]),
+    CommandSpec::new("hello", -1, &["noauth", "fast"], &["connection"]),
+    CommandSpec::new("auth", -2, &["noauth", "fast"], &["connection"]),
+    CommandSpec::new("client", -2, &[], &["connection"]),
+    CommandSpec::new("cluster", -2, &[], &[]),
+    CommandSpec::new("info", -1, &[], &["dangerous"]),
+    CommandSpec::new("acl", -2, &[], &[]),
+    CommandSpec::new("debug", -2, &["admin"], &[]),
+    CommandSpec::new("config", -2, &["admin"], &[]),
+    CommandSpec::new("latency", -2, &["admin"], &[]),
+    CommandSpec::new("replconf", -1, &["admin"], &[]),
+    CommandSpec::new("psync", -3, &["admin"], &[]),
+    CommandSpec::new("get", 2, &["readonly", "fast"], &["string"]),
+    CommandSpec::new("set", -3, &["write"], &["string"]),
+    CommandSpec::new("setnx", 3, &["write", "fast"], &["string"]),
+    CommandSpec::new("setex", 4, &["write"], &["string"]),
+    CommandSpec::new("psetex", 4, &["write"], &["string"]),
+    CommandSpec::new("mget", -2, &["readonly", "fast"], &["string"]),
+    CommandSpec::new("mset", -3, &["write"], &["string"]),
+    CommandSpec::new("msetnx", -3, &["write"], &["string"]),
+    CommandSpec::new("append", 3, &["write", "fast"], &["string"]),
+    CommandSpec::new("setrange", 4, &["write"], &["string"]),
+    CommandSpec::new("getrange", 4, &["readonly"], &["string"]),
+    CommandSpec::new("lcs", -3, &["readonly"], &["string"]),
+    CommandSpec::new("bitpos", -3, &["readonly"], &["bitmap"]),
+    CommandSpec::new("bitop", -4, &["write"], &["bitmap"]),
+    CommandSpec::new("pfadd", -2, &["write", "fast"], &["hyperloglog"]),
+    CommandSpec::new("pfcount", -2, &["readonly"], &["hyperloglog"]),
+    CommandSpec::new("pfmerge", -2, &["write"], &["hyperloglog"]),
+    CommandSpec::new("xadd", -5, &["write", "fast"], &["stream"]),
+    CommandSpec::new("xrange", -4, &["readonly"], &["stream"]),
+    CommandSpec::new("xrevrange", -4, &["readonly"], &["stream"]),
+    CommandSpec::new("xlen", 2, &["readonly", "fast"], &["stream"]),
+    CommandSpec::new("xgroup", -2, &["write"], &["stream"]),
+    CommandSpec::new("xreadgroup", -7, &["write"], &["stream", "blocking"]),
+    CommandSpec::new("xack", -4, &["write", "fast"], &["stream"]),
+    CommandSpec::new("subscribe", -2, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("unsubscribe", -1, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("psubscribe", -2, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("punsubscribe", -1, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("publish", 3, &["pubsub", "fast"], &["pubsub"]),
+    CommandSpec::new("ssubscribe", -2, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("sunsubscribe", -1, &["pubsub"], &["pubsub"]),
+    CommandSpec::new("spublish", 3, &["pubsub", "fast"], &["pubsub"]),
+    CommandSpec::new("del", -2, &["write"], &["keyspace"]),
+    CommandSpec::new("type", 2, &["readonly", "fast"], &["keyspace"]),
+    CommandSpec::new("object", -2, &["readonly"], &["keyspace"]),
+    CommandSpec::new("keys", 2, &["readonly"], &["keyspace", "dangerous"]),
+    CommandSpec::new("scan", -2, &["readonly"], &["keyspace"]),
+    CommandSpec::new("randomkey", 1, &["readonly"], &["keyspace"]),
+    CommandSpec::new("expire", -3, &["write", "fast"], &["keyspace"]),
+    CommandSpec::new("pexpire", -3, &["write", "fast"], &["keyspace"]),
+    CommandSpec::new("expireat", -3, &["write", "fast"], &["keyspace"]),
+    CommandSpec::new("pexpireat", -3, &["write", "fast"], &["keyspace"]),
+    CommandSpec::new("persist", 2, &["write", "fast"], &["keyspace"]),
+    CommandSpec::new("ttl", 2, &["readonly", "fast"], &["keyspace"]),
+    CommandSpec::new("pttl", 2, &["readonly", "fast"], &["keyspace"]),
+    CommandSpec::new("expiretime", 2, &["readonly", "fast"], &["keyspace"]),
+    CommandSpec::new("pexpiretime", 2, &["readonly", "fast"], &["keyspace"]),
+    CommandSpec::new("flushall", -1, &["write"], &["keyspace", "dangerous"]),
+    CommandSpec::new("flushdb", -1, &["write"], &["keyspace", "dangerous"]),
+    CommandSpec::new("swapdb", 3, &["fast"], &["keyspace", "dangerous"]),
+    CommandSpec::new("lpush", -3, &["write", "fast"], &["list"]),
+    CommandSpec::new("rpush", -3, &["write", "fast"], &["list"]),
+    CommandSpec::new("lpushx", -3, &["write", "fast"], &["list"]),
+    CommandSpec::new("rpushx", -3, &["write", "fast"], &["list"]),
+    CommandSpec::new("lpop", -2, &["write", "fast"], &["list"]),
+    CommandSpec::new("rpop", -2, &["write", "fast"], &["list"]),
+    CommandSpec::new("blpop", -3, &["write"], &["list", "blocking"]),
+    CommandSpec::new("brpop", -3, &["write"], &["list", "blocking"]),
+    CommandSpec::new("lmpop", -4, &["write"], &["list"]),
+    CommandSpec::new("blmpop", -5, &["write"], &["list", "blocking"]),
+    CommandSpec::new("llen", 2, &["readonly", "fast"], &["list"]),
+    CommandSpec::new("lrange", 4, &["readonly"], &["list"]),
+    CommandSpec::new("lindex", 3, &["readonly"], &["list"]),
+    CommandSpec::new("linsert", 5, &["write"], &["list"]),
+    CommandSpec::new("lset", 4, &["write"], &["list"]),
+    CommandSpec::new("lrem", 4, &["write"], &["list"]),
+    CommandSpec::new("ltrim", 4, &["write"], &["list"]),
+    CommandSpec::new("lmove", 5, &["write"], &["list"]),
+    CommandSpec::new("rpoplpush", 3, &["write"], &["list"]),
+    CommandSpec::new("blmove", 6, &["write"], &["list", "blocking"]),
+    CommandSpec::new("brpoplpush", 4, &["write"], &["list", "blocking"]),
+    CommandSpec::new("sadd", -3, &["write", "fast"], &["set"]),
+    CommandSpec::new("smismember", -3, &["readonly", "fast"], &["set"]),
+    CommandSpec::new("sinter", -2, &["readonly"], &["set"]),
+    CommandSpec::new("sinterstore", -3, &["write"], &["set"]),
+    CommandSpec::new("sintercard", -3, &["readonly"], &["set"]),
+    CommandSpec::new("sunion", -2, &["readonly"], &["set"]),
+    CommandSpec::new("sunionstore", -3, &["write"], &["set"]),
+    CommandSpec::new("sdiff", -2, &["readonly"], &["set"]),
+    CommandSpec::new("sdiffstore", -3, &["write"], &["set"]),
+    CommandSpec::new("zintercard", -3, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zadd", -4, &["write", "fast"], &["sortedset"]),
+    CommandSpec::new("zscore", 3, &["readonly", "fast"], &["sortedset"]),
+    CommandSpec::new("zcard", 2, &["readonly", "fast"], &["sortedset"]),
+    CommandSpec::new("zrank", -3, &["readonly", "fast"], &["sortedset"]),
+    CommandSpec::new("zrevrank", -3, &["readonly", "fast"], &["sortedset"]),
+    CommandSpec::new("zincrby", 4, &["write", "fast"], &["sortedset"]),
+    CommandSpec::new("zunion", -3, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zunionstore", -4, &["write"], &["sortedset"]),
+    CommandSpec::new("zinter", -3, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zinterstore", -4, &["write"], &["sortedset"]),
+    CommandSpec::new("zdiff", -3, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zdiffstore", -4, &["write"], &["sortedset"]),
+    CommandSpec::new("zrange", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zrevrange", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zrangebyscore", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zrevrangebyscore", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zrangebylex", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("zrevrangebylex", -4, &["readonly"], &["sortedset"]),
+    CommandSpec::new("hset", -4, &["write", "fast"], &["hash"]),
+    CommandSpec::new("hget", 3, &["readonly", "fast"], &["hash"]),
+    CommandSpec::new("hdel", -3, &["write", "fast"], &["hash"]),
+    CommandSpec::new("hmget", -3, &["readonly", "fast"], &["hash"]),
+    CommandSpec::new("hgetall", 2, &["readonly"], &["hash"]),
+    CommandSpec::new("hlen", 2, &["readonly", "fast"], &["hash"]),
+    CommandSpec::new("hrandfield", -2, &["readonly"], &["hash"]),
+    CommandSpec::new("hexpire", -6, &["write", "fast"], &["hash"]),
+    CommandSpec::new("hpexpire", -6, &["write", "fast"], &["hash"]),
+    CommandSpec::new("hpersist", -5, &["write", "fast"], &["hash"]),
+    CommandSpec::new("httl", -5, &["readonly", "fast"], &["hash"]),
+];
+
+// the entry for a command, names are matched without regard to case.
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+// the category as ACL CAT spells it, none if redis has no such category.
+pub fn find_category(name: &str) -> Option<&'static str> {
+    CATEGORIES.iter().find(|c| c.eq_ignore_ascii_case(name)).copied()
+}
+
+// the names of the commands in a category, in table order.
+pub fn commands_in(category: &str) -> Vec<&'static str> {
+    COMMAND_TABLE
+        .iter()
+        .filter(|spec| spec.acl_categories().contains(&category))
+        .map(|spec| spec.name)
+        .collect()
+}
+
+// whether the pair authenticates as the default user. without requirepass the default user
+// is nopass and any password is accepted.
+pub fn check_password(requirepass: Option<&str>, username: &str, password: &str) -> bool {
+    username == DEFAULT_USER && requirepass.is_none_or(|expected| expected == password)
+}
+
+// a random password of bits bits, rounded up to whole hex characters.
+pub fn genpass(bits: usize) -> String {
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    random_bytes(&mut bytes);
+
+    let mut hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.truncate(bits.div_ceil(4));
+    hex
+}
+
+// fills buf from the kernel, falling back to randomly seeded hashes where there is no urandom.
+fn random_bytes(buf: &mut [u8]) {
+    if File::open("/dev/urandom").and_then(|mut f| f.read_exact(buf)).is_ok() {
+        return;
+    }
+
+    for chunk in buf.chunks_mut(8) {
+        let random = RandomState::new().hash_one(0u8).to_le_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_categories_follow_flags() {
+        let spec = COMMAND_TABLE.iter().find(|spec| spec.name == "debug").unwrap();
+        assert_eq!(spec.acl_categories(), vec!["admin", "dangerous", "slow"]);
+
+        let spec = COMMAND_TABLE.iter().find(|spec| spec.name == "get").unwrap();
+        assert_eq!(spec.acl_categories(), vec!["read", "fast", "string"]);
+
+        // every category a command ends up in is one ACL CAT lists.
+        for spec in COMMAND_TABLE {
+            assert!(spec.acl_categories().iter().all(|c| CATEGORIES.contains(c)), "{}", spec.name);
+        }
+        assert!(commands_in("list").contains(&"lmove"));
+        assert_eq!(find_category("KEYSPACE"), Some("keyspace"));
+        assert_eq!(find_category("nope"), None);
+    }
+
+    #[test]
+    fn test_arity_counts_the_name() {
+        let get = lookup("GET").unwrap();
+        assert!(get.accepts(2) && !get.accepts(1) && !get.accepts(3));
+
+        let mset = lookup("mset").unwrap();
+        assert!(!mset.accepts(2) && mset.accepts(3) && mset.accepts(5));
+        assert!(lookup("nope").is_none());
+    }
+
+    #[test]
+    fn test_genpass_length() {
+        assert_eq!(genpass(DEFAULT_GENPASS_BITS).len(), 64);
+        assert_eq!(genpass(5).len(), 2);
+        assert!(genpass(128).bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(genpass(128), genpass(128));
+    }
+
+    #[test]
+    fn test_requirepass_maps_onto_default_user() {
+        assert!(check_password(None, "default", "anything"));
+        assert!(!check_password(None, "alice", "anything"));
+        assert!(check_password(Some("secret"), "default", "secret"));
+        assert!(!check_password(Some("secret"), "default", "wrong"));
+    }
+}
@@ -1,18 +1,119 @@
 use crate::resp::Resp;
-use crate::database::Record;
-use std::time::Duration;
+use crate::database::{ self, Record, ListEnd, ZaddFlags, ExpireCondition, StreamId, NewStreamId, StreamTrim, StreamFields };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
 use std::vec::IntoIter;
 use crate::internals::{ReplconfArguments, PsyncArguments};
+use crate::clients::{ ClientKind, KillFilter, is_valid_name };
+use crate::reply::Protocol;
+use crate::acl;
+use crate::setops::Aggregate;
+use crate::bitops::{ BitOp, BitUnit };
+use crate::pubsub::SubscriptionKind;
+use crate::connection::ReplyMode;
 
 #[derive(Debug)]
 pub enum CommandArgument {
     Ping,
-    Info,
+    Info(InfoArguments),
     Echo(EchoArguments),
     Get(GetArguments),
     Set(SetArguments),
     Replconf(ReplconfArguments),
     Psync(PsyncArguments),
+    Debug(DebugArguments),
+    Config(ConfigArguments),
+    Object(ObjectArguments),
+    Latency(LatencyArguments),
+    Lcs(LcsArguments),
+    Expire(ExpireArguments),
+    Pexpire(ExpireArguments),
+    Persist(KeyArguments),
+    Ttl(KeyArguments),
+    Pttl(KeyArguments),
+    Keys(KeysArguments),
+    Expireat(ExpireArguments),
+    Pexpireat(ExpireArguments),
+    Expiretime(KeyArguments),
+    Pexpiretime(KeyArguments),
+    Mget(MultiKeyArguments),
+    Mset(MsetArguments),
+    Msetnx(MsetArguments),
+    Del(MultiKeyArguments),
+    Setrange(SetrangeArguments),
+    Getrange(GetrangeArguments),
+    Setnx(SetArguments),
+    Client(ClientArguments),
+    Flushall(FlushArguments),
+    Flushdb(FlushArguments),
+    Randomkey,
+    Scan(ScanArguments),
+    Append(AppendArguments),
+    Type(KeyArguments),
+    Push(PushArguments),
+    Lrange(LrangeArguments),
+    Llen(KeyArguments),
+    Hello(HelloArguments),
+    Pop(PopArguments),
+    Linsert(LinsertArguments),
+    Lset(LsetArguments),
+    Lrem(LremArguments),
+    Ltrim(LrangeArguments),
+    Lindex(LindexArguments),
+    Lmove(LmoveArguments),
+    Auth(AuthArguments),
+    Acl(AclArguments),
+    BlockingPop(BlockingPopArguments),
+    Sadd(SaddArguments),
+    Smismember(SmismemberArguments),
+    Sinter(MultiKeyArguments),
+    Sinterstore(StoreArguments),
+    Sunion(MultiKeyArguments),
+    Sunionstore(StoreArguments),
+    Sdiff(MultiKeyArguments),
+    Sdiffstore(StoreArguments),
+    Sintercard(IntercardArguments),
+    Zintercard(IntercardArguments),
+    Zadd(ZaddArguments),
+    Zscore(ZscoreArguments),
+    Zcard(KeyArguments),
+    Zrank(ZrankArguments),
+    Zunion(ZcombineArguments),
+    Zunionstore(ZcombineArguments),
+    Zinter(ZcombineArguments),
+    Zinterstore(ZcombineArguments),
+    Zdiff(ZcombineArguments),
+    Zdiffstore(ZcombineArguments),
+    Bitpos(BitposArguments),
+    Bitop(BitopArguments),
+    Cluster(ClusterArguments),
+    Pfadd(PfaddArguments),
+    Pfcount(MultiKeyArguments),
+    Pfmerge(PfmergeArguments),
+    Xadd(XaddArguments),
+    Xrange(XrangeArguments),
+    Xlen(KeyArguments),
+    Xgroup(XgroupArguments),
+    Xreadgroup(XreadgroupArguments),
+    Xack(XackArguments),
+    Subscribe(ChannelsArguments),
+    Unsubscribe(ChannelsArguments),
+    Publish(PublishArguments),
+    Zincrby(ZincrbyArguments),
+    Zrange(ZrangeArguments),
+    BlockingMove(BlockingMoveArguments),
+    Mpop(MpopArguments),
+    Hset(HsetArguments),
+    Hget(HgetArguments),
+    Hdel(FieldsArguments),
+    Hmget(FieldsArguments),
+    Hgetall(KeyArguments),
+    Hlen(KeyArguments),
+    Hrandfield(HrandfieldArguments),
+    Hexpire(HexpireArguments),
+    Hpexpire(HexpireArguments),
+    Hpersist(FieldsArguments),
+    Httl(FieldsArguments),
+    Swapdb(SwapdbArguments),
 }
 
 // a trait defining an argument parser for a command
@@ -22,41 +123,1558 @@ pub trait Argument {
         Self: Sized;
 }
 
+// HELLO [protover [AUTH username password] [SETNAME clientname]]. everything is validated
+// here so a HELLO that fails never switches the protocol or changes the client.
+#[derive(Debug, Default)]
+pub struct HelloArguments {
+    // none keeps the protocol the connection already speaks.
+    pub protocol: Option<Protocol>,
+    pub auth: Option<(String, String)>,
+    pub name: Option<String>,
+}
+
+impl Argument for HelloArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<HelloArguments, String> {
+        let mut hello = HelloArguments::default();
+        let version = match args.next() {
+            Some(resp) => resp,
+            None => return Ok(hello),
+        };
+
+        let version: String = version.try_into().map_err(|_| "ERR Protocol version is not an integer or out of range".to_string())?;
+        let version = version
+            .parse::<i64>()
+            .map_err(|_| "ERR Protocol version is not an integer or out of range".to_string())?;
+        hello.protocol = Some(Protocol::from_version(version).ok_or("NOPROTO unsupported protocol version".to_string())?);
+
+        while let Some(option) = args.next() {
+            let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+            match option.to_uppercase().as_str() {
+                "AUTH" if args.len() >= 2 => {
+                    let username: String = args.next().unwrap().try_into().map_err(|_| "ERR syntax error".to_string())?;
+                    let password: String = args.next().unwrap().try_into().map_err(|_| "ERR syntax error".to_string())?;
+                    hello.auth = Some((username, password));
+                },
+
+                "SETNAME" if args.len() >= 1 => {
+                    let name: String = args.next().unwrap().try_into().map_err(|_| "ERR syntax error".to_string())?;
+                    if !is_valid_name(&name) {
+                        return Err("ERR Client names cannot contain spaces, newlines or special characters.".to_string());
+                    }
+                    hello.name = Some(name);
+                },
+
+                _ => return Err(format!("ERR Syntax error in HELLO option '{}'", option)),
+            }
+        }
+
+        Ok(hello)
+    }
+}
+
+// the sections INFO should report, lowercased. empty means the default set.
+#[derive(Debug)]
+pub struct InfoArguments {
+    pub sections: Vec<String>,
+}
+
+impl Argument for InfoArguments {
+    fn parse(args: IntoIter<Resp>) -> Result<InfoArguments, String> {
+        let mut sections = Vec::new();
+        for arg in args {
+            let section: String = arg.try_into().map_err(|_| "ERR argument not utf8")?;
+            sections.push(section.to_lowercase());
+        }
+        Ok(InfoArguments { sections })
+    }
+}
+
+#[derive(Debug)]
+pub struct ScanArguments {
+    pub cursor: u64,
+    pub pattern: Option<Vec<u8>>,
+    // how much work a single call does, not an exact number of keys returned.
+    pub count: usize,
+    // only return keys holding this type, lowercased.
+    pub kind: Option<String>,
+}
+
+impl Argument for ScanArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ScanArguments, String> {
+        let cursor = std::str::from_utf8(&next_key(&mut args)?)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .ok_or("ERR invalid cursor".to_string())?;
+
+        let mut pattern = None;
+        let mut count = 10;
+        let mut kind = None;
+
+        while let Some(arg) = args.next() {
+            let option: String = arg.try_into().map_err(|_| "ERR argument not utf8")?;
+            match option.to_uppercase().as_str() {
+                "MATCH" => pattern = Some(next_key(&mut args)?),
+                "COUNT" => {
+                    let n = next_integer(&mut args)?;
+                    if n < 1 {
+                        return Err("ERR syntax error".to_string());
+                    }
+                    count = n as usize;
+                },
+                "TYPE" => {
+                    let name = String::from_utf8(next_key(&mut args)?)
+                        .map_err(|_| "ERR argument not utf8")?;
+                    kind = Some(name.to_lowercase());
+                },
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        Ok(ScanArguments { cursor, pattern, count, kind })
+    }
+}
+
+// FLUSHALL and FLUSHDB, ASYNC frees the old dataset in the background.
+// SWAPDB index1 index2
+#[derive(Debug)]
+pub struct SwapdbArguments {
+    pub first: usize,
+    pub second: usize,
+}
+
+impl Argument for SwapdbArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<SwapdbArguments, String> {
+        let first = next_integer(&mut args).map_err(|_| "ERR invalid first DB index".to_string())?;
+        let second = next_integer(&mut args).map_err(|_| "ERR invalid second DB index".to_string())?;
+        let in_range = |index: i64| index >= 0 && (index as usize) < database::DATABASES;
+        if !in_range(first) || !in_range(second) {
+            return Err("ERR DB index is out of range".to_string());
+        }
+        Ok(SwapdbArguments { first: first as usize, second: second as usize })
+    }
+}
+
+#[derive(Debug)]
+pub struct FlushArguments {
+    pub asynchronous: bool,
+}
+
+impl Argument for FlushArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<FlushArguments, String> {
+        let asynchronous = match args.next() {
+            None => false,
+            Some(arg) => {
+                let mode: String = arg.try_into().map_err(|_| "ERR argument not utf8")?;
+                match mode.to_uppercase().as_str() {
+                    "ASYNC" => true,
+                    "SYNC" => false,
+                    _ => return Err("ERR syntax error".to_string()),
+                }
+            }
+        };
+
+        no_more_arguments(&mut args)?;
+        Ok(FlushArguments { asynchronous })
+    }
+}
+
+#[derive(Debug)]
+pub struct EchoArguments {
+    pub message: Vec<u8>,
+}
+
+impl Argument for EchoArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<EchoArguments, String> {
+        let message = match args.next() {
+            Some(resp) => resp,
+            _ => return Err("ERR wrong number of arguments for 'echo' command".to_string())
+        };
+
+        // Ensure the message is a bulk string; otherwise, return an error
+        if let Resp::BulkString(message) = message {
+            Ok(EchoArguments { message })
+        } else {
+            Err("ERR argument must be a bulk string".to_string())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GetArguments {
+    pub key: Vec<u8>,
+}
+
+impl Argument for GetArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<GetArguments, String> {
+        // Since we check that there is exactly one argument, we can safely pop it
+        let key = match args.next() {
+            Some(Resp::BulkString(b)) => b,
+            _ => return Err("ERR argument must be a bulk string".to_string()),
+        };
+
+        Ok(GetArguments { key })
+    }
+}
+
+// arguments for commands that only operate on a single key.
+#[derive(Debug)]
+pub struct KeyArguments {
+    pub key: Vec<u8>,
+}
+
+impl Argument for KeyArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<KeyArguments, String> {
+        let key = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(KeyArguments { key })
+    }
+}
+
+#[derive(Debug)]
+pub struct KeysArguments {
+    pub pattern: Vec<u8>,
+}
+
+impl Argument for KeysArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<KeysArguments, String> {
+        let pattern = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(KeysArguments { pattern })
+    }
+}
+
+// arguments for commands that take one or more keys.
+#[derive(Debug)]
+pub struct MultiKeyArguments {
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl Argument for MultiKeyArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<MultiKeyArguments, String> {
+        let mut keys = vec![next_key(&mut args)?];
+        for _ in 0..args.len() {
+            keys.push(next_key(&mut args)?);
+        }
+        Ok(MultiKeyArguments { keys })
+    }
+}
+
+// shared by MSET and MSETNX, a non empty list of key value pairs.
+#[derive(Debug)]
+pub struct MsetArguments {
+    pub pairs: Vec<(Vec<u8>, Record)>,
+}
+
+impl Argument for MsetArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<MsetArguments, String> {
+        if args.len() == 0 || !args.len().is_multiple_of(2) {
+            return Err("ERR wrong number of arguments".to_string());
+        }
+
+        let mut pairs = Vec::with_capacity(args.len() / 2);
+        while args.len() > 0 {
+            let key = next_key(&mut args)?;
+            let value = next_key(&mut args)?;
+            pairs.push((key, Record::from_vec(value)));
+        }
+
+        Ok(MsetArguments { pairs })
+    }
+}
+
+#[derive(Debug)]
+pub struct SetrangeArguments {
+    pub key: Vec<u8>,
+    pub offset: i64,
+    pub value: Vec<u8>,
+}
+
+impl Argument for SetrangeArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<SetrangeArguments, String> {
+        let key = next_key(&mut args)?;
+        let offset = next_integer(&mut args)?;
+        let value = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(SetrangeArguments { key, offset, value })
+    }
+}
+
+#[derive(Debug)]
+pub struct AppendArguments {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl Argument for AppendArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<AppendArguments, String> {
+        let key = next_key(&mut args)?;
+        let value = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(AppendArguments { key, value })
+    }
+}
+
+#[derive(Debug)]
+pub struct GetrangeArguments {
+    pub key: Vec<u8>,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Argument for GetrangeArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<GetrangeArguments, String> {
+        let key = next_key(&mut args)?;
+        let start = next_integer(&mut args)?;
+        let end = next_integer(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(GetrangeArguments { key, start, end })
+    }
+}
+
+// BITPOS key bit [start [end [BYTE|BIT]]], the range is the whole string when left out.
+#[derive(Debug)]
+pub struct BitposArguments {
+    pub key: Vec<u8>,
+    pub bit: bool,
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+impl Argument for BitposArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<BitposArguments, String> {
+        let key = next_key(&mut args)?;
+        let bit = match next_integer(&mut args)? {
+            0 => false,
+            1 => true,
+            _ => return Err("ERR The bit argument must be 1 or 0.".to_string()),
+        };
+
+        let start = if args.len() > 0 { next_integer(&mut args)? } else { 0 };
+        let end = if args.len() > 0 { Some(next_integer(&mut args)?) } else { None };
+        let unit = match args.next() {
+            Some(unit) => {
+                let unit: String = unit.try_into().map_err(|_| "ERR syntax error".to_string())?;
+                match unit.to_ascii_uppercase().as_str() {
+                    "BYTE" => BitUnit::Byte,
+                    "BIT" => BitUnit::Bit,
+                    _ => return Err("ERR syntax error".to_string()),
+                }
+            },
+            None => BitUnit::Byte,
+        };
+        no_more_arguments(&mut args)?;
+        Ok(BitposArguments { key, bit, start, end, unit })
+    }
+}
+
+// BITOP AND|OR|XOR|NOT destkey key [key ...], NOT takes exactly one key.
+#[derive(Debug)]
+pub struct BitopArguments {
+    pub op: BitOp,
+    pub destination: Vec<u8>,
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl Argument for BitopArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<BitopArguments, String> {
+        let op: String = args.next()
+            .ok_or("ERR wrong number of arguments for 'bitop' command".to_string())?
+            .try_into()
+            .map_err(|_| "ERR syntax error".to_string())?;
+        let op = match op.to_ascii_uppercase().as_str() {
+            "AND" => BitOp::And,
+            "OR" => BitOp::Or,
+            "XOR" => BitOp::Xor,
+            "NOT" => BitOp::Not,
+            _ => return Err("ERR syntax error".to_string()),
+        };
+
+        let destination = next_key(&mut args)?;
+        let mut keys = Vec::with_capacity(args.len());
+        while args.len() > 0 {
+            keys.push(next_key(&mut args)?);
+        }
+        if keys.is_empty() {
+            return Err("ERR wrong number of arguments for 'bitop' command".to_string());
+        }
+        if op == BitOp::Not && keys.len() != 1 {
+            return Err("ERR BITOP NOT must be called with a single source key.".to_string());
+        }
+        Ok(BitopArguments { op, destination, keys })
+    }
+}
+
+// LPUSH, RPUSH, LPUSHX and RPUSHX, the command decides the end and whether the key must exist.
+#[derive(Debug)]
+pub struct PushArguments {
+    pub key: Vec<u8>,
+    pub values: Vec<Vec<u8>>,
+    pub end: ListEnd,
+    pub existing_only: bool,
+}
+
+impl PushArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, end: ListEnd, existing_only: bool) -> Result<PushArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut values = vec![next_key(&mut args)?];
+        for _ in 0..args.len() {
+            values.push(next_key(&mut args)?);
+        }
+        Ok(PushArguments { key, values, end, existing_only })
+    }
+}
+
+// LPOP and RPOP, count is only set when it was given since it changes the reply shape.
+#[derive(Debug)]
+pub struct PopArguments {
+    pub key: Vec<u8>,
+    pub count: Option<usize>,
+    pub end: ListEnd,
+}
+
+impl PopArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, end: ListEnd) -> Result<PopArguments, String> {
+        let key = next_key(&mut args)?;
+        let count = match args.len() {
+            0 => None,
+            _ => {
+                let count = next_integer(&mut args)?;
+                if count < 0 {
+                    return Err("ERR value is out of range, must be positive".to_string());
+                }
+                Some(count as usize)
+            },
+        };
+        no_more_arguments(&mut args)?;
+        Ok(PopArguments { key, count, end })
+    }
+}
+
+#[derive(Debug)]
+pub struct LrangeArguments {
+    pub key: Vec<u8>,
+    pub start: i64,
+    pub stop: i64,
+}
+
+impl Argument for LrangeArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LrangeArguments, String> {
+        let key = next_key(&mut args)?;
+        let start = next_integer(&mut args)?;
+        let stop = next_integer(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LrangeArguments { key, start, stop })
+    }
+}
+
+#[derive(Debug)]
+pub struct SaddArguments {
+    pub key: Vec<u8>,
+    pub members: Vec<Vec<u8>>,
+}
+
+impl Argument for SaddArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<SaddArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut members = vec![next_key(&mut args)?];
+        for _ in 0..args.len() {
+            members.push(next_key(&mut args)?);
+        }
+        Ok(SaddArguments { key, members })
+    }
+}
+
+// PFADD key [element ...], without elements it only creates the key.
+#[derive(Debug)]
+pub struct PfaddArguments {
+    pub key: Vec<u8>,
+    pub elements: Vec<Vec<u8>>,
+}
+
+impl Argument for PfaddArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<PfaddArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut elements = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            elements.push(next_key(&mut args)?);
+        }
+        Ok(PfaddArguments { key, elements })
+    }
+}
+
+// PFMERGE destkey [sourcekey ...], the destination is merged into too.
+#[derive(Debug)]
+pub struct PfmergeArguments {
+    pub destination: Vec<u8>,
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl Argument for PfmergeArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<PfmergeArguments, String> {
+        let destination = next_key(&mut args)?;
+        let mut keys = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            keys.push(next_key(&mut args)?);
+        }
+        Ok(PfmergeArguments { destination, keys })
+    }
+}
+
+// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]
+#[derive(Debug)]
+pub struct XaddArguments {
+    pub key: Vec<u8>,
+    pub nomkstream: bool,
+    pub trim: Option<StreamTrim>,
+    pub id: NewStreamId,
+    pub fields: StreamFields,
+}
+
+impl Argument for XaddArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<XaddArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut nomkstream = false;
+        let mut trim = None;
+
+        // the options come first, the first argument that isn't one is the id.
+        let id = loop {
+            let arg = next_key(&mut args)?;
+            match arg.to_ascii_uppercase().as_slice() {
+                b"NOMKSTREAM" => nomkstream = true,
+                kind @ (b"MAXLEN" | b"MINID") => {
+                    let mut threshold = next_key(&mut args)?;
+                    let mut approximate = false;
+                    if threshold == b"=" || threshold == b"~" {
+                        approximate = threshold == b"~";
+                        threshold = next_key(&mut args)?;
+                    }
+
+                    trim = Some(if kind == b"MAXLEN" {
+                        let len = std::str::from_utf8(&threshold)
+                            .ok()
+                            .and_then(|s| s.parse::<i64>().ok())
+                            .ok_or("ERR value is not an integer or out of range".to_string())?;
+                        if len < 0 {
+                            return Err("ERR The MAXLEN argument must be >= 0.".to_string());
+                        }
+                        StreamTrim::MaxLen(len as usize)
+                    } else {
+                        StreamTrim::MinId(parse_stream_id(&threshold, 0)?)
+                    });
+
+                    // LIMIT only bounds how much an approximate trim may do, trimming here
+                    // is always exact so it is checked and then ignored.
+                    if args.as_slice().first().is_some_and(|next| matches!(next, Resp::BulkString(b) if b.eq_ignore_ascii_case(b"LIMIT"))) {
+                        args.next();
+                        if !approximate {
+                            return Err("ERR syntax error, LIMIT cannot be used without the special ~ option".to_string());
+                        }
+                        if next_integer(&mut args)? < 0 {
+                            return Err("ERR The LIMIT argument must be >= 0.".to_string());
+                        }
+                    }
+                },
+                b"*" => break NewStreamId::Auto,
+                _ => match arg.strip_suffix(b"-*") {
+                    Some(ms) => break NewStreamId::AutoSeq(parse_stream_id(ms, 0)?.ms),
+                    None => break NewStreamId::Explicit(parse_stream_id(&arg, 0)?),
+                },
+            }
+        };
+
+        if args.len() == 0 || !args.len().is_multiple_of(2) {
+            return Err("ERR wrong number of arguments for 'xadd' command".to_string());
+        }
+        let mut fields = Vec::with_capacity(args.len() / 2);
+        while args.len() > 0 {
+            fields.push((next_key(&mut args)?, next_key(&mut args)?));
+        }
+
+        Ok(XaddArguments { key, nomkstream, trim, id, fields })
+    }
+}
+
+// XRANGE key start end [COUNT count] and XREVRANGE key end start [COUNT count]. exclusive
+// bounds are turned into the inclusive ones next to them.
+#[derive(Debug)]
+pub struct XrangeArguments {
+    pub key: Vec<u8>,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub rev: bool,
+    pub count: Option<usize>,
+}
+
+impl XrangeArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, rev: bool) -> Result<XrangeArguments, String> {
+        let key = next_key(&mut args)?;
+        let (first, second) = (next_key(&mut args)?, next_key(&mut args)?);
+        let (start, end) = if rev { (second, first) } else { (first, second) };
+        let start = parse_range_id(&start, true)?;
+        let end = parse_range_id(&end, false)?;
+
+        let mut count = None;
+        while let Some(option) = args.next() {
+            let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+            match option.to_ascii_uppercase().as_str() {
+                // a negative count returns nothing, like redis.
+                "COUNT" => count = Some(next_integer(&mut args)?.max(0) as usize),
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        Ok(XrangeArguments { key, start, end, rev, count })
+    }
+}
+
+// XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER key group ...
+#[derive(Debug)]
+pub enum XgroupArguments {
+    // XGROUP CREATE key group id|$ [MKSTREAM] [ENTRIESREAD n], none for $.
+    Create { key: Vec<u8>, group: Vec<u8>, id: Option<StreamId>, mkstream: bool },
+    // XGROUP SETID key group id|$ [ENTRIESREAD n]
+    Setid { key: Vec<u8>, group: Vec<u8>, id: Option<StreamId> },
+    Destroy { key: Vec<u8>, group: Vec<u8> },
+    Createconsumer { key: Vec<u8>, group: Vec<u8>, consumer: Vec<u8> },
+    Delconsumer { key: Vec<u8>, group: Vec<u8>, consumer: Vec<u8> },
+}
+
+impl Argument for XgroupArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<XgroupArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'xgroup' command".to_string()),
+        };
+        let name = subcommand.to_lowercase();
+        let arity = |expected: usize, len: usize| {
+            if len < expected {
+                Err(format!("ERR wrong number of arguments for 'xgroup|{}' command", name))
+            } else {
+                Ok(())
+            }
+        };
+
+        match name.as_str() {
+            "create" | "setid" => {
+                arity(3, args.len())?;
+                let (key, group) = (next_key(&mut args)?, next_key(&mut args)?);
+                let id = match next_key(&mut args)?.as_slice() {
+                    b"$" => None,
+                    id => Some(parse_stream_id(id, 0)?),
+                };
+
+                let mut mkstream = false;
+                while let Some(option) = args.next() {
+                    let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+                    match option.to_ascii_uppercase().as_str() {
+                        "MKSTREAM" if name == "create" => mkstream = true,
+                        // entries read only feeds the lag XINFO reports, which isn't kept.
+                        "ENTRIESREAD" => {
+                            if next_integer(&mut args)? < -1 {
+                                return Err("ERR value for ENTRIESREAD must be positive or -1".to_string());
+                            }
+                        },
+                        _ => return Err("ERR syntax error".to_string()),
+                    }
+                }
+
+                Ok(if name == "create" {
+                    XgroupArguments::Create { key, group, id, mkstream }
+                } else {
+                    XgroupArguments::Setid { key, group, id }
+                })
+            },
+            "destroy" => {
+                arity(2, args.len())?;
+                let (key, group) = (next_key(&mut args)?, next_key(&mut args)?);
+                no_more_arguments(&mut args)?;
+                Ok(XgroupArguments::Destroy { key, group })
+            },
+            "createconsumer" | "delconsumer" => {
+                arity(3, args.len())?;
+                let (key, group, consumer) = (next_key(&mut args)?, next_key(&mut args)?, next_key(&mut args)?);
+                no_more_arguments(&mut args)?;
+                Ok(if name == "createconsumer" {
+                    XgroupArguments::Createconsumer { key, group, consumer }
+                } else {
+                    XgroupArguments::Delconsumer { key, group, consumer }
+                })
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try XGROUP HELP.", subcommand)),
+        }
+    }
+}
+
+// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+// [key ...] id [id ...]
+#[derive(Debug)]
+pub struct XreadgroupArguments {
+    pub group: Vec<u8>,
+    pub consumer: Vec<u8>,
+    pub count: Option<usize>,
+    pub block: bool,
+    // how long to block for, none blocks until something is added.
+    pub timeout: Option<Duration>,
+    pub noack: bool,
+    // each key and the id to read the consumer's pending entries after, none for >.
+    pub streams: Vec<(Vec<u8>, Option<StreamId>)>,
+}
+
+impl Argument for XreadgroupArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<XreadgroupArguments, String> {
+        let mut group = None;
+        let (mut count, mut block, mut timeout, mut noack) = (None, false, None, false);
+
+        loop {
+            let option: String = next_key(&mut args)
+                .map_err(|_| "ERR wrong number of arguments for 'xreadgroup' command".to_string())
+                .and_then(|option| String::from_utf8(option).map_err(|_| "ERR syntax error".to_string()))?;
+            match option.to_ascii_uppercase().as_str() {
+                "GROUP" => group = Some((next_key(&mut args)?, next_key(&mut args)?)),
+                // 0 like a negative count means no limit.
+                "COUNT" => count = Some(next_integer(&mut args)?).filter(|&count| count > 0).map(|count| count as usize),
+                "BLOCK" => {
+                    let ms = next_integer(&mut args)?;
+                    if ms < 0 {
+                        return Err("ERR timeout is negative".to_string());
+                    }
+                    block = true;
+                    timeout = Some(Duration::from_millis(ms as u64)).filter(|timeout| !timeout.is_zero());
+                },
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        let (group, consumer) = match group {
+            Some(group) => group,
+            None => return Err("ERR Missing GROUP option for XREADGROUP".to_string()),
+        };
+        if args.len() == 0 || !args.len().is_multiple_of(2) {
+            return Err("ERR Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '$' must be specified.".to_string());
+        }
+
+        let mut keys = Vec::with_capacity(args.len() / 2);
+        for _ in 0..args.len() / 2 {
+            keys.push(next_key(&mut args)?);
+        }
+        let mut streams = Vec::with_capacity(keys.len());
+        for key in keys {
+            let id = match next_key(&mut args)?.as_slice() {
+                b">" => None,
+                b"$" => return Err("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".to_string()),
+                id => Some(parse_stream_id(id, 0)?),
+            };
+            streams.push((key, id));
+        }
+
+        Ok(XreadgroupArguments { group, consumer, count, block, timeout, noack, streams })
+    }
+}
+
+// XACK key group id [id ...]
+#[derive(Debug)]
+pub struct XackArguments {
+    pub key: Vec<u8>,
+    pub group: Vec<u8>,
+    pub ids: Vec<StreamId>,
+}
+
+impl Argument for XackArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<XackArguments, String> {
+        let (key, group) = (next_key(&mut args)?, next_key(&mut args)?);
+        let mut ids = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            ids.push(parse_stream_id(&next_key(&mut args)?, 0)?);
+        }
+        Ok(XackArguments { key, group, ids })
+    }
+}
+
+// SUBSCRIBE channel [channel ...] and UNSUBSCRIBE [channel ...], where no channels means all
+// of them. PSUBSCRIBE and PUNSUBSCRIBE take patterns the same way, SSUBSCRIBE and SUNSUBSCRIBE
+// shard channels.
+#[derive(Debug)]
+pub struct ChannelsArguments {
+    pub kind: SubscriptionKind,
+    pub channels: Vec<Vec<u8>>,
+}
+
+impl ChannelsArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, kind: SubscriptionKind, subscribe: bool) -> Result<ChannelsArguments, String> {
+        if subscribe && args.len() == 0 {
+            let name = String::from_utf8_lossy(kind.replies().0);
+            return Err(format!("ERR wrong number of arguments for '{}' command", name));
+        }
+        let mut channels = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            channels.push(next_key(&mut args)?);
+        }
+        Ok(ChannelsArguments { kind, channels })
+    }
+}
+
+// PUBLISH channel message, and SPUBLISH for shard channels.
+#[derive(Debug)]
+pub struct PublishArguments {
+    pub channel: Vec<u8>,
+    pub message: Vec<u8>,
+    pub shard: bool,
+}
+
+impl PublishArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, shard: bool) -> Result<PublishArguments, String> {
+        if args.len() != 2 {
+            let name = if shard { "spublish" } else { "publish" };
+            return Err(format!("ERR wrong number of arguments for '{}' command", name));
+        }
+        let (channel, message) = (next_key(&mut args)?, next_key(&mut args)?);
+        Ok(PublishArguments { channel, message, shard })
+    }
+}
+
+// <ms>-<seq>, or just <ms> with missing_seq as the sequence.
+pub fn parse_stream_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, String> {
+    let invalid = || "ERR Invalid stream ID specified as stream command argument".to_string();
+    let number = |part: &[u8]| std::str::from_utf8(part).ok().and_then(|s| s.parse::<u64>().ok()).ok_or_else(invalid);
+
+    match arg.iter().position(|&b| b == b'-') {
+        Some(dash) => Ok(StreamId::new(number(&arg[..dash])?, number(&arg[dash + 1..])?)),
+        None => Ok(StreamId::new(number(arg)?, missing_seq)),
+    }
+}
+
+// one end of an XRANGE: - and + for either end of the stream, an id, or an id after a ( to
+// leave it out of the range.
+fn parse_range_id(arg: &[u8], start: bool) -> Result<StreamId, String> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => {
+            let id = parse_stream_id(id, missing_seq)?;
+            let id = if start { id.next() } else { id.prev() };
+            id.ok_or(format!("ERR invalid {} ID for the interval", if start { "start" } else { "end" }))
+        },
+        _ => parse_stream_id(arg, missing_seq),
+    }
+}
+
+// SMISMEMBER key member [member ...]
+#[derive(Debug)]
+pub struct SmismemberArguments {
+    pub key: Vec<u8>,
+    pub members: Vec<Vec<u8>>,
+}
+
+impl Argument for SmismemberArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<SmismemberArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut members = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            members.push(next_key(&mut args)?);
+        }
+        Ok(SmismemberArguments { key, members })
+    }
+}
+
+// HSET key field value [field value ...]
+#[derive(Debug)]
+pub struct HsetArguments {
+    pub key: Vec<u8>,
+    pub pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Argument for HsetArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<HsetArguments, String> {
+        if args.len() < 3 || args.len().is_multiple_of(2) {
+            return Err("ERR wrong number of arguments for 'hset' command".to_string());
+        }
+
+        let key = next_key(&mut args)?;
+        let mut pairs = Vec::with_capacity(args.len() / 2);
+        while args.len() > 0 {
+            let field = next_key(&mut args)?;
+            let value = next_key(&mut args)?;
+            pairs.push((field, value));
+        }
+        Ok(HsetArguments { key, pairs })
+    }
+}
+
+#[derive(Debug)]
+pub struct HgetArguments {
+    pub key: Vec<u8>,
+    pub field: Vec<u8>,
+}
+
+impl Argument for HgetArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<HgetArguments, String> {
+        let key = next_key(&mut args)?;
+        let field = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(HgetArguments { key, field })
+    }
+}
+
+// HRANDFIELD key [count [WITHVALUES]]
+#[derive(Debug)]
+pub struct HrandfieldArguments {
+    pub key: Vec<u8>,
+    // none replies with a single field rather than an array.
+    pub count: Option<i64>,
+    pub with_values: bool,
+}
+
+impl Argument for HrandfieldArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<HrandfieldArguments, String> {
+        let key = next_key(&mut args)?;
+        let count = match args.len() {
+            0 => None,
+            _ => Some(next_integer(&mut args)?),
+        };
+
+        let with_values = match args.next() {
+            Some(Resp::BulkString(arg)) if arg.eq_ignore_ascii_case(b"WITHVALUES") => true,
+            Some(_) => return Err("ERR syntax error".to_string()),
+            None => false,
+        };
+        no_more_arguments(&mut args)?;
+
+        // -count fields are sent back with repetition, redis refuses counts it couldn't double.
+        if count.is_some_and(|count| count < -(i64::MAX / 2)) {
+            return Err("ERR value is out of range".to_string());
+        }
+        Ok(HrandfieldArguments { key, count, with_values })
+    }
+}
+
+// HEXPIRE and HPEXPIRE key time [NX | XX | GT | LT] FIELDS numfields field [field ...]
+#[derive(Debug)]
+pub struct HexpireArguments {
+    pub key: Vec<u8>,
+    // seconds or milliseconds depending on the command, 0 removes the fields.
+    pub time: i64,
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<Vec<u8>>,
+}
+
+impl Argument for HexpireArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<HexpireArguments, String> {
+        let key = next_key(&mut args)?;
+        let time = next_integer(&mut args)?;
+        let condition = match args.as_slice().first() {
+            Some(Resp::BulkString(arg)) => match arg.to_ascii_uppercase().as_slice() {
+                b"NX" => Some(ExpireCondition::Nx),
+                b"XX" => Some(ExpireCondition::Xx),
+                b"GT" => Some(ExpireCondition::Gt),
+                b"LT" => Some(ExpireCondition::Lt),
+                _ => None,
+            },
+            _ => None,
+        };
+        if condition.is_some() {
+            args.next();
+        }
+
+        let fields = next_counted_fields(&mut args)?;
+        Ok(HexpireArguments { key, time, condition, fields })
+    }
+}
+
+// FIELDS numfields field [field ...], the way the hash field ttl commands name their fields.
+fn next_counted_fields(args: &mut IntoIter<Resp>) -> Result<Vec<Vec<u8>>, String> {
+    match args.next() {
+        Some(Resp::BulkString(arg)) if arg.eq_ignore_ascii_case(b"FIELDS") => {},
+        _ => return Err("ERR Mandatory argument FIELDS is missing or not at the right position".to_string()),
+    }
+
+    let count = next_integer(args)?;
+    if count <= 0 {
+        return Err("ERR Parameter `numFields` should be greater than 0".to_string());
+    }
+    if count as usize != args.len() {
+        return Err("ERR The `numfields` parameter must match the number of arguments".to_string());
+    }
+
+    let mut fields = Vec::with_capacity(args.len());
+    for _ in 0..args.len() {
+        fields.push(next_key(args)?);
+    }
+    Ok(fields)
+}
+
+// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+#[derive(Debug)]
+pub struct ZaddArguments {
+    pub key: Vec<u8>,
+    pub flags: ZaddFlags,
+    // reply with the members added or changed rather than only the added ones.
+    pub ch: bool,
+    // add the score to the member's current one and reply with the result, like ZINCRBY.
+    pub incr: bool,
+    pub pairs: Vec<(f64, Vec<u8>)>,
+}
+
+impl Argument for ZaddArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ZaddArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut rest = Vec::with_capacity(args.len());
+        for _ in 0..args.len() {
+            rest.push(next_key(&mut args)?);
+        }
+
+        // the options come first, the first argument that isn't one starts the pairs.
+        let mut flags = ZaddFlags::default();
+        let mut ch = false;
+        let mut incr = false;
+        let mut options = 0;
+        for arg in &rest {
+            match arg.to_ascii_uppercase().as_slice() {
+                b"NX" => flags.nx = true,
+                b"XX" => flags.xx = true,
+                b"GT" => flags.gt = true,
+                b"LT" => flags.lt = true,
+                b"CH" => ch = true,
+                b"INCR" => incr = true,
+                _ => break,
+            }
+            options += 1;
+        }
+
+        let pairs = &rest[options..];
+        if pairs.is_empty() || pairs.len() % 2 != 0 {
+            return Err("ERR syntax error".to_string());
+        }
+        if incr && pairs.len() > 2 {
+            return Err("ERR INCR option supports a single increment-element pair".to_string());
+        }
+        if flags.nx && flags.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".to_string());
+        }
+        if (flags.gt && flags.lt) || (flags.nx && (flags.gt || flags.lt)) {
+            return Err("ERR GT, LT, and/or NX options at the same time are not compatible".to_string());
+        }
+
+        let pairs = pairs
+            .chunks(2)
+            .map(|pair| parse_score(&pair[0]).map(|score| (score, pair[1].clone())))
+            .collect::<Result<_, _>>()?;
+        Ok(ZaddArguments { key, flags, ch, incr, pairs })
+    }
+}
+
+// ZSCORE key member
+#[derive(Debug)]
+pub struct ZscoreArguments {
+    pub key: Vec<u8>,
+    pub member: Vec<u8>,
+}
+
+impl Argument for ZscoreArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ZscoreArguments, String> {
+        let key = next_key(&mut args)?;
+        let member = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(ZscoreArguments { key, member })
+    }
+}
+
+// ZRANK key member [WITHSCORE], ZREVRANK ranks from the highest score.
+#[derive(Debug)]
+pub struct ZrankArguments {
+    pub key: Vec<u8>,
+    pub member: Vec<u8>,
+    pub rev: bool,
+    pub with_score: bool,
+}
+
+impl ZrankArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, rev: bool) -> Result<ZrankArguments, String> {
+        let key = next_key(&mut args)?;
+        let member = next_key(&mut args)?;
+        let with_score = match args.next() {
+            Some(Resp::BulkString(arg)) if arg.eq_ignore_ascii_case(b"WITHSCORE") => true,
+            Some(_) => return Err("ERR syntax error".to_string()),
+            None => false,
+        };
+        no_more_arguments(&mut args)?;
+        Ok(ZrankArguments { key, member, rev, with_score })
+    }
+}
+
+// ZINCRBY key increment member
+#[derive(Debug)]
+pub struct ZincrbyArguments {
+    pub key: Vec<u8>,
+    pub increment: f64,
+    pub member: Vec<u8>,
+}
+
+impl Argument for ZincrbyArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ZincrbyArguments, String> {
+        let key = next_key(&mut args)?;
+        let increment = parse_score(&next_key(&mut args)?)?;
+        let member = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(ZincrbyArguments { key, increment, member })
+    }
+}
+
+// ZUNION, ZINTER and ZDIFF numkeys key [key ...] and their STORE forms, which take a
+// destination first. all but ZDIFF[STORE] take [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX],
+// only the forms that reply with the result take [WITHSCORES].
+#[derive(Debug)]
+pub struct ZcombineArguments {
+    pub destination: Option<Vec<u8>>,
+    pub keys: Vec<Vec<u8>>,
+    // one per key, 1 unless WEIGHTS said otherwise.
+    pub weights: Vec<f64>,
+    pub aggregate: Aggregate,
+    pub with_scores: bool,
+}
+
+impl ZcombineArguments {
+    // name is the lowercase command, redis puts it in the error for a numkeys of 0.
+    pub fn parse_with(mut args: IntoIter<Resp>, name: &str, store: bool, weighted: bool) -> Result<ZcombineArguments, String> {
+        let destination = if store { Some(next_key(&mut args)?) } else { None };
+        let numkeys = next_integer(&mut args)?;
+        if numkeys <= 0 {
+            return Err(format!("ERR at least 1 input key is needed for '{}' command", name));
+        }
+        if numkeys as usize > args.len() {
+            return Err("ERR syntax error".to_string());
+        }
+
+        let mut keys = Vec::with_capacity(numkeys as usize);
+        for _ in 0..numkeys {
+            keys.push(next_key(&mut args)?);
+        }
+
+        let mut weights = vec![1.0; keys.len()];
+        let mut aggregate = Aggregate::Sum;
+        let mut with_scores = false;
+        while let Some(option) = args.next() {
+            let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+            match option.to_ascii_uppercase().as_str() {
+                "WEIGHTS" if weighted => {
+                    for weight in weights.iter_mut() {
+                        let value = next_key(&mut args).map_err(|_| "ERR syntax error".to_string())?;
+                        *weight = parse_score(&value).map_err(|_| "ERR weight value is not a float".to_string())?;
+                    }
+                },
+                "AGGREGATE" if weighted => {
+                    let value = next_key(&mut args).map_err(|_| "ERR syntax error".to_string())?;
+                    aggregate = match value.to_ascii_uppercase().as_slice() {
+                        b"SUM" => Aggregate::Sum,
+                        b"MIN" => Aggregate::Min,
+                        b"MAX" => Aggregate::Max,
+                        _ => return Err("ERR syntax error".to_string()),
+                    };
+                },
+                "WITHSCORES" if !store => with_scores = true,
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        Ok(ZcombineArguments { destination, keys, weights, aggregate, with_scores })
+    }
+}
+
+// what the two bounds of a ZRANGE are, before they are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES], and the
+// older ZRANGEBYSCORE, ZRANGEBYLEX and ZREV* commands it folds together. reversed score and
+// lex ranges take the maximum first.
+#[derive(Debug)]
+pub struct ZrangeArguments {
+    pub key: Vec<u8>,
+    pub by: database::ZrangeBy,
+    pub rev: bool,
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+}
+
+impl ZrangeArguments {
+    // only ZRANGE itself is unified and accepts BYSCORE, BYLEX and REV, the older commands
+    // have them implied by their name.
+    pub fn parse_with(mut args: IntoIter<Resp>, mut kind: RangeKind, mut rev: bool, unified: bool) -> Result<ZrangeArguments, String> {
+        let key = next_key(&mut args)?;
+        let first = next_key(&mut args)?;
+        let second = next_key(&mut args)?;
+
+        let mut limit = None;
+        let mut with_scores = false;
+        while let Some(arg) = args.next() {
+            let option = match arg {
+                Resp::BulkString(b) => b.to_ascii_uppercase(),
+                _ => return Err("ERR syntax error".to_string()),
+            };
+            match option.as_slice() {
+                b"WITHSCORES" => with_scores = true,
+                b"LIMIT" if args.len() >= 2 => limit = Some((next_integer(&mut args)?, next_integer(&mut args)?)),
+                b"BYSCORE" if unified => kind = RangeKind::Score,
+                b"BYLEX" if unified => kind = RangeKind::Lex,
+                b"REV" if unified => rev = true,
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        if limit.is_some() && kind == RangeKind::Rank {
+            return Err("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".to_string());
+        }
+        if with_scores && kind == RangeKind::Lex {
+            return Err("ERR syntax error, WITHSCORES not supported in combination with BYLEX".to_string());
+        }
+
+        let (min, max) = if rev && kind != RangeKind::Rank { (second, first) } else { (first, second) };
+        let by = match kind {
+            RangeKind::Rank => {
+                let rank = |arg: &[u8]| std::str::from_utf8(arg)
+                    .ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .ok_or("ERR value is not an integer or out of range".to_string());
+                database::ZrangeBy::Rank(rank(&min)?, rank(&max)?)
+            },
+            RangeKind::Score => database::ZrangeBy::Score(parse_score_bound(&min)?, parse_score_bound(&max)?),
+            RangeKind::Lex => database::ZrangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+        };
+        Ok(ZrangeArguments { key, by, rev, limit, with_scores })
+    }
+}
+
+// a score range bound, a score optionally preceded by ( to exclude it.
+fn parse_score_bound(arg: &[u8]) -> Result<database::ScoreBound, String> {
+    let error = |_| "ERR min or max is not a float".to_string();
+    match arg.strip_prefix(b"(") {
+        Some(score) => parse_score(score).map(database::ScoreBound::Exclusive).map_err(error),
+        None => parse_score(arg).map(database::ScoreBound::Inclusive).map_err(error),
+    }
+}
+
+// a lex range bound, - or + or a member preceded by [ to include it or ( to exclude it.
+fn parse_lex_bound(mut arg: Vec<u8>) -> Result<database::LexBound, String> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(database::LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(database::LexBound::Max),
+        Some(b'[') => Ok(database::LexBound::Inclusive(arg.split_off(1))),
+        Some(b'(') => Ok(database::LexBound::Exclusive(arg.split_off(1))),
+        _ => Err("ERR min or max not valid string range item".to_string()),
+    }
+}
+
+// a hash and one or more of its fields, shared by HDEL and HMGET. HPERSIST and HTTL count
+// theirs after a FIELDS keyword, see parse_counted.
+#[derive(Debug)]
+pub struct FieldsArguments {
+    pub key: Vec<u8>,
+    pub fields: Vec<Vec<u8>>,
+}
+
+impl FieldsArguments {
+    // key FIELDS numfields field [field ...]
+    pub fn parse_counted(mut args: IntoIter<Resp>) -> Result<FieldsArguments, String> {
+        let key = next_key(&mut args)?;
+        let fields = next_counted_fields(&mut args)?;
+        Ok(FieldsArguments { key, fields })
+    }
+}
+
+impl Argument for FieldsArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<FieldsArguments, String> {
+        let key = next_key(&mut args)?;
+        let mut fields = vec![next_key(&mut args)?];
+        for _ in 0..args.len() {
+            fields.push(next_key(&mut args)?);
+        }
+        Ok(FieldsArguments { key, fields })
+    }
+}
+
+// the destination and inputs of the *STORE variants of the set algebra.
+#[derive(Debug)]
+pub struct StoreArguments {
+    pub destination: Vec<u8>,
+    pub keys: Vec<Vec<u8>>,
+}
+
+impl Argument for StoreArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<StoreArguments, String> {
+        let destination = next_key(&mut args)?;
+        let mut keys = vec![next_key(&mut args)?];
+        for _ in 0..args.len() {
+            keys.push(next_key(&mut args)?);
+        }
+        Ok(StoreArguments { destination, keys })
+    }
+}
+
+// SINTERCARD and ZINTERCARD, numkeys key [key ...] [LIMIT limit].
+#[derive(Debug)]
+pub struct IntercardArguments {
+    pub keys: Vec<Vec<u8>>,
+    // none counts the whole intersection, LIMIT 0 means the same.
+    pub limit: Option<usize>,
+}
+
+impl Argument for IntercardArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<IntercardArguments, String> {
+        let numkeys = next_integer(&mut args)?;
+        if numkeys <= 0 {
+            return Err("ERR numkeys should be greater than 0".to_string());
+        }
+        if numkeys as usize > args.len() {
+            return Err("ERR Number of keys can't be greater than number of args".to_string());
+        }
+
+        let mut keys = Vec::with_capacity(numkeys as usize);
+        for _ in 0..numkeys {
+            keys.push(next_key(&mut args)?);
+        }
+
+        let mut limit = None;
+        while let Some(option) = args.next() {
+            let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+            if !option.eq_ignore_ascii_case("LIMIT") {
+                return Err("ERR syntax error".to_string());
+            }
+
+            let value = next_integer(&mut args)?;
+            if value < 0 {
+                return Err("ERR LIMIT can't be negative".to_string());
+            }
+            limit = if value == 0 { None } else { Some(value as usize) };
+        }
+
+        Ok(IntercardArguments { keys, limit })
+    }
+}
+
+// LMPOP numkeys key [key ...] LEFT|RIGHT [COUNT count], BLMPOP takes a timeout first.
+#[derive(Debug)]
+pub struct MpopArguments {
+    pub keys: Vec<Vec<u8>>,
+    pub end: ListEnd,
+    pub count: usize,
+    pub blocking: bool,
+    // only used when blocking, none blocks until something is pushed.
+    pub timeout: Option<Duration>,
+}
+
+impl MpopArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, blocking: bool) -> Result<MpopArguments, String> {
+        let timeout = match blocking {
+            true => parse_timeout(args.next().ok_or("ERR wrong number of arguments".to_string())?)?,
+            false => None,
+        };
+
+        let numkeys = next_integer(&mut args)?;
+        if numkeys <= 0 {
+            return Err("ERR numkeys should be greater than 0".to_string());
+        }
+        if numkeys as usize >= args.len() {
+            return Err("ERR Number of keys can't be greater than number of args".to_string());
+        }
+
+        let mut keys = Vec::with_capacity(numkeys as usize);
+        for _ in 0..numkeys {
+            keys.push(next_key(&mut args)?);
+        }
+        let end = next_list_end(&mut args)?;
+
+        let mut count = 1;
+        while let Some(option) = args.next() {
+            let option: String = option.try_into().map_err(|_| "ERR syntax error".to_string())?;
+            if !option.eq_ignore_ascii_case("COUNT") {
+                return Err("ERR syntax error".to_string());
+            }
+
+            let value = next_integer(&mut args)?;
+            if value <= 0 {
+                return Err("ERR count should be greater than 0".to_string());
+            }
+            count = value as usize;
+        }
+
+        Ok(MpopArguments { keys, end, count, blocking, timeout })
+    }
+}
+
+// BLPOP and BRPOP, key [key ...] timeout.
 #[derive(Debug)]
-pub struct EchoArguments {
-    pub message: Resp,
+pub struct BlockingPopArguments {
+    pub keys: Vec<Vec<u8>>,
+    pub end: ListEnd,
+    // none blocks until something is pushed.
+    pub timeout: Option<Duration>,
 }
 
-impl Argument for EchoArguments {
-    fn parse(mut args: IntoIter<Resp>) -> Result<EchoArguments, String> {
-        let message = match args.next() {
-            Some(resp) => resp,
-            _ => return Err("ERR wrong number of arguments for 'echo' command".to_string())
+impl BlockingPopArguments {
+    pub fn parse_with(mut args: IntoIter<Resp>, end: ListEnd) -> Result<BlockingPopArguments, String> {
+        let mut keys = vec![next_key(&mut args)?];
+        let timeout = match args.next_back() {
+            Some(resp) => parse_timeout(resp)?,
+            None => return Err("ERR wrong number of arguments".to_string()),
         };
+        for _ in 0..args.len() {
+            keys.push(next_key(&mut args)?);
+        }
+        Ok(BlockingPopArguments { keys, end, timeout })
+    }
+}
 
-        // Ensure the message is a bulk string; otherwise, return an error
-        if let Resp::BulkString(_) = message {
-            Ok(EchoArguments { message })
-        } else {
-            Err("ERR argument must be a bulk string".to_string())
+// LMOVE source destination LEFT|RIGHT LEFT|RIGHT, RPOPLPUSH is the RIGHT LEFT form.
+#[derive(Debug)]
+pub struct LmoveArguments {
+    pub source: Vec<u8>,
+    pub destination: Vec<u8>,
+    pub from: ListEnd,
+    pub to: ListEnd,
+}
+
+impl Argument for LmoveArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LmoveArguments, String> {
+        let source = next_key(&mut args)?;
+        let destination = next_key(&mut args)?;
+        let from = next_list_end(&mut args)?;
+        let to = next_list_end(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LmoveArguments { source, destination, from, to })
+    }
+}
+
+impl LmoveArguments {
+    pub fn parse_rpoplpush(mut args: IntoIter<Resp>) -> Result<LmoveArguments, String> {
+        let source = next_key(&mut args)?;
+        let destination = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LmoveArguments { source, destination, from: ListEnd::Right, to: ListEnd::Left })
+    }
+}
+
+// BLMOVE and BRPOPLPUSH, the LMOVE and RPOPLPUSH arguments followed by a timeout.
+#[derive(Debug)]
+pub struct BlockingMoveArguments {
+    pub lmove: LmoveArguments,
+    // none blocks until something is pushed.
+    pub timeout: Option<Duration>,
+}
+
+impl BlockingMoveArguments {
+    pub fn parse(mut args: IntoIter<Resp>) -> Result<BlockingMoveArguments, String> {
+        if args.len() != 5 {
+            return Err("ERR wrong number of arguments for 'blmove' command".to_string());
         }
+        let timeout = parse_timeout(args.next_back().unwrap())?;
+        Ok(BlockingMoveArguments { lmove: LmoveArguments::parse(args)?, timeout })
+    }
+
+    pub fn parse_brpoplpush(mut args: IntoIter<Resp>) -> Result<BlockingMoveArguments, String> {
+        if args.len() != 3 {
+            return Err("ERR wrong number of arguments for 'brpoplpush' command".to_string());
+        }
+        let timeout = parse_timeout(args.next_back().unwrap())?;
+        Ok(BlockingMoveArguments { lmove: LmoveArguments::parse_rpoplpush(args)?, timeout })
     }
 }
 
+// LINSERT key BEFORE|AFTER pivot element
 #[derive(Debug)]
-pub struct GetArguments {
+pub struct LinsertArguments {
     pub key: Vec<u8>,
+    pub before: bool,
+    pub pivot: Vec<u8>,
+    pub value: Vec<u8>,
 }
 
-impl Argument for GetArguments {
-    fn parse(mut args: IntoIter<Resp>) -> Result<GetArguments, String> {
-        // Since we check that there is exactly one argument, we can safely pop it
-        let key = match args.next() {
-            Some(Resp::BulkString(b)) => b,
-            _ => return Err("ERR argument must be a bulk string".to_string()),
+impl Argument for LinsertArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LinsertArguments, String> {
+        let key = next_key(&mut args)?;
+        let before = match next_key(&mut args)?.to_ascii_uppercase().as_slice() {
+            b"BEFORE" => true,
+            b"AFTER" => false,
+            _ => return Err("ERR syntax error".to_string()),
         };
+        let pivot = next_key(&mut args)?;
+        let value = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LinsertArguments { key, before, pivot, value })
+    }
+}
 
-        Ok(GetArguments { key })
+#[derive(Debug)]
+pub struct LsetArguments {
+    pub key: Vec<u8>,
+    pub index: i64,
+    pub value: Vec<u8>,
+}
+
+impl Argument for LsetArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LsetArguments, String> {
+        let key = next_key(&mut args)?;
+        let index = next_integer(&mut args)?;
+        let value = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LsetArguments { key, index, value })
+    }
+}
+
+#[derive(Debug)]
+pub struct LremArguments {
+    pub key: Vec<u8>,
+    // the sign picks the direction, 0 removes every occurrence.
+    pub count: i64,
+    pub value: Vec<u8>,
+}
+
+impl Argument for LremArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LremArguments, String> {
+        let key = next_key(&mut args)?;
+        let count = next_integer(&mut args)?;
+        let value = next_key(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LremArguments { key, count, value })
+    }
+}
+
+#[derive(Debug)]
+pub struct LindexArguments {
+    pub key: Vec<u8>,
+    pub index: i64,
+}
+
+impl Argument for LindexArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LindexArguments, String> {
+        let key = next_key(&mut args)?;
+        let index = next_integer(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(LindexArguments { key, index })
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpireArguments {
+    pub key: Vec<u8>,
+    // a relative ttl or an absolute unix timestamp depending on the command,
+    // either way a value that is already in the past deletes the key.
+    pub time: i64,
+}
+
+impl Argument for ExpireArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ExpireArguments, String> {
+        let key = next_key(&mut args)?;
+        let time = next_integer(&mut args)?;
+        no_more_arguments(&mut args)?;
+        Ok(ExpireArguments { key, time })
     }
 }
 
@@ -73,15 +1691,26 @@ pub struct SetArguments {
 
 #[derive(Debug)]
 pub enum Expiration {
+    // EX and PX, relative to when the command runs.
     Seconds(u64),
     Milliseconds(u64),
+    // EXAT and PXAT, absolute unix timestamps.
+    UnixSeconds(u64),
+    UnixMilliseconds(u64),
+    // KEEPTTL, an overwrite keeps whatever ttl the key already had.
+    KeepTtl,
 }
 
 impl Expiration {
-    pub fn as_duration(&self) -> Duration {
+    // the deadline this expiration resolves to, none for KEEPTTL which depends on the existing key.
+    // a deadline past what SystemTime can hold never arrives, so it resolves to none as well.
+    pub fn deadline(&self) -> Option<SystemTime> {
         match self {
-            Expiration::Seconds(s) => Duration::from_secs(*s),
-            Expiration::Milliseconds(ms) => Duration::from_millis(*ms),
+            Expiration::Seconds(s) => SystemTime::now().checked_add(Duration::from_secs(*s)),
+            Expiration::Milliseconds(ms) => SystemTime::now().checked_add(Duration::from_millis(*ms)),
+            Expiration::UnixSeconds(s) => UNIX_EPOCH.checked_add(Duration::from_secs(*s)),
+            Expiration::UnixMilliseconds(ms) => UNIX_EPOCH.checked_add(Duration::from_millis(*ms)),
+            Expiration::KeepTtl => None,
         }
     }
 }
@@ -109,33 +1738,34 @@ impl Argument for SetArguments {
                     let as_str = String::from_utf8(bs)
                         .map_err(|_| "ERR argument not utf8")?;
 
-                    match &as_str.to_uppercase()[..] {
-                        "NX" => nx = true,
-                        "XX" => xx = true,
+                    let option = as_str.to_uppercase();
+                    match &option[..] {
+                        "NX" if !xx => nx = true,
+                        "XX" if !nx => xx = true,
                         "GET" => get = true,
-                        "EX" => {
-                            if let Some(Resp::BulkString(next_arg)) = args.next() {
-                                let seconds = String::from_utf8(next_arg)
-                                    .map_err(|_| "ERR invalid seconds format")?
-                                    .parse::<u64>()
-                                    .map_err(|_| "ERR invalid seconds value")?;
-                                expiration = Some(Expiration::Seconds(seconds));
-                            } else {
-                                return Err("ERR expected seconds after 'EX'".to_string());
+                        "KEEPTTL" if expiration.is_none() => expiration = Some(Expiration::KeepTtl),
+                        "EX" | "PX" | "EXAT" | "PXAT" if expiration.is_none() => {
+                            // like redis, an option missing its value is a syntax error.
+                            if args.len() == 0 {
+                                return Err("ERR syntax error".to_string());
                             }
-                        },
-                        "PX" => { 
-                            if let Some(Resp::BulkString(next_arg)) = args.next() {
-                                let milliseconds = String::from_utf8(next_arg)
-                                    .map_err(|_| "ERR invalid milliseconds format")?
-                                    .parse::<u64>()
-                                    .map_err(|_| "ERR invalid milliseconds value")?;
-                                expiration = Some(Expiration::Milliseconds(milliseconds));
-                            } else {
-                                return Err("ERR expected milliseconds after 'PX'".to_string());
+                            let time = next_integer(&mut args)?;
+                            // seconds are converted to milliseconds later on, make sure that can't overflow.
+                            let in_seconds = option == "EX" || option == "EXAT";
+                            if time <= 0 || (in_seconds && time.checked_mul(1000).is_none()) {
+                                return Err("ERR invalid expire time in 'set' command".to_string());
                             }
+
+                            let time = time as u64;
+                            expiration = Some(match &option[..] {
+                                "EX" => Expiration::Seconds(time),
+                                "PX" => Expiration::Milliseconds(time),
+                                "EXAT" => Expiration::UnixSeconds(time),
+                                _ => Expiration::UnixMilliseconds(time),
+                            });
                         },
-                        _ => return Err("ERR unknown or unexpected argument".to_string()),
+                        // conflicting flags, i.e., NX with XX or more than one expiration.
+                        _ => return Err("ERR syntax error".to_string()),
                     }
                 },
                 _ => return Err("ERR arguments must be bulk strings".to_string()),
@@ -146,6 +1776,597 @@ impl Argument for SetArguments {
     }
 }
 
+// the legacy forms of SET, these map onto the same arguments with the flags already filled in.
+impl SetArguments {
+    // SETNX key value
+    pub fn parse_setnx(mut args: IntoIter<Resp>) -> Result<SetArguments, String> {
+        let key = next_key(&mut args)?;
+        let value = Record::from_vec(next_key(&mut args)?);
+        no_more_arguments(&mut args)?;
+        Ok(SetArguments { key, value, nx: true, xx: false, get: false, expiration: None })
+    }
+
+    // SETEX key seconds value, or PSETEX key milliseconds value when milliseconds is set.
+    pub fn parse_setex(mut args: IntoIter<Resp>, milliseconds: bool) -> Result<SetArguments, String> {
+        let key = next_key(&mut args)?;
+        let time = next_integer(&mut args)?;
+        let value = Record::from_vec(next_key(&mut args)?);
+        no_more_arguments(&mut args)?;
+
+        // the same guard as SET EX, seconds are converted to milliseconds later on.
+        if time <= 0 || (!milliseconds && time.checked_mul(1000).is_none()) {
+            let cmd = if milliseconds { "psetex" } else { "setex" };
+            return Err(format!("ERR invalid expire time in '{}' command", cmd));
+        }
+
+        let expiration = if milliseconds {
+            Expiration::Milliseconds(time as u64)
+        } else {
+            Expiration::Seconds(time as u64)
+        };
+
+        Ok(SetArguments { key, value, nx: false, xx: false, get: false, expiration: Some(expiration) })
+    }
+}
+
+#[derive(Debug)]
+pub enum DebugArguments {
+    Digest,
+    DigestValue(Vec<Vec<u8>>),
+    Object(Vec<u8>),
+    Listpack(Vec<u8>),
+    Quicklist(Vec<u8>),
+    Sleep(Duration),
+    SetActiveExpire(bool),
+    Jmap,
+}
+
+impl Argument for DebugArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<DebugArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'debug' command".to_string()),
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "DIGEST" => {
+                if args.next().is_some() {
+                    return Err("ERR wrong number of arguments for 'debug|digest' command".to_string());
+                }
+                Ok(DebugArguments::Digest)
+            },
+
+            "DIGEST-VALUE" => {
+                let mut keys = Vec::new();
+                for arg in args {
+                    match arg {
+                        Resp::BulkString(key) => keys.push(key),
+                        _ => return Err("ERR arguments must be bulk strings".to_string()),
+                    }
+                }
+                Ok(DebugArguments::DigestValue(keys))
+            },
+
+            "OBJECT" => {
+                let key = next_key(&mut args).map_err(|_| "ERR wrong number of arguments for 'debug|object' command".to_string())?;
+                no_more_arguments(&mut args)?;
+                Ok(DebugArguments::Object(key))
+            },
+
+            "LISTPACK" => {
+                let key = next_key(&mut args).map_err(|_| "ERR wrong number of arguments for 'debug|listpack' command".to_string())?;
+                no_more_arguments(&mut args)?;
+                Ok(DebugArguments::Listpack(key))
+            },
+
+            "QUICKLIST" => {
+                let key = next_key(&mut args).map_err(|_| "ERR wrong number of arguments for 'debug|quicklist' command".to_string())?;
+                no_more_arguments(&mut args)?;
+                Ok(DebugArguments::Quicklist(key))
+            },
+
+            // fractions of a second are allowed, i.e., DEBUG SLEEP 0.5
+            "SLEEP" => {
+                let seconds = match args.next() {
+                    Some(Resp::BulkString(b)) => std::str::from_utf8(&b)
+                        .ok()
+                        .and_then(|s| s.parse::<f64>().ok())
+                        .filter(|s| s.is_finite() && *s >= 0.0)
+                        .ok_or("ERR value is not a valid float".to_string())?,
+                    Some(_) => return Err("ERR arguments must be bulk strings".to_string()),
+                    None => return Err("ERR wrong number of arguments for 'debug|sleep' command".to_string()),
+                };
+                no_more_arguments(&mut args)?;
+                match Duration::try_from_secs_f64(seconds) {
+                    Ok(duration) => Ok(DebugArguments::Sleep(duration)),
+                    Err(_) => Err("ERR value is out of range".to_string()),
+                }
+            },
+
+            // any non zero value turns it on, like redis.
+            "SET-ACTIVE-EXPIRE" => {
+                if args.len() != 1 {
+                    return Err("ERR wrong number of arguments for 'debug|set-active-expire' command".to_string());
+                }
+                let enabled = next_integer(&mut args)?;
+                Ok(DebugArguments::SetActiveExpire(enabled != 0))
+            },
+
+            "JMAP" => {
+                no_more_arguments(&mut args)?;
+                Ok(DebugArguments::Jmap)
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try DEBUG HELP.", subcommand)),
+        }
+    }
+}
+
+// AUTH [username] password, without a username it authenticates the default user.
+#[derive(Debug)]
+pub struct AuthArguments {
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl Argument for AuthArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<AuthArguments, String> {
+        let first: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'auth' command".to_string()),
+        };
+
+        let second: Option<String> = match args.next() {
+            Some(resp) => Some(resp.try_into().map_err(|_| "ERR argument not utf8")?),
+            None => None,
+        };
+        no_more_arguments(&mut args)?;
+
+        match second {
+            Some(password) => Ok(AuthArguments { username: Some(first), password }),
+            None => Ok(AuthArguments { username: None, password: first }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AclArguments {
+    // every category, or the commands in one.
+    Cat(Option<&'static str>),
+    // a password of this many bits.
+    Genpass(usize),
+}
+
+impl Argument for AclArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<AclArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'acl' command".to_string()),
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "CAT" => {
+                let category: Option<String> = match args.next() {
+                    Some(resp) => Some(resp.try_into().map_err(|_| "ERR argument not utf8")?),
+                    None => return Ok(AclArguments::Cat(None)),
+                };
+                no_more_arguments(&mut args)?;
+
+                let category = category.unwrap();
+                match acl::find_category(&category) {
+                    Some(category) => Ok(AclArguments::Cat(Some(category))),
+                    None => Err(format!("ERR Unknown category '{}'", category)),
+                }
+            },
+
+            "GENPASS" => {
+                if args.len() == 0 {
+                    return Ok(AclArguments::Genpass(acl::DEFAULT_GENPASS_BITS));
+                }
+
+                let bits = next_integer(&mut args)?;
+                no_more_arguments(&mut args)?;
+                if bits <= 0 || bits as usize > acl::MAX_GENPASS_BITS {
+                    return Err(format!("ERR ACL GENPASS argument must be the number of bits for the output password, a positive number up to {}", acl::MAX_GENPASS_BITS));
+                }
+                Ok(AclArguments::Genpass(bits as usize))
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try ACL HELP.", subcommand)),
+        }
+    }
+}
+
+// only the subcommands that make sense without a cluster.
+#[derive(Debug)]
+pub enum ClusterArguments {
+    Keyslot(Vec<u8>),
+}
+
+impl Argument for ClusterArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ClusterArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'cluster' command".to_string()),
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "KEYSLOT" => {
+                let key = next_key(&mut args).map_err(|_| "ERR wrong number of arguments for 'cluster|keyslot' command".to_string())?;
+                if args.next().is_some() {
+                    return Err("ERR wrong number of arguments for 'cluster|keyslot' command".to_string());
+                }
+                Ok(ClusterArguments::Keyslot(key))
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CLUSTER HELP.", subcommand)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientArguments {
+    Id,
+    // an optional TYPE filter.
+    List(Option<ClientKind>),
+    // the old CLIENT KILL ip:port form.
+    KillAddr(String),
+    Kill(KillFilter),
+    Reply(ReplyMode),
+}
+
+impl Argument for ClientArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ClientArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'client' command".to_string()),
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "ID" => {
+                no_more_arguments(&mut args)?;
+                Ok(ClientArguments::Id)
+            },
+
+            "LIST" => {
+                let option: Option<String> = match args.next() {
+                    Some(resp) => Some(resp.try_into().map_err(|_| "ERR argument not utf8")?),
+                    None => return Ok(ClientArguments::List(None)),
+                };
+
+                if !option.is_some_and(|o| o.eq_ignore_ascii_case("TYPE")) {
+                    return Err("ERR syntax error".to_string());
+                }
+
+                let kind: String = match args.next() {
+                    Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+                    None => return Err("ERR syntax error".to_string()),
+                };
+                no_more_arguments(&mut args)?;
+
+                match ClientKind::parse(&kind) {
+                    Some(kind) => Ok(ClientArguments::List(Some(kind))),
+                    None => Err(format!("ERR Unknown client type '{}'", kind)),
+                }
+            },
+
+            "KILL" => {
+                let mut rest = Vec::with_capacity(args.len());
+                for arg in args {
+                    let arg: String = arg.try_into().map_err(|_| "ERR argument not utf8")?;
+                    rest.push(arg);
+                }
+
+                match rest.as_slice() {
+                    [] => Err("ERR wrong number of arguments for 'client|kill' command".to_string()),
+                    [addr] => Ok(ClientArguments::KillAddr(addr.clone())),
+                    _ if rest.len() % 2 != 0 => Err("ERR syntax error".to_string()),
+                    _ => {
+                        let mut filter = KillFilter::default();
+                        for pair in rest.chunks(2) {
+                            let value = &pair[1];
+                            match pair[0].to_uppercase().as_str() {
+                                "ID" => match value.parse::<u64>() {
+                                    Ok(id) if id > 0 => filter.id = Some(id),
+                                    _ => return Err("ERR client-id should be greater than 0".to_string()),
+                                },
+                                "ADDR" => filter.addr = Some(value.clone()),
+                                "LADDR" => filter.laddr = Some(value.clone()),
+                                "TYPE" => match ClientKind::parse(value) {
+                                    Some(kind) => filter.kind = Some(kind),
+                                    None => return Err(format!("ERR Unknown client type '{}'", value)),
+                                },
+                                "SKIPME" => match value.to_lowercase().as_str() {
+                                    "yes" => filter.skip_me = true,
+                                    "no" => filter.skip_me = false,
+                                    _ => return Err("ERR syntax error".to_string()),
+                                },
+                                _ => return Err("ERR syntax error".to_string()),
+                            }
+                        }
+                        Ok(ClientArguments::Kill(filter))
+                    },
+                }
+            },
+
+            "REPLY" => {
+                let mode: String = match args.next() {
+                    Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+                    None => return Err("ERR wrong number of arguments for 'client|reply' command".to_string()),
+                };
+                no_more_arguments(&mut args)?;
+
+                match mode.to_uppercase().as_str() {
+                    "ON" => Ok(ClientArguments::Reply(ReplyMode::On)),
+                    "OFF" => Ok(ClientArguments::Reply(ReplyMode::Off)),
+                    "SKIP" => Ok(ClientArguments::Reply(ReplyMode::Skip)),
+                    _ => Err("ERR syntax error".to_string()),
+                }
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CLIENT HELP.", subcommand)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjectArguments {
+    Encoding(Vec<u8>),
+    Idletime(Vec<u8>),
+    Freq(Vec<u8>),
+    Help,
+}
+
+impl Argument for ObjectArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ObjectArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'object' command".to_string()),
+        };
+
+        let name = subcommand.to_lowercase();
+        let object: fn(Vec<u8>) -> ObjectArguments = match name.as_str() {
+            "encoding" => ObjectArguments::Encoding,
+            "idletime" => ObjectArguments::Idletime,
+            "freq" => ObjectArguments::Freq,
+            "help" => {
+                no_more_arguments(&mut args)
+                    .map_err(|_| "ERR wrong number of arguments for 'object|help' command".to_string())?;
+                return Ok(ObjectArguments::Help);
+            },
+            _ => return Err(format!("ERR unknown subcommand '{}'. Try OBJECT HELP.", subcommand)),
+        };
+
+        let wrong_number = || format!("ERR wrong number of arguments for 'object|{}' command", name);
+        let key = next_key(&mut args).map_err(|_| wrong_number())?;
+        no_more_arguments(&mut args).map_err(|_| wrong_number())?;
+        Ok(object(key))
+    }
+}
+
+#[derive(Debug)]
+pub enum LatencyArguments {
+    Latest,
+    History(String),
+    // the events to reset, all of them when empty.
+    Reset(Vec<String>),
+    Help,
+}
+
+impl Argument for LatencyArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LatencyArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'latency' command".to_string()),
+        };
+
+        let name = subcommand.to_lowercase();
+        let wrong_number = || format!("ERR wrong number of arguments for 'latency|{}' command", name);
+        match name.as_str() {
+            "latest" | "help" => {
+                no_more_arguments(&mut args).map_err(|_| wrong_number())?;
+                match name.as_str() {
+                    "latest" => Ok(LatencyArguments::Latest),
+                    _ => Ok(LatencyArguments::Help),
+                }
+            },
+
+            "history" => {
+                let event: String = match args.next() {
+                    Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+                    None => return Err(wrong_number()),
+                };
+                no_more_arguments(&mut args).map_err(|_| wrong_number())?;
+                Ok(LatencyArguments::History(event))
+            },
+
+            "reset" => {
+                let events = args
+                    .map(|resp| resp.try_into().map_err(|_| "ERR argument not utf8".to_string()))
+                    .collect::<Result<Vec<String>, String>>()?;
+                Ok(LatencyArguments::Reset(events))
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try LATENCY HELP.", subcommand)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigArguments {
+    // glob patterns matched against parameter names.
+    Get(Vec<Vec<u8>>),
+    // parameter and value pairs, set all at once.
+    Set(Vec<(String, String)>),
+}
+
+impl Argument for ConfigArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<ConfigArguments, String> {
+        let subcommand: String = match args.next() {
+            Some(resp) => resp.try_into().map_err(|_| "ERR argument not utf8")?,
+            None => return Err("ERR wrong number of arguments for 'config' command".to_string()),
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "GET" => {
+                let mut patterns = Vec::with_capacity(args.len());
+                for arg in args {
+                    match arg {
+                        Resp::BulkString(pattern) => patterns.push(pattern),
+                        _ => return Err("ERR arguments must be bulk strings".to_string()),
+                    }
+                }
+
+                if patterns.is_empty() {
+                    return Err("ERR wrong number of arguments for 'config|get' command".to_string());
+                }
+                Ok(ConfigArguments::Get(patterns))
+            },
+
+            "SET" => {
+                if args.len() == 0 || !args.len().is_multiple_of(2) {
+                    return Err("ERR wrong number of arguments for 'config|set' command".to_string());
+                }
+
+                let mut pairs = Vec::with_capacity(args.len() / 2);
+                while let (Some(name), Some(value)) = (args.next(), args.next()) {
+                    let name: String = name.try_into().map_err(|_| "ERR argument not utf8")?;
+                    let value: String = value.try_into().map_err(|_| "ERR argument not utf8")?;
+                    pairs.push((name, value));
+                }
+                Ok(ConfigArguments::Set(pairs))
+            },
+
+            _ => Err(format!("ERR unknown subcommand '{}'. Try CONFIG HELP.", subcommand)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LcsArguments {
+    pub key1: Vec<u8>,
+    pub key2: Vec<u8>,
+    pub len: bool,
+    pub idx: bool,
+    pub min_match_len: u64,
+    pub with_match_len: bool,
+}
+
+impl Argument for LcsArguments {
+    fn parse(mut args: IntoIter<Resp>) -> Result<LcsArguments, String> {
+        let key1 = match args.next() {
+            Some(Resp::BulkString(b)) => b,
+            _ => return Err("ERR wrong number of arguments for 'lcs' command".to_string()),
+        };
+
+        let key2 = match args.next() {
+            Some(Resp::BulkString(b)) => b,
+            _ => return Err("ERR wrong number of arguments for 'lcs' command".to_string()),
+        };
+
+        let mut len = false;
+        let mut idx = false;
+        let mut min_match_len = 0;
+        let mut with_match_len = false;
+
+        while let Some(arg) = args.next() {
+            let as_str: String = arg
+                .try_into()
+                .map_err(|_| "ERR argument not utf8")?;
+
+            match &as_str.to_uppercase()[..] {
+                "LEN" => len = true,
+                "IDX" => idx = true,
+                "WITHMATCHLEN" => with_match_len = true,
+                "MINMATCHLEN" => {
+                    if let Some(Resp::BulkString(next_arg)) = args.next() {
+                        // negative lengths are accepted and behave like zero.
+                        min_match_len = String::from_utf8(next_arg)
+                            .map_err(|_| "ERR value is not an integer or out of range")?
+                            .parse::<i64>()
+                            .map_err(|_| "ERR value is not an integer or out of range")?
+                            .max(0) as u64;
+                    } else {
+                        return Err("ERR syntax error".to_string());
+                    }
+                },
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        if len && idx {
+            return Err("ERR If you want both the length and indexes, please just use IDX.".to_string());
+        }
+
+        Ok(LcsArguments { key1, key2, len, idx, min_match_len, with_match_len })
+    }
+}
+
+// pulls the next argument off as a key.
+pub fn next_key(args: &mut IntoIter<Resp>) -> Result<Vec<u8>, String> {
+    match args.next() {
+        Some(Resp::BulkString(b)) => Ok(b),
+        Some(_) => Err("ERR arguments must be bulk strings".to_string()),
+        None => Err("ERR wrong number of arguments".to_string()),
+    }
+}
+
+// pulls the next argument off as a signed integer.
+pub fn next_integer(args: &mut IntoIter<Resp>) -> Result<i64, String> {
+    match args.next() {
+        Some(Resp::BulkString(b)) => std::str::from_utf8(&b)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .ok_or("ERR value is not an integer or out of range".to_string()),
+        Some(_) => Err("ERR arguments must be bulk strings".to_string()),
+        None => Err("ERR wrong number of arguments".to_string()),
+    }
+}
+
+// a sorted set score, the infinities are allowed but NaN isn't.
+pub fn parse_score(arg: &[u8]) -> Result<f64, String> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or("ERR value is not a valid float".to_string())
+}
+
+// a blocking timeout in seconds with up to millisecond precision, 0 means forever.
+pub fn parse_timeout(resp: Resp) -> Result<Option<Duration>, String> {
+    let seconds = match resp {
+        Resp::BulkString(b) => std::str::from_utf8(&b)
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|s| s.is_finite())
+            .ok_or("ERR timeout is not a float or out of range".to_string())?,
+        _ => return Err("ERR arguments must be bulk strings".to_string()),
+    };
+
+    if seconds < 0.0 {
+        return Err("ERR timeout is negative".to_string());
+    }
+
+    let timeout = Duration::from_millis((seconds * 1000.0) as u64);
+    Ok(if timeout.is_zero() { None } else { Some(timeout) })
+}
+
+// pulls the next argument off as LEFT or RIGHT.
+pub fn next_list_end(args: &mut IntoIter<Resp>) -> Result<ListEnd, String> {
+    match next_key(args)?.to_ascii_uppercase().as_slice() {
+        b"LEFT" => Ok(ListEnd::Left),
+        b"RIGHT" => Ok(ListEnd::Right),
+        _ => Err("ERR syntax error".to_string()),
+    }
+}
+
+pub fn no_more_arguments(args: &mut IntoIter<Resp>) -> Result<(), String> {
+    match args.next() {
+        Some(_) => Err("ERR syntax error".to_string()),
+        None => Ok(()),
+    }
+}
+
 pub struct ArgumentParser;
 
 impl ArgumentParser {
@@ -157,18 +2378,145 @@ impl ArgumentParser {
                     .try_into()
                     .map_err(|_| "ERR unknown or unexpected command".to_string())?;
 
+                // the arity is checked here once for every command, the parsers below only look
+                // at what the arguments say.
+                if let Some(spec) = acl::lookup(&name) {
+                    if !spec.accepts(args.len() + 1) {
+                        return Err(format!("ERR wrong number of arguments for '{}' command", spec.name));
+                    }
+                }
+
                 match name.to_uppercase().as_str() {
                     "PING" => Ok(CommandArgument::Ping),
-                    "INFO" => Ok(CommandArgument::Info),
+                    "INFO" => Ok(CommandArgument::Info(InfoArguments::parse(args)?)),
                     "GET" => Ok(CommandArgument::Get(GetArguments::parse(args)?)),
                     "SET" => Ok(CommandArgument::Set(SetArguments::parse(args)?)),
+                    "SETNX" => Ok(CommandArgument::Setnx(SetArguments::parse_setnx(args)?)),
+                    "SETEX" => Ok(CommandArgument::Set(SetArguments::parse_setex(args, false)?)),
+                    "PSETEX" => Ok(CommandArgument::Set(SetArguments::parse_setex(args, true)?)),
                     "ECHO" => Ok(CommandArgument::Echo(EchoArguments::parse(args)?)),
                     "REPLCONF" => Ok(CommandArgument::Replconf(ReplconfArguments::parse(args)?)),
                     "PSYNC" => Ok(CommandArgument::Psync(PsyncArguments::parse(args)?)),
+                    "DEBUG" => Ok(CommandArgument::Debug(DebugArguments::parse(args)?)),
+                    "CONFIG" => Ok(CommandArgument::Config(ConfigArguments::parse(args)?)),
+                    "OBJECT" => Ok(CommandArgument::Object(ObjectArguments::parse(args)?)),
+                    "LATENCY" => Ok(CommandArgument::Latency(LatencyArguments::parse(args)?)),
+                    "CLIENT" => Ok(CommandArgument::Client(ClientArguments::parse(args)?)),
+                    "CLUSTER" => Ok(CommandArgument::Cluster(ClusterArguments::parse(args)?)),
+                    "PFADD" => Ok(CommandArgument::Pfadd(PfaddArguments::parse(args)?)),
+                    "PFCOUNT" => Ok(CommandArgument::Pfcount(MultiKeyArguments::parse(args)?)),
+                    "PFMERGE" => Ok(CommandArgument::Pfmerge(PfmergeArguments::parse(args)?)),
+                    "XADD" => Ok(CommandArgument::Xadd(XaddArguments::parse(args)?)),
+                    "XRANGE" => Ok(CommandArgument::Xrange(XrangeArguments::parse_with(args, false)?)),
+                    "XREVRANGE" => Ok(CommandArgument::Xrange(XrangeArguments::parse_with(args, true)?)),
+                    "XLEN" => Ok(CommandArgument::Xlen(KeyArguments::parse(args)?)),
+                    "XGROUP" => Ok(CommandArgument::Xgroup(XgroupArguments::parse(args)?)),
+                    "XREADGROUP" => Ok(CommandArgument::Xreadgroup(XreadgroupArguments::parse(args)?)),
+                    "XACK" => Ok(CommandArgument::Xack(XackArguments::parse(args)?)),
+                    "SUBSCRIBE" => Ok(CommandArgument::Subscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Channel, true)?)),
+                    "UNSUBSCRIBE" => Ok(CommandArgument::Unsubscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Channel, false)?)),
+                    "PSUBSCRIBE" => Ok(CommandArgument::Subscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Pattern, true)?)),
+                    "PUNSUBSCRIBE" => Ok(CommandArgument::Unsubscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Pattern, false)?)),
+                    "SSUBSCRIBE" => Ok(CommandArgument::Subscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Shard, true)?)),
+                    "SUNSUBSCRIBE" => Ok(CommandArgument::Unsubscribe(ChannelsArguments::parse_with(args, SubscriptionKind::Shard, false)?)),
+                    "PUBLISH" => Ok(CommandArgument::Publish(PublishArguments::parse_with(args, false)?)),
+                    "SPUBLISH" => Ok(CommandArgument::Publish(PublishArguments::parse_with(args, true)?)),
+                    "FLUSHALL" => Ok(CommandArgument::Flushall(FlushArguments::parse(args)?)),
+                    "FLUSHDB" => Ok(CommandArgument::Flushdb(FlushArguments::parse(args)?)),
+                    "SCAN" => Ok(CommandArgument::Scan(ScanArguments::parse(args)?)),
+                    "APPEND" => Ok(CommandArgument::Append(AppendArguments::parse(args)?)),
+                    "RANDOMKEY" => {
+                        no_more_arguments(&mut args)?;
+                        Ok(CommandArgument::Randomkey)
+                    },
+                    "LCS" => Ok(CommandArgument::Lcs(LcsArguments::parse(args)?)),
+                    "EXPIRE" => Ok(CommandArgument::Expire(ExpireArguments::parse(args)?)),
+                    "PEXPIRE" => Ok(CommandArgument::Pexpire(ExpireArguments::parse(args)?)),
+                    "PERSIST" => Ok(CommandArgument::Persist(KeyArguments::parse(args)?)),
+                    "TTL" => Ok(CommandArgument::Ttl(KeyArguments::parse(args)?)),
+                    "PTTL" => Ok(CommandArgument::Pttl(KeyArguments::parse(args)?)),
+                    "KEYS" => Ok(CommandArgument::Keys(KeysArguments::parse(args)?)),
+                    "EXPIREAT" => Ok(CommandArgument::Expireat(ExpireArguments::parse(args)?)),
+                    "PEXPIREAT" => Ok(CommandArgument::Pexpireat(ExpireArguments::parse(args)?)),
+                    "EXPIRETIME" => Ok(CommandArgument::Expiretime(KeyArguments::parse(args)?)),
+                    "PEXPIRETIME" => Ok(CommandArgument::Pexpiretime(KeyArguments::parse(args)?)),
+                    "MGET" => Ok(CommandArgument::Mget(MultiKeyArguments::parse(args)?)),
+                    "MSET" => Ok(CommandArgument::Mset(MsetArguments::parse(args)?)),
+                    "MSETNX" => Ok(CommandArgument::Msetnx(MsetArguments::parse(args)?)),
+                    "DEL" => Ok(CommandArgument::Del(MultiKeyArguments::parse(args)?)),
+                    "SETRANGE" => Ok(CommandArgument::Setrange(SetrangeArguments::parse(args)?)),
+                    "GETRANGE" => Ok(CommandArgument::Getrange(GetrangeArguments::parse(args)?)),
+                    "TYPE" => Ok(CommandArgument::Type(KeyArguments::parse(args)?)),
+                    "LPUSH" => Ok(CommandArgument::Push(PushArguments::parse_with(args, ListEnd::Left, false)?)),
+                    "RPUSH" => Ok(CommandArgument::Push(PushArguments::parse_with(args, ListEnd::Right, false)?)),
+                    "LPUSHX" => Ok(CommandArgument::Push(PushArguments::parse_with(args, ListEnd::Left, true)?)),
+                    "RPUSHX" => Ok(CommandArgument::Push(PushArguments::parse_with(args, ListEnd::Right, true)?)),
+                    "LPOP" => Ok(CommandArgument::Pop(PopArguments::parse_with(args, ListEnd::Left)?)),
+                    "RPOP" => Ok(CommandArgument::Pop(PopArguments::parse_with(args, ListEnd::Right)?)),
+                    "BLPOP" => Ok(CommandArgument::BlockingPop(BlockingPopArguments::parse_with(args, ListEnd::Left)?)),
+                    "LMPOP" => Ok(CommandArgument::Mpop(MpopArguments::parse_with(args, false)?)),
+                    "BLMPOP" => Ok(CommandArgument::Mpop(MpopArguments::parse_with(args, true)?)),
+                    "BRPOP" => Ok(CommandArgument::BlockingPop(BlockingPopArguments::parse_with(args, ListEnd::Right)?)),
+                    "LRANGE" => Ok(CommandArgument::Lrange(LrangeArguments::parse(args)?)),
+                    "LLEN" => Ok(CommandArgument::Llen(KeyArguments::parse(args)?)),
+                    "LINSERT" => Ok(CommandArgument::Linsert(LinsertArguments::parse(args)?)),
+                    "LSET" => Ok(CommandArgument::Lset(LsetArguments::parse(args)?)),
+                    "LREM" => Ok(CommandArgument::Lrem(LremArguments::parse(args)?)),
+                    "LTRIM" => Ok(CommandArgument::Ltrim(LrangeArguments::parse(args)?)),
+                    "LINDEX" => Ok(CommandArgument::Lindex(LindexArguments::parse(args)?)),
+                    "LMOVE" => Ok(CommandArgument::Lmove(LmoveArguments::parse(args)?)),
+                    "RPOPLPUSH" => Ok(CommandArgument::Lmove(LmoveArguments::parse_rpoplpush(args)?)),
+                    "BLMOVE" => Ok(CommandArgument::BlockingMove(BlockingMoveArguments::parse(args)?)),
+                    "BRPOPLPUSH" => Ok(CommandArgument::BlockingMove(BlockingMoveArguments::parse_brpoplpush(args)?)),
+                    "HELLO" => Ok(CommandArgument::Hello(HelloArguments::parse(args)?)),
+                    "AUTH" => Ok(CommandArgument::Auth(AuthArguments::parse(args)?)),
+                    "SADD" => Ok(CommandArgument::Sadd(SaddArguments::parse(args)?)),
+                    "SMISMEMBER" => Ok(CommandArgument::Smismember(SmismemberArguments::parse(args)?)),
+                    "SINTER" => Ok(CommandArgument::Sinter(MultiKeyArguments::parse(args)?)),
+                    "SINTERSTORE" => Ok(CommandArgument::Sinterstore(StoreArguments::parse(args)?)),
+                    "SUNION" => Ok(CommandArgument::Sunion(MultiKeyArguments::parse(args)?)),
+                    "SUNIONSTORE" => Ok(CommandArgument::Sunionstore(StoreArguments::parse(args)?)),
+                    "SDIFF" => Ok(CommandArgument::Sdiff(MultiKeyArguments::parse(args)?)),
+                    "SDIFFSTORE" => Ok(CommandArgument::Sdiffstore(StoreArguments::parse(args)?)),
+                    "SINTERCARD" => Ok(CommandArgument::Sintercard(IntercardArguments::parse(args)?)),
+                    "ZINTERCARD" => Ok(CommandArgument::Zintercard(IntercardArguments::parse(args)?)),
+                    "ZADD" => Ok(CommandArgument::Zadd(ZaddArguments::parse(args)?)),
+                    "ZSCORE" => Ok(CommandArgument::Zscore(ZscoreArguments::parse(args)?)),
+                    "ZCARD" => Ok(CommandArgument::Zcard(KeyArguments::parse(args)?)),
+                    "ZRANK" => Ok(CommandArgument::Zrank(ZrankArguments::parse_with(args, false)?)),
+                    "ZREVRANK" => Ok(CommandArgument::Zrank(ZrankArguments::parse_with(args, true)?)),
+                    "ZUNION" => Ok(CommandArgument::Zunion(ZcombineArguments::parse_with(args, "zunion", false, true)?)),
+                    "ZUNIONSTORE" => Ok(CommandArgument::Zunionstore(ZcombineArguments::parse_with(args, "zunionstore", true, true)?)),
+                    "ZINTER" => Ok(CommandArgument::Zinter(ZcombineArguments::parse_with(args, "zinter", false, true)?)),
+                    "ZINTERSTORE" => Ok(CommandArgument::Zinterstore(ZcombineArguments::parse_with(args, "zinterstore", true, true)?)),
+                    "ZDIFF" => Ok(CommandArgument::Zdiff(ZcombineArguments::parse_with(args, "zdiff", false, false)?)),
+                    "ZDIFFSTORE" => Ok(CommandArgument::Zdiffstore(ZcombineArguments::parse_with(args, "zdiffstore", true, false)?)),
+                    "ZINCRBY" => Ok(CommandArgument::Zincrby(ZincrbyArguments::parse(args)?)),
+                    "BITPOS" => Ok(CommandArgument::Bitpos(BitposArguments::parse(args)?)),
+                    "BITOP" => Ok(CommandArgument::Bitop(BitopArguments::parse(args)?)),
+                    "ZRANGE" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Rank, false, true)?)),
+                    "ZREVRANGE" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Rank, true, false)?)),
+                    "ZRANGEBYSCORE" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Score, false, false)?)),
+                    "ZREVRANGEBYSCORE" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Score, true, false)?)),
+                    "ZRANGEBYLEX" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Lex, false, false)?)),
+                    "ZREVRANGEBYLEX" => Ok(CommandArgument::Zrange(ZrangeArguments::parse_with(args, RangeKind::Lex, true, false)?)),
+                    "HSET" => Ok(CommandArgument::Hset(HsetArguments::parse(args)?)),
+                    "HGET" => Ok(CommandArgument::Hget(HgetArguments::parse(args)?)),
+                    "HDEL" => Ok(CommandArgument::Hdel(FieldsArguments::parse(args)?)),
+                    "HMGET" => Ok(CommandArgument::Hmget(FieldsArguments::parse(args)?)),
+                    "HGETALL" => Ok(CommandArgument::Hgetall(KeyArguments::parse(args)?)),
+                    "HLEN" => Ok(CommandArgument::Hlen(KeyArguments::parse(args)?)),
+                    "SWAPDB" => Ok(CommandArgument::Swapdb(SwapdbArguments::parse(args)?)),
+                    "HRANDFIELD" => Ok(CommandArgument::Hrandfield(HrandfieldArguments::parse(args)?)),
+                    "HEXPIRE" => Ok(CommandArgument::Hexpire(HexpireArguments::parse(args)?)),
+                    "HPEXPIRE" => Ok(CommandArgument::Hpexpire(HexpireArguments::parse(args)?)),
+                    "HPERSIST" => Ok(CommandArgument::Hpersist(FieldsArguments::parse_counted(args)?)),
+                    "HTTL" => Ok(CommandArgument::Httl(FieldsArguments::parse_counted(args)?)),
+                    "ACL" => Ok(CommandArgument::Acl(AclArguments::parse(args)?)),
                     _ => Err("ERR unknown or unexpected command".to_string())
                 }
             }
-            _ => Err("Empty arguments".to_string())
+            _ => Err("ERR empty arguments".to_string())
         }
     
     }
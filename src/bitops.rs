@@ -0,0 +1,111 @@
+// the bitmap algebra behind BITOP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+// what the start and end of a BITPOS range count, bytes unless BIT was given.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+// applies op over the inputs byte by byte. the result is as long as the longest input and the
+// shorter ones count as zero padded, so AND with a shorter input clears the tail. NOT only
+// ever gets one input.
+pub fn combine(op: BitOp, inputs: &[&[u8]]) -> Vec<u8> {
+    let len = inputs.iter().map(|input| input.len()).max().unwrap_or(0);
+    let byte = |input: &[u8], i: usize| input.get(i).copied().unwrap_or(0);
+
+    (0..len)
+        .map(|i| {
+            let mut bytes = inputs.iter().map(|input| byte(input, i));
+            let first = bytes.next().unwrap_or(0);
+            match op {
+                BitOp::And => bytes.fold(first, |acc, b| acc & b),
+                BitOp::Or => bytes.fold(first, |acc, b| acc | b),
+                BitOp::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                BitOp::Not => !first,
+            }
+        })
+        .collect()
+}
+
+// the first bit set to bit in data between start and end, counted in unit and taking negative
+// indexes like GETRANGE. bits are numbered from the most significant bit of the first byte.
+// when looking for a clear bit without an end the string counts as followed by zeros, so the
+// bit right after it is the answer when every bit in it is set. -1 if there is no such bit.
+pub fn position(data: &[u8], bit: bool, start: i64, end: Option<i64>, unit: BitUnit) -> i64 {
+    let len = match unit {
+        BitUnit::Byte => data.len() as i64,
+        BitUnit::Bit => data.len() as i64 * 8,
+    };
+
+    let resolve = |index: i64| if index < 0 { (len + index).max(0) } else { index };
+    let first = resolve(start);
+    let last = resolve(end.unwrap_or(len - 1)).min(len - 1);
+    if first > last {
+        return -1;
+    }
+
+    let (first_bit, last_bit) = match unit {
+        BitUnit::Byte => (first * 8, last * 8 + 7),
+        BitUnit::Bit => (first, last),
+    };
+
+    // whole bytes that can't hold the bit are skipped rather than tested bit by bit.
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut pos = first_bit;
+    while pos <= last_bit {
+        let byte = data[(pos / 8) as usize];
+        if pos % 8 == 0 && byte == skip && pos + 7 <= last_bit {
+            pos += 8;
+            continue;
+        }
+        if (byte >> (7 - pos % 8)) & 1 == bit as u8 {
+            return pos;
+        }
+        pos += 1;
+    }
+
+    if !bit && end.is_none() { last_bit + 1 } else { -1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_pads_shorter_inputs() {
+        let a: &[u8] = &[0b1100_1100, 0xff];
+        let b: &[u8] = &[0b1010_1010];
+        assert_eq!(combine(BitOp::And, &[a, b]), vec![0b1000_1000, 0x00]);
+        assert_eq!(combine(BitOp::Or, &[a, b]), vec![0b1110_1110, 0xff]);
+        assert_eq!(combine(BitOp::Xor, &[a, b]), vec![0b0110_0110, 0xff]);
+        assert_eq!(combine(BitOp::Not, &[a]), vec![0b0011_0011, 0x00]);
+        assert!(combine(BitOp::Or, &[&[], &[]]).is_empty());
+    }
+
+    #[test]
+    fn test_position() {
+        let data: &[u8] = &[0xff, 0xf0, 0x00];
+        assert_eq!(position(data, false, 0, None, BitUnit::Byte), 12);
+        assert_eq!(position(data, true, 2, None, BitUnit::Byte), -1);
+        assert_eq!(position(data, true, 0, None, BitUnit::Byte), 0);
+        assert_eq!(position(data, true, -2, None, BitUnit::Byte), 8);
+        assert_eq!(position(data, true, 5, Some(9), BitUnit::Bit), 5);
+        assert_eq!(position(data, false, 5, Some(11), BitUnit::Bit), -1);
+        assert_eq!(position(data, false, 5, Some(12), BitUnit::Bit), 12);
+        assert_eq!(position(data, true, 3, Some(1), BitUnit::Byte), -1);
+
+        // all ones: past the end without an end, not found with one.
+        assert_eq!(position(&[0xff, 0xff], false, 0, None, BitUnit::Byte), 16);
+        assert_eq!(position(&[0xff, 0xff], false, 0, Some(-1), BitUnit::Byte), -1);
+        assert_eq!(position(&[], false, 0, None, BitUnit::Byte), -1);
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use tokio::sync::Notify;
+
+// the connections parked on each key, i.e., a BLPOP waiting for something to be pushed. every
+// blocked connection has its own notify registered under each key it waits on, a key only has
+// an entry while someone waits on it.
+#[derive(Debug, Default)]
+pub struct KeyWaiters {
+    keys: Mutex<HashMap<Vec<u8>, Vec<Arc<Notify>>>>,
+}
+
+impl KeyWaiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // registers waiter under every key until the returned guard is dropped. anything that
+    // changes one of the keys from here on wakes it, so nothing is missed between checking a
+    // key and starting to wait.
+    pub fn register<'a>(&'a self, keys: &'a [Vec<u8>], waiter: &Arc<Notify>) -> WaitGuard<'a> {
+        let mut map = self.keys.lock().unwrap();
+        for key in keys {
+            map.entry(key.clone()).or_default().push(waiter.clone());
+        }
+        WaitGuard { waiters: self, keys, waiter: waiter.clone() }
+    }
+
+    // wakes everyone waiting on key. they race to take what was added and the ones that lose
+    // go back to waiting, the permit notify_one stores means a waiter that isn't parked yet
+    // still sees it.
+    pub fn notify(&self, key: &[u8]) {
+        if let Some(waiters) = self.keys.lock().unwrap().get(key) {
+            for waiter in waiters {
+                waiter.notify_one();
+            }
+        }
+    }
+
+    // how many connections wait on key.
+    pub fn waiting(&self, key: &[u8]) -> usize {
+        self.keys.lock().unwrap().get(key).map_or(0, |waiters| waiters.len())
+    }
+
+    fn unregister(&self, keys: &[Vec<u8>], waiter: &Arc<Notify>) {
+        let mut map = self.keys.lock().unwrap();
+        for key in keys {
+            if let Some(waiters) = map.get_mut(key) {
+                waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+                if waiters.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+    }
+}
+
+// keeps a waiter registered, dropping it unregisters even when the waiting future is cancelled.
+pub struct WaitGuard<'a> {
+    waiters: &'a KeyWaiters,
+    keys: &'a [Vec<u8>],
+    waiter: Arc<Notify>,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.waiters.unregister(self.keys, &self.waiter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_unregisters() {
+        let waiters = KeyWaiters::new();
+        let keys = vec![b"a".to_vec(), b"b".to_vec()];
+        let waiter = Arc::new(Notify::new());
+
+        let guard = waiters.register(&keys, &waiter);
+        assert_eq!(waiters.waiting(b"a"), 1);
+        assert_eq!(waiters.waiting(b"b"), 1);
+
+        drop(guard);
+        assert_eq!(waiters.waiting(b"a"), 0);
+        assert!(waiters.keys.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_notify_before_waiting_is_kept() {
+        let waiters = KeyWaiters::new();
+        let keys = vec![b"a".to_vec()];
+        let waiter = Arc::new(Notify::new());
+        let _guard = waiters.register(&keys, &waiter);
+
+        // the push happens before the waiter parks, the stored permit wakes it straight away.
+        waiters.notify(b"a");
+        waiter.notified().await;
+    }
+}
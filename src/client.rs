@@ -1,5 +1,5 @@
 use std::io;
-use crate::resp::{ Resp };
+use crate::resp::{ Resp, Frame };
 use crate::connection::Connection;
 // use crate::server::{ read_and_parse};
 
@@ -49,11 +49,25 @@ impl<'a> RedisClient<'a> {
         Ok(())
     }
 
+    // reads the next reply, any attributes sent along with it are dropped.
     pub async fn read_message(&mut self) -> io::Result<Resp> {
-        let (resp, _) = self.stream.read_message().await?;
+        let (resp, _) = self.read_message_with_attributes().await?;
         Ok(resp)
     }
 
+    // reads the next reply along with the resp3 attributes that were attached to it.
+    pub async fn read_message_with_attributes(&mut self) -> io::Result<(Resp, Vec<(Resp, Resp)>)> {
+        let (resp, _) = self.stream.read_message().await?;
+        Ok(resp.split_attributes())
+    }
+
+    // reads the next piece of a reply, a streamed reply is handed out as it arrives instead of
+    // waiting for the end of it like read_message does.
+    pub async fn read_frame(&mut self) -> io::Result<Frame> {
+        let frame = self.stream.read_frame().await?;
+        Ok(frame)
+    }
+
     pub async fn read_rdb(&mut self) -> io::Result<Vec<u8>> {
         let rdb = self.stream.read_rdb().await?;
         Ok(rdb)
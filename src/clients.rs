@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::time::Instant;
+use tokio::sync::Notify;
+
+// what a connection is being used for, CLIENT LIST TYPE filters on this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientKind {
+    Normal,
+    // a replica of this server that commands are propagated to.
+    Replica,
+    // the link from this replica to its master.
+    Master,
+    PubSub,
+}
+
+impl ClientKind {
+    // parses the TYPE argument of CLIENT LIST, "slave" is accepted as an alias for replica.
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "normal" => Some(ClientKind::Normal),
+            "replica" | "slave" => Some(ClientKind::Replica),
+            "master" => Some(ClientKind::Master),
+            "pubsub" => Some(ClientKind::PubSub),
+            _ => None,
+        }
+    }
+
+    // the single letter flag redis shows for the kind in CLIENT LIST.
+    pub fn flag(&self) -> &'static str {
+        match self {
+            ClientKind::Normal => "N",
+            ClientKind::Replica => "S",
+            ClientKind::Master => "M",
+            ClientKind::PubSub => "P",
+        }
+    }
+}
+
+// client names end up in CLIENT LIST lines, so they can't contain spaces or control characters.
+pub fn is_valid_name(name: &str) -> bool {
+    name.bytes().all(|b| (b'!'..=b'~').contains(&b))
+}
+
+// flipped by CLIENT KILL, the client's session closes the connection as soon as it's between
+// commands. a client killing itself gets its reply first since it is mid command.
+#[derive(Debug, Default)]
+pub struct KillSwitch {
+    killed: AtomicBool,
+    notify: Notify,
+}
+
+impl KillSwitch {
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    // resolves once the client was killed, there is only ever the one session waiting.
+    pub async fn killed(&self) {
+        while !self.is_killed() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+// resolves once the client was killed, never for a connection that can't be.
+pub async fn killed(kill_switch: Option<Arc<KillSwitch>>) {
+    match kill_switch {
+        Some(kill_switch) => kill_switch.killed().await,
+        None => std::future::pending().await,
+    }
+}
+
+// the clients CLIENT KILL picks, everything set has to match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub kind: Option<ClientKind>,
+    // leave the client sending the command alone, the default for the filter form.
+    pub skip_me: bool,
+}
+
+impl Default for KillFilter {
+    fn default() -> Self {
+        Self { id: None, addr: None, laddr: None, kind: None, skip_me: true }
+    }
+}
+
+impl KillFilter {
+    pub fn matches(&self, info: &ClientInfo, me: Option<u64>) -> bool {
+        self.id.is_none_or(|id| info.id == id)
+            && self.addr.as_ref().is_none_or(|addr| info.addr == *addr)
+            && self.laddr.as_ref().is_none_or(|laddr| info.laddr == *laddr)
+            && self.kind.is_none_or(|kind| info.kind == kind)
+            && !(self.skip_me && me == Some(info.id))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub laddr: String,
+    pub kind: ClientKind,
+    // set by HELLO SETNAME, empty until then.
+    pub name: String,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    // the last command this client ran, lowercased.
+    pub last_command: String,
+    pub kill_switch: Arc<KillSwitch>,
+}
+
+impl ClientInfo {
+    // a single line in the CLIENT LIST format.
+    pub fn to_line(&self) -> String {
+        let now = Instant::now();
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db=0 cmd={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name,
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            self.kind.flag(),
+            if self.last_command.is_empty() { "NULL" } else { &self.last_command },
+        )
+    }
+}
+
+// every open connection the server knows about, including replicas that moved into the history.
+#[derive(Debug)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, ClientInfo>>,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            clients: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // adds a client, it stays registered until the returned registration is dropped.
+    pub fn register(self: &Arc<Self>, addr: String, laddr: String, kind: ClientKind) -> ClientRegistration {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let kill_switch = Arc::new(KillSwitch::default());
+        let info = ClientInfo {
+            id,
+            addr,
+            laddr,
+            kind,
+            name: String::new(),
+            created: now,
+            last_interaction: now,
+            last_command: String::new(),
+            kill_switch: kill_switch.clone(),
+        };
+
+        self.clients.lock().unwrap().insert(id, info);
+        ClientRegistration { id, registry: self.clone(), kill_switch }
+    }
+
+    pub fn set_kind(&self, id: u64, kind: ClientKind) {
+        if let Some(info) = self.clients.lock().unwrap().get_mut(&id) {
+            info.kind = kind;
+        }
+    }
+
+    pub fn set_name(&self, id: u64, name: String) {
+        if let Some(info) = self.clients.lock().unwrap().get_mut(&id) {
+            info.name = name;
+        }
+    }
+
+    // records that the client just ran a command.
+    pub fn touch(&self, id: u64, command: &str) {
+        if let Some(info) = self.clients.lock().unwrap().get_mut(&id) {
+            info.last_interaction = Instant::now();
+            info.last_command = command.to_lowercase();
+        }
+    }
+
+    // every registered client ordered by id, optionally only those of one kind.
+    pub fn list(&self, kind: Option<ClientKind>) -> Vec<ClientInfo> {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|info| kind.is_none_or(|kind| info.kind == kind))
+            .cloned()
+            .collect()
+    }
+
+    pub fn count(&self, kind: ClientKind) -> usize {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|info| info.kind == kind)
+            .count()
+    }
+
+    // kills every client the filter matches, me is the client asking. returns how many.
+    pub fn kill(&self, filter: &KillFilter, me: Option<u64>) -> usize {
+        let clients = self.clients.lock().unwrap();
+        let matching: Vec<&ClientInfo> = clients.values().filter(|info| filter.matches(info, me)).collect();
+        for info in &matching {
+            info.kill_switch.kill();
+        }
+        matching.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+}
+
+// ties a registry entry to the lifetime of a connection, dropping it removes the client.
+#[derive(Debug)]
+pub struct ClientRegistration {
+    id: u64,
+    registry: Arc<ClientRegistry>,
+    kill_switch: Arc<KillSwitch>,
+}
+
+impl ClientRegistration {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn kill_switch(&self) -> Arc<KillSwitch> {
+        self.kill_switch.clone()
+    }
+
+    pub fn set_kind(&self, kind: ClientKind) {
+        self.registry.set_kind(self.id, kind);
+    }
+
+    pub fn touch(&self, command: &str) {
+        self.registry.touch(self.id, command);
+    }
+
+    pub fn set_name(&self, name: String) {
+        self.registry.set_name(self.id, name);
+    }
+}
+
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        self.registry.unregister(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_lifetime() {
+        let registry = Arc::new(ClientRegistry::new());
+        let a = registry.register("127.0.0.1:1000".to_string(), "127.0.0.1:6379".to_string(), ClientKind::Normal);
+        let b = registry.register("127.0.0.1:1001".to_string(), "127.0.0.1:6379".to_string(), ClientKind::Normal);
+        assert_eq!((a.id(), b.id()), (1, 2));
+        assert_eq!(registry.len(), 2);
+
+        drop(a);
+        let ids: Vec<u64> = registry.list(None).iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[test]
+    fn test_list_by_kind() {
+        let registry = Arc::new(ClientRegistry::new());
+        let _client = registry.register("a".to_string(), "l".to_string(), ClientKind::Normal);
+        let replica = registry.register("b".to_string(), "l".to_string(), ClientKind::Normal);
+        replica.set_kind(ClientKind::Replica);
+
+        let replicas = registry.list(ClientKind::parse("slave"));
+        assert_eq!(replicas.len(), 1);
+        assert_eq!(replicas[0].id, replica.id());
+        assert!(replicas[0].to_line().contains(" flags=S "));
+        assert_eq!(registry.list(Some(ClientKind::Normal)).len(), 1);
+    }
+
+    #[test]
+    fn test_kill_skips_the_caller_by_default() {
+        let registry = Arc::new(ClientRegistry::new());
+        let me = registry.register("a".to_string(), "l".to_string(), ClientKind::Normal);
+        let other = registry.register("b".to_string(), "l".to_string(), ClientKind::Normal);
+
+        let everyone = KillFilter::default();
+        assert_eq!(registry.kill(&everyone, Some(me.id())), 1);
+        assert!(other.kill_switch().is_killed() && !me.kill_switch().is_killed());
+
+        let by_addr = KillFilter { addr: Some("a".to_string()), skip_me: false, ..KillFilter::default() };
+        assert_eq!(registry.kill(&by_addr, Some(me.id())), 1);
+        assert!(me.kill_switch().is_killed());
+        assert_eq!(registry.kill(&KillFilter { id: Some(99), ..KillFilter::default() }, None), 0);
+    }
+}
@@ -0,0 +1,54 @@
+// the hash slots of redis cluster. there is no cluster mode here, the slots are only computed
+// so keys can be checked for where a cluster would put them.
+pub const SLOTS: u16 = 16384;
+
+// the slot a cluster keeps key in. only the hash tag is hashed when key has one, so keys
+// sharing a tag always land on the same node.
+pub fn keyslot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) % SLOTS
+}
+
+// what is between the first { and the first } after it, the whole key when there is no such
+// pair or nothing between them.
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let open = match key.iter().position(|&b| b == b'{') {
+        Some(open) => open,
+        None => return key,
+    };
+    match key[open + 1..].iter().position(|&b| b == b'}') {
+        Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+        _ => key,
+    }
+}
+
+// crc16 xmodem, the variant cluster slots are computed with.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        let mut crc = crc ^ (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyslot_and_hash_tags() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(keyslot(b"foo"), 12182);
+        assert_eq!(keyslot(b"somekey"), 11058);
+        assert_eq!(keyslot(b""), 0);
+
+        assert_eq!(keyslot(b"{user1000}.following"), keyslot(b"{user1000}.followers"));
+        assert_eq!(keyslot(b"{user1000}.following"), keyslot(b"user1000"));
+        // only the first tag counts, and an empty one means the whole key.
+        assert_eq!(keyslot(b"foo{bar}{zap}"), keyslot(b"bar"));
+        assert_eq!(keyslot(b"foo{{bar}}zap"), keyslot(b"{bar"));
+        assert_eq!(keyslot(b"foo{}{bar}"), crc16(b"foo{}{bar}") % SLOTS);
+        assert_eq!(keyslot(b"foo{bar"), crc16(b"foo{bar") % SLOTS);
+    }
+}
@@ -1,7 +1,18 @@
-use crate::resp::Resp;
-use crate::connection::Connection;
+use crate::resp::{ Resp, RespEncoder };
+use crate::reply::{ Reply, ServerError, Protocol };
+use bytes::BytesMut;
+use crate::connection::{ Connection, ReplyMode };
 use crate::context::Handle;
-use crate::arguments::{ ArgumentParser, CommandArgument, EchoArguments, SetArguments, GetArguments };
+use crate::arguments::{ ArgumentParser, CommandArgument, EchoArguments, SetArguments, Expiration, GetArguments, DebugArguments, LcsArguments, ExpireArguments, KeyArguments, KeysArguments, MultiKeyArguments, MsetArguments, SetrangeArguments, GetrangeArguments, ClientArguments, InfoArguments, FlushArguments, ScanArguments, AppendArguments, PushArguments, LrangeArguments, HelloArguments, PopArguments, LinsertArguments, LsetArguments, LremArguments, LindexArguments, LmoveArguments, AuthArguments, AclArguments, BlockingPopArguments, SaddArguments, SmismemberArguments, StoreArguments, IntercardArguments, BlockingMoveArguments, MpopArguments, HsetArguments, HgetArguments, FieldsArguments, HrandfieldArguments, HexpireArguments, ZaddArguments, ZscoreArguments, ZrangeArguments, ZrankArguments, ZincrbyArguments, ZcombineArguments, BitposArguments, BitopArguments, ClusterArguments, PfaddArguments, PfmergeArguments, XaddArguments, XrangeArguments, XgroupArguments, XreadgroupArguments, XackArguments, ChannelsArguments, PublishArguments, ConfigArguments, ObjectArguments, LatencyArguments };
+use crate::database::{ digest_to_hex, Record, ListEnd, SetFlags, SetOutcome, WrongType, ZaddFlags, StreamTrim, StreamEntry, StreamError, FieldExpiry, MAX_STRING_LEN };
+use crate::setops::SetOp;
+use crate::cluster::keyslot;
+use crate::clients::{ self, ClientKind, KillFilter };
+use crate::stats::{ self, Family };
+use crate::acl;
+use crate::pubsub;
+use crate::glob::Pattern;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
 use crate::internals::{ ReplconfCommand };
 // Enum for transaction results, used to propogate certain actions upward to the context handler
 // i.e., if we performed a write operation the handler needs to send the info out to replicas
@@ -9,6 +20,9 @@ use crate::internals::{ ReplconfCommand };
 #[derive(Debug)]
 pub enum Transaction {
     Write,
+    // a write that replicas should apply as a different command, i.e., a blocking pop that
+    // went through is a plain pop for them so they never block on their copy of the list.
+    WriteAs(Resp),
     Replicate,
     Read,
     None,
@@ -21,11 +35,108 @@ pub trait Command {
 
 // List of commands
 pub struct PingCommand;
-pub struct InfoCommand;
+pub struct InfoCommand(InfoArguments);
 pub struct EchoCommand(EchoArguments);
 pub struct SetCommand(SetArguments);
 pub struct GetCommand(GetArguments);
+pub struct SetnxCommand(SetArguments);
+pub struct ClientCommand(ClientArguments);
+pub struct FlushallCommand(FlushArguments);
+pub struct FlushdbCommand(FlushArguments);
+pub struct RandomkeyCommand;
+pub struct ScanCommand(ScanArguments);
+pub struct AppendCommand(AppendArguments);
+pub struct TypeCommand(KeyArguments);
+pub struct PushCommand(PushArguments);
+pub struct LrangeCommand(LrangeArguments);
+pub struct LlenCommand(KeyArguments);
+pub struct HelloCommand(HelloArguments);
+pub struct PopCommand(PopArguments);
+pub struct LinsertCommand(LinsertArguments);
+pub struct LsetCommand(LsetArguments);
+pub struct LremCommand(LremArguments);
+pub struct LtrimCommand(LrangeArguments);
+pub struct LindexCommand(LindexArguments);
+pub struct LmoveCommand(LmoveArguments);
+pub struct AuthCommand(AuthArguments);
+pub struct AclCommand(AclArguments);
+pub struct BlockingPopCommand(BlockingPopArguments);
+pub struct SaddCommand(SaddArguments);
+pub struct SinterCommand(MultiKeyArguments);
+pub struct SinterstoreCommand(StoreArguments);
+pub struct SunionCommand(MultiKeyArguments);
+pub struct SunionstoreCommand(StoreArguments);
+pub struct SdiffCommand(MultiKeyArguments);
+pub struct SdiffstoreCommand(StoreArguments);
+pub struct SintercardCommand(IntercardArguments);
+pub struct SmismemberCommand(SmismemberArguments);
+pub struct ZintercardCommand(IntercardArguments);
+pub struct ZaddCommand(ZaddArguments);
+pub struct ZscoreCommand(ZscoreArguments);
+pub struct ZcardCommand(KeyArguments);
+pub struct ZrankCommand(ZrankArguments);
+pub struct ZunionCommand(ZcombineArguments);
+pub struct ZunionstoreCommand(ZcombineArguments);
+pub struct ZinterCommand(ZcombineArguments);
+pub struct ZinterstoreCommand(ZcombineArguments);
+pub struct ZdiffCommand(ZcombineArguments);
+pub struct ZdiffstoreCommand(ZcombineArguments);
+pub struct ZincrbyCommand(ZincrbyArguments);
+pub struct ZrangeCommand(ZrangeArguments);
+pub struct BlockingMoveCommand(BlockingMoveArguments);
+pub struct MpopCommand(MpopArguments);
+pub struct HsetCommand(HsetArguments);
+pub struct HgetCommand(HgetArguments);
+pub struct HdelCommand(FieldsArguments);
+pub struct HmgetCommand(FieldsArguments);
+pub struct HgetallCommand(KeyArguments);
+pub struct HlenCommand(KeyArguments);
+pub struct HrandfieldCommand(HrandfieldArguments);
+pub struct HexpireCommand(HexpireArguments);
+pub struct HpexpireCommand(HexpireArguments);
+pub struct HpersistCommand(FieldsArguments);
+pub struct HttlCommand(FieldsArguments);
+pub struct SwapdbCommand;
 pub struct PsyncCommand;
+pub struct DebugCommand(DebugArguments);
+#[derive(Debug)]
+pub struct ConfigCommand(ConfigArguments);
+#[derive(Debug)]
+pub struct ObjectCommand(ObjectArguments);
+#[derive(Debug)]
+pub struct LatencyCommand(LatencyArguments);
+pub struct LcsCommand(LcsArguments);
+pub struct ExpireCommand(ExpireArguments);
+pub struct PexpireCommand(ExpireArguments);
+pub struct PersistCommand(KeyArguments);
+pub struct TtlCommand(KeyArguments);
+pub struct PttlCommand(KeyArguments);
+pub struct KeysCommand(KeysArguments);
+pub struct ExpireatCommand(ExpireArguments);
+pub struct PexpireatCommand(ExpireArguments);
+pub struct ExpiretimeCommand(KeyArguments);
+pub struct PexpiretimeCommand(KeyArguments);
+pub struct MgetCommand(MultiKeyArguments);
+pub struct MsetCommand(MsetArguments);
+pub struct MsetnxCommand(MsetArguments);
+pub struct DelCommand(MultiKeyArguments);
+pub struct SetrangeCommand(SetrangeArguments);
+pub struct GetrangeCommand(GetrangeArguments);
+pub struct BitposCommand(BitposArguments);
+pub struct BitopCommand(BitopArguments);
+pub struct ClusterCommand(ClusterArguments);
+pub struct PfaddCommand(PfaddArguments);
+pub struct PfcountCommand(MultiKeyArguments);
+pub struct PfmergeCommand(PfmergeArguments);
+pub struct XaddCommand(XaddArguments);
+pub struct XrangeCommand(XrangeArguments);
+pub struct XlenCommand(KeyArguments);
+pub struct XgroupCommand(XgroupArguments);
+pub struct XreadgroupCommand(XreadgroupArguments);
+pub struct XackCommand(XackArguments);
+pub struct SubscribeCommand(ChannelsArguments);
+pub struct UnsubscribeCommand(ChannelsArguments);
+pub struct PublishCommand(PublishArguments);
 
 // Enum for each type to ease parsing into commands.
 pub enum Cmd {
@@ -36,6 +147,225 @@ pub enum Cmd {
     Get(GetCommand),
     Info(InfoCommand),
     ReplConf(ReplconfCommand),
+    Debug(DebugCommand),
+    Config(ConfigCommand),
+    Object(ObjectCommand),
+    Latency(LatencyCommand),
+    Lcs(LcsCommand),
+    Expire(ExpireCommand),
+    Pexpire(PexpireCommand),
+    Persist(PersistCommand),
+    Ttl(TtlCommand),
+    Pttl(PttlCommand),
+    Keys(KeysCommand),
+    Expireat(ExpireatCommand),
+    Pexpireat(PexpireatCommand),
+    Expiretime(ExpiretimeCommand),
+    Pexpiretime(PexpiretimeCommand),
+    Mget(MgetCommand),
+    Mset(MsetCommand),
+    Msetnx(MsetnxCommand),
+    Del(DelCommand),
+    Setrange(SetrangeCommand),
+    Getrange(GetrangeCommand),
+    Bitpos(BitposCommand),
+    Bitop(BitopCommand),
+    Cluster(ClusterCommand),
+    Pfadd(PfaddCommand),
+    Pfcount(PfcountCommand),
+    Pfmerge(PfmergeCommand),
+    Xadd(XaddCommand),
+    Xrange(XrangeCommand),
+    Xlen(XlenCommand),
+    Xgroup(XgroupCommand),
+    Xreadgroup(XreadgroupCommand),
+    Xack(XackCommand),
+    Subscribe(SubscribeCommand),
+    Unsubscribe(UnsubscribeCommand),
+    Publish(PublishCommand),
+    Setnx(SetnxCommand),
+    Client(ClientCommand),
+    Flushall(FlushallCommand),
+    Flushdb(FlushdbCommand),
+    Randomkey(RandomkeyCommand),
+    Scan(ScanCommand),
+    Append(AppendCommand),
+    Type(TypeCommand),
+    Push(PushCommand),
+    Lrange(LrangeCommand),
+    Llen(LlenCommand),
+    Hello(HelloCommand),
+    Pop(PopCommand),
+    Linsert(LinsertCommand),
+    Lset(LsetCommand),
+    Lrem(LremCommand),
+    Ltrim(LtrimCommand),
+    Lindex(LindexCommand),
+    Lmove(LmoveCommand),
+    Auth(AuthCommand),
+    Acl(AclCommand),
+    BlockingPop(BlockingPopCommand),
+    Sadd(SaddCommand),
+    Sinter(SinterCommand),
+    Sinterstore(SinterstoreCommand),
+    Sunion(SunionCommand),
+    Sunionstore(SunionstoreCommand),
+    Sdiff(SdiffCommand),
+    Sdiffstore(SdiffstoreCommand),
+    Sintercard(SintercardCommand),
+    Smismember(SmismemberCommand),
+    Zintercard(ZintercardCommand),
+    Zadd(ZaddCommand),
+    Zscore(ZscoreCommand),
+    Zcard(ZcardCommand),
+    Zrank(ZrankCommand),
+    Zunion(ZunionCommand),
+    Zunionstore(ZunionstoreCommand),
+    Zinter(ZinterCommand),
+    Zinterstore(ZinterstoreCommand),
+    Zdiff(ZdiffCommand),
+    Zdiffstore(ZdiffstoreCommand),
+    Zincrby(ZincrbyCommand),
+    Zrange(ZrangeCommand),
+    BlockingMove(BlockingMoveCommand),
+    Mpop(MpopCommand),
+    Hset(HsetCommand),
+    Hget(HgetCommand),
+    Hdel(HdelCommand),
+    Hmget(HmgetCommand),
+    Hgetall(HgetallCommand),
+    Hlen(HlenCommand),
+    Hrandfield(HrandfieldCommand),
+    Hexpire(HexpireCommand),
+    Hpexpire(HpexpireCommand),
+    Hpersist(HpersistCommand),
+    Httl(HttlCommand),
+    Swapdb(SwapdbCommand),
+}
+
+// where the effects of a command may be propagated to once it executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    // writes, sent on to replicas when they changed the dataset.
+    All,
+    // admin and read only commands, these never enter the replication stream.
+    Never,
+}
+
+impl Propagation {
+    pub fn to_replicas(&self) -> bool {
+        matches!(self, Propagation::All)
+    }
+}
+
+impl Cmd {
+    // the propagation policy of every command, kept exhaustive so a new command has to pick one.
+    pub fn propagation(&self) -> Propagation {
+        match self {
+            Cmd::Set(_)
+            | Cmd::Setnx(_)
+            | Cmd::Mset(_)
+            | Cmd::Msetnx(_)
+            | Cmd::Setrange(_)
+            | Cmd::Append(_)
+            | Cmd::Push(_)
+            | Cmd::Pop(_)
+            | Cmd::BlockingPop(_)
+            | Cmd::BlockingMove(_)
+            | Cmd::Mpop(_)
+            | Cmd::Sadd(_)
+            | Cmd::Sinterstore(_)
+            | Cmd::Sunionstore(_)
+            | Cmd::Sdiffstore(_)
+            | Cmd::Zadd(_)
+            | Cmd::Zincrby(_)
+            | Cmd::Zunionstore(_)
+            | Cmd::Zinterstore(_)
+            | Cmd::Zdiffstore(_)
+            | Cmd::Bitop(_)
+            | Cmd::Pfadd(_)
+            | Cmd::Pfmerge(_)
+            | Cmd::Xadd(_)
+            | Cmd::Xgroup(_)
+            | Cmd::Xreadgroup(_)
+            | Cmd::Xack(_)
+            | Cmd::Publish(_)
+            | Cmd::Hset(_)
+            | Cmd::Hdel(_)
+            | Cmd::Hexpire(_)
+            | Cmd::Hpexpire(_)
+            | Cmd::Hpersist(_)
+            | Cmd::Linsert(_)
+            | Cmd::Lset(_)
+            | Cmd::Lrem(_)
+            | Cmd::Ltrim(_)
+            | Cmd::Lmove(_)
+            | Cmd::Del(_)
+            | Cmd::Expire(_)
+            | Cmd::Pexpire(_)
+            | Cmd::Expireat(_)
+            | Cmd::Pexpireat(_)
+            | Cmd::Persist(_)
+            | Cmd::Flushall(_)
+            | Cmd::Flushdb(_) => Propagation::All,
+
+            Cmd::Unexpected(_)
+            | Cmd::Ping(_)
+            | Cmd::Echo(_)
+            | Cmd::Get(_)
+            | Cmd::Info(_)
+            | Cmd::ReplConf(_)
+            | Cmd::Debug(_)
+            | Cmd::Config(_)
+            | Cmd::Object(_)
+            | Cmd::Latency(_)
+            | Cmd::Lcs(_)
+            | Cmd::Ttl(_)
+            | Cmd::Pttl(_)
+            | Cmd::Keys(_)
+            | Cmd::Expiretime(_)
+            | Cmd::Pexpiretime(_)
+            | Cmd::Mget(_)
+            | Cmd::Getrange(_)
+            | Cmd::Bitpos(_)
+            | Cmd::Pfcount(_)
+            | Cmd::Xrange(_)
+            | Cmd::Xlen(_)
+            | Cmd::Client(_)
+            | Cmd::Cluster(_)
+            | Cmd::Subscribe(_)
+            | Cmd::Unsubscribe(_)
+            | Cmd::Randomkey(_)
+            | Cmd::Swapdb(_)
+            | Cmd::Scan(_)
+            | Cmd::Type(_)
+            | Cmd::Lrange(_)
+            | Cmd::Llen(_)
+            | Cmd::Lindex(_)
+            | Cmd::Sinter(_)
+            | Cmd::Sintercard(_)
+            | Cmd::Smismember(_)
+            | Cmd::Sunion(_)
+            | Cmd::Sdiff(_)
+            | Cmd::Zintercard(_)
+            | Cmd::Zscore(_)
+            | Cmd::Zcard(_)
+            | Cmd::Zrank(_)
+            | Cmd::Zunion(_)
+            | Cmd::Zinter(_)
+            | Cmd::Zdiff(_)
+            | Cmd::Zrange(_)
+            | Cmd::Hget(_)
+            | Cmd::Hmget(_)
+            | Cmd::Hgetall(_)
+            | Cmd::Hlen(_)
+            | Cmd::Hrandfield(_)
+            | Cmd::Httl(_)
+            | Cmd::Hello(_)
+            | Cmd::Auth(_)
+            | Cmd::Acl(_) => Propagation::Never,
+        }
+    }
 }
 
 impl Command for Cmd {
@@ -47,107 +377,373 @@ impl Command for Cmd {
             Cmd::Get(c) => c.execute(stream, handle).await,
             Cmd::Info(c) => c.execute(stream, handle).await,
             Cmd::ReplConf(c) => { c.execute(stream, handle).await },
+            Cmd::Debug(c) => c.execute(stream, handle).await,
+            Cmd::Config(c) => c.execute(stream, handle).await,
+            Cmd::Object(c) => c.execute(stream, handle).await,
+            Cmd::Latency(c) => c.execute(stream, handle).await,
+            Cmd::Lcs(c) => c.execute(stream, handle).await,
+            Cmd::Expire(c) => c.execute(stream, handle).await,
+            Cmd::Pexpire(c) => c.execute(stream, handle).await,
+            Cmd::Persist(c) => c.execute(stream, handle).await,
+            Cmd::Ttl(c) => c.execute(stream, handle).await,
+            Cmd::Pttl(c) => c.execute(stream, handle).await,
+            Cmd::Keys(c) => c.execute(stream, handle).await,
+            Cmd::Expireat(c) => c.execute(stream, handle).await,
+            Cmd::Pexpireat(c) => c.execute(stream, handle).await,
+            Cmd::Expiretime(c) => c.execute(stream, handle).await,
+            Cmd::Pexpiretime(c) => c.execute(stream, handle).await,
+            Cmd::Mget(c) => c.execute(stream, handle).await,
+            Cmd::Mset(c) => c.execute(stream, handle).await,
+            Cmd::Msetnx(c) => c.execute(stream, handle).await,
+            Cmd::Del(c) => c.execute(stream, handle).await,
+            Cmd::Setrange(c) => c.execute(stream, handle).await,
+            Cmd::Getrange(c) => c.execute(stream, handle).await,
+            Cmd::Bitpos(c) => c.execute(stream, handle).await,
+            Cmd::Bitop(c) => c.execute(stream, handle).await,
+            Cmd::Setnx(c) => c.execute(stream, handle).await,
+            Cmd::Client(c) => c.execute(stream, handle).await,
+            Cmd::Cluster(c) => c.execute(stream, handle).await,
+            Cmd::Pfadd(c) => c.execute(stream, handle).await,
+            Cmd::Pfcount(c) => c.execute(stream, handle).await,
+            Cmd::Pfmerge(c) => c.execute(stream, handle).await,
+            Cmd::Xadd(c) => c.execute(stream, handle).await,
+            Cmd::Xrange(c) => c.execute(stream, handle).await,
+            Cmd::Xlen(c) => c.execute(stream, handle).await,
+            Cmd::Xgroup(c) => c.execute(stream, handle).await,
+            Cmd::Xreadgroup(c) => c.execute(stream, handle).await,
+            Cmd::Xack(c) => c.execute(stream, handle).await,
+            Cmd::Subscribe(c) => c.execute(stream, handle).await,
+            Cmd::Unsubscribe(c) => c.execute(stream, handle).await,
+            Cmd::Publish(c) => c.execute(stream, handle).await,
+            Cmd::Flushall(c) => c.execute(stream, handle).await,
+            Cmd::Flushdb(c) => c.execute(stream, handle).await,
+            Cmd::Randomkey(c) => c.execute(stream, handle).await,
+            Cmd::Scan(c) => c.execute(stream, handle).await,
+            Cmd::Append(c) => c.execute(stream, handle).await,
+            Cmd::Type(c) => c.execute(stream, handle).await,
+            Cmd::Push(c) => c.execute(stream, handle).await,
+            Cmd::Lrange(c) => c.execute(stream, handle).await,
+            Cmd::Llen(c) => c.execute(stream, handle).await,
+            Cmd::Hello(c) => c.execute(stream, handle).await,
+            Cmd::Pop(c) => c.execute(stream, handle).await,
+            Cmd::Linsert(c) => c.execute(stream, handle).await,
+            Cmd::Lset(c) => c.execute(stream, handle).await,
+            Cmd::Lrem(c) => c.execute(stream, handle).await,
+            Cmd::Ltrim(c) => c.execute(stream, handle).await,
+            Cmd::Lindex(c) => c.execute(stream, handle).await,
+            Cmd::Lmove(c) => c.execute(stream, handle).await,
+            Cmd::Auth(c) => c.execute(stream, handle).await,
+            Cmd::Acl(c) => c.execute(stream, handle).await,
+            Cmd::BlockingPop(c) => c.execute(stream, handle).await,
+            Cmd::Sadd(c) => c.execute(stream, handle).await,
+            Cmd::Hset(c) => c.execute(stream, handle).await,
+            Cmd::Hget(c) => c.execute(stream, handle).await,
+            Cmd::Hdel(c) => c.execute(stream, handle).await,
+            Cmd::Hmget(c) => c.execute(stream, handle).await,
+            Cmd::Hgetall(c) => c.execute(stream, handle).await,
+            Cmd::Hlen(c) => c.execute(stream, handle).await,
+            Cmd::Hrandfield(c) => c.execute(stream, handle).await,
+            Cmd::Hexpire(c) => c.execute(stream, handle).await,
+            Cmd::Hpexpire(c) => c.execute(stream, handle).await,
+            Cmd::Hpersist(c) => c.execute(stream, handle).await,
+            Cmd::Httl(c) => c.execute(stream, handle).await,
+            Cmd::Swapdb(c) => c.execute(stream, handle).await,
+            Cmd::Sinter(c) => c.execute(stream, handle).await,
+            Cmd::Sinterstore(c) => c.execute(stream, handle).await,
+            Cmd::Sunion(c) => c.execute(stream, handle).await,
+            Cmd::Sunionstore(c) => c.execute(stream, handle).await,
+            Cmd::Sdiff(c) => c.execute(stream, handle).await,
+            Cmd::Sdiffstore(c) => c.execute(stream, handle).await,
+            Cmd::Sintercard(c) => c.execute(stream, handle).await,
+            Cmd::Smismember(c) => c.execute(stream, handle).await,
+            Cmd::Zintercard(c) => c.execute(stream, handle).await,
+            Cmd::Zadd(c) => c.execute(stream, handle).await,
+            Cmd::Zscore(c) => c.execute(stream, handle).await,
+            Cmd::Zcard(c) => c.execute(stream, handle).await,
+            Cmd::Zrank(c) => c.execute(stream, handle).await,
+            Cmd::Zunion(c) => c.execute(stream, handle).await,
+            Cmd::Zunionstore(c) => c.execute(stream, handle).await,
+            Cmd::Zinter(c) => c.execute(stream, handle).await,
+            Cmd::Zinterstore(c) => c.execute(stream, handle).await,
+            Cmd::Zdiff(c) => c.execute(stream, handle).await,
+            Cmd::Zdiffstore(c) => c.execute(stream, handle).await,
+            Cmd::Zincrby(c) => c.execute(stream, handle).await,
+            Cmd::Zrange(c) => c.execute(stream, handle).await,
+            Cmd::BlockingMove(c) => c.execute(stream, handle).await,
+            Cmd::Mpop(c) => c.execute(stream, handle).await,
             _ => Transaction::None
         }
     }
 }
 
+// a subscribed resp2 client can't tell a reply from a message, so it gets the pong as one.
 impl Command for PingCommand {
     async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
-        let _ = stream.write_str("PONG").await;
+        let reply = if stream.is_subscribed() && stream.protocol() == Protocol::Resp2 {
+            Reply::Array(vec![Reply::bulk(b"pong"), Reply::bulk(b"")])
+        } else {
+            Reply::Status("PONG".to_string())
+        };
+        let _ = stream.write_reply(reply).await;
         Transaction::None
     }
 }
 
-impl Command for InfoCommand {
-    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
-        let payload = format!(
-            "role:{}\r\nmaster_replid:{}\r\nmaster_repl_offset:{}", 
-            handle.info.get_role(),
-            handle.info.get_master_replid(),
-            handle.info.get_master_repl_offset()
-        );
-        let _ = stream.write_bytes(&payload.as_bytes()).await;
+// every channel or pattern is confirmed with its own push, carrying the number of
+// subscriptions after it.
+impl Command for SubscribeCommand {
+    async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
+        let args = self.0;
+        let (name, _) = args.kind.replies();
+        let subscriber = match stream.subscriber_mut() {
+            Some(subscriber) => subscriber,
+            None => {
+                let err = format!("ERR {} isn't allowed on this connection", String::from_utf8_lossy(name).to_uppercase());
+                let _ = stream.write_reply(Reply::error(err)).await;
+                return Transaction::None;
+            },
+        };
+
+        let replies: Vec<Reply> = args.channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriber.subscribe(args.kind, &channel);
+                Reply::Push(vec![Reply::bulk(name), Reply::Bulk(channel), Reply::Int(count as i64)])
+            })
+            .collect();
+        for reply in replies {
+            let _ = stream.write_reply(reply).await;
+        }
         Transaction::None
     }
 }
 
-
-impl Command for EchoCommand {
+impl Command for UnsubscribeCommand {
     async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
-        let _ = stream.write_message(&self.0.message).await;
+        let args = self.0;
+        let (_, name) = args.kind.replies();
+        let subscriber = match stream.subscriber_mut() {
+            Some(subscriber) => subscriber,
+            None => {
+                let err = format!("ERR {} isn't allowed on this connection", String::from_utf8_lossy(name).to_uppercase());
+                let _ = stream.write_reply(Reply::error(err)).await;
+                return Transaction::None;
+            },
+        };
+
+        let channels = if args.channels.is_empty() { subscriber.subscriptions(args.kind) } else { args.channels };
+        let mut replies: Vec<Reply> = channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriber.unsubscribe(args.kind, &channel);
+                Reply::Push(vec![Reply::bulk(name), Reply::Bulk(channel), Reply::Int(count as i64)])
+            })
+            .collect();
+        // unsubscribing from everything while subscribed to nothing still gets an answer.
+        if replies.is_empty() {
+            replies.push(Reply::Push(vec![Reply::bulk(name), Reply::Nil, Reply::Int(0)]));
+        }
+        for reply in replies {
+            let _ = stream.write_reply(reply).await;
+        }
         Transaction::None
     }
 }
 
-
-impl Command for SetCommand {
+// replicated so the subscribers of replicas hear it too.
+impl Command for PublishCommand {
     async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
         let args = self.0;
+        let receivers = if args.shard {
+            handle.broker.publish_shard(&args.channel, &args.message)
+        } else {
+            handle.broker.publish(&args.channel, &args.message)
+        };
+        let _ = stream.write_reply(Reply::Int(receivers as i64)).await;
+        Transaction::Write
+    }
+}
 
-        let key = args.key;
-        let mut value = args.value;
-        let expiration = args.expiration;
-    
-        if let Some(expiration) = expiration {
-            value.set_expiry(expiration.as_duration());
-        }
+// the sections INFO knows how to render, in the order they are reported.
+const INFO_SECTIONS: [&str; 4] = ["clients", "memory", "stats", "replication"];
 
-        if args.nx {
-            if handle.database.exists(&key) {
-                let _ = stream.write_message(&Resp::BulkStringNull).await;
-                return Transaction::None;
-            }
-        
-            handle.database.set(key, value);
-            // this is a conflict - cant get the previous key if we just set it.
-            if args.get  {
-                let _ = stream.write_message(&Resp::BulkStringNull).await;
-                return Transaction::Write;
-            }
+impl Command for InfoCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let all = self.0.sections.is_empty() || self.0.sections
+            .iter()
+            .any(|s| s == "all" || s == "default" || s == "everything");
 
-            let _ = stream.write_str("OK").await;
-            return Transaction::Write;
+        let mut sections = Vec::new();
+        for name in INFO_SECTIONS {
+            if all || self.0.sections.iter().any(|s| s == name) {
+                sections.push(info_section(name, &handle).await);
+            }
         }
+        let payload = sections.join("\r\n");
 
-        if args.xx {
-            if !handle.database.exists(&key) {
-                let _ = stream.write_message(&Resp::BulkStringNull).await;
-                return Transaction::None;
+        let _ = stream.write_reply(Reply::Verbatim(payload)).await;
+        Transaction::None
+    }
+}
+
+async fn info_section(name: &str, handle: &Handle) -> String {
+    match name {
+        "clients" => format!(
+            "# Clients\r\nconnected_clients:{}\r\nmaxclients:{}\r\n",
+            handle.clients.len().saturating_sub(handle.clients.count(ClientKind::Replica)),
+            handle.config.get_maxclients()
+        ),
+
+        // only strings are accounted for, they are what compression applies to.
+        "memory" => {
+            let memory = handle.database.string_memory();
+            let ratio = if memory.stored_bytes == 0 { 1.0 } else { memory.logical_bytes as f64 / memory.stored_bytes as f64 };
+            format!(
+                "# Memory\r\nstring_compression_threshold:{}\r\nstrings:{}\r\ncompressed_strings:{}\r\nstring_logical_bytes:{}\r\nstring_stored_bytes:{}\r\nstring_compression_ratio:{:.2}\r\n",
+                handle.database.get_compression_threshold(),
+                memory.strings,
+                memory.compressed,
+                memory.logical_bytes,
+                memory.stored_bytes,
+                ratio
+            )
+        },
+
+        "stats" => {
+            let mut section = format!(
+                "# Stats\r\ntotal_connections_received:{}\r\nrejected_connections:{}\r\n",
+                handle.stats.get_connections_received(),
+                handle.stats.get_rejected_connections()
+            );
+
+            let (hits, misses) = (handle.stats.get_total_keyspace_hits(), handle.stats.get_total_keyspace_misses());
+            section.push_str(&format!(
+                "keyspace_hits:{}\r\nkeyspace_misses:{}\r\nkeyspace_hit_ratio:{:.4}\r\n",
+                hits, misses, stats::hit_ratio(hits, misses)
+            ));
+            for family in Family::ALL {
+                let (hits, misses) = (handle.stats.get_keyspace_hits(family), handle.stats.get_keyspace_misses(family));
+                section.push_str(&format!(
+                    "keyspace_family_{}:hits={},misses={},hit_ratio={:.4}\r\n",
+                    family.name(), hits, misses, stats::hit_ratio(hits, misses)
+                ));
             }
 
-            let prev = handle.database.set(key, value);
+            // only worth reporting when accepting is sharded over more than one socket.
+            let accepted = handle.stats.get_accepted_by_acceptor();
+            if accepted.len() > 1 {
+                for (i, count) in accepted.iter().enumerate() {
+                    section.push_str(&format!("acceptor{}:connections_received={}\r\n", i, count));
+                }
+            }
+            section
+        },
 
-            if args.get {
-                if let Some(prev) = prev {
-                    let _ = stream.write_bytes(&prev.data).await;
-                    return Transaction::Write;
+        _ => {
+            let mut section = format!(
+                "# Replication\r\nrole:{}\r\n",
+                handle.info.get_role()
+            );
+
+            if handle.info.is_replica() {
+                let status = if handle.info.is_master_link_up() { "up" } else { "down" };
+                section.push_str(&format!("master_link_status:{}\r\n", status));
+            } else {
+                let replicas = handle.history.replicas().await;
+                section.push_str(&format!("connected_slaves:{}\r\n", replicas.len()));
+                for (i, replica) in replicas.iter().enumerate() {
+                    section.push_str(&format!(
+                        "slave{}:id={},state=online,offset={},lag={},lag_bytes={}\r\n",
+                        i, replica.id, replica.ack_offset, replica.lag_seconds, replica.lag_bytes
+                    ));
                 }
-                
-                let _ = stream.write_message(&Resp::BulkStringNull).await;
-                return Transaction::Write;
             }
 
+            section.push_str(&format!(
+                "master_replid:{}\r\nmaster_repl_offset:{}\r\n",
+                handle.info.get_master_replid(),
+                handle.info.get_master_repl_offset()
+            ));
+
+            // the backlog is trimmed to repl-backlog-size, redis counts its first byte from 1.
+            if !handle.info.is_replica() {
+                let (trimmed, histlen) = handle.history.backlog().await;
+                section.push_str(&format!(
+                    "repl_backlog_active:1\r\nrepl_backlog_size:{}\r\nrepl_backlog_first_byte_offset:{}\r\nrepl_backlog_histlen:{}\r\n",
+                    handle.config.get_repl_backlog_size(),
+                    if histlen == 0 { 0 } else { trimmed + 1 },
+                    histlen
+                ));
+            }
 
-            let _ = stream.write_str("OK").await;
-            return Transaction::Write;
+            section
         }
+    }
+}
 
-        // if we get here, we're just setting the key
-        let prev = handle.database.set(key, value);
 
-        if args.get {
-            if let Some(value) = prev {
-                let _ = stream.write_bytes(&value.data);
-                return Transaction::Write;
-            }
-            let _ = stream.write_message(&Resp::BulkStringNull).await;
-            return Transaction::Write;
+impl Command for EchoCommand {
+    async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
+        let _ = stream.write_reply(Reply::Bulk(self.0.message)).await;
+        Transaction::None
+    }
+}
+
+
+impl Command for SetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let compat_version = handle.config.get_compat_version();
+
+        // GET was added to SET in 6.2 and could only be combined with NX from 7.0 onwards.
+        if args.get && (!compat_version.at_least(6, 2) || (args.nx && !compat_version.at_least(7, 0))) {
+            let _ = stream.write_reply(Reply::Error(ServerError::Syntax)).await;
+            return Transaction::None;
         }
 
-        let _ = stream.write_str("OK").await;
-        Transaction::Write
+        let get = args.get;
+        let outcome = match apply_set(&handle, args) {
+            Ok(outcome) => outcome,
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                return Transaction::None;
+            },
+        };
+
+        let reply = match (get, outcome.previous) {
+            (true, Some(previous)) => Reply::Bulk(previous),
+            (true, None) => Reply::Nil,
+            (false, _) if outcome.written => Reply::Ok,
+            (false, _) => Reply::Nil,
+        };
+
+        let _ = stream.write_reply(reply).await;
+        if outcome.written { Transaction::Write } else { Transaction::None }
+    }
+}
+
+// SET NX with the legacy integer reply.
+impl Command for SetnxCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        // without GET the type of the existing key never matters.
+        let written = apply_set(&handle, self.0).is_ok_and(|outcome| outcome.written);
+        let _ = stream.write_reply(Reply::Int(written as i64)).await;
+        if written { Transaction::Write } else { Transaction::None }
+    }
+}
+
+// resolves the expiration and hands the conditions to the database, which checks them under
+// the same lock as the write. the replies differ between SET and its legacy variants.
+fn apply_set(handle: &Handle, args: SetArguments) -> Result<SetOutcome, WrongType> {
+    let mut value = args.value;
+    let keep_ttl = matches!(args.expiration, Some(Expiration::KeepTtl));
+
+    if let Some(deadline) = args.expiration.and_then(|expiration| expiration.deadline()) {
+        value.set_expiry_at(deadline);
     }
+
+    let flags = SetFlags { nx: args.nx, xx: args.xx, keep_ttl, get: args.get };
+    handle.database.set_with(args.key, value, flags)
 }
 
 impl Command for GetCommand {
@@ -156,19 +752,32 @@ impl Command for GetCommand {
         let value = handle.database.get(&key);
 
         if value.is_none() {
-            let _ = stream.write_message(&Resp::BulkStringNull).await;
+            handle.stats.incr_keyspace_lookup(Family::String, false);
+            let _ = stream.write_reply(Reply::Nil).await;
             return Transaction::None;
         }
 
         let payload = value.unwrap();
 
+        // lazily drop the expired record, replicas expire it on their own so there is
+        // nothing to propagate and the replication offset stays put.
         if payload.has_expired() {
-            handle.database.del(&key);
-            let _ = stream.write_message(&Resp::BulkStringNull).await;
-            return Transaction::Write;
+            handle.database.remove_if_expired(&key);
+            handle.stats.incr_keyspace_lookup(Family::String, false);
+            let _ = stream.write_reply(Reply::Nil).await;
+            return Transaction::None;
         }
 
-        let _ = stream.write_bytes(&payload.data).await;
+        // only a string value is a hit, a type error isn't counted as a lookup at all.
+        let reply = match payload.into_string() {
+            Ok(data) => {
+                handle.stats.incr_keyspace_lookup(Family::String, true);
+                Reply::Bulk(data)
+            },
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
         Transaction::Read 
     }
 }
@@ -191,83 +800,2699 @@ impl Command for PsyncCommand {
     }
 }
 
-pub struct CmdParser;
+const OBJECT_HELP: [&str; 11] = [
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "HELP",
+];
 
-impl CmdParser {
-    pub fn parse(input: Resp) -> Cmd {
-        match input {
-            Resp::Array(args) => {
-                let args_iter = args.into_iter();
-                return Self::route_cmd(args_iter);
+const FREQ_WITHOUT_LFU: &str = "ERR An LFU maxmemory policy is not selected, access frequency not tracked.";
+
+const IDLETIME_UNDER_LFU: &str = "ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.";
+
+// none of these count as an access, so looking at IDLETIME doesn't reset it.
+impl Command for ObjectCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match self.0 {
+            ObjectArguments::Encoding(key) => match handle.database.encoding(&key) {
+                Some(encoding) => Reply::bulk(encoding.as_bytes()),
+                None => Reply::Nil,
             },
 
-            _ => return Cmd::Unexpected("expected array of args".to_string())
-        }
+            // the counters are kept whatever the policy, but like redis each is only reported
+            // under the policy that would use it.
+            ObjectArguments::Idletime(key) => match handle.database.idle_time(&key) {
+                Some(_) if handle.config.is_lfu_policy() => Reply::error(IDLETIME_UNDER_LFU),
+                Some(idle) => Reply::Int(idle.as_secs() as i64),
+                None => Reply::Nil,
+            },
+
+            ObjectArguments::Freq(key) => match handle.database.frequency(&key) {
+                Some(_) if !handle.config.is_lfu_policy() => Reply::error(FREQ_WITHOUT_LFU),
+                Some(freq) => Reply::Int(freq as i64),
+                None => Reply::Nil,
+            },
+
+            ObjectArguments::Help => Reply::Array(OBJECT_HELP.iter().map(|line| Reply::Status(line.to_string())).collect()),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
     }
+}
 
-    fn route_cmd(args: std::vec::IntoIter<Resp>) -> Cmd {
-        let command_arg = ArgumentParser::get_from(args);
+const LATENCY_HELP: [&str; 9] = [
+    "LATENCY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+    "HELP",
+];
+
+// spikes are only recorded once latency-monitor-threshold is set, until then every reply is empty.
+impl Command for LatencyCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let monitor = handle.stats.latency();
+        let reply = match self.0 {
+            LatencyArguments::Latest => Reply::Array(
+                monitor
+                    .latest()
+                    .into_iter()
+                    .map(|(event, last, max)| Reply::Array(vec![
+                        Reply::bulk(event.as_bytes()),
+                        Reply::Int(last.time as i64),
+                        Reply::Int(last.latency as i64),
+                        Reply::Int(max as i64),
+                    ]))
+                    .collect(),
+            ),
+
+            LatencyArguments::History(event) => Reply::Array(
+                monitor
+                    .history(&event.to_lowercase())
+                    .into_iter()
+                    .map(|sample| Reply::Array(vec![Reply::Int(sample.time as i64), Reply::Int(sample.latency as i64)]))
+                    .collect(),
+            ),
+
+            LatencyArguments::Reset(events) => Reply::Int(monitor.reset(&events) as i64),
+
+            LatencyArguments::Help => Reply::Array(LATENCY_HELP.iter().map(|line| Reply::Status(line.to_string())).collect()),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+// parameter names are matched without regard to case, like redis does.
+impl Command for ConfigCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match self.0 {
+            ConfigArguments::Get(patterns) => {
+                let patterns: Vec<Pattern> = patterns.iter().map(|pattern| Pattern::new(pattern, true)).collect();
+                let params = handle.config
+                    .get_matching(&patterns)
+                    .into_iter()
+                    .map(|(name, value)| (Reply::bulk(name.as_bytes()), Reply::Bulk(value.into_bytes())))
+                    .collect();
+                Reply::Map(params)
+            },
+
+            ConfigArguments::Set(pairs) => match handle.config.set_many(&pairs) {
+                Ok(()) => Reply::Ok,
+                Err(err) => Reply::error(err),
+            },
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+impl Command for DebugCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        match self.0 {
+            DebugArguments::Digest => {
+                let digest = handle.database.digest();
+                let _ = stream.write_reply(Reply::Status(digest_to_hex(&digest))).await;
+            },
 
-        if command_arg.is_err() {
-            return Cmd::Unexpected(command_arg.unwrap_err());
+            DebugArguments::DigestValue(keys) => {
+                let digests = keys
+                    .iter()
+                    .map(|key| Reply::Status(digest_to_hex(&handle.database.digest_value(key))))
+                    .collect();
+                let _ = stream.write_reply(Reply::Array(digests)).await;
+            },
+
+            // looking at the key through DEBUG OBJECT doesn't count as an access. there is no
+            // object sharing, so the refcount is always 1.
+            DebugArguments::Object(key) => {
+                let reply = match handle.database.object_info(&key) {
+                    Some(info) => Reply::Status(format!(
+                        "refcount:1 encoding:{} serializedlength:{} lru_seconds_idle:{} type:{} freq:{}",
+                        info.encoding, info.serialized_len, info.idle.as_secs(), info.kind, info.freq,
+                    )),
+                    None => Reply::error("ERR no such key"),
+                };
+                let _ = stream.write_reply(reply).await;
+            },
+
+            // redis prints these to its log, the dump is more useful in the reply here. every
+            // aggregate is dumped by LISTPACK, only lists are quicklists.
+            DebugArguments::Listpack(key) => {
+                let reply = match handle.database.layout(&key) {
+                    Ok(Some(layout)) => Reply::Verbatim(layout.to_lines()),
+                    Ok(None) => Reply::error("ERR no such key"),
+                    Err(_) => Reply::error("ERR Not a listpack encoded object."),
+                };
+                let _ = stream.write_reply(reply).await;
+            },
+
+            DebugArguments::Quicklist(key) => {
+                let reply = match handle.database.layout(&key) {
+                    Ok(Some(layout)) if layout.kind == "list" => Reply::Verbatim(layout.to_lines()),
+                    Ok(None) => Reply::error("ERR no such key"),
+                    _ => Reply::error("ERR Not a quicklist encoded object."),
+                };
+                let _ = stream.write_reply(reply).await;
+            },
+
+            // stalls the whole keyspace like redis stalls its only thread. the wait happens off
+            // the runtime so other connections still get as far as the lock.
+            DebugArguments::Sleep(duration) => {
+                let database = handle.database.clone();
+                let _ = tokio::task::spawn_blocking(move || database.stall(duration)).await;
+                let _ = stream.write_reply(Reply::Ok).await;
+            },
+
+            DebugArguments::SetActiveExpire(enabled) => {
+                handle.database.set_active_expire(enabled);
+                let _ = stream.write_reply(Reply::Ok).await;
+            },
+
+            // there is no allocator heap to dump, it only answers so scripts calling it go on.
+            DebugArguments::Jmap => {
+                let _ = stream.write_reply(Reply::Ok).await;
+            },
         }
 
-        match command_arg.unwrap() {
-            CommandArgument::Ping => { 
-                Cmd::Ping(PingCommand) 
+        Transaction::None
+    }
+}
+
+impl Command for ClusterCommand {
+    async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
+        let reply = match self.0 {
+            ClusterArguments::Keyslot(key) => Reply::Int(keyslot(&key) as i64),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+impl Command for ClientCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match self.0 {
+            ClientArguments::Id => match stream.client() {
+                Some(client) => Reply::Int(client.id() as i64),
+                None => Reply::error("ERR connection is not registered as a client"),
             },
 
-            CommandArgument::Info => {
-                Cmd::Info(InfoCommand)
+            ClientArguments::List(kind) => {
+                let list: String = handle.clients
+                    .list(kind)
+                    .iter()
+                    .map(|info| format!("{}\n", info.to_line()))
+                    .collect();
+                Reply::Verbatim(list)
             },
 
-            CommandArgument::Echo(echo_args) => {
-                Cmd::Echo(EchoCommand(echo_args))
+            // the old form never skips the caller, killing yourself closes the connection
+            // once this reply went out.
+            ClientArguments::KillAddr(addr) => {
+                let me = stream.client().map(|client| client.id());
+                let filter = KillFilter { addr: Some(addr), skip_me: false, ..KillFilter::default() };
+                match handle.clients.kill(&filter, me) {
+                    0 => Reply::error("ERR No such client"),
+                    _ => Reply::Ok,
+                }
             },
 
-            CommandArgument::Get(_get_args) => {
-                Cmd::Get(GetCommand(_get_args))
+            ClientArguments::Kill(filter) => {
+                let me = stream.client().map(|client| client.id());
+                Reply::Int(handle.clients.kill(&filter, me) as i64)
             },
 
-            CommandArgument::Set(_set_args) => {
-                Cmd::Set(SetCommand(_set_args))
+            // only ON replies, a skip asked for while replies are off changes nothing.
+            ClientArguments::Reply(ReplyMode::On) => {
+                stream.set_reply_mode(ReplyMode::On);
+                Reply::Ok
             },
 
-            CommandArgument::Replconf(replconf_args) => {
-                Cmd::ReplConf(ReplconfCommand(replconf_args))
+            ClientArguments::Reply(mode) => {
+                if stream.reply_mode() != ReplyMode::Off {
+                    stream.set_reply_mode(mode);
+                }
+                return Transaction::None;
+            },
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+const WRONGPASS: &str = "WRONGPASS invalid username-password pair or user is disabled.";
+
+impl Command for AuthCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let requirepass = handle.config.get_requirepass();
+
+        let reply = match (&args.username, &requirepass) {
+            (None, None) => Reply::error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?"),
+            (username, requirepass) => {
+                let username = username.as_deref().unwrap_or(acl::DEFAULT_USER);
+                if acl::check_password(requirepass.as_deref(), username, &args.password) {
+                    stream.set_authenticated(true);
+                    Reply::Ok
+                } else {
+                    Reply::error(WRONGPASS)
+                }
+            },
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+impl Command for AclCommand {
+    async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
+        let reply = match self.0 {
+            AclArguments::Cat(None) => Reply::Array(acl::CATEGORIES.iter().map(|c| Reply::bulk(c.as_bytes())).collect()),
+            AclArguments::Cat(Some(category)) => Reply::Array(
+                acl::commands_in(category)
+                    .into_iter()
+                    .map(|name| Reply::bulk(name.as_bytes()))
+                    .collect()
+            ),
+            AclArguments::Genpass(bits) => Reply::Bulk(acl::genpass(bits).into_bytes()),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
+    }
+}
+
+impl Command for HelloCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+
+        if let Some((username, password)) = &args.auth {
+            if !acl::check_password(handle.config.get_requirepass().as_deref(), username, password) {
+                let _ = stream.write_reply(Reply::error(WRONGPASS)).await;
+                return Transaction::None;
             }
+            stream.set_authenticated(true);
+        }
+
+        if !stream.is_authenticated() && handle.config.get_requirepass().is_some() {
+            let _ = stream.write_reply(Reply::error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time")).await;
+            return Transaction::None;
+        }
 
-            _ => Cmd::Unexpected("unknown or unexpected command".to_string())
+        if let (Some(name), Some(client)) = (args.name, stream.client()) {
+            client.set_name(name);
         }
+
+        // the reply already goes out in the protocol that was asked for.
+        if let Some(protocol) = args.protocol {
+            stream.set_protocol(protocol);
+        }
+
+        let reply = Reply::Map(vec![
+            (Reply::bulk(b"server"), Reply::bulk(b"redis")),
+            (Reply::bulk(b"version"), Reply::Bulk(format!("{}.0", handle.config.get_compat_version()).into_bytes())),
+            (Reply::bulk(b"proto"), Reply::Int(stream.protocol().version())),
+            (Reply::bulk(b"id"), Reply::Int(stream.client().map_or(0, |client| client.id() as i64))),
+            (Reply::bulk(b"mode"), Reply::bulk(b"standalone")),
+            (Reply::bulk(b"role"), Reply::bulk(if handle.info.is_replica() { b"replica" } else { b"master" })),
+            (Reply::bulk(b"modules"), Reply::Array(Vec::new())),
+        ]);
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::None
     }
 }
 
+impl Command for FlushallCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        flush(stream, handle, self.0.asynchronous).await
+    }
+}
 
-// TEMPORARY, REMOVE THIS AFTER RDB FILES ARE IMPLEMENTED
-fn get_empty_rdb_file() -> Vec<u8> {
-    let file_hex = b"524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
-    let mut res = Vec::with_capacity(file_hex.len() / 2);
+// there is a single database, so FLUSHDB and FLUSHALL do the same thing.
+impl Command for FlushdbCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        flush(stream, handle, self.0.asynchronous).await
+    }
+}
 
-    fn decode(hex: u8) -> u8 {
-        if hex as i32 - (b'a' as i32) < 0 {
-            hex - b'0'
-        } else {
-            hex - b'a' + 10
-        }
+// a flush always propagates, even on an empty dataset, so replicas that drifted are reset too.
+// with ASYNC the old records are freed on a blocking thread instead of the connection's task.
+async fn flush(stream: &mut Connection, handle: Handle, asynchronous: bool) -> Transaction {
+    let old = handle.database.flush();
+    if asynchronous {
+        tokio::task::spawn_blocking(move || drop(old));
+    } else {
+        drop(old);
     }
 
-    for chunk in file_hex.chunks(2) {
+    let _ = stream.write_reply(Reply::Ok).await;
+    Transaction::Write
+}
 
-        if chunk.len() < 2 {
-            panic!("Invalid hex string")
-        }
+// there is only the one database, so the only swap the arguments allow is 0 with 0. it
+// changes nothing, so it isn't a write and isn't propagated.
+impl Command for SwapdbCommand {
+    async fn execute(self, stream: &mut Connection, _handle: Handle) -> Transaction {
+        let _ = stream.write_reply(Reply::Ok).await;
+        Transaction::None
+    }
+}
 
-        let upper = chunk[0];
-        let lower = chunk[1];
+impl Command for RandomkeyCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.sample_keys(1).pop() {
+            Some(key) => Reply::Bulk(key),
+            None => Reply::Nil,
+        };
 
-        let decoded = (decode(upper) << 4) | decode(lower);
-        res.push(decoded);
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
     }
+}
 
-    res
-}
\ No newline at end of file
+impl Command for ScanCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let (cursor, keys) = handle.database.scan(args.cursor, args.count);
+        let pattern = args.pattern.map(|pattern| stream.pattern(&pattern));
+
+        // MATCH and TYPE filter the batch after it was collected, like redis does, so a call
+        // may come back empty while the iteration is still going.
+        let keys = keys
+            .into_iter()
+            .filter(|key| pattern.as_ref().is_none_or(|pattern| pattern.matches(key)))
+            .filter(|key| args.kind.as_ref().is_none_or(|kind| handle.database.type_of(key) == Some(kind.as_str())))
+            .map(Reply::Bulk)
+            .collect();
+
+        let reply = Reply::Array(vec![
+            Reply::Bulk(cursor.to_string().into_bytes()),
+            Reply::Array(keys),
+        ]);
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for LcsCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+
+        // missing keys behave like empty strings.
+        let a = handle.database.get(&args.key1)
+            .filter(|record| !record.has_expired())
+            .map(Record::into_string)
+            .unwrap_or(Ok(Vec::new()));
+
+        let b = handle.database.get(&args.key2)
+            .filter(|record| !record.has_expired())
+            .map(Record::into_string)
+            .unwrap_or(Ok(Vec::new()));
+
+        let (a, b) = match (a, b) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(wrong_type), _) | (_, Err(wrong_type)) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                return Transaction::None;
+            },
+        };
+
+        let lcs = Lcs::new(&a, &b);
+
+        if args.len {
+            let _ = stream.write_reply(Reply::Int(lcs.len() as i64)).await;
+            return Transaction::Read;
+        }
+
+        if !args.idx {
+            let _ = stream.write_reply(Reply::Bulk(lcs.subsequence())).await;
+            return Transaction::Read;
+        }
+
+        let matches = lcs
+            .matches()
+            .into_iter()
+            .filter(|m| m.match_len() >= args.min_match_len)
+            .map(|m| {
+                let mut entry = vec![
+                    Reply::Array(vec![Reply::Int(m.a.0 as i64), Reply::Int(m.a.1 as i64)]),
+                    Reply::Array(vec![Reply::Int(m.b.0 as i64), Reply::Int(m.b.1 as i64)]),
+                ];
+
+                if args.with_match_len {
+                    entry.push(Reply::Int(m.match_len() as i64));
+                }
+
+                Reply::Array(entry)
+            })
+            .collect();
+
+        let reply = Reply::Map(vec![
+            (Reply::bulk(b"matches"), Reply::Array(matches)),
+            (Reply::bulk(b"len"), Reply::Int(lcs.len() as i64)),
+        ]);
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for ExpireCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        match self.0.time.checked_mul(1000) {
+            Some(ttl) => expire(stream, handle, &self.0.key, ttl).await,
+            None => {
+                let _ = stream.write_reply(Reply::error("ERR invalid expire time in 'expire' command")).await;
+                Transaction::None
+            }
+        }
+    }
+}
+
+impl Command for PexpireCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        expire(stream, handle, &self.0.key, self.0.time).await
+    }
+}
+
+impl Command for ExpireatCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        match self.0.time.checked_mul(1000) {
+            Some(timestamp) => expire_at(stream, handle, &self.0.key, timestamp).await,
+            None => {
+                let _ = stream.write_reply(Reply::error("ERR invalid expire time in 'expireat' command")).await;
+                Transaction::None
+            }
+        }
+    }
+}
+
+impl Command for PexpireatCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        expire_at(stream, handle, &self.0.key, self.0.time).await
+    }
+}
+
+// shared by EXPIREAT and PEXPIREAT, a timestamp that is already in the past deletes the key.
+async fn expire_at(stream: &mut Connection, handle: Handle, key: &[u8], timestamp_ms: i64) -> Transaction {
+    let deadline = UNIX_EPOCH + Duration::from_millis(timestamp_ms.max(0) as u64);
+
+    let changed = if deadline <= SystemTime::now() {
+        handle.database.del(key)
+    } else {
+        handle.database.expire_at(key, deadline)
+    };
+
+    let _ = stream.write_reply(Reply::Int(changed as i64)).await;
+    if changed { Transaction::Write } else { Transaction::None }
+}
+
+impl Command for ExpiretimeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.expire_time(&self.0.key) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(timestamp)) => timestamp.as_secs() as i64,
+        };
+
+        let _ = stream.write_reply(Reply::Int(reply)).await;
+        Transaction::Read
+    }
+}
+
+impl Command for PexpiretimeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.expire_time(&self.0.key) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(timestamp)) => timestamp.as_millis() as i64,
+        };
+
+        let _ = stream.write_reply(Reply::Int(reply)).await;
+        Transaction::Read
+    }
+}
+
+// shared by EXPIRE and PEXPIRE, a ttl that is already in the past deletes the key.
+async fn expire(stream: &mut Connection, handle: Handle, key: &[u8], ttl_ms: i64) -> Transaction {
+    let changed = if ttl_ms <= 0 {
+        handle.database.del(key)
+    } else {
+        handle.database.expire(key, Duration::from_millis(ttl_ms as u64))
+    };
+
+    let _ = stream.write_reply(Reply::Int(changed as i64)).await;
+    if changed { Transaction::Write } else { Transaction::None }
+}
+
+impl Command for PersistCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let changed = handle.database.persist(&self.0.key);
+        let _ = stream.write_reply(Reply::Int(changed as i64)).await;
+        if changed { Transaction::Write } else { Transaction::None }
+    }
+}
+
+impl Command for TtlCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.ttl(&self.0.key) {
+            None => -2,
+            Some(None) => -1,
+            // round to the closest second like redis does.
+            Some(Some(ttl)) => ((ttl.as_millis() + 500) / 1000) as i64,
+        };
+
+        let _ = stream.write_reply(Reply::Int(reply)).await;
+        Transaction::Read
+    }
+}
+
+impl Command for PttlCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.ttl(&self.0.key) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(ttl)) => ttl.as_millis() as i64,
+        };
+
+        let _ = stream.write_reply(Reply::Int(reply)).await;
+        Transaction::Read
+    }
+}
+
+impl Command for MgetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let values = handle.database
+            .get_many(&self.0.keys)
+            .into_iter()
+            // keys holding anything but a string read as nil rather than failing the whole batch.
+            .map(|record| match record.map(Record::into_string) {
+                Some(Ok(data)) => {
+                    handle.stats.incr_keyspace_lookup(Family::String, true);
+                    Reply::Bulk(data)
+                },
+                _ => {
+                    handle.stats.incr_keyspace_lookup(Family::String, false);
+                    Reply::Nil
+                },
+            })
+            .collect();
+
+        let _ = stream.write_reply(Reply::Array(values)).await;
+        Transaction::Read
+    }
+}
+
+impl Command for MsetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        handle.database.set_many(self.0.pairs);
+        let _ = stream.write_reply(Reply::Ok).await;
+        Transaction::Write
+    }
+}
+
+impl Command for MsetnxCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let changed = handle.database.set_many_nx(self.0.pairs);
+        let _ = stream.write_reply(Reply::Int(changed as i64)).await;
+        if changed { Transaction::Write } else { Transaction::None }
+    }
+}
+
+impl Command for DelCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let deleted = handle.database.del_many(&self.0.keys);
+        let _ = stream.write_reply(Reply::Int(deleted as i64)).await;
+        // deleting keys that don't exist changes nothing, so there is nothing to propagate.
+        if deleted > 0 { Transaction::Write } else { Transaction::None }
+    }
+}
+
+impl Command for SetrangeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+
+        if args.offset < 0 {
+            let _ = stream.write_reply(Reply::error("ERR offset is out of range")).await;
+            return Transaction::None;
+        }
+
+        let offset = args.offset as usize;
+        if !args.value.is_empty() && offset.saturating_add(args.value.len()) > MAX_STRING_LEN {
+            let _ = stream.write_reply(Reply::error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")).await;
+            return Transaction::None;
+        }
+
+        let len = match handle.database.set_range(&args.key, offset, &args.value) {
+            Ok(len) => len,
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                return Transaction::None;
+            },
+        };
+
+        let _ = stream.write_reply(Reply::Int(len as i64)).await;
+        // an empty value only reports the current length.
+        if args.value.is_empty() { Transaction::None } else { Transaction::Write }
+    }
+}
+
+impl Command for AppendCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+
+        match handle.database.append(&args.key, &args.value) {
+            Ok(len) => {
+                let _ = stream.write_reply(Reply::Int(len as i64)).await;
+                Transaction::Write
+            },
+            Err(err) => {
+                let _ = stream.write_reply(Reply::error(err)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for GetrangeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.get_range(&args.key, args.start, args.end) {
+            Ok(range) => Reply::Bulk(range),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// a missing key has no bits, so a clear one is found at 0 and a set one nowhere.
+impl Command for BitposCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.bit_position(&args.key, args.bit, args.start, args.end, args.unit) {
+            Ok(Some(pos)) => Reply::Int(pos),
+            Ok(None) => Reply::Int(if args.bit { -1 } else { 0 }),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// like the STORE set commands, even an empty result changes the destination so it always propagates.
+impl Command for BitopCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.bit_op_store(&args.destination, args.op, &args.keys) {
+            Ok(len) => {
+                let _ = stream.write_reply(Reply::Int(len as i64)).await;
+                Transaction::Write
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+// only a change to the registers is propagated.
+impl Command for PfaddCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.hll_add(&args.key, &args.elements) {
+            Ok(changed) => {
+                let _ = stream.write_reply(Reply::Int(changed as i64)).await;
+                if changed { Transaction::Write } else { Transaction::None }
+            },
+            Err(err) => {
+                let _ = stream.write_reply(Reply::error(err)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for PfcountCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hll_count(&self.0.keys) {
+            Ok(count) => Reply::Int(count as i64),
+            Err(err) => Reply::error(err),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for PfmergeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.hll_merge(&args.destination, &args.keys) {
+            Ok(()) => {
+                let _ = stream.write_reply(Reply::Ok).await;
+                Transaction::Write
+            },
+            Err(err) => {
+                let _ = stream.write_reply(Reply::error(err)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+// replicas get the id the entry was given rather than *, and an exact trim.
+impl Command for XaddCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let mut rewritten = vec![Resp::BulkString(b"XADD".to_vec()), Resp::BulkString(args.key.clone())];
+        match args.trim {
+            Some(StreamTrim::MaxLen(len)) => rewritten.extend(["MAXLEN", "=", &len.to_string()].map(|a| Resp::BulkString(a.as_bytes().to_vec()))),
+            Some(StreamTrim::MinId(id)) => rewritten.extend(["MINID", "=", &id.to_string()].map(|a| Resp::BulkString(a.as_bytes().to_vec()))),
+            None => {},
+        }
+        let fields: Vec<Resp> = args.fields
+            .iter()
+            .flat_map(|(field, value)| [Resp::BulkString(field.clone()), Resp::BulkString(value.clone())])
+            .collect();
+
+        match handle.database.stream_add(&args.key, args.id, args.fields, args.nomkstream, args.trim) {
+            Ok(Some(id)) => {
+                let id = id.to_string().into_bytes();
+                rewritten.push(Resp::BulkString(id.clone()));
+                rewritten.extend(fields);
+                let _ = stream.write_reply(Reply::Bulk(id)).await;
+                Transaction::WriteAs(Resp::Array(rewritten))
+            },
+            Ok(None) => {
+                let _ = stream.write_reply(Reply::Nil).await;
+                Transaction::None
+            },
+            Err(err) => {
+                let _ = stream.write_reply(Reply::error(err)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for XrangeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.stream_range(&args.key, args.start, args.end, args.rev, args.count) {
+            Ok(entries) => stream_entries(entries),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// every entry as its id and a flat array of its fields and values.
+fn stream_entries(entries: Vec<StreamEntry>) -> Reply {
+    Reply::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| {
+                let fields = fields.into_iter().flat_map(|(field, value)| [Reply::Bulk(field), Reply::Bulk(value)]).collect();
+                Reply::Array(vec![Reply::Bulk(id.to_string().into_bytes()), Reply::Array(fields)])
+            })
+            .collect(),
+    )
+}
+
+impl Command for XlenCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.stream_len(&self.0.key) {
+            Ok(len) => Reply::Int(len as i64),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for XgroupCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let database = &handle.database;
+        let result = match self.0 {
+            XgroupArguments::Create { key, group, id, mkstream } => database.stream_group_create(&key, &group, id, mkstream).map(|_| (Reply::Ok, true)),
+            XgroupArguments::Setid { key, group, id } => database.stream_group_set_id(&key, &group, id).map(|_| (Reply::Ok, true)),
+            XgroupArguments::Destroy { key, group } => database.stream_group_destroy(&key, &group).map(|destroyed| (Reply::Int(destroyed as i64), destroyed)),
+            XgroupArguments::Createconsumer { key, group, consumer } => {
+                database.stream_group_create_consumer(&key, &group, &consumer).map(|created| (Reply::Int(created as i64), created))
+            },
+            XgroupArguments::Delconsumer { key, group, consumer } => {
+                database.stream_group_delete_consumer(&key, &group, &consumer).map(|pending| (Reply::Int(pending as i64), true))
+            },
+        };
+
+        let (reply, changed) = match result {
+            Ok(done) => done,
+            Err(err) => (Reply::error(err), false),
+        };
+        let _ = stream.write_reply(reply).await;
+        if changed { Transaction::Write } else { Transaction::None }
+    }
+}
+
+// replicas get the read without BLOCK, by the time it reaches them it has already happened.
+impl Command for XreadgroupCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let result = if args.block {
+            let keys: Vec<Vec<u8>> = args.streams.iter().map(|(key, _)| key.clone()).collect();
+            let read = handle.database
+                .wait_for(&keys, args.timeout, |db| db.stream_read_group(&args.streams, &args.group, &args.consumer, args.count, args.noack));
+            match until_killed(stream, read).await {
+                Some(result) => result,
+                None => return Transaction::None,
+            }
+        } else {
+            handle.database.stream_read_group(&args.streams, &args.group, &args.consumer, args.count, args.noack)
+        };
+
+        let read = match result {
+            Ok(Some(read)) => read,
+            Ok(None) if args.block => {
+                let _ = stream.write_reply(Reply::NilArray).await;
+                return Transaction::None;
+            },
+            Ok(None) => Vec::new(),
+            Err(StreamError::NoGroup { key, group }) => {
+                let msg = format!(
+                    "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                    String::from_utf8_lossy(&key),
+                    String::from_utf8_lossy(&group),
+                );
+                let _ = stream.write_reply(Reply::error(msg)).await;
+                return Transaction::None;
+            },
+            Err(err) => {
+                let _ = stream.write_reply(Reply::error(err)).await;
+                return Transaction::None;
+            },
+        };
+
+        let reply = if read.is_empty() {
+            Reply::NilArray
+        } else {
+            Reply::Array(
+                read.into_iter()
+                    .map(|(key, entries)| {
+                        let entries = entries
+                            .into_iter()
+                            .map(|(id, fields)| {
+                                let id = Reply::Bulk(id.to_string().into_bytes());
+                                match fields {
+                                    Some(fields) => Reply::Array(vec![id, Reply::Array(fields.into_iter().flat_map(|(field, value)| [Reply::Bulk(field), Reply::Bulk(value)]).collect())]),
+                                    None => Reply::Array(vec![id, Reply::Nil]),
+                                }
+                            })
+                            .collect();
+                        Reply::Array(vec![Reply::Bulk(key), Reply::Array(entries)])
+                    })
+                    .collect(),
+            )
+        };
+        let _ = stream.write_reply(reply).await;
+
+        let bulk = |arg: &[u8]| Resp::BulkString(arg.to_vec());
+        let mut rewritten = vec![bulk(b"XREADGROUP"), bulk(b"GROUP"), bulk(&args.group), bulk(&args.consumer)];
+        if let Some(count) = args.count {
+            rewritten.extend([bulk(b"COUNT"), bulk(count.to_string().as_bytes())]);
+        }
+        if args.noack {
+            rewritten.push(bulk(b"NOACK"));
+        }
+        rewritten.push(bulk(b"STREAMS"));
+        rewritten.extend(args.streams.iter().map(|(key, _)| bulk(key)));
+        rewritten.extend(args.streams.iter().map(|(_, id)| match id {
+            Some(id) => bulk(id.to_string().as_bytes()),
+            None => bulk(b">"),
+        }));
+        Transaction::WriteAs(Resp::Array(rewritten))
+    }
+}
+
+impl Command for XackCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let (reply, changed) = match handle.database.stream_ack(&args.key, &args.group, &args.ids) {
+            Ok(acked) => (Reply::Int(acked as i64), acked > 0),
+            Err(wrong_type) => (Reply::error(wrong_type), false),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        if changed { Transaction::Write } else { Transaction::None }
+    }
+}
+
+impl Command for PushCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.push(&args.key, args.values, args.end, args.existing_only) {
+            Ok(len) => {
+                let _ = stream.write_reply(Reply::Int(len as i64)).await;
+                // LPUSHX and RPUSHX on a missing key change nothing.
+                if len > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for PopCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let popped = match handle.database.pop(&args.key, args.end, args.count.unwrap_or(1)) {
+            Ok(popped) => popped,
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                return Transaction::None;
+            },
+        };
+
+        let changed = popped.as_ref().is_some_and(|items| !items.is_empty());
+
+        // without a count the reply is a single value, with one it is always an array.
+        let reply = match (args.count, popped) {
+            (None, Some(mut items)) => items.pop().map_or(Reply::Nil, Reply::Bulk),
+            (None, None) => Reply::Nil,
+            (Some(_), Some(items)) => Reply::Array(items.into_iter().map(Reply::Bulk).collect()),
+            (Some(_), None) => Reply::NilArray,
+        };
+
+        let _ = stream.write_reply(reply).await;
+        if changed { Transaction::Write } else { Transaction::None }
+    }
+}
+
+// a blocking command parks until wait is done or its client is killed, whichever comes first,
+// so CLIENT KILL also reaches clients blocked without a timeout. none means it was killed, the
+// session hangs up without a reply and nothing was taken from the database. a resp3 client may
+// block while subscribed, what is published to it in the meantime goes out as pushes rather
+// than waiting for the reply.
+async fn until_killed<T>(stream: &mut Connection, wait: impl std::future::Future<Output = T>) -> Option<T> {
+    let kill_switch = stream.kill_switch();
+    let mailbox = stream.mailbox();
+    tokio::pin!(wait);
+
+    loop {
+        tokio::select! {
+            result = &mut wait => return Some(result),
+            _ = clients::killed(kill_switch.clone()) => return None,
+            Some(published) = pubsub::next_message(mailbox.as_ref()) => {
+                let _ = stream.write_push(published.into_reply()).await;
+            },
+        }
+    }
+}
+
+impl Command for BlockingPopCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let pop = handle.database.wait_for(&args.keys, args.timeout, |db| db.pop_first(&args.keys, args.end, 1));
+        let popped = match until_killed(stream, pop).await {
+            Some(popped) => popped,
+            None => return Transaction::None,
+        };
+
+        match popped {
+            Ok(Some((key, mut values))) => {
+                let rewritten = pop_command(&key, args.end, None);
+                let value = values.pop().unwrap_or_default();
+                let _ = stream.write_reply(Reply::Array(vec![Reply::Bulk(key), Reply::Bulk(value)])).await;
+                Transaction::WriteAs(rewritten)
+            },
+            Ok(None) => {
+                let _ = stream.write_reply(Reply::NilArray).await;
+                Transaction::None
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for MpopCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let popped = if args.blocking {
+            let pop = handle.database.wait_for(&args.keys, args.timeout, |db| db.pop_first(&args.keys, args.end, args.count));
+            match until_killed(stream, pop).await {
+                Some(popped) => popped,
+                None => return Transaction::None,
+            }
+        } else {
+            handle.database.pop_first(&args.keys, args.end, args.count)
+        };
+
+        match popped {
+            Ok(Some((key, values))) => {
+                let rewritten = pop_command(&key, args.end, Some(values.len()));
+                let values = Reply::Array(values.into_iter().map(Reply::Bulk).collect());
+                let _ = stream.write_reply(Reply::Array(vec![Reply::Bulk(key), values])).await;
+                Transaction::WriteAs(rewritten)
+            },
+            Ok(None) => {
+                let _ = stream.write_reply(Reply::NilArray).await;
+                Transaction::None
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+// the plain LPOP or RPOP the multi key and blocking pops are replicated as, so replicas pop
+// exactly what the master did.
+fn pop_command(key: &[u8], end: ListEnd, count: Option<usize>) -> Resp {
+    let name = match end {
+        ListEnd::Left => "LPOP",
+        ListEnd::Right => "RPOP",
+    };
+
+    let mut command = vec![Resp::BulkString(name.as_bytes().to_vec()), Resp::BulkString(key.to_vec())];
+    if let Some(count) = count {
+        command.push(Resp::BulkString(count.to_string().into_bytes()));
+    }
+    Resp::Array(command)
+}
+
+impl Command for LrangeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.list_range(&args.key, args.start, args.stop) {
+            Ok(items) => {
+                handle.stats.incr_keyspace_lookup(Family::List, !items.is_empty());
+                Reply::Array(items.into_iter().map(Reply::Bulk).collect())
+            },
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for LlenCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.list_len(&self.0.key) {
+            Ok(len) => Reply::Int(len as i64),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for LmoveCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.list_move(&args.source, &args.destination, args.from, args.to) {
+            Ok(moved) => {
+                let transaction = if moved.is_some() { Transaction::Write } else { Transaction::None };
+                let _ = stream.write_reply(moved.map_or(Reply::Nil, Reply::Bulk)).await;
+                transaction
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for BlockingMoveCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let BlockingMoveArguments { lmove: args, timeout } = self.0;
+        let keys = [args.source.clone()];
+        let lmove = handle.database.wait_for(&keys, timeout, |db| db.list_move(&args.source, &args.destination, args.from, args.to));
+        let moved = match until_killed(stream, lmove).await {
+            Some(moved) => moved,
+            None => return Transaction::None,
+        };
+
+        match moved {
+            Ok(Some(value)) => {
+                // replicas apply it as the LMOVE it turned into.
+                let end = |end: ListEnd| match end {
+                    ListEnd::Left => b"LEFT".to_vec(),
+                    ListEnd::Right => b"RIGHT".to_vec(),
+                };
+                let rewritten = Resp::Array(vec![
+                    Resp::BulkString(b"LMOVE".to_vec()),
+                    Resp::BulkString(args.source),
+                    Resp::BulkString(args.destination),
+                    Resp::BulkString(end(args.from)),
+                    Resp::BulkString(end(args.to)),
+                ]);
+                let _ = stream.write_reply(Reply::Bulk(value)).await;
+                Transaction::WriteAs(rewritten)
+            },
+            Ok(None) => {
+                let _ = stream.write_reply(Reply::Nil).await;
+                Transaction::None
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for LinsertCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.list_insert(&args.key, args.before, &args.pivot, args.value) {
+            Ok(len) => {
+                let _ = stream.write_reply(Reply::Int(len)).await;
+                if len > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for LsetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let (reply, transaction) = match handle.database.list_set(&args.key, args.index, args.value) {
+            Ok(Some(true)) => (Reply::Ok, Transaction::Write),
+            Ok(Some(false)) => (Reply::error("ERR index out of range"), Transaction::None),
+            Ok(None) => (Reply::error("ERR no such key"), Transaction::None),
+            Err(wrong_type) => (Reply::error(wrong_type), Transaction::None),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        transaction
+    }
+}
+
+impl Command for LremCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.list_remove(&args.key, args.count, &args.value) {
+            Ok(removed) => {
+                let _ = stream.write_reply(Reply::Int(removed as i64)).await;
+                if removed > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for LtrimCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.list_trim(&args.key, args.start, args.stop) {
+            Ok(trimmed) => {
+                let _ = stream.write_reply(Reply::Ok).await;
+                if trimmed { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for LindexCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.list_index(&args.key, args.index) {
+            Ok(value) => {
+                handle.stats.incr_keyspace_lookup(Family::List, value.is_some());
+                value.map_or(Reply::Nil, Reply::Bulk)
+            },
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for SaddCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.set_add(&args.key, args.members) {
+            Ok(added) => {
+                let _ = stream.write_reply(Reply::Int(added as i64)).await;
+                if added > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for SinterCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op(stream, handle, SetOp::Inter, self.0).await
+    }
+}
+
+impl Command for SunionCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op(stream, handle, SetOp::Union, self.0).await
+    }
+}
+
+impl Command for SdiffCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op(stream, handle, SetOp::Diff, self.0).await
+    }
+}
+
+impl Command for SinterstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op_store(stream, handle, SetOp::Inter, self.0).await
+    }
+}
+
+impl Command for SunionstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op_store(stream, handle, SetOp::Union, self.0).await
+    }
+}
+
+impl Command for SdiffstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        set_op_store(stream, handle, SetOp::Diff, self.0).await
+    }
+}
+
+async fn set_op(stream: &mut Connection, handle: Handle, op: SetOp, args: MultiKeyArguments) -> Transaction {
+    let reply = match handle.database.set_combine(op, &args.keys) {
+        Ok(members) => Reply::Set(members.into_iter().map(Reply::Bulk).collect()),
+        Err(wrong_type) => Reply::error(wrong_type),
+    };
+
+    let _ = stream.write_reply(reply).await;
+    Transaction::Read
+}
+
+// the result is always stored, even an empty one deletes the destination, so it always propagates.
+async fn set_op_store(stream: &mut Connection, handle: Handle, op: SetOp, args: StoreArguments) -> Transaction {
+    match handle.database.set_combine_store(&args.destination, op, &args.keys) {
+        Ok(len) => {
+            let _ = stream.write_reply(Reply::Int(len as i64)).await;
+            Transaction::Write
+        },
+        Err(wrong_type) => {
+            let _ = stream.write_reply(Reply::error(wrong_type)).await;
+            Transaction::None
+        },
+    }
+}
+
+// 1 for every member in the set and 0 otherwise, in the order they were asked for.
+impl Command for SmismemberCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.set_contains_many(&self.0.key, &self.0.members) {
+            Ok(found) => Reply::Array(found.into_iter().map(|found| Reply::Int(found as i64)).collect()),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for SintercardCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        intercard(stream, handle, self.0, false).await
+    }
+}
+
+impl Command for ZintercardCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        intercard(stream, handle, self.0, true).await
+    }
+}
+
+impl Command for ZaddCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let mut args = self.0;
+        if args.incr {
+            let (increment, member) = args.pairs.remove(0);
+            return zadd_incr(stream, handle, args.key, increment, member, args.flags).await;
+        }
+
+        match handle.database.zset_add(&args.key, args.pairs, args.flags) {
+            Ok((added, updated)) => {
+                let count = if args.ch { added + updated } else { added };
+                let _ = stream.write_reply(Reply::Int(count as i64)).await;
+                if added + updated > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+// the new score, or nil when a flag kept the increment from being applied.
+async fn zadd_incr(stream: &mut Connection, handle: Handle, key: Vec<u8>, increment: f64, member: Vec<u8>, flags: ZaddFlags) -> Transaction {
+    let (reply, transaction) = match handle.database.zset_incr(&key, increment, member, flags) {
+        Ok(Some(score)) if score.is_nan() => (Reply::error("ERR resulting score is not a number (NaN)"), Transaction::None),
+        Ok(Some(score)) => (Reply::Double(score), Transaction::Write),
+        Ok(None) => (Reply::Nil, Transaction::None),
+        Err(wrong_type) => (Reply::error(wrong_type), Transaction::None),
+    };
+
+    let _ = stream.write_reply(reply).await;
+    transaction
+}
+
+impl Command for ZscoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.zset_score(&self.0.key, &self.0.member) {
+            Ok(score) => score.map_or(Reply::Nil, Reply::Double),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for ZunionCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op(stream, handle, SetOp::Union, self.0).await
+    }
+}
+
+impl Command for ZunionstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op_store(stream, handle, SetOp::Union, self.0).await
+    }
+}
+
+impl Command for ZinterCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op(stream, handle, SetOp::Inter, self.0).await
+    }
+}
+
+impl Command for ZinterstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op_store(stream, handle, SetOp::Inter, self.0).await
+    }
+}
+
+impl Command for ZdiffCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op(stream, handle, SetOp::Diff, self.0).await
+    }
+}
+
+impl Command for ZdiffstoreCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        zset_op_store(stream, handle, SetOp::Diff, self.0).await
+    }
+}
+
+// the result in score order, with the scores when asked for like ZRANGE.
+async fn zset_op(stream: &mut Connection, handle: Handle, op: SetOp, args: ZcombineArguments) -> Transaction {
+    let reply = match handle.database.zset_combine(op, &args.keys, &args.weights, args.aggregate) {
+        Ok(result) if args.with_scores => Reply::Pairs(result.into_iter().map(|(member, score)| (Reply::Bulk(member), Reply::Double(score))).collect()),
+        Ok(result) => Reply::Array(result.into_iter().map(|(member, _)| Reply::Bulk(member)).collect()),
+        Err(wrong_type) => Reply::error(wrong_type),
+    };
+
+    let _ = stream.write_reply(reply).await;
+    Transaction::Read
+}
+
+// like set_op_store, even an empty result changes the destination so it always propagates.
+async fn zset_op_store(stream: &mut Connection, handle: Handle, op: SetOp, args: ZcombineArguments) -> Transaction {
+    let destination = args.destination.unwrap_or_default();
+    match handle.database.zset_combine_store(&destination, op, &args.keys, &args.weights, args.aggregate) {
+        Ok(len) => {
+            let _ = stream.write_reply(Reply::Int(len as i64)).await;
+            Transaction::Write
+        },
+        Err(wrong_type) => {
+            let _ = stream.write_reply(Reply::error(wrong_type)).await;
+            Transaction::None
+        },
+    }
+}
+
+// with WITHSCORE the rank comes with the score, nil either way when there is no such member.
+impl Command for ZrankCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.zset_rank(&args.key, &args.member, args.rev) {
+            Ok(Some((rank, score))) if args.with_score => Reply::Array(vec![Reply::Int(rank as i64), Reply::Double(score)]),
+            Ok(Some((rank, _))) => Reply::Int(rank as i64),
+            // the WITHSCORE form replies with an array, so a miss is a nil array in resp2.
+            Ok(None) if args.with_score => Reply::NilArray,
+            Ok(None) => Reply::Nil,
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// ZADD INCR without the flags, so there is always a new score.
+impl Command for ZincrbyCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        zadd_incr(stream, handle, args.key, args.increment, args.member, ZaddFlags::default()).await
+    }
+}
+
+impl Command for ZcardCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.zset_card(&self.0.key) {
+            Ok(len) => Reply::Int(len as i64),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// with scores every member comes as a member score pair, nested in resp3 and flat otherwise.
+impl Command for ZrangeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.zset_range(&args.key, &args.by, args.rev, args.limit) {
+            Ok(range) if args.with_scores => Reply::Pairs(range.into_iter().map(|(member, score)| (Reply::Bulk(member), Reply::Double(score))).collect()),
+            Ok(range) => Reply::Array(range.into_iter().map(|(member, _)| Reply::Bulk(member)).collect()),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for HsetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        match handle.database.hash_set(&args.key, args.pairs) {
+            Ok(added) => {
+                let _ = stream.write_reply(Reply::Int(added as i64)).await;
+                Transaction::Write
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for HgetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hash_get(&self.0.key, &self.0.field) {
+            Ok(Some(value)) => {
+                handle.stats.incr_keyspace_lookup(Family::Hash, true);
+                Reply::Bulk(value)
+            },
+            Ok(None) => {
+                handle.stats.incr_keyspace_lookup(Family::Hash, false);
+                Reply::Nil
+            },
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for HdelCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        match handle.database.hash_del(&self.0.key, &self.0.fields) {
+            Ok(removed) => {
+                let _ = stream.write_reply(Reply::Int(removed as i64)).await;
+                if removed > 0 { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for HmgetCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hash_get_many(&self.0.key, &self.0.fields) {
+            Ok(values) => Reply::Array(values.into_iter().map(|value| {
+                handle.stats.incr_keyspace_lookup(Family::Hash, value.is_some());
+                value.map_or(Reply::Nil, Reply::Bulk)
+            }).collect()),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// a map in resp3, the flat field value array redis 2 clients expect otherwise.
+impl Command for HgetallCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hash_get_all(&self.0.key) {
+            Ok(pairs) => {
+                handle.stats.incr_keyspace_lookup(Family::Hash, !pairs.is_empty());
+                Reply::Map(pairs.into_iter().map(|(field, value)| (Reply::Bulk(field), Reply::Bulk(value))).collect())
+            },
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+impl Command for HlenCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hash_len(&self.0.key) {
+            Ok(len) => Reply::Int(len as i64),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// without a count a single field or nil, with one an array of fields, or of field value pairs
+// with WITHVALUES.
+impl Command for HrandfieldCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let args = self.0;
+        let reply = match handle.database.hash_random_fields(&args.key, args.count.unwrap_or(1)) {
+            Ok(mut fields) if args.count.is_none() => fields.pop().map_or(Reply::Nil, |(field, _)| Reply::Bulk(field)),
+            Ok(fields) if args.with_values => Reply::Pairs(fields.into_iter().map(|(field, value)| (Reply::Bulk(field), Reply::Bulk(value))).collect()),
+            Ok(fields) => Reply::Array(fields.into_iter().map(|(field, _)| Reply::Bulk(field)).collect()),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+// redis keeps the deadlines of hash fields in 48 bits of unix milliseconds.
+const HASH_FIELD_MAX_DEADLINE_MS: i64 = (1 << 48) - 1;
+
+impl Command for HexpireCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let ttl = self.0.time.checked_mul(1000);
+        hash_expire(stream, handle, self.0, ttl).await
+    }
+}
+
+impl Command for HpexpireCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let ttl = Some(self.0.time);
+        hash_expire(stream, handle, self.0, ttl).await
+    }
+}
+
+// shared by HEXPIRE and HPEXPIRE, ttl is none when it overflowed. replies with a code per
+// field: -2 when it doesn't exist, 0 when the condition held it back, 1 when the ttl was set
+// and 2 when a ttl of 0 removed the field.
+async fn hash_expire(stream: &mut Connection, handle: Handle, args: HexpireArguments, ttl_ms: Option<i64>) -> Transaction {
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    let deadline = match ttl_ms.filter(|ttl| *ttl >= 0).and_then(|ttl| ttl.checked_add(now_ms)) {
+        Some(deadline_ms) if deadline_ms <= HASH_FIELD_MAX_DEADLINE_MS => UNIX_EPOCH + Duration::from_millis(deadline_ms as u64),
+        _ => {
+            let error = format!("ERR invalid expire time, must be >= 0 and <= {}", HASH_FIELD_MAX_DEADLINE_MS);
+            let _ = stream.write_reply(Reply::error(error)).await;
+            return Transaction::None;
+        },
+    };
+
+    let results = match handle.database.hash_expire(&args.key, &args.fields, deadline, args.condition) {
+        Ok(results) => results,
+        Err(wrong_type) => {
+            let _ = stream.write_reply(Reply::error(wrong_type)).await;
+            return Transaction::None;
+        },
+    };
+
+    let changed = results.iter().any(|result| matches!(result, FieldExpiry::Set | FieldExpiry::Removed));
+    let codes = results.into_iter().map(|result| match result {
+        FieldExpiry::Missing => Reply::Int(-2),
+        FieldExpiry::Unchanged => Reply::Int(0),
+        FieldExpiry::Set => Reply::Int(1),
+        FieldExpiry::Removed => Reply::Int(2),
+    });
+
+    let _ = stream.write_reply(Reply::Array(codes.collect())).await;
+    if changed { Transaction::Write } else { Transaction::None }
+}
+
+// -2 for a field that doesn't exist, -1 for one without a ttl and 1 once its ttl is removed.
+impl Command for HpersistCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        match handle.database.hash_persist(&self.0.key, &self.0.fields) {
+            Ok(results) => {
+                let changed = results.contains(&Some(true));
+                let codes = results.into_iter().map(|result| match result {
+                    None => Reply::Int(-2),
+                    Some(false) => Reply::Int(-1),
+                    Some(true) => Reply::Int(1),
+                });
+                let _ = stream.write_reply(Reply::Array(codes.collect())).await;
+                if changed { Transaction::Write } else { Transaction::None }
+            },
+            Err(wrong_type) => {
+                let _ = stream.write_reply(Reply::error(wrong_type)).await;
+                Transaction::None
+            },
+        }
+    }
+}
+
+impl Command for HttlCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let reply = match handle.database.hash_ttl(&self.0.key, &self.0.fields) {
+            Ok(ttls) => Reply::Array(ttls.into_iter().map(|ttl| match ttl {
+                None => Reply::Int(-2),
+                Some(None) => Reply::Int(-1),
+                // rounded to the closest second like TTL.
+                Some(Some(ttl)) => Reply::Int(((ttl.as_millis() + 500) / 1000) as i64),
+            }).collect()),
+            Err(wrong_type) => Reply::error(wrong_type),
+        };
+
+        let _ = stream.write_reply(reply).await;
+        Transaction::Read
+    }
+}
+
+async fn intercard(stream: &mut Connection, handle: Handle, args: IntercardArguments, sorted: bool) -> Transaction {
+    let reply = match handle.database.set_intersect(&args.keys, args.limit, sorted) {
+        Ok(members) => Reply::Int(members.len() as i64),
+        Err(wrong_type) => Reply::error(wrong_type),
+    };
+
+    let _ = stream.write_reply(reply).await;
+    Transaction::Read
+}
+
+impl Command for TypeCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let name = handle.database.type_of(&self.0.key).unwrap_or("none");
+        let _ = stream.write_reply(Reply::Status(name.to_string())).await;
+        Transaction::Read
+    }
+}
+
+// how many encoded bytes of a KEYS reply to buffer before flushing them to the client.
+const KEYS_CHUNK_SIZE: usize = 64 * 1024;
+
+impl Command for KeysCommand {
+    async fn execute(self, stream: &mut Connection, handle: Handle) -> Transaction {
+        let max = handle.config.get_keys_max_results();
+
+        // the matches are encoded straight into bounded chunks under the read lock, the lock
+        // can't be held across the socket writes so the chunks go out once it is released.
+        let mut chunks = Vec::new();
+        let mut chunk = BytesMut::with_capacity(KEYS_CHUNK_SIZE);
+        let matched = handle.database.keys(&self.0.pattern, max, |key| {
+            RespEncoder::encode_bulk_string(key, &mut chunk);
+            if chunk.len() >= KEYS_CHUNK_SIZE {
+                chunks.push(std::mem::replace(&mut chunk, BytesMut::with_capacity(KEYS_CHUNK_SIZE)));
+            }
+        });
+        chunks.push(chunk);
+
+        let matched = match matched {
+            Some(matched) => matched,
+            None => {
+                let _ = stream.write_reply(Reply::error(format!("ERR KEYS matched more than keys-max-results ({}) keys", max))).await;
+                return Transaction::None;
+            }
+        };
+
+        let mut header = BytesMut::new();
+        RespEncoder::encode_array_len(matched, &mut header);
+        stream.write(&header);
+
+        // yielding between chunks so a large reply doesn't starve the other connections on this worker.
+        for chunk in chunks {
+            stream.write(&chunk);
+            if stream.flush().await.is_err() {
+                return Transaction::None;
+            }
+            tokio::task::yield_now().await;
+        }
+
+        Transaction::Read
+    }
+}
+
+// a matched range of the lcs, inclusive (start, end) offsets into each string.
+#[derive(Debug, PartialEq)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl LcsMatch {
+    pub fn match_len(&self) -> u64 {
+        (self.a.1 - self.a.0 + 1) as u64
+    }
+}
+
+// the dynamic programming table for the longest common subsequence of two strings,
+// table[i][j] holds the lcs length of a[..i] and b[..j].
+pub struct Lcs<'a> {
+    a: &'a [u8],
+    b: &'a [u8],
+    table: Vec<u32>,
+}
+
+impl<'a> Lcs<'a> {
+    pub fn new(a: &'a [u8], b: &'a [u8]) -> Self {
+        let width = b.len() + 1;
+        let mut table = vec![0u32; (a.len() + 1) * width];
+
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                table[i * width + j] = if a[i - 1] == b[j - 1] {
+                    table[(i - 1) * width + j - 1] + 1
+                } else {
+                    table[(i - 1) * width + j].max(table[i * width + j - 1])
+                };
+            }
+        }
+
+        Self { a, b, table }
+    }
+
+    fn at(&self, i: usize, j: usize) -> u32 {
+        self.table[i * (self.b.len() + 1) + j]
+    }
+
+    pub fn len(&self) -> usize {
+        self.at(self.a.len(), self.b.len()) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn subsequence(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.len());
+        let (mut i, mut j) = (self.a.len(), self.b.len());
+
+        while i > 0 && j > 0 {
+            if self.a[i - 1] == self.b[j - 1] {
+                result.push(self.a[i - 1]);
+                i -= 1;
+                j -= 1;
+            } else if self.at(i - 1, j) > self.at(i, j - 1) {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+        }
+
+        result.reverse();
+        result
+    }
+
+    // walks the table backwards the same way redis does, so the ranges come out
+    // from the end of the strings towards the start.
+    pub fn matches(&self) -> Vec<LcsMatch> {
+        let mut matches = Vec::new();
+        let mut current: Option<LcsMatch> = None;
+        let (mut i, mut j) = (self.a.len(), self.b.len());
+
+        while i > 0 && j > 0 {
+            let mut emit = false;
+
+            if self.a[i - 1] == self.b[j - 1] {
+                match current.as_mut() {
+                    Some(range) if range.a.0 == i && range.b.0 == j => {
+                        // contiguous with the current range, extend it backwards.
+                        range.a.0 -= 1;
+                        range.b.0 -= 1;
+                    },
+                    Some(_) => emit = true,
+                    None => current = Some(LcsMatch { a: (i - 1, i - 1), b: (j - 1, j - 1) }),
+                }
+
+                if let Some(range) = current.as_ref() {
+                    if range.a.0 == 0 || range.b.0 == 0 {
+                        emit = true;
+                    }
+                }
+
+                i -= 1;
+                j -= 1;
+            } else {
+                if self.at(i - 1, j) > self.at(i, j - 1) {
+                    i -= 1;
+                } else {
+                    j -= 1;
+                }
+
+                emit = current.is_some();
+            }
+
+            if emit {
+                if let Some(range) = current.take() {
+                    matches.push(range);
+                }
+            }
+        }
+
+        matches
+    }
+}
+
+pub struct CmdParser;
+
+impl CmdParser {
+    pub fn parse(input: Resp) -> Cmd {
+        match input {
+            Resp::Array(args) => {
+                let args_iter = args.into_iter();
+                Self::route_cmd(args_iter)
+            },
+
+            _ => Cmd::Unexpected("ERR expected array of args".to_string())
+        }
+    }
+
+    fn route_cmd(args: std::vec::IntoIter<Resp>) -> Cmd {
+        let command_arg = match ArgumentParser::get_from(args) {
+            Ok(arg) => arg,
+            Err(err) => return Cmd::Unexpected(err),
+        };
+
+        match command_arg {
+            CommandArgument::Ping => { 
+                Cmd::Ping(PingCommand) 
+            },
+
+            CommandArgument::Info(info_args) => {
+                Cmd::Info(InfoCommand(info_args))
+            },
+
+            CommandArgument::Echo(echo_args) => {
+                Cmd::Echo(EchoCommand(echo_args))
+            },
+
+            CommandArgument::Get(_get_args) => {
+                Cmd::Get(GetCommand(_get_args))
+            },
+
+            CommandArgument::Set(_set_args) => {
+                Cmd::Set(SetCommand(_set_args))
+            },
+
+            CommandArgument::Replconf(replconf_args) => {
+                Cmd::ReplConf(ReplconfCommand(replconf_args))
+            },
+
+            CommandArgument::Debug(debug_args) => {
+                Cmd::Debug(DebugCommand(debug_args))
+            },
+
+            CommandArgument::Config(config_args) => {
+                Cmd::Config(ConfigCommand(config_args))
+            },
+
+            CommandArgument::Object(object_args) => {
+                Cmd::Object(ObjectCommand(object_args))
+            },
+
+            CommandArgument::Latency(latency_args) => Cmd::Latency(LatencyCommand(latency_args)),
+
+            CommandArgument::Lcs(lcs_args) => {
+                Cmd::Lcs(LcsCommand(lcs_args))
+            },
+
+            CommandArgument::Expire(expire_args) => Cmd::Expire(ExpireCommand(expire_args)),
+            CommandArgument::Pexpire(expire_args) => Cmd::Pexpire(PexpireCommand(expire_args)),
+            CommandArgument::Persist(key_args) => Cmd::Persist(PersistCommand(key_args)),
+            CommandArgument::Ttl(key_args) => Cmd::Ttl(TtlCommand(key_args)),
+            CommandArgument::Pttl(key_args) => Cmd::Pttl(PttlCommand(key_args)),
+            CommandArgument::Keys(keys_args) => Cmd::Keys(KeysCommand(keys_args)),
+            CommandArgument::Expireat(expire_args) => Cmd::Expireat(ExpireatCommand(expire_args)),
+            CommandArgument::Pexpireat(expire_args) => Cmd::Pexpireat(PexpireatCommand(expire_args)),
+            CommandArgument::Expiretime(key_args) => Cmd::Expiretime(ExpiretimeCommand(key_args)),
+            CommandArgument::Pexpiretime(key_args) => Cmd::Pexpiretime(PexpiretimeCommand(key_args)),
+            CommandArgument::Mget(mget_args) => Cmd::Mget(MgetCommand(mget_args)),
+            CommandArgument::Mset(mset_args) => Cmd::Mset(MsetCommand(mset_args)),
+            CommandArgument::Msetnx(mset_args) => Cmd::Msetnx(MsetnxCommand(mset_args)),
+            CommandArgument::Del(key_args) => Cmd::Del(DelCommand(key_args)),
+            CommandArgument::Setrange(setrange_args) => Cmd::Setrange(SetrangeCommand(setrange_args)),
+            CommandArgument::Getrange(getrange_args) => Cmd::Getrange(GetrangeCommand(getrange_args)),
+            CommandArgument::Bitpos(bitpos_args) => Cmd::Bitpos(BitposCommand(bitpos_args)),
+            CommandArgument::Bitop(bitop_args) => Cmd::Bitop(BitopCommand(bitop_args)),
+            CommandArgument::Setnx(set_args) => Cmd::Setnx(SetnxCommand(set_args)),
+            CommandArgument::Client(client_args) => Cmd::Client(ClientCommand(client_args)),
+            CommandArgument::Cluster(cluster_args) => Cmd::Cluster(ClusterCommand(cluster_args)),
+            CommandArgument::Pfadd(pfadd_args) => Cmd::Pfadd(PfaddCommand(pfadd_args)),
+            CommandArgument::Pfcount(key_args) => Cmd::Pfcount(PfcountCommand(key_args)),
+            CommandArgument::Pfmerge(pfmerge_args) => Cmd::Pfmerge(PfmergeCommand(pfmerge_args)),
+            CommandArgument::Xadd(xadd_args) => Cmd::Xadd(XaddCommand(xadd_args)),
+            CommandArgument::Xrange(xrange_args) => Cmd::Xrange(XrangeCommand(xrange_args)),
+            CommandArgument::Xlen(key_args) => Cmd::Xlen(XlenCommand(key_args)),
+            CommandArgument::Xgroup(xgroup_args) => Cmd::Xgroup(XgroupCommand(xgroup_args)),
+            CommandArgument::Xreadgroup(xreadgroup_args) => Cmd::Xreadgroup(XreadgroupCommand(xreadgroup_args)),
+            CommandArgument::Xack(xack_args) => Cmd::Xack(XackCommand(xack_args)),
+            CommandArgument::Subscribe(channels_args) => Cmd::Subscribe(SubscribeCommand(channels_args)),
+            CommandArgument::Unsubscribe(channels_args) => Cmd::Unsubscribe(UnsubscribeCommand(channels_args)),
+            CommandArgument::Publish(publish_args) => Cmd::Publish(PublishCommand(publish_args)),
+            CommandArgument::Flushall(flush_args) => Cmd::Flushall(FlushallCommand(flush_args)),
+            CommandArgument::Flushdb(flush_args) => Cmd::Flushdb(FlushdbCommand(flush_args)),
+            CommandArgument::Randomkey => Cmd::Randomkey(RandomkeyCommand),
+            CommandArgument::Scan(scan_args) => Cmd::Scan(ScanCommand(scan_args)),
+            CommandArgument::Append(append_args) => Cmd::Append(AppendCommand(append_args)),
+            CommandArgument::Type(key_args) => Cmd::Type(TypeCommand(key_args)),
+            CommandArgument::Push(push_args) => Cmd::Push(PushCommand(push_args)),
+            CommandArgument::Lrange(lrange_args) => Cmd::Lrange(LrangeCommand(lrange_args)),
+            CommandArgument::Llen(key_args) => Cmd::Llen(LlenCommand(key_args)),
+            CommandArgument::Hello(hello_args) => Cmd::Hello(HelloCommand(hello_args)),
+            CommandArgument::Pop(pop_args) => Cmd::Pop(PopCommand(pop_args)),
+            CommandArgument::Linsert(linsert_args) => Cmd::Linsert(LinsertCommand(linsert_args)),
+            CommandArgument::Lset(lset_args) => Cmd::Lset(LsetCommand(lset_args)),
+            CommandArgument::Lrem(lrem_args) => Cmd::Lrem(LremCommand(lrem_args)),
+            CommandArgument::Ltrim(lrange_args) => Cmd::Ltrim(LtrimCommand(lrange_args)),
+            CommandArgument::Lindex(lindex_args) => Cmd::Lindex(LindexCommand(lindex_args)),
+            CommandArgument::Lmove(lmove_args) => Cmd::Lmove(LmoveCommand(lmove_args)),
+            CommandArgument::Auth(auth_args) => Cmd::Auth(AuthCommand(auth_args)),
+            CommandArgument::Acl(acl_args) => Cmd::Acl(AclCommand(acl_args)),
+            CommandArgument::BlockingPop(pop_args) => Cmd::BlockingPop(BlockingPopCommand(pop_args)),
+            CommandArgument::Sadd(sadd_args) => Cmd::Sadd(SaddCommand(sadd_args)),
+            CommandArgument::Sinter(key_args) => Cmd::Sinter(SinterCommand(key_args)),
+            CommandArgument::Sinterstore(store_args) => Cmd::Sinterstore(SinterstoreCommand(store_args)),
+            CommandArgument::Sunion(key_args) => Cmd::Sunion(SunionCommand(key_args)),
+            CommandArgument::Sunionstore(store_args) => Cmd::Sunionstore(SunionstoreCommand(store_args)),
+            CommandArgument::Sdiff(key_args) => Cmd::Sdiff(SdiffCommand(key_args)),
+            CommandArgument::Sdiffstore(store_args) => Cmd::Sdiffstore(SdiffstoreCommand(store_args)),
+            CommandArgument::Sintercard(card_args) => Cmd::Sintercard(SintercardCommand(card_args)),
+            CommandArgument::Smismember(member_args) => Cmd::Smismember(SmismemberCommand(member_args)),
+            CommandArgument::Zintercard(card_args) => Cmd::Zintercard(ZintercardCommand(card_args)),
+            CommandArgument::Zadd(zadd_args) => Cmd::Zadd(ZaddCommand(zadd_args)),
+            CommandArgument::Zscore(zscore_args) => Cmd::Zscore(ZscoreCommand(zscore_args)),
+            CommandArgument::Zcard(key_args) => Cmd::Zcard(ZcardCommand(key_args)),
+            CommandArgument::Zrank(zrank_args) => Cmd::Zrank(ZrankCommand(zrank_args)),
+            CommandArgument::Zunion(combine_args) => Cmd::Zunion(ZunionCommand(combine_args)),
+            CommandArgument::Zunionstore(combine_args) => Cmd::Zunionstore(ZunionstoreCommand(combine_args)),
+            CommandArgument::Zinter(combine_args) => Cmd::Zinter(ZinterCommand(combine_args)),
+            CommandArgument::Zinterstore(combine_args) => Cmd::Zinterstore(ZinterstoreCommand(combine_args)),
+            CommandArgument::Zdiff(combine_args) => Cmd::Zdiff(ZdiffCommand(combine_args)),
+            CommandArgument::Zdiffstore(combine_args) => Cmd::Zdiffstore(ZdiffstoreCommand(combine_args)),
+            CommandArgument::Zincrby(zincrby_args) => Cmd::Zincrby(ZincrbyCommand(zincrby_args)),
+            CommandArgument::Zrange(zrange_args) => Cmd::Zrange(ZrangeCommand(zrange_args)),
+            CommandArgument::BlockingMove(move_args) => Cmd::BlockingMove(BlockingMoveCommand(move_args)),
+            CommandArgument::Mpop(mpop_args) => Cmd::Mpop(MpopCommand(mpop_args)),
+            CommandArgument::Hset(hset_args) => Cmd::Hset(HsetCommand(hset_args)),
+            CommandArgument::Hget(hget_args) => Cmd::Hget(HgetCommand(hget_args)),
+            CommandArgument::Hdel(field_args) => Cmd::Hdel(HdelCommand(field_args)),
+            CommandArgument::Hmget(field_args) => Cmd::Hmget(HmgetCommand(field_args)),
+            CommandArgument::Hgetall(key_args) => Cmd::Hgetall(HgetallCommand(key_args)),
+            CommandArgument::Hlen(key_args) => Cmd::Hlen(HlenCommand(key_args)),
+            CommandArgument::Hrandfield(rand_args) => Cmd::Hrandfield(HrandfieldCommand(rand_args)),
+            CommandArgument::Hexpire(expire_args) => Cmd::Hexpire(HexpireCommand(expire_args)),
+            CommandArgument::Hpexpire(expire_args) => Cmd::Hpexpire(HpexpireCommand(expire_args)),
+            CommandArgument::Hpersist(fields_args) => Cmd::Hpersist(HpersistCommand(fields_args)),
+            CommandArgument::Httl(fields_args) => Cmd::Httl(HttlCommand(fields_args)),
+            // the arguments only need validating while db 0 is the only database.
+            CommandArgument::Swapdb(_) => Cmd::Swapdb(SwapdbCommand),
+
+            _ => Cmd::Unexpected("ERR unknown or unexpected command".to_string())
+        }
+    }
+}
+
+
+// TEMPORARY, REMOVE THIS AFTER RDB FILES ARE IMPLEMENTED
+fn get_empty_rdb_file() -> Vec<u8> {
+    let file_hex = b"524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
+    let mut res = Vec::with_capacity(file_hex.len() / 2);
+
+    fn decode(hex: u8) -> u8 {
+        if hex as i32 - (b'a' as i32) < 0 {
+            hex - b'0'
+        } else {
+            hex - b'a' + 10
+        }
+    }
+
+    for chunk in file_hex.chunks(2) {
+
+        if chunk.len() < 2 {
+            panic!("Invalid hex string")
+        }
+
+        let upper = chunk[0];
+        let lower = chunk[1];
+
+        let decoded = (decode(upper) << 4) | decode(lower);
+        res.push(decoded);
+    }
+
+    res
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::duplex;
+    use crate::database::Database;
+    use crate::history::History;
+    use crate::server::ServerInfo;
+    use crate::config::{ Config, CompatVersion };
+    use crate::clients::ClientRegistry;
+    use crate::stats::Stats;
+    use crate::pubsub::Broker;
+
+    fn master_handle() -> Handle {
+        Handle {
+            database: Arc::new(Database::new()),
+            history: Arc::new(History::new()),
+            config: Arc::new(Config::new()),
+            clients: Arc::new(ClientRegistry::new()),
+            stats: Arc::new(Stats::new()),
+            broker: Arc::new(Broker::new()),
+            info: Arc::new(ServerInfo::master()),
+        }
+    }
+
+    // runs a command the way a session would, returns the reply the client read and what the
+    // session would have done with the command. like the session, parse errors are replied here.
+    async fn run(handle: &Handle, args: &[&str]) -> (Resp, Transaction) {
+        let (client, server) = duplex(64 * 1024);
+        let (mut client, mut server) = (Connection::new(client), Connection::new(server));
+        let execute = async {
+            match CmdParser::parse(Resp::command(args)) {
+                Cmd::Unexpected(err) => {
+                    server.write_reply(Reply::error(err)).await.unwrap();
+                    Transaction::None
+                },
+                cmd => cmd.execute(&mut server, handle.clone()).await,
+            }
+        };
+        // read while the command runs, a large reply doesn't fit in the pipe.
+        let (transaction, read) = tokio::join!(execute, client.read_message());
+        (read.unwrap().0, transaction)
+    }
+
+    async fn reply(handle: &Handle, args: &[&str]) -> Resp {
+        run(handle, args).await.0
+    }
+
+    fn bulk(value: &str) -> Resp {
+        Resp::BulkString(value.as_bytes().to_vec())
+    }
+
+    fn ok() -> Resp {
+        Resp::SimpleString("OK".to_string())
+    }
+
+    fn nil() -> Resp {
+        Resp::BulkStringNull
+    }
+
+    fn syntax_error() -> Resp {
+        Resp::SimpleError("ERR syntax error".to_string())
+    }
+
+    #[test]
+    fn test_propagation_policies() {
+        let propagation = |args: &[&str]| CmdParser::parse(Resp::command(args)).propagation();
+        assert_eq!(propagation(&["FLUSHALL"]), Propagation::All);
+        assert_eq!(propagation(&["FLUSHDB", "ASYNC"]), Propagation::All);
+        assert_eq!(propagation(&["SET", "foo", "1"]), Propagation::All);
+        assert_eq!(propagation(&["CLIENT", "ID"]), Propagation::Never);
+        assert_eq!(propagation(&["INFO"]), Propagation::Never);
+        assert_eq!(propagation(&["SWAPDB", "0", "0"]), Propagation::Never);
+        assert_eq!(propagation(&["GET", "foo"]), Propagation::Never);
+        assert!(Propagation::All.to_replicas() && !Propagation::Never.to_replicas());
+    }
+
+    #[tokio::test]
+    async fn test_swapdb_validates_indexes() {
+        let handle = master_handle();
+        reply(&handle, &["SET", "foo", "1"]).await;
+
+        let (swapped, transaction) = run(&handle, &["SWAPDB", "0", "0"]).await;
+        assert_eq!(swapped, ok());
+        assert!(matches!(transaction, Transaction::None));
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, bulk("1"));
+
+        assert_eq!(reply(&handle, &["SWAPDB", "x", "0"]).await, Resp::SimpleError("ERR invalid first DB index".to_string()));
+        assert_eq!(reply(&handle, &["SWAPDB", "0", "x"]).await, Resp::SimpleError("ERR invalid second DB index".to_string()));
+        assert_eq!(reply(&handle, &["SWAPDB", "0", "1"]).await, Resp::SimpleError("ERR DB index is out of range".to_string()));
+        assert_eq!(reply(&handle, &["SWAPDB", "-1", "0"]).await, Resp::SimpleError("ERR DB index is out of range".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_flushall_empties_the_keyspace() {
+        let handle = master_handle();
+        for mode in [&["FLUSHALL", "ASYNC"][..], &["FLUSHALL", "SYNC"], &["FLUSHDB"]] {
+            reply(&handle, &["SET", "foo", "1"]).await;
+            reply(&handle, &["RPUSH", "list", "a"]).await;
+
+            let (flushed, transaction) = run(&handle, mode).await;
+            assert_eq!(flushed, ok(), "{:?}", mode);
+            assert!(matches!(transaction, Transaction::Write));
+            assert_eq!(reply(&handle, &["KEYS", "*"]).await, Resp::Array(vec![]), "{:?}", mode);
+            assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_get_follows_the_compat_version() {
+        let handle = master_handle();
+        handle.config.set_compat_version(CompatVersion::new(6, 0));
+        assert_eq!(reply(&handle, &["SET", "foo", "1", "GET"]).await, syntax_error());
+        assert_eq!(reply(&handle, &["SET", "bar", "1"]).await, ok());
+
+        // 6.2 has SET GET but not combined with NX.
+        handle.config.set_compat_version(CompatVersion::new(6, 2));
+        assert_eq!(reply(&handle, &["SET", "foo", "1", "GET"]).await, nil());
+        assert_eq!(reply(&handle, &["SET", "foo", "2", "NX", "GET"]).await, syntax_error());
+
+        handle.config.set_compat_version(CompatVersion::new(7, 0));
+        assert_eq!(reply(&handle, &["SET", "foo", "2", "NX", "GET"]).await, bulk("1"));
+    }
+
+    #[tokio::test]
+    async fn test_expire_in_the_past_deletes() {
+        let handle = master_handle();
+        for ttl in ["0", "-5"] {
+            reply(&handle, &["SET", "foo", "1"]).await;
+            let (deleted, transaction) = run(&handle, &["EXPIRE", "foo", ttl]).await;
+            assert_eq!(deleted, Resp::Integer(1));
+            assert!(matches!(transaction, Transaction::Write | Transaction::WriteAs(_)));
+            assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+        }
+
+        reply(&handle, &["SET", "foo", "1"]).await;
+        assert_eq!(reply(&handle, &["PEXPIRE", "foo", "0"]).await, Resp::Integer(1));
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+
+        // nothing to delete is not a change.
+        let (missing, transaction) = run(&handle, &["EXPIRE", "foo", "0"]).await;
+        assert_eq!(missing, Resp::Integer(0));
+        assert!(matches!(transaction, Transaction::None));
+    }
+
+    #[tokio::test]
+    async fn test_expire_argument_errors() {
+        let handle = master_handle();
+        let error = |e: &str| Resp::SimpleError(e.to_string());
+        assert_eq!(reply(&handle, &["EXPIRE", "foo"]).await, error("ERR wrong number of arguments for 'expire' command"));
+        assert_eq!(reply(&handle, &["PEXPIRE"]).await, error("ERR wrong number of arguments for 'pexpire' command"));
+        assert_eq!(reply(&handle, &["EXPIRE", "foo", "soon"]).await, error("ERR value is not an integer or out of range"));
+
+        reply(&handle, &["SET", "foo", "1"]).await;
+        assert_eq!(reply(&handle, &["EXPIRE", "foo", &i64::MAX.to_string()]).await, error("ERR invalid expire time in 'expire' command"));
+        assert_eq!(reply(&handle, &["TTL", "foo"]).await, Resp::Integer(-1));
+    }
+
+    #[tokio::test]
+    async fn test_hash_field_ttls() {
+        let handle = master_handle();
+        let ints = |codes: &[i64]| Resp::Array(codes.iter().map(|code| Resp::Integer(*code)).collect());
+        reply(&handle, &["HSET", "h", "a", "1", "b", "2", "c", "3"]).await;
+
+        let (set, transaction) = run(&handle, &["HEXPIRE", "h", "100", "FIELDS", "2", "a", "nope"]).await;
+        assert_eq!(set, ints(&[1, -2]));
+        assert!(matches!(transaction, Transaction::Write));
+        let (unchanged, transaction) = run(&handle, &["HPEXPIRE", "h", "5000", "nx", "FIELDS", "1", "a"]).await;
+        assert_eq!(unchanged, ints(&[0]));
+        assert!(matches!(transaction, Transaction::None));
+        assert_eq!(reply(&handle, &["HPEXPIRE", "h", "200000", "GT", "FIELDS", "2", "a", "b"]).await, ints(&[1, 0]));
+        assert_eq!(reply(&handle, &["HTTL", "h", "FIELDS", "3", "a", "b", "nope"]).await, ints(&[200, -1, -2]));
+        assert_eq!(reply(&handle, &["HTTL", "missing", "FIELDS", "1", "a"]).await, ints(&[-2]));
+
+        assert_eq!(reply(&handle, &["HPERSIST", "h", "FIELDS", "3", "a", "b", "nope"]).await, ints(&[1, -1, -2]));
+        assert_eq!(reply(&handle, &["HTTL", "h", "FIELDS", "1", "a"]).await, ints(&[-1]));
+
+        // a ttl of 0 deletes the field right away.
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", "0", "FIELDS", "1", "c"]).await, ints(&[2]));
+        assert_eq!(reply(&handle, &["HGET", "h", "c"]).await, nil());
+        assert_eq!(reply(&handle, &["HLEN", "h"]).await, Resp::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_hash_field_ttl_argument_errors() {
+        let handle = master_handle();
+        let error = |e: &str| Resp::SimpleError(e.to_string());
+        reply(&handle, &["HSET", "h", "a", "1"]).await;
+
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", "10", "FIELDS", "1"]).await, error("ERR wrong number of arguments for 'hexpire' command"));
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", "10", "XX", "a", "1", "a"]).await, error("ERR Mandatory argument FIELDS is missing or not at the right position"));
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", "10", "FIELDS", "0", "a"]).await, error("ERR Parameter `numFields` should be greater than 0"));
+        assert_eq!(reply(&handle, &["HTTL", "h", "FIELDS", "2", "a"]).await, error("ERR The `numfields` parameter must match the number of arguments"));
+        assert_eq!(reply(&handle, &["HPERSIST", "h", "FIELDS", "x", "a"]).await, error("ERR value is not an integer or out of range"));
+
+        let invalid = error("ERR invalid expire time, must be >= 0 and <= 281474976710655");
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", "-1", "FIELDS", "1", "a"]).await, invalid);
+        assert_eq!(reply(&handle, &["HEXPIRE", "h", &i64::MAX.to_string(), "FIELDS", "1", "a"]).await, invalid);
+        assert_eq!(reply(&handle, &["HPEXPIRE", "h", "281474976710655", "FIELDS", "1", "a"]).await, invalid);
+
+        reply(&handle, &["SET", "s", "x"]).await;
+        assert_eq!(reply(&handle, &["HTTL", "s", "FIELDS", "1", "a"]).await, error("WRONGTYPE Operation against a key holding the wrong kind of value"));
+    }
+
+    #[tokio::test]
+    async fn test_set_expiration_options() {
+        let handle = master_handle();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        // EXAT and PXAT set the deadline as given.
+        let at = now.as_secs() + 3600;
+        assert_eq!(reply(&handle, &["SET", "foo", "1", "EXAT", &at.to_string()]).await, ok());
+        assert_eq!(reply(&handle, &["EXPIRETIME", "foo"]).await, Resp::Integer(at as i64));
+        let at_ms = now.as_millis() as u64 + 7_200_000;
+        assert_eq!(reply(&handle, &["SET", "foo", "2", "PXAT", &at_ms.to_string()]).await, ok());
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(at_ms as i64));
+
+        // KEEPTTL keeps it across an overwrite, a plain SET drops it.
+        assert_eq!(reply(&handle, &["SET", "foo", "3", "KEEPTTL"]).await, ok());
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(at_ms as i64));
+        assert_eq!(reply(&handle, &["SET", "foo", "4", "KEEPTTL", "GET"]).await, bulk("3"));
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(at_ms as i64));
+        assert_eq!(reply(&handle, &["SET", "foo", "5"]).await, ok());
+        assert_eq!(reply(&handle, &["TTL", "foo"]).await, Resp::Integer(-1));
+
+        // KEEPTTL on a missing key sets no ttl.
+        assert_eq!(reply(&handle, &["SET", "bar", "1", "KEEPTTL"]).await, ok());
+        assert_eq!(reply(&handle, &["TTL", "bar"]).await, Resp::Integer(-1));
+
+        // a deadline in the past leaves nothing behind.
+        assert_eq!(reply(&handle, &["SET", "bar", "2", "PXAT", "1"]).await, ok());
+        assert_eq!(reply(&handle, &["GET", "bar"]).await, nil());
+        assert_eq!(reply(&handle, &["TTL", "bar"]).await, Resp::Integer(-2));
+    }
+
+    #[tokio::test]
+    async fn test_zadd_options() {
+        let handle = master_handle();
+        let error = |message: &str| Resp::SimpleError(message.to_string());
+        let nx_xx = error("ERR XX and NX options at the same time are not compatible");
+        let gt_lt_nx = error("ERR GT, LT, and/or NX options at the same time are not compatible");
+
+        let cases: [(&[&str], Resp); 7] = [
+            (&["ZADD", "z", "NX", "XX", "1", "a"], nx_xx),
+            (&["ZADD", "z", "GT", "LT", "1", "a"], gt_lt_nx.clone()),
+            (&["ZADD", "z", "NX", "GT", "1", "a"], gt_lt_nx.clone()),
+            (&["ZADD", "z", "NX", "LT", "1", "a"], gt_lt_nx),
+            (&["ZADD", "z", "INCR", "1", "a", "2", "b"], error("ERR INCR option supports a single increment-element pair")),
+            (&["ZADD", "z", "1", "a", "2"], syntax_error()),
+            (&["ZADD", "z", "x", "a"], error("ERR value is not a valid float")),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(reply(&handle, args).await, expected, "{:?}", args);
+        }
+        assert_eq!(reply(&handle, &["KEYS", "*"]).await, Resp::Array(vec![]));
+
+        assert_eq!(reply(&handle, &["ZADD", "z", "XX", "INCR", "1", "a"]).await, nil());
+        assert_eq!(reply(&handle, &["ZADD", "z", "5", "a"]).await, Resp::Integer(1));
+        assert_eq!(reply(&handle, &["ZADD", "z", "CH", "GT", "6", "a", "1", "b"]).await, Resp::Integer(2));
+        assert_eq!(reply(&handle, &["ZADD", "z", "GT", "INCR", "-1", "a"]).await, nil());
+        assert_eq!(reply(&handle, &["ZADD", "z", "INCR", "1.5", "a"]).await, bulk("7.5"));
+
+        let (added, transaction) = run(&handle, &["ZADD", "z", "NX", "INCR", "1", "a"]).await;
+        assert_eq!(added, nil());
+        assert!(matches!(transaction, Transaction::None));
+
+        assert_eq!(reply(&handle, &["ZADD", "z", "inf", "a"]).await, Resp::Integer(0));
+        assert_eq!(reply(&handle, &["ZADD", "z", "INCR", "-inf", "a"]).await, error("ERR resulting score is not a number (NaN)"));
+        assert_eq!(reply(&handle, &["ZSCORE", "z", "a"]).await, bulk("inf"));
+    }
+
+    #[tokio::test]
+    async fn test_zrange_by_score_and_lex() {
+        let handle = master_handle();
+        let members = |names: &[&str]| Resp::Array(names.iter().map(|name| bulk(name)).collect());
+        reply(&handle, &["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d"]).await;
+        reply(&handle, &["ZADD", "lex", "0", "a", "0", "b", "0", "c", "0", "d"]).await;
+
+        let cases: [(&[&str], Resp); 10] = [
+            (&["ZRANGE", "z", "(1", "(4", "BYSCORE"], members(&["b", "c"])),
+            (&["ZRANGE", "z", "-inf", "+inf", "BYSCORE", "LIMIT", "1", "-1"], members(&["b", "c", "d"])),
+            // under REV the maximum comes first, the other way around is an empty range.
+            (&["ZRANGE", "z", "+inf", "(2", "BYSCORE", "REV"], members(&["d", "c"])),
+            (&["ZRANGE", "z", "(2", "+inf", "BYSCORE", "REV"], members(&[])),
+            (&["ZREVRANGEBYSCORE", "z", "3", "-inf", "LIMIT", "0", "2"], members(&["c", "b"])),
+            (&["ZRANGEBYSCORE", "z", "(3", "inf", "WITHSCORES"], members(&["d", "4"])),
+            (&["ZRANGE", "lex", "-", "(c", "BYLEX"], members(&["a", "b"])),
+            (&["ZRANGE", "lex", "+", "[b", "BYLEX", "REV", "LIMIT", "1", "5"], members(&["c", "b"])),
+            (&["ZREVRANGEBYLEX", "lex", "[c", "-"], members(&["c", "b", "a"])),
+            (&["ZRANGEBYLEX", "lex", "(a", "+"], members(&["b", "c", "d"])),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(reply(&handle, args).await, expected, "{:?}", args);
+        }
+
+        let error = |message: &str| Resp::SimpleError(message.to_string());
+        let errors: [(&[&str], Resp); 6] = [
+            (&["ZRANGE", "z", "0", "-1", "LIMIT", "0", "1"], error("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")),
+            (&["ZRANGE", "lex", "-", "+", "BYLEX", "WITHSCORES"], error("ERR syntax error, WITHSCORES not supported in combination with BYLEX")),
+            (&["ZRANGE", "z", "(x", "1", "BYSCORE"], error("ERR min or max is not a float")),
+            (&["ZRANGE", "lex", "a", "+", "BYLEX"], error("ERR min or max not valid string range item")),
+            (&["ZRANGEBYSCORE", "z", "0", "1", "REV"], syntax_error()),
+            (&["ZRANGE", "z", "0", "1", "BYSCORE", "LIMIT", "0"], syntax_error()),
+        ];
+        for (args, expected) in errors {
+            assert_eq!(reply(&handle, args).await, expected, "{:?}", args);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zrank_and_zincrby() {
+        let handle = master_handle();
+        let error = |message: &str| Resp::SimpleError(message.to_string());
+        assert_eq!(reply(&handle, &["ZRANK", "z", "a"]).await, nil());
+        assert_eq!(reply(&handle, &["ZRANK", "z", "a", "WITHSCORE"]).await, Resp::ArrayNull);
+
+        // ZINCRBY creates the key and the member.
+        let (score, transaction) = run(&handle, &["ZINCRBY", "z", "2", "a"]).await;
+        assert_eq!(score, bulk("2"));
+        assert!(matches!(transaction, Transaction::Write));
+        assert_eq!(reply(&handle, &["ZINCRBY", "z", "1.5", "b"]).await, bulk("1.5"));
+        assert_eq!(reply(&handle, &["ZINCRBY", "z", "-0.5", "a"]).await, bulk("1.5"));
+        assert_eq!(reply(&handle, &["ZINCRBY", "z", "1", "c"]).await, bulk("1"));
+
+        // ties rank by member.
+        assert_eq!(reply(&handle, &["ZRANK", "z", "c"]).await, Resp::Integer(0));
+        assert_eq!(reply(&handle, &["ZRANK", "z", "b"]).await, Resp::Integer(2));
+        assert_eq!(reply(&handle, &["ZREVRANK", "z", "b"]).await, Resp::Integer(0));
+        assert_eq!(reply(&handle, &["ZRANK", "z", "a", "WITHSCORE"]).await, Resp::Array(vec![Resp::Integer(1), bulk("1.5")]));
+        assert_eq!(reply(&handle, &["ZREVRANK", "z", "c", "withscore"]).await, Resp::Array(vec![Resp::Integer(2), bulk("1")]));
+        assert_eq!(reply(&handle, &["ZRANK", "z", "nope"]).await, nil());
+        assert_eq!(reply(&handle, &["ZREVRANK", "z", "nope", "WITHSCORE"]).await, Resp::ArrayNull);
+        assert_eq!(reply(&handle, &["ZRANK", "z", "a", "WITHSCORES"]).await, syntax_error());
+
+        assert_eq!(reply(&handle, &["ZINCRBY", "z", "one", "a"]).await, error("ERR value is not a valid float"));
+        assert_eq!(reply(&handle, &["ZINCRBY", "z", "inf", "a"]).await, bulk("inf"));
+        let (nan, transaction) = run(&handle, &["ZINCRBY", "z", "-inf", "a"]).await;
+        assert_eq!(nan, error("ERR resulting score is not a number (NaN)"));
+        assert!(matches!(transaction, Transaction::None));
+        assert_eq!(reply(&handle, &["ZSCORE", "z", "a"]).await, bulk("inf"));
+
+        reply(&handle, &["SET", "s", "x"]).await;
+        assert!(matches!(reply(&handle, &["ZINCRBY", "s", "1", "a"]).await, Resp::SimpleError(e) if e.starts_with("WRONGTYPE")));
+        assert!(matches!(reply(&handle, &["ZRANK", "s", "a"]).await, Resp::SimpleError(e) if e.starts_with("WRONGTYPE")));
+    }
+
+    #[tokio::test]
+    async fn test_object_freq_needs_an_lfu_policy() {
+        let handle = master_handle();
+        reply(&handle, &["SET", "foo", "1"]).await;
+        assert_eq!(reply(&handle, &["OBJECT", "FREQ", "foo"]).await, Resp::SimpleError(FREQ_WITHOUT_LFU.to_string()));
+        assert_eq!(reply(&handle, &["OBJECT", "FREQ", "nope"]).await, nil());
+        assert_eq!(reply(&handle, &["OBJECT", "IDLETIME", "foo"]).await, Resp::Integer(0));
+
+        reply(&handle, &["CONFIG", "SET", "maxmemory-policy", "allkeys-lfu"]).await;
+        assert!(matches!(reply(&handle, &["OBJECT", "FREQ", "foo"]).await, Resp::Integer(_)));
+        assert_eq!(reply(&handle, &["OBJECT", "IDLETIME", "foo"]).await, Resp::SimpleError(IDLETIME_UNDER_LFU.to_string()));
+        assert_eq!(reply(&handle, &["OBJECT", "IDLETIME", "nope"]).await, nil());
+    }
+
+    #[tokio::test]
+    async fn test_debug_sleep_arguments() {
+        let handle = master_handle();
+        let error = |message: &str| Resp::SimpleError(message.to_string());
+        assert_eq!(reply(&handle, &["DEBUG", "SLEEP", "0"]).await, ok());
+        assert_eq!(reply(&handle, &["DEBUG", "SLEEP", "0.001"]).await, ok());
+
+        let cases: [(&[&str], Resp); 6] = [
+            (&["DEBUG", "SLEEP", "1e300"], error("ERR value is out of range")),
+            (&["DEBUG", "SLEEP", "inf"], error("ERR value is not a valid float")),
+            (&["DEBUG", "SLEEP", "-1"], error("ERR value is not a valid float")),
+            (&["DEBUG", "SLEEP", "soon"], error("ERR value is not a valid float")),
+            (&["DEBUG", "SLEEP"], error("ERR wrong number of arguments for 'debug|sleep' command")),
+            (&["DEBUG", "SLEEP", "1", "2"], syntax_error()),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(reply(&handle, args).await, expected, "{:?}", args);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_option_conflicts() {
+        let handle = master_handle();
+        reply(&handle, &["SET", "foo", "1"]).await;
+        let invalid_expire = Resp::SimpleError("ERR invalid expire time in 'set' command".to_string());
+
+        let cases: [(&[&str], Resp); 9] = [
+            (&["SET", "foo", "2", "KEEPTTL", "EX", "10"], syntax_error()),
+            (&["SET", "foo", "2", "EX", "10", "KEEPTTL"], syntax_error()),
+            (&["SET", "foo", "2", "EX", "10", "PX", "10"], syntax_error()),
+            (&["SET", "foo", "2", "NX", "XX"], syntax_error()),
+            (&["SET", "foo", "2", "EX"], syntax_error()),
+            (&["SET", "foo", "2", "EX", "0"], invalid_expire.clone()),
+            (&["SET", "foo", "2", "PX", "-1"], invalid_expire.clone()),
+            (&["SET", "foo", "2", "PXAT", "0"], invalid_expire.clone()),
+            (&["SET", "foo", "2", "EX", &(i64::MAX / 100).to_string()], invalid_expire),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(reply(&handle, args).await, expected, "{:?}", args);
+        }
+
+        // none of them wrote anything.
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, bulk("1"));
+        assert_eq!(reply(&handle, &["TTL", "foo"]).await, Resp::Integer(-1));
+    }
+
+    #[tokio::test]
+    async fn test_get_counts_only_string_hits() {
+        let handle = master_handle();
+        reply(&handle, &["SET", "foo", "1"]).await;
+        reply(&handle, &["RPUSH", "list", "a"]).await;
+
+        reply(&handle, &["GET", "foo"]).await;
+        reply(&handle, &["GET", "missing"]).await;
+        assert!(matches!(reply(&handle, &["GET", "list"]).await, Resp::SimpleError(e) if e.starts_with("WRONGTYPE")));
+        assert_eq!(handle.stats.get_keyspace_hits(Family::String), 1);
+        assert_eq!(handle.stats.get_keyspace_misses(Family::String), 1);
+    }
+
+    #[tokio::test]
+    async fn test_setex_rejects_times_that_overflow() {
+        let handle = master_handle();
+        let max = i64::MAX.to_string();
+        let error = |cmd: &str| Resp::SimpleError(format!("ERR invalid expire time in '{}' command", cmd));
+
+        assert_eq!(reply(&handle, &["SETEX", "foo", &max, "1"]).await, error("setex"));
+        assert_eq!(reply(&handle, &["SETEX", "foo", "0", "1"]).await, error("setex"));
+        assert_eq!(reply(&handle, &["PSETEX", "foo", "-1", "1"]).await, error("psetex"));
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+
+        // milliseconds never get multiplied, the largest one is a valid ttl.
+        assert_eq!(reply(&handle, &["PSETEX", "foo", &max, "1"]).await, ok());
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, bulk("1"));
+    }
+
+    #[tokio::test]
+    async fn test_set_get_leaves_other_types_alone() {
+        let handle = master_handle();
+        reply(&handle, &["RPUSH", "list", "a"]).await;
+        let (wrong_type, transaction) = run(&handle, &["SET", "list", "1", "GET"]).await;
+        assert!(matches!(wrong_type, Resp::SimpleError(e) if e.starts_with("WRONGTYPE")));
+        assert!(matches!(transaction, Transaction::None));
+        assert_eq!(reply(&handle, &["LRANGE", "list", "0", "-1"]).await, Resp::Array(vec![bulk("a")]));
+
+        // without GET the type doesn't matter.
+        assert_eq!(reply(&handle, &["SET", "list", "1"]).await, ok());
+        assert_eq!(reply(&handle, &["GET", "list"]).await, bulk("1"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_setnx_takes_the_lock_once() {
+        for round in 0..20 {
+            let handle = master_handle();
+            let key = format!("lock:{}", round);
+            let tasks: Vec<_> = (0..16)
+                .map(|i| {
+                    let (handle, key) = (handle.clone(), key.clone());
+                    tokio::spawn(async move { run(&handle, &["SETNX", &key, &i.to_string()]).await.0 })
+                })
+                .collect();
+
+            let mut taken = 0;
+            for task in tasks {
+                if task.await.unwrap() == Resp::Integer(1) {
+                    taken += 1;
+                }
+            }
+            assert_eq!(taken, 1, "round {}", round);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_ops_are_not_writes() {
+        let handle = master_handle();
+        reply(&handle, &["SET", "foo", "1", "PX", "1"]).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let cases: [(&[&str], Resp); 4] = [
+            (&["SET", "bar", "1", "XX"], nil()),
+            (&["DEL", "bar"], Resp::Integer(0)),
+            // the expired key is dropped on the way, but replicas expire it on their own.
+            (&["GET", "foo"], nil()),
+            (&["DEL", "foo"], Resp::Integer(0)),
+        ];
+        for (args, expected) in cases {
+            let (actual, transaction) = run(&handle, args).await;
+            assert_eq!(actual, expected, "{:?}", args);
+            assert!(matches!(transaction, Transaction::None), "{:?} {:?}", args, transaction);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_msetnx_only_propagates_a_write() {
+        let handle = master_handle();
+        let (written, transaction) = run(&handle, &["MSETNX", "a", "1", "b", "1"]).await;
+        assert_eq!(written, Resp::Integer(1));
+        assert!(matches!(transaction, Transaction::Write));
+
+        let (blocked, transaction) = run(&handle, &["MSETNX", "c", "2", "a", "2"]).await;
+        assert_eq!(blocked, Resp::Integer(0));
+        assert!(matches!(transaction, Transaction::None));
+        assert_eq!(reply(&handle, &["MGET", "a", "c"]).await, Resp::Array(vec![bulk("1"), nil()]));
+    }
+
+    #[tokio::test]
+    async fn test_unrouted_commands_reply_with_an_error_code() {
+        let handle = master_handle();
+        let unknown = Resp::SimpleError("ERR unknown or unexpected command".to_string());
+
+        // PSYNC parses but is only served during the replication handshake.
+        assert_eq!(reply(&handle, &["PSYNC", "?", "-1"]).await, unknown);
+        assert_eq!(reply(&handle, &["NOPE"]).await, unknown);
+    }
+
+    #[tokio::test]
+    async fn test_absolute_expiry() {
+        let handle = master_handle();
+        assert_eq!(reply(&handle, &["EXPIRETIME", "foo"]).await, Resp::Integer(-2));
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(-2));
+        assert_eq!(reply(&handle, &["EXPIREAT", "foo", "1"]).await, Resp::Integer(0));
+
+        reply(&handle, &["SET", "foo", "1"]).await;
+        assert_eq!(reply(&handle, &["EXPIRETIME", "foo"]).await, Resp::Integer(-1));
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(-1));
+
+        // a deadline in the future reads back exactly.
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600;
+        assert_eq!(reply(&handle, &["EXPIREAT", "foo", &at.to_string()]).await, Resp::Integer(1));
+        assert_eq!(reply(&handle, &["EXPIRETIME", "foo"]).await, Resp::Integer(at as i64));
+        assert_eq!(reply(&handle, &["PEXPIRETIME", "foo"]).await, Resp::Integer(at as i64 * 1000));
+
+        // one in the past deletes the key.
+        assert_eq!(reply(&handle, &["EXPIREAT", "foo", "1"]).await, Resp::Integer(1));
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+        assert_eq!(reply(&handle, &["EXPIRETIME", "foo"]).await, Resp::Integer(-2));
+
+        reply(&handle, &["SET", "foo", "1"]).await;
+        assert_eq!(reply(&handle, &["PEXPIREAT", "foo", "-1"]).await, Resp::Integer(1));
+        assert_eq!(reply(&handle, &["GET", "foo"]).await, nil());
+    }
+
+    #[tokio::test]
+    async fn test_keys_replies_across_chunks() {
+        let handle = master_handle();
+        // enough keys that the reply spans several chunks.
+        let value = "x".repeat(64);
+        for i in 0..3000 {
+            reply(&handle, &["SET", &format!("key:{:04}:{}", i, value), "1"]).await;
+        }
+        reply(&handle, &["SET", "other", "1"]).await;
+
+        let keys = match reply(&handle, &["KEYS", "key:*"]).await {
+            Resp::Array(keys) => keys,
+            other => panic!("unexpected KEYS reply {:?}", other),
+        };
+        assert_eq!(keys.len(), 3000);
+        assert!(keys.contains(&bulk(&format!("key:2999:{}", value))));
+
+        handle.config.set_keys_max_results(100);
+        assert_eq!(reply(&handle, &["KEYS", "key:*"]).await, Resp::SimpleError("ERR KEYS matched more than keys-max-results (100) keys".to_string()));
+        assert_eq!(reply(&handle, &["KEYS", "oth*"]).await, Resp::Array(vec![bulk("other")]));
+    }
+
+    #[test]
+    fn test_lcs_subsequence() {
+        let lcs = Lcs::new(b"ohmytext", b"mynewtext");
+        assert_eq!(lcs.len(), 6);
+        assert_eq!(lcs.subsequence(), b"mytext".to_vec());
+    }
+
+    #[test]
+    fn test_lcs_matches() {
+        let lcs = Lcs::new(b"ohmytext", b"mynewtext");
+        assert_eq!(lcs.matches(), vec![
+            LcsMatch { a: (4, 7), b: (5, 8) },
+            LcsMatch { a: (2, 3), b: (0, 1) },
+        ]);
+    }
+
+    #[test]
+    fn test_lcs_empty() {
+        let lcs = Lcs::new(b"", b"abc");
+        assert!(lcs.is_empty());
+        assert!(lcs.matches().is_empty());
+    }
+}
@@ -0,0 +1,613 @@
+use std::sync::Mutex;
+use std::fmt::Display;
+use std::path::Path;
+use crate::glob::Pattern;
+
+// the redis version whose behavior the server should mimic, this lets test suites written
+// against an older server keep passing when behaviors changed between releases. it switches:
+//  - SET GET, refused before 6.2.
+//  - SET NX GET, refused before 7.0.
+//  - the version HELLO reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompatVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl CompatVersion {
+    // the newest behavior set the server knows about, used when nothing was configured.
+    pub const LATEST: CompatVersion = CompatVersion { major: 7, minor: 2 };
+
+    pub fn new(major: u8, minor: u8) -> Self {
+        Self { major, minor }
+    }
+
+    // parses "major.minor" and ignores any patch version, i.e., "6.2.14" -> 6.2
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut parts = input.split('.');
+        let major = parts.next()?.parse::<u8>().ok()?;
+        let minor = match parts.next() {
+            Some(minor) => minor.parse::<u8>().ok()?,
+            None => 0,
+        };
+        Some(Self { major, minor })
+    }
+
+    pub fn at_least(&self, major: u8, minor: u8) -> bool {
+        *self >= CompatVersion::new(major, minor)
+    }
+}
+
+impl Display for CompatVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+// the commands only the internal port runs once one is bound, the ones that reconfigure or stop
+// the server. most of them don't exist here yet but are still kept off the public port.
+pub const DEFAULT_INTERNAL_COMMANDS: [&str; 5] = ["config", "shutdown", "debug", "replicaof", "slaveof"];
+
+pub const DEFAULT_MAXCLIENTS: usize = 10000;
+
+pub const DEFAULT_DBFILENAME: &str = "dump.rdb";
+
+// how many bytes of past writes the replication history keeps, redis' default of 1mb.
+pub const DEFAULT_REPL_BACKLOG_SIZE: u64 = 1024 * 1024;
+
+// redis' own default snapshot rules, once an hour after a change, every 5 minutes after 100 and
+// every minute after 10000.
+pub const DEFAULT_SAVE_RULES: [(u64, u64); 3] = [(3600, 1), (300, 100), (60, 10000)];
+
+// the eviction policies redis accepts for maxmemory-policy, in the order its error lists them.
+pub const MAXMEMORY_POLICIES: [&str; 8] = [
+    "volatile-lru", "volatile-lfu", "volatile-random", "volatile-ttl", "allkeys-lru", "allkeys-lfu", "allkeys-random", "noeviction",
+];
+
+// when an append only file would be synced to disk, for appendfsync.
+pub const APPENDFSYNC_POLICIES: [&str; 3] = ["always", "everysec", "no"];
+
+// runs when CONFIG SET changes the parameter it observes, with the configuration about to take
+// effect. an error fails the whole CONFIG SET and is reported to the client, like the apply
+// functions of redis. it runs under the config lock, so it must not call back into the config.
+pub type Observer = Box<dyn Fn(&ConfigInner) -> Result<(), String> + Send + Sync>;
+
+// runtime configuration shared by every connection. subsystems that hold on to a setting
+// observe it, so CONFIG SET reaches them while the server runs.
+pub struct Config {
+    inner: Mutex<ConfigInner>,
+    observers: Mutex<Vec<(&'static str, Observer)>>,
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Config").field("inner", &self.inner).finish_non_exhaustive()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(ConfigInner::new()),
+            observers: Mutex::new(Vec::new()),
+        }
+    }
+
+    // calls observer whenever CONFIG SET changes the parameter name, which has to exist.
+    pub fn observe(&self, name: &'static str, observer: Observer) {
+        debug_assert!(PARAMETERS.iter().any(|param| param.name == name), "no parameter {}", name);
+        self.observers.lock().unwrap().push((name, observer));
+    }
+
+    pub fn get_compat_version(&self) -> CompatVersion {
+        self.inner.lock().unwrap().compat_version
+    }
+
+    pub fn set_compat_version(&self, version: CompatVersion) {
+        self.inner.lock().unwrap().compat_version = version;
+    }
+
+    pub fn get_keys_max_results(&self) -> usize {
+        self.inner.lock().unwrap().keys_max_results
+    }
+
+    pub fn set_keys_max_results(&self, max: usize) {
+        self.inner.lock().unwrap().keys_max_results = max;
+    }
+
+    pub fn get_latency_monitor_threshold(&self) -> u64 {
+        self.inner.lock().unwrap().latency_monitor_threshold
+    }
+
+    pub fn set_latency_monitor_threshold(&self, threshold: u64) {
+        self.inner.lock().unwrap().latency_monitor_threshold = threshold;
+    }
+
+    pub fn get_maxclients(&self) -> usize {
+        self.inner.lock().unwrap().maxclients
+    }
+
+    pub fn set_maxclients(&self, max: usize) {
+        self.inner.lock().unwrap().maxclients = max;
+    }
+
+    pub fn get_requirepass(&self) -> Option<String> {
+        self.inner.lock().unwrap().requirepass.clone()
+    }
+
+    pub fn set_requirepass(&self, password: Option<String>) {
+        self.inner.lock().unwrap().requirepass = password;
+    }
+
+    pub fn get_replica_serve_stale_data(&self) -> bool {
+        self.inner.lock().unwrap().replica_serve_stale_data
+    }
+
+    pub fn set_replica_serve_stale_data(&self, serve: bool) {
+        self.inner.lock().unwrap().replica_serve_stale_data = serve;
+    }
+
+    pub fn get_internal_commands(&self) -> Vec<String> {
+        self.inner.lock().unwrap().internal_commands.clone()
+    }
+
+    pub fn set_internal_commands(&self, commands: Vec<String>) {
+        self.inner.lock().unwrap().internal_commands = commands.into_iter().map(|c| c.to_lowercase()).collect();
+    }
+
+    // whether the public port refuses name, compared like command names are.
+    pub fn is_internal_command(&self, name: &str) -> bool {
+        self.inner.lock().unwrap().internal_commands.iter().any(|c| c.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_dir(&self) -> String {
+        self.inner.lock().unwrap().dir.clone()
+    }
+
+    pub fn set_dir(&self, dir: String) {
+        self.inner.lock().unwrap().dir = dir;
+    }
+
+    pub fn get_dbfilename(&self) -> String {
+        self.inner.lock().unwrap().dbfilename.clone()
+    }
+
+    pub fn set_dbfilename(&self, name: String) {
+        self.inner.lock().unwrap().dbfilename = name;
+    }
+
+    pub fn get_maxmemory(&self) -> u64 {
+        self.inner.lock().unwrap().maxmemory
+    }
+
+    // whether maxmemory-policy is one of the lfu ones, OBJECT FREQ is only answered then.
+    pub fn is_lfu_policy(&self) -> bool {
+        self.inner.lock().unwrap().maxmemory_policy.ends_with("-lfu")
+    }
+
+    pub fn get_save_rules(&self) -> Vec<(u64, u64)> {
+        self.inner.lock().unwrap().save.clone()
+    }
+
+    pub fn get_appendonly(&self) -> bool {
+        self.inner.lock().unwrap().appendonly
+    }
+
+    pub fn get_repl_backlog_size(&self) -> u64 {
+        self.inner.lock().unwrap().repl_backlog_size
+    }
+
+    // CONFIG GET, the parameters matching any of the patterns with their values. each comes
+    // once, in the order of the parameter table.
+    pub fn get_matching(&self, patterns: &[Pattern]) -> Vec<(&'static str, String)> {
+        let inner = self.inner.lock().unwrap();
+        PARAMETERS
+            .iter()
+            .filter(|param| patterns.iter().any(|pattern| pattern.matches(param.name.as_bytes())))
+            .map(|param| (param.name, (param.get)(&inner)))
+            .collect()
+    }
+
+    // CONFIG SET, every pair is applied or none of them is. the error is the one the client gets.
+    // the observers of the changed parameters run once every value parsed, if one of them fails
+    // the ones before it are handed the old configuration again.
+    pub fn set_many(&self, pairs: &[(String, String)]) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut updated = inner.clone();
+        let mut seen: Vec<&str> = Vec::with_capacity(pairs.len());
+
+        for (name, value) in pairs {
+            let param = match PARAMETERS.iter().find(|param| param.name.eq_ignore_ascii_case(name)) {
+                Some(param) => param,
+                None => return Err(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name)),
+            };
+
+            if seen.contains(&param.name) {
+                return Err(format!("ERR CONFIG SET failed (possibly related to argument '{}') - duplicate parameter", name));
+            }
+            seen.push(param.name);
+
+            (param.set)(&mut updated, value)
+                .map_err(|reason| format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, reason))?;
+        }
+
+        let observers = self.observers.lock().unwrap();
+        let affected: Vec<&(&'static str, Observer)> = observers.iter().filter(|(name, _)| seen.contains(name)).collect();
+        for (i, (name, observer)) in affected.iter().enumerate() {
+            if let Err(reason) = observer(&updated) {
+                for (_, applied) in &affected[..i] {
+                    let _ = applied(&inner);
+                }
+                return Err(format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, reason));
+            }
+        }
+
+        *inner = updated;
+        Ok(())
+    }
+}
+
+// a parameter CONFIG GET and CONFIG SET know about, values go in and out as redis prints them.
+struct Parameter {
+    name: &'static str,
+    get: fn(&ConfigInner) -> String,
+    set: fn(&mut ConfigInner, &str) -> Result<(), &'static str>,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "dir",
+        get: |c| c.dir.clone(),
+        set: |c, v| {
+            if !Path::new(v).is_dir() {
+                return Err("No such file or directory");
+            }
+            c.dir = v.to_string();
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dbfilename",
+        get: |c| c.dbfilename.clone(),
+        set: |c, v| {
+            if v.contains('/') {
+                return Err("dbfilename can't be a path, just a filename");
+            }
+            c.dbfilename = v.to_string();
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory",
+        get: |c| c.maxmemory.to_string(),
+        set: |c, v| {
+            c.maxmemory = parse_memory(v).ok_or("argument must be a memory value")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory-policy",
+        get: |c| c.maxmemory_policy.to_string(),
+        set: |c, v| {
+            let policy = v.to_lowercase();
+            c.maxmemory_policy = MAXMEMORY_POLICIES
+                .iter()
+                .find(|name| **name == policy)
+                .ok_or("argument(s) must be one of the following: volatile-lru, volatile-lfu, volatile-random, volatile-ttl, allkeys-lru, allkeys-lfu, allkeys-random, noeviction")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "save",
+        get: |c| c.save.iter().map(|(seconds, changes)| format!("{} {}", seconds, changes)).collect::<Vec<_>>().join(" "),
+        set: |c, v| {
+            c.save = parse_save_rules(v).ok_or("Invalid save parameters")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendonly",
+        get: |c| yes_no(c.appendonly),
+        set: |c, v| {
+            c.appendonly = parse_yes_no(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendfsync",
+        get: |c| c.appendfsync.to_string(),
+        set: |c, v| {
+            let policy = v.to_lowercase();
+            c.appendfsync = APPENDFSYNC_POLICIES
+                .iter()
+                .find(|name| **name == policy)
+                .ok_or("argument(s) must be one of the following: always, everysec, no")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "repl-backlog-size",
+        get: |c| c.repl_backlog_size.to_string(),
+        set: |c, v| {
+            match parse_memory(v) {
+                Some(size) if size > 0 => c.repl_backlog_size = size,
+                _ => return Err("argument must be a memory value greater than 0"),
+            }
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxclients",
+        get: |c| c.maxclients.to_string(),
+        set: |c, v| {
+            match v.parse::<usize>() {
+                Ok(max) if max > 0 => c.maxclients = max,
+                _ => return Err("argument must be a positive integer"),
+            }
+            Ok(())
+        },
+    },
+    // an empty password turns authentication off again.
+    Parameter {
+        name: "requirepass",
+        get: |c| c.requirepass.clone().unwrap_or_default(),
+        set: |c, v| {
+            c.requirepass = Some(v.to_string()).filter(|v| !v.is_empty());
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "replica-serve-stale-data",
+        get: |c| yes_no(c.replica_serve_stale_data),
+        set: |c, v| {
+            c.replica_serve_stale_data = parse_yes_no(v)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "keys-max-results",
+        get: |c| c.keys_max_results.to_string(),
+        set: |c, v| {
+            c.keys_max_results = v.parse::<usize>().map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "latency-monitor-threshold",
+        get: |c| c.latency_monitor_threshold.to_string(),
+        set: |c, v| {
+            c.latency_monitor_threshold = v.parse::<u64>().map_err(|_| "argument couldn't be parsed into an integer")?;
+            Ok(())
+        },
+    },
+];
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, &'static str> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'"),
+    }
+}
+
+// a byte count with an optional unit like redis takes them, k is 1000 and kb is 1024.
+pub fn parse_memory(value: &str) -> Option<u64> {
+    let value = value.to_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+// "seconds changes" pairs separated by spaces, empty turns snapshots off.
+fn parse_save_rules(value: &str) -> Option<Vec<(u64, u64)>> {
+    let numbers = value
+        .split_whitespace()
+        .map(|n| n.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+
+    if !numbers.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigInner {
+    compat_version: CompatVersion,
+    // the most keys a single KEYS call may reply with, 0 means unlimited.
+    keys_max_results: usize,
+    // the most connections that may be open at once, new ones are rejected past it.
+    maxclients: usize,
+    // the password of the default user, none leaves it nopass.
+    requirepass: Option<String>,
+    // whether a replica keeps answering reads while its link to the master is down.
+    replica_serve_stale_data: bool,
+    // lowercase names of the commands the public port refuses when an internal port is bound.
+    internal_commands: Vec<String>,
+    // where snapshots go and what they are called, the working directory by default.
+    dir: String,
+    dbfilename: String,
+    // nothing enforces these yet, there is no eviction or append only file. they are kept so
+    // clients can read back what they set.
+    maxmemory: u64,
+    maxmemory_policy: &'static str,
+    save: Vec<(u64, u64)>,
+    appendonly: bool,
+    appendfsync: &'static str,
+    // how many bytes of past writes the replication history keeps.
+    repl_backlog_size: u64,
+    // the milliseconds an event has to take to be recorded as a latency spike, 0 turns it off.
+    latency_monitor_threshold: u64,
+}
+
+impl Default for ConfigInner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigInner {
+    pub fn new() -> Self {
+        Self {
+            compat_version: CompatVersion::LATEST,
+            keys_max_results: 0,
+            maxclients: DEFAULT_MAXCLIENTS,
+            requirepass: None,
+            replica_serve_stale_data: true,
+            internal_commands: DEFAULT_INTERNAL_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            dir: std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| ".".to_string()),
+            dbfilename: DEFAULT_DBFILENAME.to_string(),
+            maxmemory: 0,
+            maxmemory_policy: "noeviction",
+            save: DEFAULT_SAVE_RULES.to_vec(),
+            appendonly: false,
+            appendfsync: "everysec",
+            repl_backlog_size: DEFAULT_REPL_BACKLOG_SIZE,
+            latency_monitor_threshold: 0,
+        }
+    }
+
+    pub fn repl_backlog_size(&self) -> u64 {
+        self.repl_backlog_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        patterns.iter().map(|p| Pattern::new(p.as_bytes(), true)).collect()
+    }
+
+    #[test]
+    fn test_get_matches_globs_once() {
+        let config = Config::new();
+        config.set_dbfilename("test.rdb".to_string());
+        assert_eq!(config.get_matching(&patterns(&["DBFILENAME"])), vec![("dbfilename", "test.rdb".to_string())]);
+
+        let matched: Vec<_> = config.get_matching(&patterns(&["max*", "maxmemory"])).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(matched, vec!["maxmemory", "maxmemory-policy", "maxclients"]);
+        assert!(config.get_matching(&patterns(&["nope"])).is_empty());
+    }
+
+    #[test]
+    fn test_set_is_all_or_nothing() {
+        let config = Config::new();
+        let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect::<Vec<_>>();
+
+        config.set_many(&pairs(&[("maxmemory", "1mb"), ("appendonly", "yes"), ("save", "")])).unwrap();
+        assert_eq!(config.get_maxmemory(), 1024 * 1024);
+        assert!(config.get_appendonly());
+        assert!(config.get_save_rules().is_empty());
+
+        let err = config.set_many(&pairs(&[("maxmemory", "2mb"), ("appendonly", "maybe")])).unwrap_err();
+        assert!(err.contains("'appendonly'"), "{}", err);
+        assert_eq!(config.get_maxmemory(), 1024 * 1024);
+
+        assert!(config.set_many(&pairs(&[("nope", "1")])).unwrap_err().starts_with("ERR Unknown option"));
+        assert!(config.set_many(&pairs(&[("save", "1 2"), ("SAVE", "3 4")])).unwrap_err().contains("duplicate"));
+        assert!(config.set_many(&pairs(&[("dbfilename", "a/b.rdb")])).is_err());
+    }
+
+    #[test]
+    fn test_maxmemory_policy() {
+        let config = Config::new();
+        let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect::<Vec<_>>();
+        assert_eq!(config.get_matching(&patterns(&["maxmemory-policy"])), vec![("maxmemory-policy", "noeviction".to_string())]);
+        assert!(!config.is_lfu_policy());
+
+        config.set_many(&pairs(&[("maxmemory-policy", "ALLKEYS-LFU")])).unwrap();
+        assert_eq!(config.get_matching(&patterns(&["maxmemory-policy"])), vec![("maxmemory-policy", "allkeys-lfu".to_string())]);
+        assert!(config.is_lfu_policy());
+
+        assert!(config.set_many(&pairs(&[("maxmemory-policy", "lfu")])).unwrap_err().contains("must be one of"));
+        config.set_many(&pairs(&[("maxmemory-policy", "volatile-lru")])).unwrap();
+        assert!(!config.is_lfu_policy());
+    }
+
+    #[test]
+    fn test_observers_see_changes_or_undo_them() {
+        use std::sync::Arc;
+        use std::sync::atomic::{ AtomicU64, Ordering };
+
+        let config = Config::new();
+        let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect::<Vec<_>>();
+        let (backlog, calls) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+
+        let (seen, counted) = (backlog.clone(), calls.clone());
+        config.observe("repl-backlog-size", Box::new(move |c| {
+            seen.store(c.repl_backlog_size(), Ordering::SeqCst);
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }));
+        // a subsystem that can't take the new value, its error goes to the client.
+        config.observe("maxmemory", Box::new(|c| match c.maxmemory {
+            0..=1000 => Ok(()),
+            _ => Err("too much".to_string()),
+        }));
+
+        config.set_many(&pairs(&[("keys-max-results", "5")])).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        config.set_many(&pairs(&[("repl-backlog-size", "1kb")])).unwrap();
+        assert_eq!(backlog.load(Ordering::SeqCst), 1024);
+
+        let err = config.set_many(&pairs(&[("repl-backlog-size", "2kb"), ("maxmemory", "1mb")])).unwrap_err();
+        assert_eq!(err, "ERR CONFIG SET failed (possibly related to argument 'maxmemory') - too much");
+        // the backlog observer already ran and is handed the old value back.
+        assert_eq!(backlog.load(Ordering::SeqCst), 1024);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(config.get_repl_backlog_size(), 1024);
+        assert_eq!(config.get_maxmemory(), 0);
+
+        assert!(config.set_many(&pairs(&[("repl-backlog-size", "0")])).unwrap_err().contains("greater than 0"));
+        assert!(config.set_many(&pairs(&[("appendfsync", "sometimes")])).unwrap_err().contains("always, everysec, no"));
+        config.set_many(&pairs(&[("appendfsync", "ALWAYS")])).unwrap();
+        assert_eq!(config.get_matching(&patterns(&["appendfsync"])), vec![("appendfsync", "always".to_string())]);
+    }
+
+    #[test]
+    fn test_compat_version_parse_and_compare() {
+        assert_eq!(CompatVersion::parse("6.2.14"), Some(CompatVersion::new(6, 2)));
+        assert_eq!(CompatVersion::parse("7"), Some(CompatVersion::new(7, 0)));
+        assert_eq!(CompatVersion::parse(""), None);
+        assert_eq!(CompatVersion::parse("seven"), None);
+        assert_eq!(CompatVersion::parse("6.x"), None);
+        assert_eq!(CompatVersion::parse("300.1"), None);
+
+        let version = CompatVersion::new(6, 2);
+        assert!(version.at_least(6, 2) && version.at_least(6, 0) && version.at_least(5, 9));
+        assert!(!version.at_least(6, 3) && !version.at_least(7, 0));
+        assert_eq!(version.to_string(), "6.2");
+    }
+
+    #[test]
+    fn test_parse_memory_units() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("1KB"), Some(1024));
+        assert_eq!(parse_memory("2gb"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+}
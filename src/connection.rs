@@ -1,8 +1,21 @@
-use crate::resp::{ Resp, RespParser, RespEncoder, ParseError };
+use crate::resp::{ Resp, RespParser, RespEncoder, ParseError, Frame };
+use crate::reply::{ Reply, Protocol };
+use crate::clients::{ ClientRegistration, KillSwitch };
+use crate::glob::{ Pattern, PatternCache };
+use crate::pubsub::{ Mailbox, Subscriber };
+use std::sync::Arc;
 use bytes::{ BytesMut, Buf };
-use tokio::net::{ TcpStream };
-use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::io::{ AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadBuf, ReadHalf, WriteHalf };
 use std::io::{ self, Cursor };
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+use std::fmt;
+
+// any duplex byte stream a connection can run on top of, i.e., a tcp socket in production
+// or an in memory pipe in tests.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
 
 #[derive(Debug)]
 pub enum Error {
@@ -23,18 +36,27 @@ impl From<io::Error> for Error {
 impl From<Error> for io::Error {
     fn from(e: Error) -> Self  {
         match e {
-            Error::ParseError(e) => { return io::Error::new(io::ErrorKind::InvalidInput, e) },
-            Error::ConnectionClosed => { return io::Error::new(io::ErrorKind::ConnectionAborted, "Connection Closed") },
-            Error::IoError(e) => { return e }
-            _ => { return io::Error::new(io::ErrorKind::Other, "Noop") }
+            Error::ParseError(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            Error::ConnectionClosed => io::Error::new(io::ErrorKind::ConnectionAborted, "Connection Closed"),
+            Error::IoError(e) => e,
+            _ => io::Error::other("Noop"),
         }
     }
 }
 
+// what CLIENT REPLY set, whether the commands a client sends get their replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyMode {
+    On,
+    Off,
+    // only the next command goes without a reply.
+    Skip,
+}
+
 #[derive(Debug)]
 pub struct Connection {
     // the stream to read an write to.
-    stream: TcpStream,
+    stream: Box<dyn Stream>,
     // a read buffer for incoming data.
     read_buf: Cursor<BytesMut>,
     // a write buffer for incoming data.
@@ -43,28 +65,55 @@ pub struct Connection {
     // replicas replies always be no-ops rather than actual data while maintaining the same exact 
     // implementation as the master server. 
     writable: bool,
-    readable: bool
+    readable: bool,
+    // the protocol version replies are encoded with for this client.
+    protocol: Protocol,
+    // whether AUTH or HELLO AUTH succeeded on this connection.
+    authenticated: bool,
+    // the client registry entry for this connection, it travels with the connection
+    // so a replica handed to the history stays visible in CLIENT LIST.
+    client: Option<ClientRegistration>,
+    // the glob patterns this client matched with lately, compiled.
+    patterns: PatternCache,
+    // the pub/sub channels this client subscribed to and the queue their messages arrive on,
+    // set by the session serving it.
+    subscriber: Option<Subscriber>,
+    mailbox: Option<Mailbox>,
+    // what CLIENT REPLY asked for, and whether the command running now is replied to. unlike
+    // closing the write half, muted writes succeed so nobody notices they went nowhere.
+    reply_mode: ReplyMode,
+    muted: bool,
 }
 
 impl Connection {
-    pub fn new(stream: TcpStream) -> Self {
+    pub fn new<S: Stream + 'static>(stream: S) -> Self {
         Self {
-            stream: stream,
+            stream: Box::new(stream),
             read_buf: Cursor::new(BytesMut::with_capacity(4 * 1024)),
             write_buf: BytesMut::with_capacity(4 * 1024),
             writable: true,
             readable: true,
+            protocol: Protocol::Resp2,
+            authenticated: false,
+            client: None,
+            patterns: PatternCache::default(),
+            subscriber: None,
+            mailbox: None,
+            reply_mode: ReplyMode::On,
+            muted: false,
         }
     }
 
+    // a connection with nothing behind it, reads see the end of the stream and writes fail.
+    // useful as a stand in while the real connection is moved somewhere else.
+    pub fn detached() -> Self {
+        Connection::new(Detached)
+    }
+
     // returns the resp decoded value and a number indication how large the original
     // message was.
     pub async fn read_message(&mut self) -> Result<(Resp, u64), Error> {
-        self.ensure_readable()?;
-
-        while !self.is_complete_message()? {
-            self.fill_buffer().await?;
-        }
+        self.fill_until_complete(false).await?;
 
         let start_pos = self.read_buf.position();
         let mut parser = RespParser::new(&mut self.read_buf);
@@ -72,6 +121,33 @@ impl Connection {
         Ok((res, self.read_buf.position() - start_pos))
     }
 
+    // like read_message but a streamed reply comes back one frame at a time rather than once
+    // all of it arrived.
+    pub async fn read_frame(&mut self) -> Result<Frame, Error> {
+        self.fill_until_complete(true).await?;
+
+        let mut parser = RespParser::new(&mut self.read_buf);
+        parser.parse_frame().map_err(Error::ParseError)
+    }
+
+    async fn fill_until_complete(&mut self, frame: bool) -> Result<(), Error> {
+        self.ensure_readable()?;
+
+        loop {
+            match self.is_complete_message(frame) {
+                Ok(true) => return Ok(()),
+                Ok(false) => self.fill_buffer().await?,
+                Err(Error::ParseError(e)) => {
+                    // the peer sent something that can never parse, tell it why and hang up
+                    // the same way redis does rather than trying to resynchronize.
+                    self.reject_protocol_error(&e).await;
+                    return Err(Error::ParseError(e));
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     pub async fn read_rdb(&mut self) -> Result<Vec<u8>, Error> {
         self.ensure_readable()?;
 
@@ -83,7 +159,7 @@ impl Connection {
     }
 
     async fn fill_buffer(&mut self) -> Result<(), Error> {
-        let nbytes = self.stream.read_buf(&mut self.read_buf.get_mut()).await?;
+        let nbytes = self.stream.read_buf(self.read_buf.get_mut()).await?;
         if nbytes == 0 {
             self.read_buf.get_mut().clear();
             self.read_buf.set_position(0);
@@ -92,6 +168,16 @@ impl Connection {
         Ok(())
     }
 
+    async fn reject_protocol_error(&mut self, e: &ParseError) {
+        if self.writable {
+            let reply = Reply::error(format!("ERR Protocol error: {}", e));
+            let _ = self.write_reply(reply).await;
+        }
+        let _ = self.stream.shutdown().await;
+        self.read_buf.get_mut().clear();
+        self.read_buf.set_position(0);
+    }
+
     fn ensure_readable(&self) -> Result<(), Error> {
         if !self.readable {
             Err(Error::NotReadable)
@@ -100,11 +186,12 @@ impl Connection {
         }
     }
 
-    fn is_complete_message(&mut self) -> Result<bool, Error> {
+    fn is_complete_message(&mut self, frame: bool) -> Result<bool, Error> {
         let mut parser = RespParser::new(&mut self.read_buf);
-        match parser.check() {
+        let checked = if frame { parser.check_frame().map(|_| ()) } else { parser.check().map(|_| ()) };
+        match checked {
             Ok(_) => Ok(true),
-            Err(ParseError::UnexpectedEndOfInput) => Ok(false),
+            Err(e) if e.is_incomplete() => Ok(false),
             Err(e) => Err(Error::ParseError(e)),
         }
     }
@@ -112,13 +199,14 @@ impl Connection {
     fn is_complete_rdb(&mut self) -> Result<bool, Error> {
         match self.try_parse_rdb() {
             Ok(_) => Ok(true),
-            Err(_) => Ok(false),
+            Err(Error::ParseError(e)) if e.is_incomplete() => Ok(false),
+            Err(e) => Err(e),
         }
     }
 
     // TEMPORARY UNTIL WE ADD BONEFIDE RDB PARSING
     fn parse_rdb(&mut self) -> Result<Vec<u8>, Error> {
-        let first = next_byte(&mut self.read_buf)?;
+        let _first = next_byte(&mut self.read_buf)?;
         let len_bytes = parse_until_crlf(&mut self.read_buf)?;
         let len_str = std::str::from_utf8(&len_bytes).map_err(|_| Error::ParseError(ParseError::InvalidByte))?;
         let len = len_str.parse::<usize>().map_err(|_| Error::ParseError(ParseError::InvalidByte))?;
@@ -129,22 +217,38 @@ impl Connection {
     // TEMPORARY UNTIL WE ADD BONEFIDE RDB PARSING
     fn try_parse_rdb(&mut self) -> Result<(), Error> {
         let start_pos = self.read_buf.position(); // get the current position.
-        let _ = self.parse_rdb()?;
+        let result = self.parse_rdb();
         self.read_buf.set_position(start_pos); // set it back
-        Ok(())
+        result.map(|_| ())
     }
 
     // takes a resp encoded value and writes it to the buffer...
     pub async fn write_message(&mut self, payload: &Resp) -> Result<(), Error> {
         if !self.writable { return Err(Error::NotWritable) }
+        if self.muted { return Ok(()) }
         RespEncoder::encode_resp(payload, &mut self.write_buf);
         let result = self.stream.write_all(&self.write_buf).await;
         self.write_buf.clear();
         Ok(result?)
     }
 
+    // converts a command reply into resp for this client's protocol version and writes it.
+    pub async fn write_reply(&mut self, reply: Reply) -> Result<(), Error> {
+        let payload = reply.into_resp(self.protocol);
+        self.write_message(&payload).await
+    }
+
+    // a reply the client gets whatever CLIENT REPLY says, i.e., published messages.
+    pub async fn write_push(&mut self, reply: Reply) -> Result<(), Error> {
+        let muted = std::mem::replace(&mut self.muted, false);
+        let result = self.write_reply(reply).await;
+        self.muted = muted;
+        result
+    }
+
     pub async fn write_str(&mut self, payload: &str) -> Result<(), Error> {
         if !self.writable { return Err(Error::NotWritable) }
+        if self.muted { return Ok(()) }
         RespEncoder::encode_simple_string(payload, &mut self.write_buf);
         let result = self.stream.write_all(&self.write_buf).await;
         self.write_buf.clear();
@@ -153,6 +257,7 @@ impl Connection {
 
     pub async fn write_err(&mut self, payload: &str) -> Result<(), Error> {
         if !self.writable { return Err(Error::NotWritable) }
+        if self.muted { return Ok(()) }
         RespEncoder::encode_simple_error(payload, &mut self.write_buf);
         let result = self.stream.write_all(&self.write_buf).await;
         self.write_buf.clear();
@@ -161,6 +266,7 @@ impl Connection {
 
     pub async fn write_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
         if !self.writable { return Err(Error::NotWritable) }
+        if self.muted { return Ok(()) }
         RespEncoder::encode_bulk_string(payload, &mut self.write_buf);
         let result = self.stream.write_all(&self.write_buf).await;
         self.write_buf.clear();
@@ -168,7 +274,7 @@ impl Connection {
     }
 
     pub fn write(&mut self, payload: &[u8]) {
-        if !self.writable { return; }
+        if !self.writable || self.muted { return; }
         self.write_buf.extend_from_slice(payload);
     }
 
@@ -179,6 +285,86 @@ impl Connection {
         Ok(())
     }
 
+    pub fn client(&self) -> Option<&ClientRegistration> {
+        self.client.as_ref()
+    }
+
+    pub fn set_client(&mut self, client: ClientRegistration) {
+        self.client = Some(client);
+    }
+
+    // what CLIENT KILL trips for this connection, none when it isn't a registered client.
+    pub fn kill_switch(&self) -> Option<Arc<KillSwitch>> {
+        self.client.as_ref().map(|client| client.kill_switch())
+    }
+
+    // pattern compiled, from the cache when this client used it before.
+    pub fn pattern(&mut self, pattern: &[u8]) -> Arc<Pattern> {
+        self.patterns.get(pattern, false)
+    }
+
+    pub fn subscriber_mut(&mut self) -> Option<&mut Subscriber> {
+        self.subscriber.as_mut()
+    }
+
+    pub fn set_subscriber(&mut self, subscriber: Subscriber, mailbox: Mailbox) {
+        self.subscriber = Some(subscriber);
+        self.mailbox = Some(mailbox);
+    }
+
+    // where the messages for this client's subscriptions arrive, for whoever is waiting on
+    // its behalf.
+    pub fn mailbox(&self) -> Option<Mailbox> {
+        self.mailbox.clone()
+    }
+
+    // whether the client subscribed to any channel, a resp2 client can only manage its
+    // subscriptions then.
+    pub fn is_subscribed(&self) -> bool {
+        self.subscriber.as_ref().is_some_and(|subscriber| subscriber.is_subscribed())
+    }
+
+    pub fn reply_mode(&self) -> ReplyMode {
+        self.reply_mode
+    }
+
+    // turning replies back on takes effect right away, so CLIENT REPLY ON gets its OK.
+    pub fn set_reply_mode(&mut self, mode: ReplyMode) {
+        self.reply_mode = mode;
+        if mode == ReplyMode::On {
+            self.muted = false;
+        }
+    }
+
+    // called before each command is handled, decides whether its replies go out. a skip only
+    // covers the one command after it.
+    pub fn start_command(&mut self) {
+        self.muted = match self.reply_mode {
+            ReplyMode::On => false,
+            ReplyMode::Off => true,
+            ReplyMode::Skip => {
+                self.reply_mode = ReplyMode::On;
+                true
+            },
+        };
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    pub fn set_authenticated(&mut self, authenticated: bool) {
+        self.authenticated = authenticated;
+    }
+
     pub fn close_write(&mut self) {
         self.writable = false;
     }
@@ -195,15 +381,163 @@ impl Connection {
         self.readable = true;
     }
 
-    pub fn take_stream(self) -> TcpStream {
+    // splits the connection so reads and writes can happen from different tasks, i.e., the history
+    // writes to a replica while its context reads the acks coming back. the client registration
+    // and anything waiting to be written stay with the write half.
+    pub fn split(self) -> (Connection, Connection) {
+        let (read_half, write_half) = tokio::io::split(self.stream);
+
+        let reader = Connection {
+            stream: Box::new(ReadOnly(read_half)),
+            read_buf: self.read_buf,
+            write_buf: BytesMut::new(),
+            writable: false,
+            readable: self.readable,
+            protocol: self.protocol,
+            authenticated: self.authenticated,
+            client: None,
+            patterns: self.patterns,
+            subscriber: self.subscriber,
+            mailbox: self.mailbox,
+            reply_mode: self.reply_mode,
+            muted: self.muted,
+        };
+
+        let writer = Connection {
+            stream: Box::new(WriteOnly(write_half)),
+            read_buf: Cursor::new(BytesMut::new()),
+            write_buf: self.write_buf,
+            writable: self.writable,
+            readable: false,
+            protocol: self.protocol,
+            authenticated: self.authenticated,
+            client: self.client,
+            patterns: PatternCache::default(),
+            subscriber: None,
+            mailbox: None,
+            reply_mode: ReplyMode::On,
+            muted: false,
+        };
+
+        (reader, writer)
+    }
+
+    pub fn take_stream(self) -> Box<dyn Stream> {
         self.stream
     }
 
-    pub fn borrow_stream(&mut self) -> &mut TcpStream {
+    pub fn borrow_stream(&mut self) -> &mut Box<dyn Stream> {
         &mut self.stream
     }
 }
 
+// the read half of a split connection, writing to it fails like a socket shut for writing.
+#[derive(Debug)]
+struct ReadOnly(ReadHalf<Box<dyn Stream>>);
+
+impl AsyncRead for ReadOnly {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ReadOnly {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// the write half of a split connection, reading from it looks like the peer hung up.
+#[derive(Debug)]
+struct WriteOnly(WriteHalf<Box<dyn Stream>>);
+
+impl AsyncRead for WriteOnly {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WriteOnly {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+// how much either end of a memory transport buffers before writes wait for the other side to
+// read, plenty for the replies tests look at.
+const MEMORY_TRANSPORT_CAPACITY: usize = 64 * 1024;
+
+// one end of an in memory pipe that stands in for a socket, what one end writes the other
+// reads in order. nothing is bound, so a whole server can be driven without picking a port.
+#[derive(Debug)]
+pub struct MemoryTransport(DuplexStream);
+
+impl MemoryTransport {
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let (a, b) = tokio::io::duplex(MEMORY_TRANSPORT_CAPACITY);
+        (MemoryTransport(a), MemoryTransport(b))
+    }
+}
+
+impl AsyncRead for MemoryTransport {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for MemoryTransport {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[derive(Debug)]
+struct Detached;
+
+impl AsyncRead for Detached {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Detached {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
 // Temporary helpers for reading an rdb file...
 fn next_byte(buf: &mut Cursor<BytesMut>) -> Result<u8, Error> {
     if buf.has_remaining() {
@@ -216,17 +550,15 @@ fn next_byte(buf: &mut Cursor<BytesMut>) -> Result<u8, Error> {
 fn parse_until_crlf(buf: &mut Cursor<BytesMut>) -> Result<Vec<u8>, Error> {
     let mut result = Vec::new();
     loop {
-        let byte = next_byte(buf)?;
-        if byte == b'\r' {
-            let next_byte = next_byte(buf)?;
-            if next_byte == b'\n' {
+        match next_byte(buf)? {
+            b'\r' => {
+                if next_byte(buf)? != b'\n' {
+                    return Err(Error::ParseError(ParseError::InvalidCrlf));
+                }
                 return Ok(result)
-            } else {
-                result.push(byte);
-                result.push(next_byte);
-            }
-        } else {
-            result.push(byte);
+            },
+            b'\n' => return Err(Error::ParseError(ParseError::InvalidCrlf)),
+            byte => result.push(byte),
         }
     }
 }
@@ -4,13 +4,30 @@ use crate::connection::Connection;
 use crate::database::Database; 
 use crate::history::History;
 use crate::server::ServerInfo;
-use crate::command::{CmdParser, Cmd, Command, Transaction};
+use crate::config::Config;
+use crate::clients::ClientRegistry;
+use crate::stats::Stats;
+use crate::pubsub::Broker;
+use crate::resp::Resp;
+use crate::session::{ Session, CommandFilter };
+use crate::listener::PortPolicy;
+use crate::reply::Protocol;
+use crate::acl;
+use crate::latency;
+
+// what a resp2 client may still run while subscribed to a channel.
+const SUBSCRIBED_COMMANDS: [&str; 8] = ["subscribe", "unsubscribe", "psubscribe", "punsubscribe", "ssubscribe", "sunsubscribe", "ping", "quit"];
 
 // this is a handler that can be passed around to simplify function signatures etc...
+#[derive(Clone)]
 pub struct Handle {
     pub database: Arc<Database>,
     pub history: Arc<History>,
-    pub info: Arc<ServerInfo>
+    pub info: Arc<ServerInfo>,
+    pub config: Arc<Config>,
+    pub clients: Arc<ClientRegistry>,
+    pub stats: Arc<Stats>,
+    pub broker: Arc<Broker>,
 }
 
 // The state of the request response cycle for each client request...
@@ -18,158 +35,570 @@ pub struct Context {
     pub stream: Connection, // the currently connected client.
     pub database: Arc<Database>, // database to alter if need be.
     pub history: Arc<History>, // struct for writing to replicas and recording transactions.
-    pub info: Arc<ServerInfo> // information about the current server running.
+    pub info: Arc<ServerInfo>, // information about the current server running.
+    pub config: Arc<Config>, // runtime configuration shared by all connections.
+    pub clients: Arc<ClientRegistry>, // every connected client, for CLIENT LIST and friends.
+    pub stats: Arc<Stats>, // server wide counters for INFO.
+    pub broker: Arc<Broker>, // the pub/sub channels every connection shares.
+    pub policy: PortPolicy, // what the port the client connected to lets it run.
 }
 
 impl Context {
-    pub fn new(stream: Connection, database: Arc<Database>, history: Arc<History>, info: Arc<ServerInfo>) -> Self {
+    pub fn new(stream: Connection, database: Arc<Database>, history: Arc<History>, info: Arc<ServerInfo>, config: Arc<Config>, clients: Arc<ClientRegistry>, stats: Arc<Stats>) -> Self {
         Context {
             stream,
             database,
             history,
-            info
+            info,
+            config,
+            clients,
+            stats,
+            broker: Arc::new(Broker::new()),
+            policy: PortPolicy::Open,
+        }
+    }
+
+    // shares broker with the other connections, a context has a broker of its own otherwise.
+    pub fn with_broker(mut self, broker: Arc<Broker>) -> Self {
+        self.broker = broker;
+        self
+    }
+
+    pub(crate) fn handle(&self) -> Handle {
+        Handle {
+            database: self.database.clone(),
+            history: self.history.clone(),
+            info: self.info.clone(),
+            config: self.config.clone(),
+            clients: self.clients.clone(),
+            stats: self.stats.clone(),
+            broker: self.broker.clone(),
+        }
+    }
+
+    // records the command against this connection's client entry.
+    pub(crate) fn touch_client(&self, message: &Resp) {
+        if let (Some(client), Some(name)) = (self.stream.client(), command_name(message)) {
+            client.touch(&name);
+        }
+    }
+
+    // whether the port this client connected to refuses the command, whatever the ACLs say.
+    pub(crate) fn refuses(&self, message: &Resp) -> Option<String> {
+        if self.policy != PortPolicy::Public {
+            return None;
         }
+        command_name(message)
+            .filter(|name| self.config.is_internal_command(name))
+            .map(|name| format!("ERR {} is only allowed on the internal port", name.to_uppercase()))
+    }
+
+    // a resp2 client that subscribed to a channel reads messages off the same connection it
+    // sends commands on, so it may only change its subscriptions until it unsubscribed from
+    // everything. resp3 clients can tell pushes from replies and run anything.
+    pub(crate) fn refuses_while_subscribed(&self, message: &Resp) -> Option<String> {
+        if self.stream.protocol() != Protocol::Resp2 || !self.stream.is_subscribed() {
+            return None;
+        }
+        command_name(message)
+            .filter(|name| !SUBSCRIBED_COMMANDS.contains(&name.to_lowercase().as_str()))
+            .map(|name| format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                name.to_lowercase(),
+            ))
     }
 
     // handle all commands with unlimited functionality.
     pub async fn handle_all(self) -> io::Result<()> {
-        if self.info.is_replica() {
-            self.replica_exec_all().await
+        let filter = if self.info.is_replica() {
+            CommandFilter::MasterLink
         } else {
-            self.master_exec_all().await
-        }
+            CommandFilter::Master
+        };
+        Session::new(self, filter).run().await
     }
     
+    // a connection from the reserve kept for operators once maxclients is reached.
+    pub async fn handle_reserved(self) -> io::Result<()> {
+        Session::new(self, CommandFilter::Reserved).run().await
+    }
+
     // only handle a limited command set for this client. 
     // this is used so the replica can receive and respond to certain commands without actually executing them.
     // i.e., you can get info on the replica, but only the connection to the master will allow write commands.
-    pub async fn handle_limited(mut self) -> io::Result<()> {
-        loop {
-            let (message, _msg_len) = self.stream.read_message().await?;
-            let cmd = CmdParser::parse(message.clone());
-
-            match cmd {
-                Cmd::Info(c) => {
-                    let handle = Handle {
-                        database: self.database.clone(),
-                        history: self.history.clone(),
-                        info: self.info.clone()
-                    };
-    
-                    c.execute(
-                        &mut self.stream, 
-                        handle
-                    ).await;
-    
-                    return Ok(());
-                },
-    
-                Cmd::Get(c) => {
-                    let handle = Handle {
-                        database: self.database.clone(),
-                        history: self.history.clone(),
-                        info: self.info.clone()
-                    };
-    
-                    c.execute(
-                        &mut self.stream, 
-                        handle
-                    ).await;
-                },
-    
-                _ => {
-                    self.stream.write_err("ERR direct messaging to replica not allowed").await?;
-                    // should error here but ok for now.
-                    return Ok(());
-                }
-            }
+    pub async fn handle_limited(self) -> io::Result<()> {
+        Session::new(self, CommandFilter::ReplicaClient).run().await
+    }
+}
+
+// the latency event a command's run time is recorded under. blocking commands are left out,
+// they are slow on purpose.
+pub(crate) fn latency_event(message: &Resp) -> Option<&'static str> {
+    let spec = command_name(message).and_then(|name| acl::lookup(&name))?;
+    if spec.categories.contains(&"blocking") {
+        return None;
+    }
+    match spec.flags.contains(&"fast") {
+        true => Some(latency::FAST_COMMAND),
+        false => Some(latency::COMMAND),
+    }
+}
+
+// whether the command table flags the message's command readonly, the reads a replica serves.
+pub(crate) fn is_read(message: &Resp) -> bool {
+    command_name(message)
+        .and_then(|name| acl::lookup(&name))
+        .is_some_and(|spec| spec.flags.contains(&"readonly"))
+}
+
+// the name a message was sent with, as the client spelled it.
+fn command_name(message: &Resp) -> Option<std::borrow::Cow<'_, str>> {
+    message
+        .as_slice()
+        .and_then(|args| args.first())
+        .and_then(|arg| arg.as_bytes())
+        .map(String::from_utf8_lossy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    // a replica context serving the master link on one end of a pipe, the other end plays the master.
+    fn replica_link() -> (Connection, Arc<Database>) {
+        let (master, replica) = duplex(64 * 1024);
+        let database = Arc::new(Database::new());
+        let info = Arc::new(ServerInfo::replica(("127.0.0.1".to_string(), "6379".to_string())));
+        info.set_master_repl_offset(0);
+
+        let ctx = Context::new(
+            Connection::new(replica),
+            database.clone(),
+            Arc::new(History::new()),
+            info,
+            Arc::new(Config::new()),
+            Arc::new(ClientRegistry::new()),
+            Arc::new(Stats::new()),
+        );
+        tokio::spawn(ctx.handle_all());
+        (Connection::new(master), database)
+    }
+
+    async fn getack(master: &mut Connection) -> Resp {
+        master.write_message(&Resp::command(&["REPLCONF", "GETACK", "*"])).await.unwrap();
+        master.read_message().await.unwrap().0
+    }
+
+    #[tokio::test]
+    async fn test_getack_offsets() {
+        let (mut master, database) = replica_link();
+
+        // nothing processed before the first GETACK.
+        assert_eq!(getack(&mut master).await, Resp::command(&["REPLCONF", "ACK", "0"]));
+
+        // the previous GETACK (37 bytes) and a PING (14 bytes).
+        master.write_message(&Resp::command(&["PING"])).await.unwrap();
+        assert_eq!(getack(&mut master).await, Resp::command(&["REPLCONF", "ACK", "51"]));
+
+        // the previous GETACK and two SETs (29 bytes each), all pipelined.
+        master.write_message(&Resp::command(&["SET", "foo", "1"])).await.unwrap();
+        master.write_message(&Resp::command(&["SET", "bar", "2"])).await.unwrap();
+        assert_eq!(getack(&mut master).await, Resp::command(&["REPLCONF", "ACK", "146"]));
+        assert_eq!(database.get(b"bar").unwrap().into_string().unwrap(), b"2");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_commands_count_without_a_reply() {
+        let (mut master, _) = replica_link();
+
+        // 21 bytes that fail to route, the master must not get an error back.
+        master.write_message(&Resp::command(&["NOPE", "x"])).await.unwrap();
+        assert_eq!(getack(&mut master).await, Resp::command(&["REPLCONF", "ACK", "21"]));
+    }
+
+    // a context on a replica serving one end of a pipe, with the filter handle_all or
+    // handle_limited would pick.
+    fn replica_session(info: &Arc<ServerInfo>, config: &Arc<Config>, link: bool) -> Connection {
+        let (client, server) = duplex(64 * 1024);
+        let ctx = Context::new(
+            Connection::new(server),
+            Arc::new(Database::new()),
+            Arc::new(History::new()),
+            info.clone(),
+            config.clone(),
+            Arc::new(ClientRegistry::new()),
+            Arc::new(Stats::new()),
+        );
+        if link {
+            tokio::spawn(ctx.handle_all());
+        } else {
+            tokio::spawn(ctx.handle_limited());
         }
+        Connection::new(client)
     }
 
-    async fn master_exec_all(mut self) -> io::Result<()> {
-        loop {
-            let (message, _) = self.stream.read_message().await?;
-            let cmd = CmdParser::parse(message.clone());
-    
-            match cmd {
-                Cmd::Unexpected(err_msg) => {
-                    self.stream.write_err(&format!("ERR {}", err_msg)).await?;
-                }
-    
-                valid_cmd => {
-                    let handle = Handle {
-                        database: self.database.clone(),
-                        history: self.history.clone(),
-                        info: self.info.clone()
-                    };
-                    
-                    let transaction = valid_cmd.execute(
-                        &mut self.stream, 
-                        handle
-                    ).await;
-
-                    match transaction {
-                        Transaction::Replicate => {
-                            // preserver this connection and move on.
-                            self.history.add_replica(self.stream).await;
-                            break Ok(());
-                        }
-
-                        Transaction::Write => {
-                            self.history.add_write(message).await;
-                        }
-
-                        _ => {}
-                    }
-                }
-            }
+    #[tokio::test]
+    async fn test_stale_reads_follow_the_master_link() {
+        let info = Arc::new(ServerInfo::replica(("127.0.0.1".to_string(), "6379".to_string())));
+        let config = Arc::new(Config::new());
+        config.set_replica_serve_stale_data(false);
+        let mut client = replica_session(&info, &config, false);
+        let masterdown = Resp::SimpleError("MASTERDOWN Link with MASTER is down and replica-serve-stale-data is set to 'no'.".to_string());
+
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, masterdown);
+        client.write_message(&Resp::command(&["INFO", "replication"])).await.unwrap();
+        let reply = client.read_message().await.unwrap().0;
+        assert!(String::from_utf8_lossy(reply.as_bytes().unwrap()).contains("master_link_status:down\r\n"));
+
+        // reads are served again while the link is up.
+        let master = replica_session(&info, &config, true);
+        while !info.is_master_link_up() {
+            tokio::task::yield_now().await;
+        }
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::BulkStringNull);
+
+        // and refused once the master hangs up, unless stale data may be served.
+        drop(master);
+        while info.is_master_link_up() {
+            tokio::task::yield_now().await;
         }
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, masterdown);
+
+        config.set_replica_serve_stale_data(true);
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::BulkStringNull);
     }
 
-    async fn replica_exec_all(mut self) -> io::Result<()> {
-        loop {
-            let (message, msg_len) = self.stream.read_message().await?;
-            let cmd = CmdParser::parse(message.clone());
-      
-            match cmd {
-                Cmd::Unexpected(err_msg) => {
-                    self.stream.write_err(&format!("ERR {}", err_msg)).await?;
-                }
-    
-                Cmd::ReplConf(c) => {
-                    let handle = Handle {
-                        database: self.database.clone(),
-                        history: self.history.clone(),
-                        info: self.info.clone()
-                    };
-    
-                    c.execute(
-                        &mut self.stream, 
-                        handle
-                    ).await;
-                }
-    
-                valid_cmd => {
-                    let handle = Handle {
-                        database: self.database.clone(),
-                        history: self.history.clone(),
-                        info: self.info.clone()
-                    };
-
-                    self.stream.close_write(); // close the write end of the stream. no need to send back messages right now.
-                    
-                    let _ = valid_cmd.execute(
-                        &mut self.stream, 
-                        handle
-                    ).await;
-
-                    self.stream.open_write(); // open the write end of the stream again.
-                }
-            }
-
-            // after the command is processed update the offset we're at.
-            // in the error case need to decide if this should be incremented or not?
-            self.info.incr_master_repl_offset(msg_len as i64);
+    // a master context serving one end of a pipe, everything shares the history and info given.
+    fn master_link(history: &Arc<History>, info: &Arc<ServerInfo>) -> Connection {
+        master_link_with_config(history, info, Arc::new(Config::new()))
+    }
+
+    fn master_link_with_config(history: &Arc<History>, info: &Arc<ServerInfo>, config: Arc<Config>) -> Connection {
+        let (client, server) = duplex(64 * 1024);
+        let ctx = Context::new(
+            Connection::new(server),
+            Arc::new(Database::new()),
+            history.clone(),
+            info.clone(),
+            config,
+            Arc::new(ClientRegistry::new()),
+            Arc::new(Stats::new()),
+        );
+        tokio::spawn(ctx.handle_all());
+        Connection::new(client)
+    }
+
+    #[tokio::test]
+    async fn test_no_ops_leave_the_replication_offset() {
+        let history = Arc::new(History::new());
+        let info = Arc::new(ServerInfo::master());
+        let mut client = master_link(&history, &info);
+
+        client.write_message(&Resp::command(&["SET", "foo", "1", "PX", "1"])).await.unwrap();
+        client.read_message().await.unwrap();
+        let offset = history.offset().await;
+        assert!(offset > 0);
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        for args in [&["SET", "bar", "1", "XX"][..], &["DEL", "bar"], &["GET", "foo"], &["DEL", "foo"]] {
+            client.write_message(&Resp::command(args)).await.unwrap();
+            client.read_message().await.unwrap();
+        }
+        assert_eq!(history.offset().await, offset);
+        assert_eq!(info.get_master_repl_offset(), offset as i64);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_lengths_close_the_connection() {
+        use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+
+        for header in [&b"*9223372036854775807\r\n"[..], b"*1\r\n$536870913\r\n"] {
+            let (mut client, server) = duplex(64 * 1024);
+            let ctx = Context::new(
+                Connection::new(server),
+                Arc::new(Database::new()),
+                Arc::new(History::new()),
+                Arc::new(ServerInfo::master()),
+                Arc::new(Config::new()),
+                Arc::new(ClientRegistry::new()),
+                Arc::new(Stats::new()),
+            );
+            let session = tokio::spawn(ctx.handle_all());
+
+            client.write_all(header).await.unwrap();
+            let mut reply = Vec::new();
+            client.read_to_end(&mut reply).await.unwrap();
+            assert_eq!(reply, b"-ERR Protocol error: Invalid length\r\n");
+            assert!(session.await.unwrap().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replica_acks_update_lag() {
+        let history = Arc::new(History::new());
+        let info = Arc::new(ServerInfo::master());
+        let mut replica = master_link(&history, &info);
+        let mut client = master_link(&history, &info);
+
+        replica.write_message(&Resp::command(&["REPLCONF", "listening-port", "6380"])).await.unwrap();
+        replica.read_message().await.unwrap();
+        replica.write_message(&Resp::command(&["REPLCONF", "capa", "psync2"])).await.unwrap();
+        replica.read_message().await.unwrap();
+        replica.write_message(&Resp::command(&["PSYNC", "?", "-1"])).await.unwrap();
+        replica.read_message().await.unwrap();
+        replica.read_rdb().await.unwrap();
+
+        client.write_message(&Resp::command(&["SET", "foo", "1"])).await.unwrap();
+        client.read_message().await.unwrap();
+        assert_eq!(replica.read_message().await.unwrap().0, Resp::command(&["SET", "foo", "1"]));
+        assert_eq!(info.get_master_repl_offset(), 29);
+        assert_eq!(history.replicas().await[0].lag_bytes, 29);
+
+        replica.write_message(&Resp::command(&["REPLCONF", "ACK", "29"])).await.unwrap();
+        while history.replicas().await[0].lag_bytes != 0 {
+            tokio::task::yield_now().await;
+        }
+
+        client.write_message(&Resp::command(&["INFO", "replication"])).await.unwrap();
+        let reply = client.read_message().await.unwrap().0;
+        let reply = String::from_utf8(reply.as_bytes().unwrap().to_vec()).unwrap();
+        assert!(reply.contains("connected_slaves:1\r\n"));
+        assert!(reply.contains(",offset=29,lag=0,lag_bytes=0\r\n"));
+        assert!(reply.contains("repl_backlog_first_byte_offset:1\r\nrepl_backlog_histlen:29\r\n"));
+
+        // the replica goes away once its link closes.
+        drop(replica);
+        while !history.replicas().await.is_empty() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    // the proto field of a HELLO reply, in either encoding.
+    fn hello_proto(reply: Resp) -> Resp {
+        let pairs: Vec<(Resp, Resp)> = match reply {
+            Resp::Map(pairs) => pairs,
+            Resp::Array(items) => items.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect(),
+            other => panic!("unexpected HELLO reply {:?}", other),
+        };
+        pairs.into_iter().find(|(k, _)| *k == Resp::BulkString(b"proto".to_vec())).unwrap().1
+    }
+
+    #[tokio::test]
+    async fn test_hello_handshakes() {
+        let history = Arc::new(History::new());
+        let info = Arc::new(ServerInfo::master());
+
+        // redis-py with protocol=3.
+        let mut client = master_link(&history, &info);
+        client.write_message(&Resp::command(&["HELLO", "3"])).await.unwrap();
+        let reply = client.read_message().await.unwrap().0;
+        assert!(matches!(reply, Resp::Map(_)));
+        assert_eq!(hello_proto(reply), Resp::Integer(3));
+
+        // fred authenticates and names the connection in the same HELLO.
+        let mut client = master_link(&history, &info);
+        client.write_message(&Resp::command(&["HELLO", "3", "AUTH", "default", "secret", "SETNAME", "fred"])).await.unwrap();
+        assert_eq!(hello_proto(client.read_message().await.unwrap().0), Resp::Integer(3));
+
+        // and HELLO 2 switches back down.
+        client.write_message(&Resp::command(&["HELLO", "2"])).await.unwrap();
+        let reply = client.read_message().await.unwrap().0;
+        assert!(matches!(reply, Resp::Array(_)));
+        assert_eq!(hello_proto(reply), Resp::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_hello_errors_keep_the_protocol() {
+        let history = Arc::new(History::new());
+        let info = Arc::new(ServerInfo::master());
+        let mut client = master_link(&history, &info);
+
+        let cases: [(&[&str], &str); 5] = [
+            (&["HELLO", "4"], "NOPROTO unsupported protocol version"),
+            (&["HELLO", "1"], "NOPROTO unsupported protocol version"),
+            (&["HELLO", "three"], "ERR Protocol version is not an integer or out of range"),
+            (&["HELLO", "3", "FOO"], "ERR Syntax error in HELLO option 'FOO'"),
+            (&["HELLO", "3", "AUTH", "default"], "ERR Syntax error in HELLO option 'AUTH'"),
+        ];
+
+        for (args, error) in cases {
+            client.write_message(&Resp::command(args)).await.unwrap();
+            assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleError(error.to_string()));
+        }
+
+        client.write_message(&Resp::command(&["HELLO", "3", "AUTH", "someone", "secret"])).await.unwrap();
+        assert!(matches!(client.read_message().await.unwrap().0, Resp::SimpleError(e) if e.starts_with("WRONGPASS")));
+
+        // none of the failed attempts left resp2.
+        client.write_message(&Resp::command(&["HELLO"])).await.unwrap();
+        assert_eq!(hello_proto(client.read_message().await.unwrap().0), Resp::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_requirepass_gates_commands() {
+        let history = Arc::new(History::new());
+        let info = Arc::new(ServerInfo::master());
+        let config = Arc::new(Config::new());
+        config.set_requirepass(Some("secret".to_string()));
+
+        let mut client = master_link_with_config(&history, &info, config.clone());
+        let cases: [(&[&str], Resp); 5] = [
+            (&["SET", "foo", "1"], Resp::SimpleError("NOAUTH Authentication required.".to_string())),
+            (&["AUTH", "wrong"], Resp::SimpleError("WRONGPASS invalid username-password pair or user is disabled.".to_string())),
+            (&["AUTH", "someone", "secret"], Resp::SimpleError("WRONGPASS invalid username-password pair or user is disabled.".to_string())),
+            (&["AUTH", "secret"], Resp::SimpleString("OK".to_string())),
+            (&["SET", "foo", "1"], Resp::SimpleString("OK".to_string())),
+        ];
+
+        for (args, reply) in cases {
+            client.write_message(&Resp::command(args)).await.unwrap();
+            assert_eq!(client.read_message().await.unwrap().0, reply);
         }
+
+        // HELLO only runs unauthenticated when it carries the password itself.
+        let mut client = master_link_with_config(&history, &info, config);
+        client.write_message(&Resp::command(&["HELLO", "3"])).await.unwrap();
+        assert!(matches!(client.read_message().await.unwrap().0, Resp::SimpleError(e) if e.starts_with("NOAUTH")));
+        client.write_message(&Resp::command(&["HELLO", "3", "AUTH", "default", "secret"])).await.unwrap();
+        assert_eq!(hello_proto(client.read_message().await.unwrap().0), Resp::Integer(3));
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::Null);
+    }
+
+    #[test]
+    fn test_latency_events_by_command() {
+        assert_eq!(latency_event(&Resp::command(&["get", "foo"])), Some(latency::FAST_COMMAND));
+        assert_eq!(latency_event(&Resp::command(&["KEYS", "*"])), Some(latency::COMMAND));
+        assert_eq!(latency_event(&Resp::command(&["BLPOP", "foo", "0"])), None);
+        assert_eq!(latency_event(&Resp::command(&["NOPE"])), None);
+    }
+
+    // a master context serving one end of a pipe, every client made with the same broker
+    // hears what the others publish.
+    fn pubsub_client(broker: &Arc<Broker>) -> Connection {
+        let (client, server) = duplex(64 * 1024);
+        let ctx = Context::new(
+            Connection::new(server),
+            Arc::new(Database::new()),
+            Arc::new(History::new()),
+            Arc::new(ServerInfo::master()),
+            Arc::new(Config::new()),
+            Arc::new(ClientRegistry::new()),
+            Arc::new(Stats::new()),
+        )
+        .with_broker(broker.clone());
+        tokio::spawn(ctx.handle_all());
+        Connection::new(client)
+    }
+
+    fn push(kind: &str, channel: &str, last: Resp) -> Resp {
+        Resp::Array(vec![Resp::BulkString(kind.as_bytes().to_vec()), Resp::BulkString(channel.as_bytes().to_vec()), last])
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_subscribers_receive_published_messages() {
+        let broker = Arc::new(Broker::new());
+        let mut subscriber = pubsub_client(&broker);
+        let mut publisher = pubsub_client(&broker);
+
+        subscriber.write_message(&Resp::command(&["SUBSCRIBE", "news", "sport"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("subscribe", "news", Resp::Integer(1)));
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("subscribe", "sport", Resp::Integer(2)));
+
+        publisher.write_message(&Resp::command(&["PUBLISH", "news", "hello"])).await.unwrap();
+        assert_eq!(publisher.read_message().await.unwrap().0, Resp::Integer(1));
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("message", "news", Resp::BulkString(b"hello".to_vec())));
+
+        // a pattern hears the same message again, tagged with the pattern it matched.
+        subscriber.write_message(&Resp::command(&["PSUBSCRIBE", "n*"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("psubscribe", "n*", Resp::Integer(3)));
+        publisher.write_message(&Resp::command(&["PUBLISH", "news", "again"])).await.unwrap();
+        assert_eq!(publisher.read_message().await.unwrap().0, Resp::Integer(2));
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("message", "news", Resp::BulkString(b"again".to_vec())));
+        assert_eq!(subscriber.read_message().await.unwrap().0, Resp::command(&["pmessage", "n*", "news", "again"]));
+        subscriber.write_message(&Resp::command(&["PUNSUBSCRIBE"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("punsubscribe", "n*", Resp::Integer(2)));
+
+        // a subscribed resp2 client can only manage its subscriptions.
+        subscriber.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert!(matches!(subscriber.read_message().await.unwrap().0, Resp::SimpleError(e) if e.starts_with("ERR Can't execute 'get'")));
+        subscriber.write_message(&Resp::command(&["PING"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, Resp::command(&["pong", ""]));
+
+        subscriber.write_message(&Resp::command(&["UNSUBSCRIBE"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("unsubscribe", "news", Resp::Integer(1)));
+        assert_eq!(subscriber.read_message().await.unwrap().0, push("unsubscribe", "sport", Resp::Integer(0)));
+        subscriber.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(subscriber.read_message().await.unwrap().0, Resp::BulkStringNull);
+
+        publisher.write_message(&Resp::command(&["PUBLISH", "news", "anyone?"])).await.unwrap();
+        assert_eq!(publisher.read_message().await.unwrap().0, Resp::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_resp3_subscribers_keep_running_commands() {
+        let broker = Arc::new(Broker::new());
+        let mut client = pubsub_client(&broker);
+        let mut publisher = pubsub_client(&broker);
+        let message = Resp::Push(["message", "news", "hello"].iter().map(|s| Resp::BulkString(s.as_bytes().to_vec())).collect());
+
+        client.write_message(&Resp::command(&["HELLO", "3"])).await.unwrap();
+        client.read_message().await.unwrap();
+        client.write_message(&Resp::command(&["SUBSCRIBE", "news"])).await.unwrap();
+        assert!(client.read_message().await.unwrap().0.is_push());
+
+        // pushes can't be mistaken for replies in resp3, so anything runs and PING answers as usual.
+        client.write_message(&Resp::command(&["SET", "foo", "1"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        client.write_message(&Resp::command(&["PING"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleString("PONG".to_string()));
+
+        // a message published while the client is blocked arrives right away, the reply after it.
+        client.write_message(&Resp::command(&["BLPOP", "queue", "0.5"])).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        publisher.write_message(&Resp::command(&["PUBLISH", "news", "hello"])).await.unwrap();
+        assert_eq!(publisher.read_message().await.unwrap().0, Resp::Integer(1));
+        let pushed = tokio::time::timeout(std::time::Duration::from_millis(250), client.read_message()).await;
+        assert_eq!(pushed.unwrap().unwrap().0, message);
+        assert_eq!(client.read_message().await.unwrap().0, Resp::Null);
+    }
+
+    #[tokio::test]
+    async fn test_client_reply_modes() {
+        let broker = Arc::new(Broker::new());
+        let mut client = pubsub_client(&broker);
+
+        // nothing comes back for the skip or the command after it.
+        client.write_message(&Resp::command(&["CLIENT", "REPLY", "SKIP"])).await.unwrap();
+        client.write_message(&Resp::command(&["SET", "foo", "1"])).await.unwrap();
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::BulkString(b"1".to_vec()));
+
+        // errors are swallowed too while replies are off, published messages still arrive. a
+        // resp2 subscriber couldn't send CLIENT REPLY at all.
+        client.write_message(&Resp::command(&["HELLO", "3"])).await.unwrap();
+        client.read_message().await.unwrap();
+        client.write_message(&Resp::command(&["SUBSCRIBE", "news"])).await.unwrap();
+        assert!(client.read_message().await.unwrap().0.is_push());
+        client.write_message(&Resp::command(&["CLIENT", "REPLY", "OFF"])).await.unwrap();
+        client.write_message(&Resp::command(&["NOPE"])).await.unwrap();
+        client.write_message(&Resp::command(&["SET", "foo", "2"])).await.unwrap();
+        client.write_message(&Resp::command(&["CLIENT", "REPLY", "SKIP"])).await.unwrap();
+
+        let mut publisher = pubsub_client(&broker);
+        publisher.write_message(&Resp::command(&["PUBLISH", "news", "hello"])).await.unwrap();
+        assert_eq!(publisher.read_message().await.unwrap().0, Resp::Integer(1));
+        let message = ["message", "news", "hello"].iter().map(|s| Resp::BulkString(s.as_bytes().to_vec())).collect();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::Push(message));
+
+        client.write_message(&Resp::command(&["CLIENT", "REPLY", "ON"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        client.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::BulkString(b"2".to_vec()));
+    }
+}
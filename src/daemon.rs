@@ -0,0 +1,163 @@
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+// where the pid is written when daemonizing without a pidfile, the same default redis uses.
+pub const DEFAULT_PIDFILE: &str = "/var/run/redis.pid";
+
+// the pid of the running server, written on start and removed again when the server shuts
+// down gracefully so init scripts can tell a stale file from a running server.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<PidFile> {
+        let path = path.as_ref().to_path_buf();
+        write_atomically(&path, format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(PidFile { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// replaces path with contents so readers see either the old file or the whole new one, never
+// a partial write. the data is synced before the rename and, where directories can be synced,
+// the rename is synced too. rename replaces an existing file on windows as well.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let result = fs::File::create(&temp)
+        .and_then(|mut file| {
+            io::Write::write_all(&mut file, contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+
+    sync_parent(path);
+    Ok(())
+}
+
+// a directory is opened like a file on unix and can be synced, windows has no such thing and
+// persists the rename with the file's metadata.
+#[cfg(unix)]
+fn sync_parent(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = fs::File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) {}
+
+// detaches from the terminal: the parent exits, the child becomes a session leader and its
+// stdio goes to /dev/null. this has to run before the tokio runtime starts any threads, only
+// the calling thread survives a fork.
+#[cfg(unix)]
+pub fn daemonize() -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    extern "C" {
+        fn fork() -> i32;
+        fn setsid() -> i32;
+        fn dup2(old: i32, new: i32) -> i32;
+    }
+
+    // safety: called before any other thread exists, so the child has everything the parent had.
+    match unsafe { fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {},
+        _ => std::process::exit(0),
+    }
+
+    if unsafe { setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let null = fs::OpenOptions::new().read(true).write(true).open("/dev/null")?;
+    for fd in 0..=2 {
+        if unsafe { dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// there is nothing to detach from without fork, the server keeps running in the foreground
+// and a service manager is expected to background it.
+#[cfg(not(unix))]
+pub fn daemonize() -> io::Result<()> {
+    println!("daemonize is only supported on unix, running in the foreground");
+    Ok(())
+}
+
+// resolves once the process is asked to stop, i.e., ctrl-c or the SIGTERM init scripts send.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{ signal, SignalKind };
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            },
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pidfile_is_removed_on_drop() {
+        let path = std::env::temp_dir().join(format!("redis-clone-test-{}.pid", std::process::id()));
+        let pidfile = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(pidfile.path()).unwrap(), format!("{}\n", std::process::id()));
+
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_write_atomically_replaces_the_file() {
+        let path = std::env::temp_dir().join(format!("redis-clone-atomic-{}", std::process::id()));
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        assert!(!PathBuf::from(temp).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
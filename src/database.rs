@@ -1,68 +1,4165 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{ BTreeMap, BTreeSet, HashMap, HashSet, VecDeque };
+use std::ops::Bound;
+use std::fmt::Display;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState};
 use crate::resp::{Resp};
-use std::sync::{ RwLock };
-use std::time::{Instant, Duration};
+use crate::glob::Pattern;
+use crate::lfu::{ LfuCounter, LfuPolicy };
+use crate::blocking::KeyWaiters;
+use crate::setops::{ self, Aggregate, Members, SetOp };
+use crate::bitops::{ self, BitOp, BitUnit };
+use crate::lzf;
+use crate::hyperloglog::{ self, HllError };
+use crate::skiplist::SkipList;
+use std::sync::{ Arc, RwLock };
+use std::sync::atomic::{ AtomicBool, AtomicU64, AtomicUsize, Ordering };
+use tokio::sync::Notify;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
-pub struct Record {
-    pub data: Vec<u8>,
-    expiry: Option<(Instant, Duration)>,
+// the length in bytes of a dataset or value digest, matches the 40 hex chars redis replies with.
+pub const DIGEST_LEN: usize = 20;
+
+// the largest string value a command may build, mirrors the default proto-max-bulk-len.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+// the logical databases SELECT and SWAPDB index into, only db 0 exists so far.
+pub const DATABASES: usize = 1;
+
+// how many expired keys one pass of active expiration removes before giving the lock back.
+const EXPIRE_BATCH: usize = 256;
+
+// the value was not of the type the command operates on, replied to as WRONGTYPE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongType;
+
+// why a string couldn't be appended to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringError {
+    WrongType,
+    // the result would be longer than MAX_STRING_LEN.
+    TooLarge,
 }
 
-impl Record {
-    // converts from a parsed Resp to a Record for storage
-    // client always sends a bulk string, so we can safely assume that
-    pub fn from_resp(resp: Resp) -> Option<Record> {
-        match resp {
-            Resp::BulkString(b) => Some(Record { data: b, expiry: None }),
-            _ => None,
+impl From<WrongType> for StringError {
+    fn from(_: WrongType) -> Self {
+        StringError::WrongType
+    }
+}
+
+// a score ordered the way redis orders them, totally so it can key an ordered index. -0 and 0
+// compare equal like they do in redis, scores are never nan.
+#[derive(Clone, Copy, Debug)]
+pub struct Score(pub f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or_else(|| self.0.total_cmp(&other.0))
+    }
+}
+
+// a member of a sorted set and its score.
+pub type MemberScore = (Vec<u8>, f64);
+
+// one end of a score range, ( in front of the score makes it exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+// one end of a lex range, - and + are below and above every member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    // whether member is on or above this bound used as the minimum.
+    fn admits_from(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(bound) => member >= bound.as_slice(),
+            LexBound::Exclusive(bound) => member > bound.as_slice(),
         }
     }
 
-    pub fn from_vec(v: Vec<u8>) -> Record {
-        Record { data: v, expiry: None }
+    // whether member is on or below this bound used as the maximum.
+    fn admits_to(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member <= bound.as_slice(),
+            LexBound::Exclusive(bound) => member < bound.as_slice(),
+        }
     }
+}
 
-    pub fn set_expiry(&mut self, duration: Duration) {
-        self.expiry = Some((Instant::now(), duration));
+// what a ZRANGE selects members by, ranks, scores or, when all scores are equal, members.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZrangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+// the members of a sorted set and their scores. the map answers score lookups, the skip list
+// keeps the members ordered by score and then member and knows the rank of each of them.
+#[derive(Clone, Debug, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    order: SkipList,
+}
+
+// the order is built from the scores, so two sets with the same scores are equal whatever
+// shape their skip lists grew into.
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
     }
+}
 
-    pub fn has_expired(&self) -> bool {
-        if let Some((start, duration)) = self.expiry {
-            start.elapsed() >= duration
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.scores.capacity()
+    }
+
+    // sets the score of member, returns the score it had before if it was already there.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> Option<f64> {
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.order.remove(previous, &member);
+        }
+        self.order.insert(score, member);
+        previous
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.scores.contains_key(member)
+    }
+
+    // the position of member counted from the lowest score, or the highest when rev, and its
+    // score.
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<(usize, f64)> {
+        let score = self.score(member)?;
+        let below = self.order.count_before(score, member);
+        let rank = if rev { self.len() - 1 - below } else { below };
+        Some((rank, score))
+    }
+
+    // every member and its score, lowest score first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Vec<u8>, f64)> {
+        self.order.iter()
+    }
+
+    // the members from rank start to stop inclusive, counted from the highest score when rev.
+    pub fn range(&self, start: usize, stop: usize, rev: bool) -> Vec<MemberScore> {
+        let owned = |(member, score): (&Vec<u8>, f64)| (member.clone(), score);
+        if rev {
+            let len = self.len();
+            self.order.slice(len - 1 - stop, len - start).rev().map(owned).collect()
         } else {
-            false
+            self.order.slice(start, stop + 1).map(owned).collect()
         }
     }
+
+    // the members scored between min and max, lowest score first. the list is ordered by score
+    // and then member, so an empty member sorts before every member with the same score.
+    pub fn by_score(&self, min: ScoreBound, max: ScoreBound) -> impl DoubleEndedIterator<Item = (&Vec<u8>, f64)> {
+        let start = match min {
+            ScoreBound::Inclusive(score) => self.order.count_before(score, b""),
+            ScoreBound::Exclusive(score) if score == f64::INFINITY => self.len(),
+            ScoreBound::Exclusive(score) => self.order.count_before(score.next_up(), b""),
+        };
+        let end = match max {
+            ScoreBound::Inclusive(score) if score == f64::INFINITY => self.len(),
+            ScoreBound::Inclusive(score) => self.order.count_before(score.next_up(), b""),
+            ScoreBound::Exclusive(score) => self.order.count_before(score, b""),
+        };
+        self.order.slice(start, end)
+    }
+
+    // the members between min and max, which only means something when all scores are equal.
+    pub fn by_lex<'a>(&'a self, min: &'a LexBound, max: &'a LexBound) -> impl Iterator<Item = (&'a Vec<u8>, f64)> + 'a {
+        self.iter()
+            .skip_while(move |(member, _)| !min.admits_from(member))
+            .take_while(move |(member, _)| max.admits_to(member))
+    }
 }
 
-#[derive(Debug)]
-pub struct Database {
-    // (key, value)
-    store: RwLock<HashMap<Vec<u8>, Record>>,
+// the conditions ZADD puts on its updates. nx only adds and xx only updates, gt and lt only
+// update when the new score is greater or less than the current one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ZaddFlags {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
 }
 
+// the conditions SET puts on its write, checked under the same lock as the write itself. nx
+// only writes a missing key and xx only an existing one, keep_ttl carries the old deadline over
+// and get asks for the string the key held before.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SetFlags {
+    pub nx: bool,
+    pub xx: bool,
+    pub keep_ttl: bool,
+    pub get: bool,
+}
 
-impl Database {
-    pub fn new() -> Self {
-        Database {
-            store: RwLock::new(HashMap::new()),
+// the fields of a hash and their values. since redis 7.4 a field can have a deadline of its
+// own, after which it reads as missing until it is removed. the deadlines are also kept
+// soonest first so the expired fields are found without walking the whole hash.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HashFields {
+    values: HashMap<Vec<u8>, Vec<u8>>,
+    deadlines: HashMap<Vec<u8>, SystemTime>,
+    // (deadline, field) for every field with a ttl.
+    expiring: BTreeSet<(SystemTime, Vec<u8>)>,
+}
+
+impl HashFields {
+    // the fields that haven't expired.
+    pub fn len(&self) -> usize {
+        let now = SystemTime::now();
+        self.values.len() - self.expiring.iter().take_while(|(deadline, _)| *deadline <= now).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        self.values.get(field).filter(|_| !self.has_expired(field))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.values.iter().filter(|(field, _)| !self.has_expired(field))
+    }
+
+    // sets field to value and returns the value it had. like in redis writing a field drops
+    // its ttl.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        let expired = self.has_expired(&field);
+        self.set_deadline(&field, None);
+        self.values.insert(field, value).filter(|_| !expired)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        let expired = self.has_expired(field);
+        self.set_deadline(field, None);
+        self.values.remove(field).filter(|_| !expired)
+    }
+
+    // when field expires, none if it has no ttl.
+    pub fn deadline(&self, field: &[u8]) -> Option<SystemTime> {
+        self.deadlines.get(field).copied()
+    }
+
+    // replaces the deadline of field, returns the one it had.
+    pub fn set_deadline(&mut self, field: &[u8], deadline: Option<SystemTime>) -> Option<SystemTime> {
+        let previous = match deadline {
+            Some(deadline) => self.deadlines.insert(field.to_vec(), deadline),
+            None => self.deadlines.remove(field),
+        };
+
+        if let Some(previous) = previous {
+            self.expiring.remove(&(previous, field.to_vec()));
         }
+        if let Some(deadline) = deadline {
+            self.expiring.insert((deadline, field.to_vec()));
+        }
+        previous
     }
 
-    pub fn set(&self, key: Vec<u8>, value: Record) -> Option<Record> {
-        self.store.write().unwrap().insert(key, value)
+    // the soonest deadline of any field.
+    pub fn next_deadline(&self) -> Option<SystemTime> {
+        self.expiring.first().map(|(deadline, _)| *deadline)
     }
 
-    pub fn get(&self, key: &[u8]) -> Option<Record> {
-        self.store.read().unwrap().get(key).cloned()
+    // removes every field whose deadline has passed, returns how many there were.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let mut removed = 0;
+        while let Some((deadline, _)) = self.expiring.first() {
+            if *deadline > now {
+                break;
+            }
+
+            let (_, field) = self.expiring.pop_first().unwrap();
+            self.deadlines.remove(&field);
+            self.values.remove(&field);
+            removed += 1;
+        }
+        removed
     }
 
-    pub fn exists(&self, key: &[u8]) -> bool {
-        self.store.read().unwrap().contains_key(key)
+    fn has_expired(&self, field: &[u8]) -> bool {
+        self.deadlines.get(field).is_some_and(|deadline| *deadline <= SystemTime::now())
     }
+}
 
-    pub fn del(&self, key: &[u8]) -> bool {
-        self.store.write().unwrap().remove(key).is_some()
+// the condition HEXPIRE puts on replacing a field's deadline. a field without a ttl counts as
+// expiring never, so gt never replaces it and lt always does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+impl ExpireCondition {
+    fn admits(self, current: Option<SystemTime>, deadline: SystemTime) -> bool {
+        match self {
+            ExpireCondition::Nx => current.is_none(),
+            ExpireCondition::Xx => current.is_some(),
+            ExpireCondition::Gt => current.is_some_and(|current| deadline > current),
+            ExpireCondition::Lt => current.is_none_or(|current| deadline < current),
+        }
+    }
+}
+
+// what HEXPIRE did to one field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldExpiry {
+    // there is no such field.
+    Missing,
+    // the condition didn't admit the new deadline.
+    Unchanged,
+    Set,
+    // the deadline had already passed, so the field was removed instead.
+    Removed,
+}
+
+// what a conditional set did. previous is only filled in when the flags asked for it, and is
+// none when the key was missing.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SetOutcome {
+    pub written: bool,
+    pub previous: Option<Vec<u8>>,
+}
+
+// a stream entry id, <milliseconds>-<sequence>.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    // the id right after this one, none for the last possible id.
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    // the id right before this one, none for 0-0.
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// the id XADD was asked to add an entry under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewStreamId {
+    // *, taken from the clock.
+    Auto,
+    // <ms>-*, the next sequence number within ms.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+// what XADD trims the stream down to after adding. trimming is always exact, ~ only lets
+// redis trim less than asked so trimming all the way is still what it promises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTrim {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+// why a stream couldn't be changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    WrongType,
+    // the new id isn't past the last one in the stream.
+    IdNotGreater,
+    IdZero,
+    // the last entry has the largest id there is.
+    Exhausted,
+    // XGROUP needs the stream to exist, short of CREATE with MKSTREAM.
+    NoKey,
+    GroupExists,
+    NoGroup { key: Vec<u8>, group: Vec<u8> },
+}
+
+impl From<WrongType> for StreamError {
+    fn from(_: WrongType) -> Self {
+        StreamError::WrongType
+    }
+}
+
+// the field value pairs of a single stream entry, in the order they were added.
+pub type StreamFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+// a key and the values that were popped off the list it holds.
+pub type KeyedValues = (Vec<u8>, Vec<Vec<u8>>);
+
+// a field of a hash and its value.
+pub type FieldValue = (Vec<u8>, Vec<u8>);
+
+// an entry of a stream as commands reply with it.
+pub type StreamEntry = (StreamId, StreamFields);
+
+// an entry read through a consumer group, none in place of the fields when it was deleted
+// while still pending.
+pub type GroupEntry = (StreamId, Option<StreamFields>);
+
+// a stream and what a consumer group read from it.
+pub type KeyedGroupEntries = (Vec<u8>, Vec<GroupEntry>);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stream {
+    pub entries: BTreeMap<StreamId, StreamFields>,
+    pub last_id: StreamId,
+    pub groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+// a consumer group reading a stream, what it has handed out so far and who still has to ack it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConsumerGroup {
+    // > reads the entries after this one.
+    pub last_delivered: StreamId,
+    // the pending entries list, every entry delivered and not acked yet.
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    // unix ms of the last delivery.
+    pub delivered_ms: u64,
+    pub deliveries: u64,
+}
+
+// a consumer's own share of its group's pending entries, kept in step with the group's list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Consumer {
+    pub pending: BTreeSet<StreamId>,
+    pub seen_ms: u64,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Self { last_delivered, ..Self::default() }
+    }
+
+    // creates the consumer if it's new, true if it was.
+    pub fn create_consumer(&mut self, name: &[u8], now_ms: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumers.insert(name.to_vec(), Consumer { pending: BTreeSet::new(), seen_ms: now_ms });
+        true
+    }
+
+    // removes the consumer along with its pending entries, none if there is no such consumer.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    // acknowledges the ids, returns how many of them were pending.
+    pub fn ack(&mut self, ids: &[StreamId]) -> usize {
+        let mut acked = 0;
+        for id in ids {
+            if let Some(entry) = self.pending.remove(id) {
+                if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+                    consumer.pending.remove(id);
+                }
+                acked += 1;
+            }
+        }
+        acked
+    }
+
+    // hands id to consumer for the first time. it may have been pending with someone else
+    // when the group was moved back with SETID, it only belongs to the new consumer then.
+    fn deliver(&mut self, id: StreamId, consumer: &[u8], now_ms: u64) {
+        let entry = PendingEntry { consumer: consumer.to_vec(), delivered_ms: now_ms, deliveries: 1 };
+        if let Some(previous) = self.pending.insert(id, entry) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.consumers.entry(consumer.to_vec()).or_default().pending.insert(id);
+    }
+}
+
+impl Stream {
+    // the id an entry added at now_ms gets. ids only ever grow, even when the clock goes back
+    // or the last entry was trimmed away.
+    fn resolve_id(&self, id: NewStreamId, now_ms: u64) -> Result<StreamId, StreamError> {
+        let last = self.last_id;
+        let id = match id {
+            NewStreamId::Auto if now_ms > last.ms => StreamId::new(now_ms, 0),
+            NewStreamId::Auto => last.next().ok_or(StreamError::Exhausted)?,
+            NewStreamId::AutoSeq(ms) if ms > last.ms => StreamId::new(ms, 0),
+            NewStreamId::AutoSeq(ms) if ms == last.ms => StreamId::new(ms, last.seq.checked_add(1).ok_or(StreamError::IdNotGreater)?),
+            NewStreamId::AutoSeq(_) => return Err(StreamError::IdNotGreater),
+            NewStreamId::Explicit(id) => id,
+        };
+
+        if id == StreamId::MIN {
+            return Err(StreamError::IdZero);
+        }
+        if id <= last {
+            return Err(StreamError::IdNotGreater);
+        }
+        Ok(id)
+    }
+
+    pub fn add(&mut self, id: NewStreamId, fields: StreamFields, now_ms: u64) -> Result<StreamId, StreamError> {
+        let id = self.resolve_id(id, now_ms)?;
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    // removes entries from the front, returns how many.
+    pub fn trim(&mut self, trim: StreamTrim) -> usize {
+        let mut removed = 0;
+        while let Some((&first, _)) = self.entries.first_key_value() {
+            let keep = match trim {
+                StreamTrim::MaxLen(len) => self.entries.len() <= len,
+                StreamTrim::MinId(min) => first >= min,
+            };
+            if keep {
+                break;
+            }
+            self.entries.pop_first();
+            removed += 1;
+        }
+        removed
+    }
+
+    // reads for consumer of group, creating the consumer if needed. without an id these are
+    // the entries never delivered to the group, which become pending with the consumer unless
+    // noack. with one they are the consumer's pending entries after it, delivered again. none
+    // if there is no such group.
+    pub fn read_group(&mut self, group: &[u8], consumer: &[u8], after: Option<StreamId>, count: Option<usize>, noack: bool, now_ms: u64) -> Option<Vec<GroupEntry>> {
+        let group = self.groups.get_mut(group)?;
+        let count = count.unwrap_or(usize::MAX);
+        group.create_consumer(consumer, now_ms);
+        if let Some(consumer) = group.consumers.get_mut(consumer) {
+            consumer.seen_ms = now_ms;
+        }
+
+        match after {
+            None => {
+                let entries: Vec<StreamEntry> = self.entries
+                    .range((Bound::Excluded(group.last_delivered), Bound::Unbounded))
+                    .take(count)
+                    .map(|(id, fields)| (*id, fields.clone()))
+                    .collect();
+                for (id, _) in &entries {
+                    group.last_delivered = *id;
+                    if !noack {
+                        group.deliver(*id, consumer, now_ms);
+                    }
+                }
+                Some(entries.into_iter().map(|(id, fields)| (id, Some(fields))).collect())
+            },
+            Some(after) => {
+                let ids: Vec<StreamId> = group.consumers[consumer].pending
+                    .range((Bound::Excluded(after), Bound::Unbounded))
+                    .take(count)
+                    .copied()
+                    .collect();
+                for id in &ids {
+                    if let Some(entry) = group.pending.get_mut(id) {
+                        entry.delivered_ms = now_ms;
+                        entry.deliveries += 1;
+                    }
+                }
+                Some(ids.into_iter().map(|id| (id, self.entries.get(&id).cloned())).collect())
+            },
+        }
+    }
+
+    // the entries between start and end inclusive, last first when rev. at most count of
+    // them when there is a count.
+    pub fn range(&self, start: StreamId, end: StreamId, rev: bool, count: Option<usize>) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+
+        let entries = self.entries.range(start..=end).map(|(id, fields)| (*id, fields.clone()));
+        let count = count.unwrap_or(usize::MAX);
+        if rev {
+            entries.rev().take(count).collect()
+        } else {
+            entries.take(count).collect()
+        }
+    }
+}
+
+// the in memory shape of an aggregate, what DEBUG LISTPACK and DEBUG QUICKLIST report.
+// bytes counts the payload of every entry and largest the biggest single entry.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub kind: &'static str,
+    pub repr: &'static str,
+    pub entries: usize,
+    pub capacity: usize,
+    pub bytes: usize,
+    pub largest: usize,
+}
+
+impl Layout {
+    pub fn to_lines(&self) -> String {
+        format!(
+            "type:{}\nrepr:{}\nentries:{}\ncapacity:{}\nbytes:{}\nlargest:{}\n",
+            self.kind, self.repr, self.entries, self.capacity, self.bytes, self.largest
+        )
+    }
+}
+
+// what INFO memory reports about strings, logical bytes are what the values hold and stored
+// bytes what they take with compression.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StringMemory {
+    pub strings: usize,
+    pub compressed: usize,
+    pub logical_bytes: usize,
+    pub stored_bytes: usize,
+}
+
+// which end of a list an operation works on, left is the head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+// a string kept lzf compressed, len is how long it is once decompressed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Compressed {
+    pub data: Vec<u8>,
+    pub len: usize,
+}
+
+impl Compressed {
+    // none when compressing wouldn't make it any smaller.
+    pub fn new(value: &[u8]) -> Option<Self> {
+        lzf::compress(value).map(|data| Self { data, len: value.len() })
+    }
+
+    pub fn decompress(&self) -> Vec<u8> {
+        lzf::decompress(&self.data, self.len).expect("compressed strings are always valid lzf")
+    }
+}
+
+// everything a key can hold.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(Vec<u8>),
+    // a string past the compression threshold, see Database::set_compression_threshold.
+    Compressed(Compressed),
+    List(VecDeque<Vec<u8>>),
+    Hash(HashFields),
+    Set(HashSet<Vec<u8>>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    // the name TYPE and SCAN TYPE use for this kind of value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) | Value::Compressed(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    // redis never keeps a list, hash, set or sorted set without elements, the key goes away
+    // with its last element. streams are the exception, they stay around empty.
+    pub fn is_empty_aggregate(&self) -> bool {
+        match self {
+            Value::List(items) => items.is_empty(),
+            Value::Hash(fields) => fields.is_empty(),
+            Value::Set(members) => members.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+            Value::String(_) | Value::Compressed(_) | Value::Stream(_) => false,
+        }
+    }
+
+    // the name OBJECT ENCODING reports, redis' name for the representation closest to ours.
+    // strings are classified like redis would store them, compressed ones count as raw since
+    // redis only compresses on disk.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(data) if is_int_encodable(data) => "int",
+            Value::String(data) if data.len() <= EMBSTR_MAX_LEN => "embstr",
+            Value::String(_) | Value::Compressed(_) => "raw",
+            Value::List(_) => "quicklist",
+            Value::Hash(_) | Value::Set(_) => "hashtable",
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    // roughly what the value takes in an rdb file, DEBUG OBJECT's serializedlength. strings are
+    // counted the way rdb writes them, aggregates as the plain sequence of their elements.
+    pub fn serialized_len(&self) -> usize {
+        let pairs = |fields: &StreamFields| -> usize {
+            rdb_len_len(fields.len()) + fields.iter().map(|(f, v)| rdb_string_len(f) + rdb_string_len(v)).sum::<usize>()
+        };
+
+        match self {
+            Value::String(data) => rdb_string_len(data),
+            Value::Compressed(compressed) => 1 + rdb_len_len(compressed.data.len()) + rdb_len_len(compressed.len) + compressed.data.len(),
+            Value::List(items) => rdb_len_len(items.len()) + items.iter().map(|item| rdb_string_len(item)).sum::<usize>(),
+            Value::Hash(fields) => rdb_len_len(fields.len()) + fields.iter().map(|(f, v)| rdb_string_len(f) + rdb_string_len(v)).sum::<usize>(),
+            Value::Set(members) => rdb_len_len(members.len()) + members.iter().map(|member| rdb_string_len(member)).sum::<usize>(),
+            // scores are written as binary doubles.
+            Value::SortedSet(zset) => rdb_len_len(zset.len()) + zset.iter().map(|(member, _)| rdb_string_len(member) + 8).sum::<usize>(),
+            // every id is two 64 bit integers.
+            Value::Stream(stream) => rdb_len_len(stream.entries.len()) + stream.entries.values().map(|fields| 16 + pairs(fields)).sum::<usize>(),
+        }
+    }
+
+    // how an aggregate is held in memory, none for strings. every aggregate keeps the one
+    // representation it was created with, there are no small encodings to convert from.
+    pub fn layout(&self) -> Option<Layout> {
+        let (repr, entries, capacity, sizes): (_, _, _, Vec<usize>) = match self {
+            Value::String(_) | Value::Compressed(_) => return None,
+            Value::List(items) => ("vecdeque", items.len(), items.capacity(), items.iter().map(Vec::len).collect()),
+            Value::Hash(fields) => ("hashmap", fields.len(), fields.capacity(), fields.iter().map(|(f, v)| f.len() + v.len()).collect()),
+            Value::Set(members) => ("hashset", members.len(), members.capacity(), members.iter().map(Vec::len).collect()),
+            Value::SortedSet(zset) => ("skiplist+hashmap", zset.len(), zset.capacity(), zset.iter().map(|(m, _)| m.len() + 8).collect()),
+            Value::Stream(stream) => (
+                "btreemap",
+                stream.entries.len(),
+                stream.entries.len(),
+                stream.entries.values().map(|fields| fields.iter().map(|(f, v)| f.len() + v.len()).sum()).collect(),
+            ),
+        };
+
+        Some(Layout {
+            kind: self.type_name(),
+            repr,
+            entries,
+            capacity,
+            bytes: sizes.iter().sum(),
+            largest: sizes.into_iter().max().unwrap_or(0),
+        })
+    }
+
+    // hashes the contents so equal values hash the same, regardless of the iteration
+    // order of unordered collections.
+    fn digest_into(&self, hasher: &mut DefaultHasher) {
+        match self {
+            Value::String(data) => data.hash(hasher),
+            Value::Compressed(compressed) => compressed.decompress().hash(hasher),
+            Value::List(items) => {
+                self.type_name().hash(hasher);
+                items.hash(hasher);
+            },
+            Value::Hash(fields) => {
+                self.type_name().hash(hasher);
+                unordered_hash(fields.iter()).hash(hasher);
+            },
+            Value::Set(members) => {
+                self.type_name().hash(hasher);
+                unordered_hash(members.iter()).hash(hasher);
+            },
+            Value::SortedSet(zset) => {
+                self.type_name().hash(hasher);
+                unordered_hash(zset.iter().map(|(member, score)| (member, score.to_bits()))).hash(hasher);
+            },
+            Value::Stream(stream) => {
+                self.type_name().hash(hasher);
+                stream.entries.hash(hasher);
+                stream.last_id.hash(hasher);
+            },
+        }
+    }
+}
+
+// the longest string redis keeps embedded in its object header.
+const EMBSTR_MAX_LEN: usize = 44;
+
+// whether redis would store the string as an integer, only the canonical form of an i64 is.
+fn is_int_encodable(data: &[u8]) -> bool {
+    data.len() <= 20
+        && std::str::from_utf8(data)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .is_some_and(|n| n.to_string().as_bytes() == data)
+}
+
+// the bytes rdb spends on a length, 6 bits, 14 bits or a whole 32 or 64 bit integer.
+fn rdb_len_len(len: usize) -> usize {
+    match len {
+        0..64 => 1,
+        64..16384 => 2,
+        _ if len <= u32::MAX as usize => 5,
+        _ => 9,
+    }
+}
+
+// small integers are written as 8, 16 or 32 bit integers, everything else with its length.
+fn rdb_string_len(data: &[u8]) -> usize {
+    let int = match is_int_encodable(data) {
+        true => std::str::from_utf8(data).ok().and_then(|s| s.parse::<i64>().ok()),
+        false => None,
+    };
+
+    match int {
+        Some(n) if i8::try_from(n).is_ok() => 2,
+        Some(n) if i16::try_from(n).is_ok() => 3,
+        Some(n) if i32::try_from(n).is_ok() => 5,
+        _ => rdb_len_len(data.len()) + data.len(),
+    }
+}
+
+// when a record was last looked up by a command, in milliseconds since the epoch. atomic like
+// the lfu counter so a read under a shared lock still counts.
+#[derive(Debug)]
+struct AccessTime(AtomicU64);
+
+impl Clone for AccessTime {
+    fn clone(&self) -> Self {
+        AccessTime(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl AccessTime {
+    fn now() -> Self {
+        AccessTime(AtomicU64::new(unix_ms()))
+    }
+
+    fn touch(&self) {
+        self.0.store(unix_ms(), Ordering::Relaxed);
+    }
+
+    fn idle(&self) -> Duration {
+        Duration::from_millis(unix_ms().saturating_sub(self.0.load(Ordering::Relaxed)))
     }
-}
\ No newline at end of file
+}
+
+// everything DEBUG OBJECT says about a key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+    pub kind: &'static str,
+    pub encoding: &'static str,
+    pub serialized_len: usize,
+    pub idle: Duration,
+    pub freq: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub value: Value,
+    // the absolute wall clock time at which this record expires.
+    expiry: Option<SystemTime>,
+    // how often the key is accessed, for lfu eviction.
+    lfu: LfuCounter,
+    // when the key was last accessed, for OBJECT IDLETIME.
+    accessed: AccessTime,
+}
+
+impl Record {
+    // converts from a parsed Resp to a Record for storage
+    // client always sends a bulk string, so we can safely assume that
+    pub fn from_resp(resp: Resp) -> Option<Record> {
+        match resp {
+            Resp::BulkString(b) => Some(Record::from_vec(b)),
+            _ => None,
+        }
+    }
+
+    pub fn from_vec(v: Vec<u8>) -> Record {
+        Record::new(Value::String(v))
+    }
+
+    pub fn new(value: Value) -> Record {
+        Record { value, expiry: None, lfu: LfuCounter::new(), accessed: AccessTime::now() }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.value.type_name()
+    }
+
+    // the access frequency counter with any decay applied, this doesn't count as an access.
+    pub fn frequency(&self, policy: &LfuPolicy) -> u8 {
+        self.lfu.frequency(policy)
+    }
+
+    // how long since a command last accessed the key.
+    pub fn idle_time(&self) -> Duration {
+        self.accessed.idle()
+    }
+
+    fn touch(&self, policy: &LfuPolicy) {
+        self.lfu.touch(policy, random_unit());
+        self.accessed.touch();
+    }
+
+    // a compressed string is decompressed into a copy, the record keeps it compressed.
+    pub fn string(&self) -> Result<Cow<'_, [u8]>, WrongType> {
+        match &self.value {
+            Value::String(data) => Ok(Cow::Borrowed(data)),
+            Value::Compressed(compressed) => Ok(Cow::Owned(compressed.decompress())),
+            _ => Err(WrongType),
+        }
+    }
+
+    // changing a compressed string in place decompresses it for good, it is only compressed
+    // again when the whole value is stored.
+    pub fn string_mut(&mut self) -> Result<&mut Vec<u8>, WrongType> {
+        if let Value::Compressed(compressed) = &self.value {
+            self.value = Value::String(compressed.decompress());
+        }
+        match &mut self.value {
+            Value::String(data) => Ok(data),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn list(&self) -> Result<&VecDeque<Vec<u8>>, WrongType> {
+        match &self.value {
+            Value::List(items) => Ok(items),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn list_mut(&mut self) -> Result<&mut VecDeque<Vec<u8>>, WrongType> {
+        match &mut self.value {
+            Value::List(items) => Ok(items),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn set(&self) -> Result<&HashSet<Vec<u8>>, WrongType> {
+        match &self.value {
+            Value::Set(members) => Ok(members),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn hash(&self) -> Result<&HashFields, WrongType> {
+        match &self.value {
+            Value::Hash(fields) => Ok(fields),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn stream(&self) -> Result<&Stream, WrongType> {
+        match &self.value {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn stream_mut(&mut self) -> Result<&mut Stream, WrongType> {
+        match &mut self.value {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn sorted_set(&self) -> Result<&SortedSet, WrongType> {
+        match &self.value {
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    // the members of a set or sorted set, the zset commands accept plain sets as inputs.
+    pub fn members(&self) -> Result<&dyn Members, WrongType> {
+        match &self.value {
+            Value::Set(members) => Ok(members),
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn into_string(self) -> Result<Vec<u8>, WrongType> {
+        match self.value {
+            Value::String(data) => Ok(data),
+            Value::Compressed(compressed) => Ok(compressed.decompress()),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn set_expiry(&mut self, duration: Duration) {
+        self.expiry = Some(SystemTime::now() + duration);
+    }
+
+    pub fn set_expiry_at(&mut self, deadline: SystemTime) {
+        self.expiry = Some(deadline);
+    }
+
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expiry
+    }
+
+    pub fn clear_expiry(&mut self) -> bool {
+        self.expiry.take().is_some()
+    }
+
+    // the time left before this record expires, none if it never does.
+    pub fn remaining_ttl(&self) -> Option<Duration> {
+        self.expiry.map(|deadline| {
+            deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    // overwrites the value starting at offset, zero padding any gap past the current end.
+    // returns the new length of the value.
+    pub fn set_range(&mut self, offset: usize, value: &[u8]) -> Result<usize, WrongType> {
+        let data = self.string_mut()?;
+        let end = offset + value.len();
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(value);
+        Ok(data.len())
+    }
+
+    // the inclusive range [start, end] of the value, negative indexes count back from the end.
+    pub fn get_range(&self, start: i64, end: i64) -> Result<Vec<u8>, WrongType> {
+        let data = self.string()?;
+        let len = data.len() as i64;
+        if len == 0 || (start < 0 && end < 0 && start > end) {
+            return Ok(Vec::new());
+        }
+
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+
+        if start > end {
+            return Ok(Vec::new());
+        }
+
+        Ok(data[start as usize..=end as usize].to_vec())
+    }
+
+    // swaps a string of at least threshold bytes for its compressed form, if that is smaller.
+    // a threshold of 0 never compresses.
+    fn compress(&mut self, threshold: usize) {
+        if let Value::String(data) = &self.value {
+            if threshold > 0 && data.len() >= threshold {
+                if let Some(compressed) = Compressed::new(data) {
+                    self.value = Value::Compressed(compressed);
+                }
+            }
+        }
+    }
+
+    pub fn has_expired(&self) -> bool {
+        if let Some(deadline) = self.expiry {
+            SystemTime::now() >= deadline
+        } else {
+            false
+        }
+    }
+
+    // hashes the key, value and ttl of this record into a fixed size digest.
+    // only the presence of a ttl is hashed, the deadline itself is derived from the local clock
+    // when the write is applied so a master and its replicas will never agree on it exactly.
+    pub fn digest(&self, key: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut digest = [0u8; DIGEST_LEN];
+
+        for (seed, chunk) in digest.chunks_mut(8).enumerate() {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            self.value.digest_into(&mut hasher);
+            self.expiry.is_some().hash(&mut hasher);
+            let bytes = hasher.finish().to_be_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+
+        digest
+    }
+}
+
+// the records plus an index of their deadlines, so expiring keys never means walking the
+// whole keyspace. every change to a record's expiry has to go through here to keep the two in sync.
+// the keys are also kept in the order SCAN walks them, so a cursor resumes where it left off.
+// hashes whose fields expire are indexed the same way, by the soonest deadline of their fields.
+#[derive(Debug, Default)]
+struct Keyspace {
+    records: HashMap<Vec<u8>, Record>,
+    // (deadline, key) for every record with a ttl, ordered soonest first.
+    deadlines: BTreeSet<(SystemTime, Vec<u8>)>,
+    // (soonest field deadline, key) for every hash with fields that expire.
+    field_deadlines: BTreeSet<(SystemTime, Vec<u8>)>,
+    // (scan hash, key) for every record.
+    scan_order: BTreeSet<(u64, Vec<u8>)>,
+    lfu: LfuPolicy,
+    // strings stored with at least this many bytes are kept compressed, 0 turns it off.
+    compression_threshold: AtomicUsize,
+}
+
+impl Keyspace {
+    // a lookup on behalf of a command, it counts as an access to the key.
+    fn get(&self, key: &[u8]) -> Option<&Record> {
+        let record = self.records.get(key)?;
+        record.touch(&self.lfu);
+        Some(record)
+    }
+
+    // a lookup that leaves the access frequency alone, for introspection like TTL and TYPE.
+    fn peek(&self, key: &[u8]) -> Option<&Record> {
+        self.records.get(key)
+    }
+
+    // for changing the value only, ttls are changed with set_expiry.
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut Record> {
+        let record = self.records.get_mut(key)?;
+        record.touch(&self.lfu);
+        Some(record)
+    }
+
+    // removes every record, the lfu policy stays.
+    fn take_records(&mut self) -> HashMap<Vec<u8>, Record> {
+        self.deadlines.clear();
+        self.field_deadlines.clear();
+        self.scan_order.clear();
+        std::mem::take(&mut self.records)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.records.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Record)> {
+        self.records.iter()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, mut record: Record) -> Option<Record> {
+        record.compress(self.compression_threshold.load(Ordering::Relaxed));
+        let deadline = record.expiry;
+        let field_deadline = next_field_deadline(&record);
+        let previous = self.records.insert(key.clone(), record);
+
+        if let Some(previous) = previous.as_ref().and_then(|record| record.expiry) {
+            self.deadlines.remove(&(previous, key.clone()));
+        }
+        if let Some(previous) = previous.as_ref().and_then(next_field_deadline) {
+            self.field_deadlines.remove(&(previous, key.clone()));
+        }
+        if let Some(field_deadline) = field_deadline {
+            self.field_deadlines.insert((field_deadline, key.clone()));
+        }
+        if previous.is_none() {
+            self.scan_order.insert((scan_hash(&key), key.clone()));
+        }
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, key));
+        }
+        previous
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Record> {
+        let record = self.records.remove(key)?;
+        if let Some(deadline) = record.expiry {
+            self.deadlines.remove(&(deadline, key.to_vec()));
+        }
+        if let Some(deadline) = next_field_deadline(&record) {
+            self.field_deadlines.remove(&(deadline, key.to_vec()));
+        }
+        self.scan_order.remove(&(scan_hash(key), key.to_vec()));
+        Some(record)
+    }
+
+    // replaces the deadline of an existing record, returns the one it had.
+    fn set_expiry(&mut self, key: &[u8], deadline: Option<SystemTime>) -> Option<SystemTime> {
+        let record = self.records.get_mut(key)?;
+        let previous = std::mem::replace(&mut record.expiry, deadline);
+
+        if let Some(previous) = previous {
+            self.deadlines.remove(&(previous, key.to_vec()));
+        }
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, key.to_vec()));
+        }
+        previous
+    }
+
+    // removes up to limit records whose deadline has passed, soonest first.
+    fn remove_expired(&mut self, now: SystemTime, limit: usize) -> usize {
+        let mut removed = 0;
+        while removed < limit {
+            match self.deadlines.first() {
+                Some((deadline, _)) if *deadline <= now => {},
+                _ => break,
+            }
+
+            let (_, key) = self.deadlines.pop_first().unwrap();
+            if let Some(deadline) = self.records.remove(&key).as_ref().and_then(next_field_deadline) {
+                self.field_deadlines.remove(&(deadline, key.clone()));
+            }
+            self.scan_order.remove(&(scan_hash(&key), key));
+            removed += 1;
+        }
+        removed
+    }
+
+    // changes the fields of the hash at key on behalf of a command, which counts as an access.
+    fn change_hash<T>(&mut self, key: &[u8], change: impl FnOnce(&mut HashFields) -> T) -> Result<Option<T>, WrongType> {
+        if let Some(record) = self.records.get(key) {
+            record.touch(&self.lfu);
+        }
+        self.change_fields(key, change)
+    }
+
+    // changes the fields of the hash at key, keeping the index of field deadlines in sync and
+    // removing the key once no fields are left. expired fields are dropped before change runs.
+    // none when there is no live key.
+    fn change_fields<T>(&mut self, key: &[u8], change: impl FnOnce(&mut HashFields) -> T) -> Result<Option<T>, WrongType> {
+        let fields = match self.records.get_mut(key) {
+            Some(record) if !record.has_expired() => match &mut record.value {
+                Value::Hash(fields) => fields,
+                _ => return Err(WrongType),
+            },
+            _ => return Ok(None),
+        };
+
+        let before = fields.next_deadline();
+        fields.remove_expired(SystemTime::now());
+        let result = change(fields);
+        let after = fields.next_deadline();
+        let empty = fields.is_empty();
+
+        if before != after {
+            if let Some(before) = before {
+                self.field_deadlines.remove(&(before, key.to_vec()));
+            }
+            if let Some(after) = after {
+                self.field_deadlines.insert((after, key.to_vec()));
+            }
+        }
+        if empty {
+            self.remove(key);
+        }
+        Ok(Some(result))
+    }
+
+    // drops the expired fields of up to limit hashes, soonest first, returns how many hashes
+    // were visited.
+    fn remove_expired_fields(&mut self, now: SystemTime, limit: usize) -> usize {
+        let mut visited = 0;
+        while visited < limit {
+            match self.field_deadlines.first() {
+                Some((deadline, _)) if *deadline <= now => {},
+                _ => break,
+            }
+
+            let (_, key) = self.field_deadlines.pop_first().unwrap();
+            // the entry is gone already, change_fields puts back the one for what remains. an
+            // expired key is left to remove_expired.
+            let _ = self.change_fields(&key, |_| ());
+            visited += 1;
+        }
+        visited
+    }
+}
+
+// the soonest deadline of the fields of a hash, none for anything else.
+fn next_field_deadline(record: &Record) -> Option<SystemTime> {
+    match &record.value {
+        Value::Hash(fields) => fields.next_deadline(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    // (key, value)
+    store: RwLock<Keyspace>,
+    // connections blocked until one of their keys is pushed to.
+    waiters: KeyWaiters,
+    // whether the background cycle removes expired keys, DEBUG SET-ACTIVE-EXPIRE turns it off
+    // so tests can watch keys expire lazily.
+    active_expire: AtomicBool,
+}
+
+
+impl Database {
+    pub fn new() -> Self {
+        Database {
+            store: RwLock::new(Keyspace::default()),
+            waiters: KeyWaiters::new(),
+            active_expire: AtomicBool::new(true),
+        }
+    }
+
+    // storing an empty aggregate deletes the key instead, see Value::is_empty_aggregate.
+    pub fn set(&self, key: Vec<u8>, value: Record) -> Option<Record> {
+        let mut store = self.store.write().unwrap();
+        if value.value.is_empty_aggregate() {
+            return store.remove(&key);
+        }
+        store.insert(key, value)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Record> {
+        self.store.read().unwrap().get(key).cloned()
+    }
+
+    // SET with its conditions, the existing key is looked at and replaced under a single write
+    // lock so two SET NX can never both succeed. SET GET only ever returns a string, so with get
+    // a key holding anything else is left alone.
+    pub fn set_with(&self, key: Vec<u8>, mut value: Record, flags: SetFlags) -> Result<SetOutcome, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let existing = store.peek(&key).filter(|record| !record.has_expired());
+        let exists = existing.is_some();
+
+        if flags.get && existing.is_some_and(|record| record.type_name() != "string") {
+            return Err(WrongType);
+        }
+
+        if (flags.nx && exists) || (flags.xx && !exists) {
+            let previous = existing
+                .filter(|_| flags.get)
+                .and_then(|record| record.string().ok())
+                .map(|data| data.into_owned());
+            return Ok(SetOutcome { written: false, previous });
+        }
+
+        if let Some(deadline) = existing.and_then(|record| record.expires_at()).filter(|_| flags.keep_ttl) {
+            value.set_expiry_at(deadline);
+        }
+
+        // the replaced record is moved out rather than cloned, an expired one is as good as missing.
+        let previous = store
+            .insert(key, value)
+            .filter(|record| flags.get && !record.has_expired())
+            .and_then(|record| record.into_string().ok());
+        Ok(SetOutcome { written: true, previous })
+    }
+
+    // the values of several keys read under a single lock, none for missing or expired keys.
+    pub fn get_many(&self, keys: &[Vec<u8>]) -> Vec<Option<Record>> {
+        let store = self.store.read().unwrap();
+        keys.iter()
+            .map(|key| store.get(key).filter(|record| !record.has_expired()).cloned())
+            .collect()
+    }
+
+    // sets every pair under a single write lock so readers never observe a partial batch.
+    pub fn set_many(&self, pairs: Vec<(Vec<u8>, Record)>) {
+        let mut store = self.store.write().unwrap();
+        for (key, value) in pairs {
+            store.insert(key, value);
+        }
+    }
+
+    // sets every pair only if none of the keys exist, returns false without writing anything otherwise.
+    pub fn set_many_nx(&self, pairs: Vec<(Vec<u8>, Record)>) -> bool {
+        let mut store = self.store.write().unwrap();
+        let any_exists = pairs.iter().any(|(key, _)| {
+            store.peek(key).is_some_and(|record| !record.has_expired())
+        });
+
+        if any_exists {
+            return false;
+        }
+
+        for (key, value) in pairs {
+            store.insert(key, value);
+        }
+        true
+    }
+
+    pub fn exists(&self, key: &[u8]) -> bool {
+        self.store.read().unwrap().contains_key(key)
+    }
+
+    // returns false if there was nothing live to delete, an expired record is still
+    // cleaned up but doesn't count as a change.
+    pub fn del(&self, key: &[u8]) -> bool {
+        self.store
+            .write()
+            .unwrap()
+            .remove(key)
+            .is_some_and(|record| !record.has_expired())
+    }
+
+    // drops the record at key if it has expired, checked again under the write lock so a value
+    // written since the caller read the expired one is left alone. returns whether one was dropped.
+    pub fn remove_if_expired(&self, key: &[u8]) -> bool {
+        let mut store = self.store.write().unwrap();
+        match store.peek(key) {
+            Some(record) if record.has_expired() => store.remove(key).is_some(),
+            _ => false,
+        }
+    }
+
+    // deletes every key under a single write lock, returns how many live keys were removed.
+    pub fn del_many(&self, keys: &[Vec<u8>]) -> usize {
+        let mut store = self.store.write().unwrap();
+        keys.iter()
+            .filter(|key| store.remove(key).is_some_and(|record| !record.has_expired()))
+            .count()
+    }
+
+    // writes value into the key at offset in place, a missing key starts out as an empty string.
+    // returns the new length, an empty value never creates the key.
+    pub fn set_range(&self, key: &[u8], offset: usize, value: &[u8]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.get(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        match store.get_mut(key) {
+            Some(record) if value.is_empty() => Ok(record.string()?.len()),
+            Some(record) => record.set_range(offset, value),
+            None if value.is_empty() => Ok(0),
+            None => {
+                let mut record = Record::from_vec(Vec::new());
+                let len = record.set_range(offset, value)?;
+                store.insert(key.to_vec(), record);
+                Ok(len)
+            },
+        }
+    }
+
+    // the length of the value at key, zero if it does not exist.
+    pub fn strlen(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.string()?.len()),
+            _ => Ok(0),
+        }
+    }
+
+    // appends to the value at key in place, a missing key is created. returns the new length.
+    // the record is owned by the store so this extends the existing buffer rather than copying
+    // it, keeping repeated appends amortized O(1) in the length of the value.
+    pub fn append(&self, key: &[u8], value: &[u8]) -> Result<usize, StringError> {
+        self.append_within(key, value, MAX_STRING_LEN)
+    }
+
+    // the size is checked under the same lock as the append, so concurrent appends can't
+    // together grow the value past max_len.
+    fn append_within(&self, key: &[u8], value: &[u8], max_len: usize) -> Result<usize, StringError> {
+        let mut store = self.store.write().unwrap();
+
+        match store.get_mut(key) {
+            Some(record) if !record.has_expired() => {
+                let data = record.string_mut()?;
+                if data.len().saturating_add(value.len()) > max_len {
+                    return Err(StringError::TooLarge);
+                }
+                data.extend_from_slice(value);
+                Ok(data.len())
+            },
+            _ if value.len() > max_len => Err(StringError::TooLarge),
+            _ => {
+                store.insert(key.to_vec(), Record::from_vec(value.to_vec()));
+                Ok(value.len())
+            },
+        }
+    }
+
+    // a copy of the inclusive range of the value at key, empty if the key does not exist.
+    pub fn get_range(&self, key: &[u8], start: i64, end: i64) -> Result<Vec<u8>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => record.get_range(start, end),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // the first bit set to bit in the value at key, see bitops::position. none if the key does
+    // not exist, there is no range to look in then.
+    pub fn bit_position(&self, key: &[u8], bit: bool, start: i64, end: Option<i64>, unit: BitUnit) -> Result<Option<i64>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(Some(bitops::position(&record.string()?, bit, start, end, unit))),
+            _ => Ok(None),
+        }
+    }
+
+    // combines the strings at keys into destination, missing keys count as empty strings. an
+    // empty result deletes destination. returns the length of the result.
+    pub fn bit_op_store(&self, destination: &[u8], op: BitOp, keys: &[Vec<u8>]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let mut inputs = Vec::with_capacity(keys.len());
+        for key in keys {
+            match store.get(key) {
+                Some(record) if !record.has_expired() => inputs.push(record.string()?),
+                _ => inputs.push(Cow::Borrowed(&[][..])),
+            }
+        }
+
+        let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_ref()).collect();
+        let result = bitops::combine(op, &inputs);
+        let len = result.len();
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            store.insert(destination.to_vec(), Record::from_vec(result));
+        }
+        Ok(len)
+    }
+
+    // adds an entry to the stream at key and trims it if asked to, returns the id the entry got.
+    // a missing key is created unless nomkstream is set, nothing is added then. a failed add
+    // never leaves an empty stream behind.
+    pub fn stream_add(&self, key: &[u8], id: NewStreamId, fields: StreamFields, nomkstream: bool, trim: Option<StreamTrim>) -> Result<Option<StreamId>, StreamError> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        let now_ms = unix_ms();
+        let add = |stream: &mut Stream| -> Result<Option<StreamId>, StreamError> {
+            let id = stream.add(id, fields, now_ms)?;
+            if let Some(trim) = trim {
+                stream.trim(trim);
+            }
+            Ok(Some(id))
+        };
+
+        let added = match store.get_mut(key) {
+            Some(record) => add(record.stream_mut()?)?,
+            None if nomkstream => None,
+            None => {
+                let mut stream = Stream::default();
+                let id = add(&mut stream)?;
+                store.insert(key.to_vec(), Record::new(Value::Stream(stream)));
+                id
+            },
+        };
+
+        drop(store);
+        if added.is_some() {
+            self.waiters.notify(key);
+        }
+        Ok(added)
+    }
+
+    // see Stream::range, a missing key is an empty stream.
+    pub fn stream_range(&self, key: &[u8], start: StreamId, end: StreamId, rev: bool, count: Option<usize>) -> Result<Vec<StreamEntry>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.stream()?.range(start, end, rev, count)),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub fn stream_len(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.stream()?.entries.len()),
+            _ => Ok(0),
+        }
+    }
+
+    // runs f on the stream at key, which the XGROUP subcommands need to exist.
+    fn with_stream<T>(&self, key: &[u8], f: impl FnOnce(&mut Stream) -> Result<T, StreamError>) -> Result<T, StreamError> {
+        let mut store = self.store.write().unwrap();
+        match store.get_mut(key) {
+            Some(record) if !record.has_expired() => f(record.stream_mut()?),
+            _ => Err(StreamError::NoKey),
+        }
+    }
+
+    // creates group on the stream at key, delivering what comes after id or only what is
+    // added from now on without one. mkstream creates a missing stream empty.
+    pub fn stream_group_create(&self, key: &[u8], group: &[u8], id: Option<StreamId>, mkstream: bool) -> Result<(), StreamError> {
+        let create = |stream: &mut Stream| {
+            if stream.groups.contains_key(group) {
+                return Err(StreamError::GroupExists);
+            }
+            let id = id.unwrap_or(stream.last_id);
+            stream.groups.insert(group.to_vec(), ConsumerGroup::new(id));
+            Ok(())
+        };
+
+        let mut store = self.store.write().unwrap();
+        match store.get_mut(key) {
+            Some(record) if !record.has_expired() => create(record.stream_mut()?),
+            _ if mkstream => {
+                let mut stream = Stream::default();
+                create(&mut stream)?;
+                store.insert(key.to_vec(), Record::new(Value::Stream(stream)));
+                Ok(())
+            },
+            _ => Err(StreamError::NoKey),
+        }
+    }
+
+    // true if there was such a group.
+    pub fn stream_group_destroy(&self, key: &[u8], group: &[u8]) -> Result<bool, StreamError> {
+        self.with_stream(key, |stream| Ok(stream.groups.remove(group).is_some()))
+    }
+
+    // see ConsumerGroup::create_consumer.
+    pub fn stream_group_create_consumer(&self, key: &[u8], group: &[u8], consumer: &[u8]) -> Result<bool, StreamError> {
+        self.with_stream(key, |stream| Ok(stream_group(stream, key, group)?.create_consumer(consumer, unix_ms())))
+    }
+
+    // returns how many entries the consumer had pending, they are dropped with it.
+    pub fn stream_group_delete_consumer(&self, key: &[u8], group: &[u8], consumer: &[u8]) -> Result<usize, StreamError> {
+        self.with_stream(key, |stream| Ok(stream_group(stream, key, group)?.delete_consumer(consumer).unwrap_or(0)))
+    }
+
+    // moves where group reads new entries from, to the end of the stream without an id.
+    pub fn stream_group_set_id(&self, key: &[u8], group: &[u8], id: Option<StreamId>) -> Result<(), StreamError> {
+        self.with_stream(key, |stream| {
+            let id = id.unwrap_or(stream.last_id);
+            stream_group(stream, key, group)?.last_delivered = id;
+            Ok(())
+        })
+    }
+
+    // reads each stream through group as consumer, see Stream::read_group. an id of none reads
+    // new entries, streams without any are left out. every stream must have the group, nothing
+    // is read otherwise. none when nothing was read, which is what XREADGROUP blocks on.
+    pub fn stream_read_group(
+        &self,
+        streams: &[(Vec<u8>, Option<StreamId>)],
+        group: &[u8],
+        consumer: &[u8],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Option<Vec<KeyedGroupEntries>>, StreamError> {
+        let mut store = self.store.write().unwrap();
+        for (key, _) in streams {
+            let found = match store.get(key) {
+                Some(record) if !record.has_expired() => record.stream()?.groups.contains_key(group),
+                _ => false,
+            };
+            if !found {
+                return Err(StreamError::NoGroup { key: key.clone(), group: group.to_vec() });
+            }
+        }
+
+        let now_ms = unix_ms();
+        let mut read = Vec::new();
+        for (key, after) in streams {
+            let stream = match store.get_mut(key) {
+                Some(record) => record.stream_mut()?,
+                None => continue,
+            };
+            let entries = stream.read_group(group, consumer, *after, count, noack, now_ms).unwrap_or_default();
+            if after.is_some() || !entries.is_empty() {
+                read.push((key.clone(), entries));
+            }
+        }
+        Ok(if read.is_empty() { None } else { Some(read) })
+    }
+
+    // acknowledges ids in group, returns how many were pending. a missing stream or group has
+    // nothing pending.
+    pub fn stream_ack(&self, key: &[u8], group: &[u8], ids: &[StreamId]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        match store.get_mut(key) {
+            Some(record) if !record.has_expired() => Ok(record.stream_mut()?.groups.get_mut(group).map_or(0, |group| group.ack(ids))),
+            _ => Ok(0),
+        }
+    }
+
+    // counts elements into the hyperloglog at key, creating it if needed. true if that changed
+    // it, creating counts as a change even without elements.
+    pub fn hll_add(&self, key: &[u8], elements: &[Vec<u8>]) -> Result<bool, HllError> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        let mut changed = false;
+        if store.peek(key).is_none() {
+            store.insert(key.to_vec(), Record::from_vec(hyperloglog::new()));
+            changed = true;
+        }
+
+        let data = store.get_mut(key).unwrap().string_mut()?;
+        hyperloglog::validate(data)?;
+        for element in elements {
+            changed |= hyperloglog::add(data, element);
+        }
+        Ok(changed)
+    }
+
+    // the cardinality of the union of the hyperloglogs at keys, missing keys are empty.
+    pub fn hll_count(&self, keys: &[Vec<u8>]) -> Result<u64, HllError> {
+        let store = self.store.read().unwrap();
+        let mut union: Option<Vec<u8>> = None;
+        for key in keys {
+            let data = match store.get(key) {
+                Some(record) if !record.has_expired() => record.string()?,
+                _ => continue,
+            };
+            hyperloglog::validate(&data)?;
+
+            match union.as_mut() {
+                Some(union) => {
+                    hyperloglog::merge(union, &data);
+                },
+                None => union = Some(data.into_owned()),
+            }
+        }
+        Ok(union.map_or(0, |union| hyperloglog::count(&union)))
+    }
+
+    // merges the hyperloglogs at keys into the one at destination, which is created if needed
+    // and keeps its ttl otherwise.
+    pub fn hll_merge(&self, destination: &[u8], keys: &[Vec<u8>]) -> Result<(), HllError> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(destination).is_some_and(|record| record.has_expired()) {
+            store.remove(destination);
+        }
+
+        let mut merged = match store.get(destination) {
+            Some(record) => record.string()?.into_owned(),
+            None => hyperloglog::new(),
+        };
+        hyperloglog::validate(&merged)?;
+
+        for key in keys {
+            match store.get(key) {
+                Some(record) if !record.has_expired() => {
+                    let data = record.string()?;
+                    hyperloglog::validate(&data)?;
+                    hyperloglog::merge(&mut merged, &data);
+                },
+                _ => {},
+            }
+        }
+
+        match store.get_mut(destination) {
+            Some(record) => *record.string_mut()? = merged,
+            None => {
+                store.insert(destination.to_vec(), Record::from_vec(merged));
+            },
+        }
+        Ok(())
+    }
+
+    // pushes the values one at a time onto the given end of the list at key, so LPUSH a b c
+    // leaves c at the head. a missing key is created unless existing_only is set, in which
+    // case nothing happens. returns the length of the list afterwards.
+    pub fn push(&self, key: &[u8], values: Vec<Vec<u8>>, end: ListEnd, existing_only: bool) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        let items = match store.get_mut(key) {
+            Some(record) => record.list_mut()?,
+            None if existing_only => return Ok(0),
+            None => {
+                store.insert(key.to_vec(), Record::new(Value::List(VecDeque::new())));
+                store.get_mut(key).unwrap().list_mut()?
+            },
+        };
+
+        for value in values {
+            match end {
+                ListEnd::Left => items.push_front(value),
+                ListEnd::Right => items.push_back(value),
+            }
+        }
+
+        let len = items.len();
+        drop(store);
+        self.waiters.notify(key);
+        Ok(len)
+    }
+
+    // removes up to count values from the given end of the list at key, deleting the key once
+    // the list is empty. none if the key does not exist.
+    pub fn pop(&self, key: &[u8], end: ListEnd, count: usize) -> Result<Option<Vec<Vec<u8>>>, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        let items = match store.get_mut(key) {
+            Some(record) => record.list_mut()?,
+            None => return Ok(None),
+        };
+
+        let count = count.min(items.len());
+        let popped = match end {
+            ListEnd::Left => items.drain(..count).collect(),
+            ListEnd::Right => items.drain(items.len() - count..).rev().collect(),
+        };
+
+        if items.is_empty() {
+            store.remove(key);
+        }
+        Ok(Some(popped))
+    }
+
+    // pops a value off one end of the list at source and pushes it onto the given end of the
+    // list at destination under a single lock, creating destination if needed. source and
+    // destination may be the same list, which rotates it. none if source does not exist, and
+    // nothing moves if either key holds another type.
+    pub fn list_move(&self, source: &[u8], destination: &[u8], from: ListEnd, to: ListEnd) -> Result<Option<Vec<u8>>, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        for key in [source, destination] {
+            if store.peek(key).is_some_and(|record| record.has_expired()) {
+                store.remove(key);
+            }
+        }
+
+        if let Some(record) = store.peek(destination) {
+            record.list()?;
+        }
+
+        let items = match store.get_mut(source) {
+            Some(record) => record.list_mut()?,
+            None => return Ok(None),
+        };
+
+        let value = match from {
+            ListEnd::Left => items.pop_front(),
+            ListEnd::Right => items.pop_back(),
+        };
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        if items.is_empty() && source != destination {
+            store.remove(source);
+        }
+
+        let items = match store.get_mut(destination) {
+            Some(record) => record.list_mut()?,
+            None => {
+                store.insert(destination.to_vec(), Record::new(Value::List(VecDeque::new())));
+                store.get_mut(destination).unwrap().list_mut()?
+            },
+        };
+
+        match to {
+            ListEnd::Left => items.push_front(value.clone()),
+            ListEnd::Right => items.push_back(value.clone()),
+        }
+
+        drop(store);
+        self.waiters.notify(destination);
+        Ok(Some(value))
+    }
+
+    // pops up to count values off the first of keys holding a non empty list, in the order
+    // given. a key of another type fails the whole call even if a later key has values.
+    pub fn pop_first(&self, keys: &[Vec<u8>], end: ListEnd, count: usize) -> Result<Option<KeyedValues>, WrongType> {
+        for key in keys {
+            match self.pop(key, end, count)? {
+                Some(popped) if !popped.is_empty() => return Ok(Some((key.clone(), popped))),
+                _ => {},
+            }
+        }
+        Ok(None)
+    }
+
+    // retries attempt every time one of keys is pushed to until it returns something or the
+    // timeout passes, none waits forever. this is what the blocking list and stream commands
+    // park on.
+    pub async fn wait_for<T, E>(
+        &self,
+        keys: &[Vec<u8>],
+        timeout: Option<Duration>,
+        mut attempt: impl FnMut(&Self) -> Result<Option<T>, E>,
+    ) -> Result<Option<T>, E> {
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        let waiter = Arc::new(Notify::new());
+        let _guard = self.waiters.register(keys, &waiter);
+
+        loop {
+            if let Some(found) = attempt(self)? {
+                return Ok(Some(found));
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, waiter.notified()).await.is_err() {
+                        return Ok(None);
+                    }
+                },
+                None => waiter.notified().await,
+            }
+        }
+    }
+
+    // a copy of the inclusive range [start, stop] of the list at key, negative indexes count
+    // back from the tail. empty if the key does not exist.
+    pub fn list_range(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Vec<u8>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let items = match store.get(key) {
+            Some(record) if !record.has_expired() => record.list()?,
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(match list_bounds(items.len(), start, stop) {
+            Some((start, stop)) => items.range(start..=stop).cloned().collect(),
+            None => Vec::new(),
+        })
+    }
+
+    pub fn list_len(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.list()?.len()),
+            _ => Ok(0),
+        }
+    }
+
+    // the value at index in the list at key, negative indexes count back from the tail.
+    pub fn list_index(&self, key: &[u8], index: i64) -> Result<Option<Vec<u8>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let items = match store.get(key) {
+            Some(record) if !record.has_expired() => record.list()?,
+            _ => return Ok(None),
+        };
+
+        Ok(list_position(items.len(), index).map(|i| items[i].clone()))
+    }
+
+    // replaces the value at index in the list at key. none if the key does not exist and
+    // false if the index is out of range, which redis reports as two different errors.
+    pub fn list_set(&self, key: &[u8], index: i64, value: Vec<u8>) -> Result<Option<bool>, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let items = match store.get_mut(key) {
+            Some(record) if !record.has_expired() => record.list_mut()?,
+            _ => return Ok(None),
+        };
+
+        match list_position(items.len(), index) {
+            Some(i) => {
+                items[i] = value;
+                Ok(Some(true))
+            },
+            None => Ok(Some(false)),
+        }
+    }
+
+    // inserts value next to the first occurrence of pivot, scanning from the head. returns the
+    // new length, 0 when the key does not exist and -1 when the pivot was not found.
+    pub fn list_insert(&self, key: &[u8], before: bool, pivot: &[u8], value: Vec<u8>) -> Result<i64, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let items = match store.get_mut(key) {
+            Some(record) if !record.has_expired() => record.list_mut()?,
+            _ => return Ok(0),
+        };
+
+        match items.iter().position(|item| item == pivot) {
+            Some(i) => {
+                items.insert(if before { i } else { i + 1 }, value);
+                Ok(items.len() as i64)
+            },
+            None => Ok(-1),
+        }
+    }
+
+    // removes occurrences of value from the list at key, the first count from the head when
+    // count is positive, the last count from the tail when negative and all of them when 0.
+    // the key is deleted once the list is empty. returns how many were removed.
+    pub fn list_remove(&self, key: &[u8], count: i64, value: &[u8]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let items = match store.get_mut(key) {
+            Some(record) if !record.has_expired() => record.list_mut()?,
+            _ => return Ok(0),
+        };
+
+        let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+        let mut removed = 0;
+        if count < 0 {
+            let mut i = items.len();
+            while i > 0 && removed < limit {
+                i -= 1;
+                if items[i] == value {
+                    items.remove(i);
+                    removed += 1;
+                }
+            }
+        } else {
+            let mut i = 0;
+            while i < items.len() && removed < limit {
+                if items[i] == value {
+                    items.remove(i);
+                    removed += 1;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        if items.is_empty() {
+            store.remove(key);
+        }
+        Ok(removed)
+    }
+
+    // keeps only the inclusive range [start, stop] of the list at key, deleting the key when
+    // the range is empty. returns whether anything was removed.
+    pub fn list_trim(&self, key: &[u8], start: i64, stop: i64) -> Result<bool, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let items = match store.get_mut(key) {
+            Some(record) if !record.has_expired() => record.list_mut()?,
+            _ => return Ok(false),
+        };
+
+        let len = items.len();
+        match list_bounds(len, start, stop) {
+            Some((start, stop)) => {
+                items.truncate(stop + 1);
+                items.drain(..start);
+                Ok(items.len() < len)
+            },
+            None => {
+                store.remove(key);
+                Ok(true)
+            },
+        }
+    }
+
+    // adds the members to the set at key, creating it if needed. returns how many were new.
+    pub fn set_add(&self, key: &[u8], members: Vec<Vec<u8>>) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        if store.peek(key).is_none() {
+            store.insert(key.to_vec(), Record::new(Value::Set(HashSet::new())));
+        }
+
+        match &mut store.get_mut(key).unwrap().value {
+            Value::Set(set) => Ok(members.into_iter().filter(|member| set.insert(member.clone())).count()),
+            _ => Err(WrongType),
+        }
+    }
+
+    // whether each of members is in the set at key, all false if the key does not exist.
+    pub fn set_contains_many(&self, key: &[u8], members: &[Vec<u8>]) -> Result<Vec<bool>, WrongType> {
+        let store = self.store.read().unwrap();
+        let set = match store.get(key) {
+            Some(record) if !record.has_expired() => record.set()?,
+            _ => return Ok(vec![false; members.len()]),
+        };
+
+        Ok(members.iter().map(|member| set.contains(member)).collect())
+    }
+
+    // sets the scores of members of the sorted set at key as flags allow, creating it if needed.
+    // returns how many members were added and how many had their score changed.
+    pub fn zset_add(&self, key: &[u8], pairs: Vec<(f64, Vec<u8>)>, flags: ZaddFlags) -> Result<(usize, usize), WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        // xx never adds, so it never creates the key either.
+        if store.peek(key).is_none() {
+            if flags.xx {
+                return Ok((0, 0));
+            }
+            store.insert(key.to_vec(), Record::new(Value::SortedSet(SortedSet::default())));
+        }
+
+        let zset = match &mut store.get_mut(key).unwrap().value {
+            Value::SortedSet(zset) => zset,
+            _ => return Err(WrongType),
+        };
+
+        let (mut added, mut updated) = (0, 0);
+        for (score, member) in pairs {
+            match zset.score(&member) {
+                None if !flags.xx => {
+                    zset.insert(member, score);
+                    added += 1;
+                },
+                Some(current) if !flags.nx
+                    && current != score
+                    && (!flags.gt || score > current)
+                    && (!flags.lt || score < current) => {
+                    zset.insert(member, score);
+                    updated += 1;
+                },
+                _ => {},
+            }
+        }
+        Ok((added, updated))
+    }
+
+    // adds increment to the score of member like ZADD INCR, a missing member starts from 0.
+    // returns the new score, none when the flags kept it from being applied. a NaN score is
+    // returned without being applied, the caller reports it.
+    pub fn zset_incr(&self, key: &[u8], increment: f64, member: Vec<u8>, flags: ZaddFlags) -> Result<Option<f64>, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        let current = match store.get(key) {
+            Some(record) => record.sorted_set()?.score(&member),
+            None => None,
+        };
+
+        let score = match current {
+            None if flags.xx => return Ok(None),
+            Some(_) if flags.nx => return Ok(None),
+            None => increment,
+            Some(current) => current + increment,
+        };
+
+        if score.is_nan() {
+            return Ok(Some(score));
+        }
+        if current.is_some_and(|current| (flags.gt && score <= current) || (flags.lt && score >= current)) {
+            return Ok(None);
+        }
+
+        if store.peek(key).is_none() {
+            store.insert(key.to_vec(), Record::new(Value::SortedSet(SortedSet::default())));
+        }
+        if let Value::SortedSet(zset) = &mut store.get_mut(key).unwrap().value {
+            zset.insert(member, score);
+        }
+        Ok(Some(score))
+    }
+
+    pub fn zset_score(&self, key: &[u8], member: &[u8]) -> Result<Option<f64>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.sorted_set()?.score(member)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn zset_rank(&self, key: &[u8], member: &[u8], rev: bool) -> Result<Option<(usize, f64)>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.sorted_set()?.rank(member, rev)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn zset_card(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.sorted_set()?.len()),
+            _ => Ok(0),
+        }
+    }
+
+    // the members a ZRANGE selects, highest first when rev. ranks take negative indexes like
+    // LRANGE, score and lex ranges may skip offset members and return at most count of them.
+    pub fn zset_range(&self, key: &[u8], by: &ZrangeBy, rev: bool, limit: Option<(i64, i64)>) -> Result<Vec<MemberScore>, WrongType> {
+        let store = self.store.read().unwrap();
+        let zset = match store.get(key) {
+            Some(record) if !record.has_expired() => record.sorted_set()?,
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(match by {
+            ZrangeBy::Rank(start, stop) => match list_bounds(zset.len(), *start, *stop) {
+                Some((start, stop)) => zset.range(start, stop, rev),
+                None => Vec::new(),
+            },
+            ZrangeBy::Score(min, max) if rev => limited(zset.by_score(*min, *max).rev(), limit),
+            ZrangeBy::Score(min, max) => limited(zset.by_score(*min, *max), limit),
+            ZrangeBy::Lex(min, max) => {
+                let mut entries: Vec<_> = zset.by_lex(min, max).collect();
+                if rev {
+                    entries.reverse();
+                }
+                limited(entries.into_iter(), limit)
+            },
+        })
+    }
+
+    // the members the sets at keys have in common, up to limit of them. with sorted set
+    // the inputs may also be sorted sets, like ZINTERCARD allows.
+    pub fn set_intersect(&self, keys: &[Vec<u8>], limit: Option<usize>, sorted: bool) -> Result<Vec<Vec<u8>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let common = match set_inputs(&store, keys, sorted)?.into_iter().collect::<Option<Vec<_>>>() {
+            Some(inputs) => setops::intersect(inputs, limit).into_iter().cloned().collect(),
+            None => Vec::new(),
+        };
+        Ok(common)
+    }
+
+    // the result of op over the sets at keys, a missing key counts as an empty set.
+    pub fn set_combine(&self, op: SetOp, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let inputs = set_inputs(&store, keys, false)?;
+        Ok(setops::combine(op, inputs).into_iter().cloned().collect())
+    }
+
+    // stores the result of op over the sets at keys as a set at destination, replacing whatever
+    // was there. an empty result deletes destination. returns the size of the result.
+    pub fn set_combine_store(&self, destination: &[u8], op: SetOp, keys: &[Vec<u8>]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let result: HashSet<Vec<u8>> = setops::combine(op, set_inputs(&store, keys, false)?)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        let len = result.len();
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            store.insert(destination.to_vec(), Record::new(Value::Set(result)));
+        }
+        Ok(len)
+    }
+
+    // op over the sets and sorted sets at keys with their scores weighted and aggregated, lowest
+    // score first like the sorted set it would be stored as.
+    pub fn zset_combine(&self, op: SetOp, keys: &[Vec<u8>], weights: &[f64], aggregate: Aggregate) -> Result<Vec<MemberScore>, WrongType> {
+        let store = self.store.read().unwrap();
+        let result = scored_set(setops::combine_scored(op, set_inputs(&store, keys, true)?, weights, aggregate));
+        Ok(result.iter().map(|(member, score)| (member.clone(), score)).collect())
+    }
+
+    // stores the result of zset_combine as a sorted set at destination, replacing whatever was
+    // there. an empty result deletes destination. returns the size of the result.
+    pub fn zset_combine_store(&self, destination: &[u8], op: SetOp, keys: &[Vec<u8>], weights: &[f64], aggregate: Aggregate) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let result = scored_set(setops::combine_scored(op, set_inputs(&store, keys, true)?, weights, aggregate));
+
+        let len = result.len();
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            store.insert(destination.to_vec(), Record::new(Value::SortedSet(result)));
+        }
+        Ok(len)
+    }
+
+    // sets the fields of the hash at key, creating it if needed. returns how many fields
+    // were new, overwritten ones don't count.
+    pub fn hash_set(&self, key: &[u8], pairs: Vec<FieldValue>) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+
+        if store.peek(key).is_some_and(|record| record.has_expired()) {
+            store.remove(key);
+        }
+
+        if store.peek(key).is_none() {
+            store.insert(key.to_vec(), Record::new(Value::Hash(HashFields::default())));
+        }
+
+        let added = store.change_hash(key, |fields| pairs.into_iter().map(|(field, value)| fields.insert(field, value)).filter(Option::is_none).count())?;
+        Ok(added.unwrap_or(0))
+    }
+
+    pub fn hash_get(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.hash()?.get(field).cloned()),
+            _ => Ok(None),
+        }
+    }
+
+    // the values of fields in the order they were asked for, none for the missing ones.
+    pub fn hash_get_many(&self, key: &[u8], fields: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let hash = match store.get(key) {
+            Some(record) if !record.has_expired() => record.hash()?,
+            _ => return Ok(vec![None; fields.len()]),
+        };
+
+        Ok(fields.iter().map(|field| hash.get(field).cloned()).collect())
+    }
+
+    // every field and value of the hash at key, empty if the key does not exist.
+    pub fn hash_get_all(&self, key: &[u8]) -> Result<Vec<FieldValue>, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.hash()?.iter().map(|(f, v)| (f.clone(), v.clone())).collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // removes the fields from the hash at key, deleting the key once the hash is empty.
+    // returns how many fields were removed.
+    pub fn hash_del(&self, key: &[u8], fields: &[Vec<u8>]) -> Result<usize, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let removed = store.change_hash(key, |hash| fields.iter().filter(|field| hash.remove(field).is_some()).count())?;
+        Ok(removed.unwrap_or(0))
+    }
+
+    // gives each of fields in the hash at key the deadline, as long as condition admits it. a
+    // deadline that already passed removes the field. every result is missing if the key is.
+    pub fn hash_expire(&self, key: &[u8], fields: &[Vec<u8>], deadline: SystemTime, condition: Option<ExpireCondition>) -> Result<Vec<FieldExpiry>, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let expired = deadline <= SystemTime::now();
+        let results = store.change_hash(key, |hash| {
+            fields.iter().map(|field| {
+                if hash.get(field).is_none() {
+                    return FieldExpiry::Missing;
+                }
+                if condition.is_some_and(|condition| !condition.admits(hash.deadline(field), deadline)) {
+                    return FieldExpiry::Unchanged;
+                }
+                if expired {
+                    hash.remove(field);
+                    return FieldExpiry::Removed;
+                }
+                hash.set_deadline(field, Some(deadline));
+                FieldExpiry::Set
+            }).collect()
+        })?;
+        Ok(results.unwrap_or_else(|| vec![FieldExpiry::Missing; fields.len()]))
+    }
+
+    // removes the ttl of each of fields in the hash at key. none for the fields that don't
+    // exist, otherwise whether the field had a ttl to remove.
+    pub fn hash_persist(&self, key: &[u8], fields: &[Vec<u8>]) -> Result<Vec<Option<bool>>, WrongType> {
+        let mut store = self.store.write().unwrap();
+        let results = store.change_hash(key, |hash| {
+            fields.iter().map(|field| {
+                hash.get(field)?;
+                Some(hash.set_deadline(field, None).is_some())
+            }).collect()
+        })?;
+        Ok(results.unwrap_or_else(|| vec![None; fields.len()]))
+    }
+
+    // how long each of fields in the hash at key has left, shaped like ttl: none for a missing
+    // field and some(none) for one without a ttl.
+    pub fn hash_ttl(&self, key: &[u8], fields: &[Vec<u8>]) -> Result<Vec<Option<Option<Duration>>>, WrongType> {
+        let store = self.store.read().unwrap();
+        let hash = match store.peek(key) {
+            Some(record) if !record.has_expired() => record.hash()?,
+            _ => return Ok(vec![None; fields.len()]),
+        };
+
+        let now = SystemTime::now();
+        Ok(fields.iter().map(|field| {
+            hash.get(field)?;
+            Some(hash.deadline(field).map(|deadline| deadline.duration_since(now).unwrap_or_default()))
+        }).collect())
+    }
+
+    // random fields of the hash at key with their values. a positive count returns that many
+    // distinct fields at most, a negative one returns exactly -count and may repeat fields.
+    pub fn hash_random_fields(&self, key: &[u8], count: i64) -> Result<Vec<FieldValue>, WrongType> {
+        let store = self.store.read().unwrap();
+        let hash = match store.get(key) {
+            Some(record) if !record.has_expired() => record.hash()?,
+            _ => return Ok(Vec::new()),
+        };
+
+        if count < 0 {
+            let fields: Vec<(&Vec<u8>, &Vec<u8>)> = hash.iter().collect();
+            // every field can have expired without the key being gone yet.
+            if fields.is_empty() {
+                return Ok(Vec::new());
+            }
+            return Ok((0..count.unsigned_abs())
+                .map(|_| fields[(random_u64() % fields.len() as u64) as usize])
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect());
+        }
+
+        // reservoir sampling, every field is equally likely to end up in the sample.
+        let n = count as usize;
+        let mut sample: Vec<(&Vec<u8>, &Vec<u8>)> = Vec::with_capacity(n.min(hash.len()));
+        for (seen, pair) in hash.iter().enumerate() {
+            if sample.len() < n {
+                sample.push(pair);
+                continue;
+            }
+
+            let slot = random_u64() % (seen as u64 + 1);
+            if slot < n as u64 {
+                sample[slot as usize] = pair;
+            }
+        }
+
+        Ok(sample.into_iter().map(|(field, value)| (field.clone(), value.clone())).collect())
+    }
+
+    pub fn hash_len(&self, key: &[u8]) -> Result<usize, WrongType> {
+        match self.store.read().unwrap().get(key) {
+            Some(record) if !record.has_expired() => Ok(record.hash()?.len()),
+            _ => Ok(0),
+        }
+    }
+
+    // the type name of the value at key, none if it does not exist.
+    pub fn type_of(&self, key: &[u8]) -> Option<&'static str> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => Some(record.type_name()),
+            _ => None,
+        }
+    }
+
+    // removes every key and hands the old records back, so the caller decides where the
+    // cost of freeing a large dataset is paid.
+    pub fn flush(&self) -> HashMap<Vec<u8>, Record> {
+        self.store.write().unwrap().take_records()
+    }
+
+    // up to n distinct live keys chosen uniformly at random, every key when there are fewer than n.
+    // this is a reservoir sample over one pass of the store; once the store is split into shards
+    // each shard can be sampled the same way with n scaled by its share of the keys.
+    pub fn sample_keys(&self, n: usize) -> Vec<Vec<u8>> {
+        let store = self.store.read().unwrap();
+        let mut sample: Vec<Vec<u8>> = Vec::with_capacity(n.min(store.len()));
+        if n == 0 {
+            return sample;
+        }
+
+        let mut seen = 0u64;
+        for (key, record) in store.iter() {
+            if record.has_expired() {
+                continue;
+            }
+
+            seen += 1;
+            if sample.len() < n {
+                sample.push(key.clone());
+                continue;
+            }
+
+            let slot = random_u64() % seen;
+            if slot < n as u64 {
+                sample[slot as usize] = key.clone();
+            }
+        }
+
+        sample
+    }
+
+    // one step of a SCAN. keys are visited in the order of a fixed hash and the cursor is the
+    // next hash to visit, so a key that exists for the whole iteration is returned exactly once
+    // no matter what is inserted or deleted in between. every key sharing the last hash of a
+    // batch is included so collisions never straddle two calls. the keyspace keeps its keys in
+    // that order, so a call starts right at the cursor and only walks its own batch. returns
+    // the next cursor, zero once the iteration is complete.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Vec<u8>>) {
+        let store = self.store.read().unwrap();
+        let count = count.max(1);
+        let mut batch = Vec::new();
+        let mut last = None;
+
+        for (hash, key) in store.scan_order.range((cursor, Vec::new())..) {
+            if batch.len() >= count && last != Some(*hash) {
+                return (*hash, batch);
+            }
+            if store.peek(key).is_some_and(|record| !record.has_expired()) {
+                batch.push(key.clone());
+                last = Some(*hash);
+            }
+        }
+        (0, batch)
+    }
+
+    // hands every live key matching the glob pattern to visit while the read lock is held, so
+    // the caller can encode them without cloning each key first. returns how many matched, or
+    // none as soon as there are more than max matches (0 means no limit).
+    pub fn keys(&self, pattern: &[u8], max: usize, mut visit: impl FnMut(&[u8])) -> Option<usize> {
+        let store = self.store.read().unwrap();
+        let pattern = Pattern::new(pattern, false);
+        let mut matched = 0;
+
+        for (key, record) in store.iter() {
+            if record.has_expired() || !pattern.matches(key) {
+                continue;
+            }
+
+            if max > 0 && matched >= max {
+                return None;
+            }
+
+            visit(key);
+            matched += 1;
+        }
+
+        Some(matched)
+    }
+
+    // sets a new ttl on an existing key, returns false if the key does not exist.
+    pub fn expire(&self, key: &[u8], duration: Duration) -> bool {
+        self.expire_at(key, SystemTime::now() + duration)
+    }
+
+    // sets an absolute deadline on an existing key, returns false if the key does not exist.
+    pub fn expire_at(&self, key: &[u8], deadline: SystemTime) -> bool {
+        let mut store = self.store.write().unwrap();
+        match store.get(key) {
+            Some(record) if !record.has_expired() => {
+                store.set_expiry(key, Some(deadline));
+                true
+            },
+            Some(_) => {
+                store.remove(key);
+                false
+            },
+            None => false,
+        }
+    }
+
+    // removes the ttl of a key, returns false if the key does not exist or had no ttl.
+    pub fn persist(&self, key: &[u8]) -> bool {
+        let mut store = self.store.write().unwrap();
+        match store.get(key) {
+            Some(record) if !record.has_expired() => store.set_expiry(key, None).is_some(),
+            Some(_) => {
+                store.remove(key);
+                false
+            },
+            None => false,
+        }
+    }
+
+    // none if the key does not exist, some(none) if it exists without a ttl.
+    pub fn ttl(&self, key: &[u8]) -> Option<Option<Duration>> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => Some(record.remaining_ttl()),
+            _ => None,
+        }
+    }
+
+    // like ttl but reports the absolute deadline as a duration since the unix epoch.
+    pub fn expire_time(&self, key: &[u8]) -> Option<Option<Duration>> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => Some(record.expires_at().map(|deadline| {
+                deadline
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO)
+            })),
+            _ => None,
+        }
+    }
+
+    // what OBJECT ENCODING reports, none if the key does not exist.
+    pub fn encoding(&self, key: &[u8]) -> Option<&'static str> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => Some(record.value.encoding()),
+            _ => None,
+        }
+    }
+
+    // what DEBUG OBJECT reports, none if the key does not exist. looking doesn't count as an access.
+    pub fn object_info(&self, key: &[u8]) -> Option<ObjectInfo> {
+        let store = self.store.read().unwrap();
+        match store.peek(key) {
+            Some(record) if !record.has_expired() => Some(ObjectInfo {
+                kind: record.type_name(),
+                encoding: record.value.encoding(),
+                serialized_len: record.value.serialized_len(),
+                idle: record.idle_time(),
+                freq: record.frequency(&store.lfu),
+            }),
+            _ => None,
+        }
+    }
+
+    // what OBJECT IDLETIME reports, none if the key does not exist.
+    pub fn idle_time(&self, key: &[u8]) -> Option<Duration> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => Some(record.idle_time()),
+            _ => None,
+        }
+    }
+
+    // the counter DEBUG OBJECT reports as freq, none if the key does not exist.
+    pub fn frequency(&self, key: &[u8]) -> Option<u8> {
+        let store = self.store.read().unwrap();
+        match store.peek(key) {
+            Some(record) if !record.has_expired() => Some(record.frequency(&store.lfu)),
+            _ => None,
+        }
+    }
+
+    pub fn set_lfu_log_factor(&self, factor: u32) {
+        self.store.read().unwrap().lfu.set_log_factor(factor);
+    }
+
+    pub fn set_lfu_decay_time(&self, minutes: u32) {
+        self.store.read().unwrap().lfu.set_decay_time(minutes);
+    }
+
+    pub fn get_compression_threshold(&self) -> usize {
+        self.store.read().unwrap().compression_threshold.load(Ordering::Relaxed)
+    }
+
+    // strings of at least bytes are compressed when they are stored from now on, the ones
+    // already stored are left as they are. 0 turns compression off.
+    pub fn set_compression_threshold(&self, bytes: usize) {
+        self.store.read().unwrap().compression_threshold.store(bytes, Ordering::Relaxed);
+    }
+
+    // the string values and how much of them is compressed, for INFO memory. walks every key.
+    pub fn string_memory(&self) -> StringMemory {
+        let store = self.store.read().unwrap();
+        let mut memory = StringMemory::default();
+        for (_, record) in store.iter() {
+            match &record.value {
+                Value::String(data) => {
+                    memory.strings += 1;
+                    memory.logical_bytes += data.len();
+                    memory.stored_bytes += data.len();
+                },
+                Value::Compressed(compressed) => {
+                    memory.strings += 1;
+                    memory.compressed += 1;
+                    memory.logical_bytes += compressed.len;
+                    memory.stored_bytes += compressed.data.len();
+                },
+                _ => {},
+            }
+        }
+        memory
+    }
+
+    pub fn is_active_expire(&self) -> bool {
+        self.active_expire.load(Ordering::Relaxed)
+    }
+
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.active_expire.store(enabled, Ordering::Relaxed);
+    }
+
+    // holds the keyspace for duration so nothing else can touch it, DEBUG SLEEP. this blocks
+    // the calling thread.
+    pub fn stall(&self, duration: Duration) {
+        let _store = self.store.write().unwrap();
+        std::thread::sleep(duration);
+    }
+
+    // removes every key whose deadline has passed, a batch at a time so writers are never held
+    // up for long. returns how many keys were removed.
+    pub fn remove_expired(&self) -> usize {
+        let now = SystemTime::now();
+        let mut total = 0;
+
+        loop {
+            let mut store = self.store.write().unwrap();
+            let removed = store.remove_expired(now, EXPIRE_BATCH);
+            let visited = store.remove_expired_fields(now, EXPIRE_BATCH);
+            drop(store);
+
+            total += removed;
+            if removed < EXPIRE_BATCH && visited < EXPIRE_BATCH {
+                return total;
+            }
+        }
+    }
+
+    // how many keys currently have a ttl.
+    pub fn expires(&self) -> usize {
+        self.store.read().unwrap().deadlines.len()
+    }
+
+    // an order independent digest of the whole dataset, all zeros when the dataset is empty.
+    // two servers holding the same keys, values and ttls will produce the same digest.
+    pub fn digest(&self) -> [u8; DIGEST_LEN] {
+        let mut digest = [0u8; DIGEST_LEN];
+
+        for (key, record) in self.store.read().unwrap().iter() {
+            if record.has_expired() {
+                continue;
+            }
+
+            for (acc, byte) in digest.iter_mut().zip(record.digest(key)) {
+                *acc ^= byte;
+            }
+        }
+
+        digest
+    }
+
+    // the layout of the aggregate at key, none if it does not exist and wrong type for strings.
+    pub fn layout(&self, key: &[u8]) -> Result<Option<Layout>, WrongType> {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => record.value.layout().map(Some).ok_or(WrongType),
+            _ => Ok(None),
+        }
+    }
+
+    // the digest of a single key, all zeros if the key does not exist.
+    pub fn digest_value(&self, key: &[u8]) -> [u8; DIGEST_LEN] {
+        match self.store.read().unwrap().peek(key) {
+            Some(record) if !record.has_expired() => record.digest(key),
+            _ => [0u8; DIGEST_LEN],
+        }
+    }
+}
+
+// applies a LIMIT offset count, a negative offset selects nothing and a negative count
+// everything after the offset.
+fn limited<'a>(entries: impl Iterator<Item = (&'a Vec<u8>, f64)>, limit: Option<(i64, i64)>) -> Vec<MemberScore> {
+    let (offset, count) = limit.unwrap_or((0, -1));
+    if offset < 0 {
+        return Vec::new();
+    }
+
+    let entries = entries.skip(offset as usize).map(|(member, score)| (member.clone(), score));
+    match usize::try_from(count) {
+        Ok(count) => entries.take(count).collect(),
+        Err(_) => entries.collect(),
+    }
+}
+
+// resolves redis style list indexes into an inclusive range of positions, none if it is empty.
+fn list_bounds(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+// the sets at keys in order, none for keys that don't exist. with sorted the inputs may also
+// be sorted sets. every key is type checked before anything is computed, so a wrong type fails
+// the call even when an earlier key is missing.
+fn set_inputs<'a>(store: &'a Keyspace, keys: &[Vec<u8>], sorted: bool) -> Result<Vec<Option<&'a dyn Members>>, WrongType> {
+    let mut inputs = Vec::with_capacity(keys.len());
+    for key in keys {
+        let input = match store.get(key) {
+            Some(record) if !record.has_expired() => Some(if sorted { record.members()? } else { record.set()? as &dyn Members }),
+            _ => None,
+        };
+        inputs.push(input);
+    }
+    Ok(inputs)
+}
+
+fn scored_set(scored: Vec<(&Vec<u8>, f64)>) -> SortedSet {
+    let mut zset = SortedSet::default();
+    for (member, score) in scored {
+        zset.insert(member.clone(), score);
+    }
+    zset
+}
+
+// resolves a single redis style list index, none if it falls outside the list.
+// the group of stream at key, which the subcommands acting on a group need to exist.
+fn stream_group<'a>(stream: &'a mut Stream, key: &[u8], group: &[u8]) -> Result<&'a mut ConsumerGroup, StreamError> {
+    stream.groups.get_mut(group).ok_or_else(|| StreamError::NoGroup { key: key.to_vec(), group: group.to_vec() })
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+fn list_position(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index < 0 || index >= len as i64 {
+        return None;
+    }
+    Some(index as usize)
+}
+
+// an order independent hash of the items, equal collections agree however they iterate.
+fn unordered_hash<T: Hash>(items: impl Iterator<Item = T>) -> u64 {
+    items
+        .map(|item| {
+            let mut hasher = DefaultHasher::new();
+            item.hash(&mut hasher);
+            hasher.finish()
+        })
+        .fold(0, |acc, hash| acc ^ hash)
+}
+
+// the background half of expiration, keys nobody reads again are still freed shortly after
+// their deadline instead of waiting for a lookup. on_cycle is told how long each sweep took.
+pub async fn active_expire(database: Arc<Database>, interval: Duration, on_cycle: impl Fn(Duration) + Send + 'static) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if database.is_active_expire() {
+            let started = std::time::Instant::now();
+            database.remove_expired();
+            on_cycle(started.elapsed());
+        }
+    }
+}
+
+// the order SCAN walks keys in, DefaultHasher::new is unkeyed so this is stable for the
+// lifetime of the process which is all a cursor needs.
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// a fresh random number per call, RandomState is reseeded every time it is created
+// which is plenty for sampling and avoids pulling in an rng.
+fn random_u64() -> u64 {
+    RandomState::new().hash_one(0u8)
+}
+
+// a random number in [0, 1).
+fn random_unit() -> f64 {
+    (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub fn digest_to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_range_pads_with_zeros() {
+        let mut record = Record::from_vec(b"Hello".to_vec());
+        assert_eq!(record.set_range(6, b"World"), Ok(11));
+        assert_eq!(&*record.string().unwrap(), b"Hello\0World");
+        assert_eq!(record.set_range(0, b"J"), Ok(11));
+        assert_eq!(&*record.string().unwrap(), b"Jello\0World");
+    }
+
+    #[test]
+    fn test_get_range_indexes() {
+        let record = Record::from_vec(b"This is a string".to_vec());
+        assert_eq!(record.get_range(0, 3).unwrap(), b"This");
+        assert_eq!(record.get_range(-3, -1).unwrap(), b"ing");
+        assert_eq!(record.get_range(0, -1).unwrap(), b"This is a string");
+        assert_eq!(record.get_range(10, 100).unwrap(), b"string");
+        assert_eq!(record.get_range(-1, -5).unwrap(), b"");
+        assert_eq!(record.get_range(5, 2).unwrap(), b"");
+        assert_eq!(record.get_range(-100, 1).unwrap(), b"Th");
+    }
+
+    #[test]
+    fn test_sample_keys() {
+        let db = Database::new();
+        assert!(db.sample_keys(3).is_empty());
+
+        for i in 0..10u8 {
+            db.set(vec![i], Record::from_vec(vec![i]));
+        }
+
+        let mut sample = db.sample_keys(4);
+        assert_eq!(sample.len(), 4);
+        sample.sort();
+        sample.dedup();
+        assert_eq!(sample.len(), 4);
+
+        let mut all = db.sample_keys(100);
+        all.sort();
+        assert_eq!(all, (0..10u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scan_visits_every_key_once() {
+        let db = Database::new();
+        for i in 0..100u8 {
+            db.set(vec![i], Record::from_vec(vec![i]));
+        }
+
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, keys) = db.scan(cursor, 7);
+            seen.extend(keys);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        seen.sort();
+        assert_eq!(seen, (0..100u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scan_order_follows_the_keyspace() {
+        let db = Database::new();
+        for i in 0..20u8 {
+            db.set(vec![i], Record::from_vec(vec![i]));
+        }
+        // overwriting a key doesn't index it twice.
+        db.set(vec![0], Record::from_vec(b"again".to_vec()));
+        db.del(&[1]);
+        let mut record = Record::from_vec(b"gone".to_vec());
+        record.set_expiry(Duration::ZERO);
+        db.set(vec![2], record);
+
+        let scan_order = |db: &Database| db.store.read().unwrap().scan_order.len();
+        assert_eq!(scan_order(&db), 19);
+        // the expired key is indexed until it is removed, but never returned.
+        let (cursor, keys) = db.scan(0, 100);
+        assert_eq!((cursor, keys.len()), (0, 18));
+        db.remove_expired();
+        assert_eq!(scan_order(&db), 18);
+
+        drop(db.flush());
+        assert_eq!(scan_order(&db), 0);
+        assert_eq!(db.scan(0, 10), (0, Vec::new()));
+    }
+
+    #[test]
+    fn test_scan_survives_concurrent_changes() {
+        let db = Database::new();
+        for i in 0..50u8 {
+            db.set(vec![i], Record::from_vec(vec![i]));
+        }
+
+        let mut seen = Vec::new();
+        let (mut cursor, keys) = db.scan(0, 10);
+        seen.extend(keys);
+
+        // churn between calls, keys 0..25 stay around for the whole iteration.
+        for i in 25..50u8 {
+            db.del(&[i]);
+        }
+        for i in 100..150u8 {
+            db.set(vec![i], Record::from_vec(vec![i]));
+        }
+
+        while cursor != 0 {
+            let (next, keys) = db.scan(cursor, 10);
+            seen.extend(keys);
+            cursor = next;
+        }
+
+        let mut unique = seen.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), seen.len());
+        for i in 0..25u8 {
+            assert!(seen.contains(&vec![i]));
+        }
+    }
+
+    // the SCAN contract under concurrent writers: every key that exists for the whole iteration
+    // is returned, and no key is returned twice within one iteration.
+    #[test]
+    fn test_scan_contract_under_concurrent_writers() {
+        use std::sync::Arc;
+        use std::sync::atomic::{ AtomicBool, Ordering };
+
+        let db = Arc::new(Database::new());
+        let stable: Vec<Vec<u8>> = (0..500u32).map(|i| format!("stable:{}", i).into_bytes()).collect();
+        for key in &stable {
+            db.set(key.clone(), Record::from_vec(b"v".to_vec()));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let writers: Vec<_> = (0..4u32)
+            .map(|w| {
+                let db = db.clone();
+                let stop = stop.clone();
+                std::thread::spawn(move || {
+                    let mut i = 0u32;
+                    while !stop.load(Ordering::Relaxed) {
+                        let key = format!("churn:{}:{}", w, i % 300).into_bytes();
+                        if i.is_multiple_of(3) {
+                            db.del(&key);
+                        } else {
+                            db.set(key, Record::from_vec(b"x".to_vec()));
+                        }
+                        i = i.wrapping_add(1);
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..10 {
+            let mut seen = Vec::new();
+            let mut cursor = 0;
+            loop {
+                let (next, keys) = db.scan(cursor, 25);
+                seen.extend(keys);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+
+            let total = seen.len();
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen.len(), total, "a key was returned twice in one iteration");
+            for key in &stable {
+                assert!(seen.binary_search(key).is_ok(), "missed {:?}", String::from_utf8_lossy(key));
+            }
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        for writer in writers {
+            writer.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_database_set_range_missing_key() {
+        let db = Database::new();
+        assert_eq!(db.set_range(b"key", 2, b""), Ok(0));
+        assert!(!db.exists(b"key"));
+        assert_eq!(db.set_range(b"key", 2, b"ab"), Ok(4));
+        assert_eq!(db.get(b"key").unwrap().into_string().unwrap(), b"\0\0ab");
+    }
+
+    #[test]
+    fn test_append_grows_the_stored_buffer() {
+        let db = Database::new();
+        assert_eq!(db.append(b"key", b"ab"), Ok(2));
+        assert_eq!(db.append(b"key", b"cd"), Ok(4));
+        assert_eq!(db.get(b"key").unwrap().into_string().unwrap(), b"abcd");
+
+        // the buffer is extended where it is, so a thousand appends only reallocate a handful
+        // of times rather than copying the value on every call.
+        let capacity = |db: &Database| match &db.store.read().unwrap().peek(b"key").unwrap().value {
+            Value::String(data) => data.capacity(),
+            _ => panic!("expected a plain string"),
+        };
+        let mut reallocations = 0;
+        let mut last = capacity(&db);
+        for _ in 0..1000 {
+            db.append(b"key", b"x").unwrap();
+            let now = capacity(&db);
+            if now != last {
+                reallocations += 1;
+                last = now;
+            }
+        }
+        assert_eq!(db.strlen(b"key"), Ok(1004));
+        assert!(reallocations <= 10, "{} reallocations", reallocations);
+
+        // an expired value is replaced rather than appended to.
+        let mut record = Record::from_vec(b"old".to_vec());
+        record.set_expiry(Duration::ZERO);
+        db.set(b"expired".to_vec(), record);
+        assert_eq!(db.append(b"expired", b"new"), Ok(3));
+        assert_eq!(db.get(b"expired").unwrap().into_string().unwrap(), b"new");
+
+        db.set(b"list".to_vec(), Record::new(Value::List(VecDeque::from([b"a".to_vec()]))));
+        assert_eq!(db.append(b"list", b"x"), Err(StringError::WrongType));
+    }
+
+    #[test]
+    fn test_append_past_the_size_limit() {
+        let db = Database::new();
+        assert_eq!(db.append_within(b"key", b"abcdef", 4), Err(StringError::TooLarge));
+        assert!(db.get(b"key").is_none());
+
+        assert_eq!(db.append_within(b"key", b"ab", 4), Ok(2));
+        assert_eq!(db.append_within(b"key", b"cd", 4), Ok(4));
+        assert_eq!(db.append_within(b"key", b"e", 4), Err(StringError::TooLarge));
+        assert_eq!(db.get(b"key").unwrap().into_string().unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn test_append_to_a_compressed_value() {
+        let value = b"abcdefgh".repeat(64);
+        let db = Database::new();
+        db.set_compression_threshold(100);
+        db.set(b"big".to_vec(), Record::from_vec(value.clone()));
+        assert!(matches!(db.get(b"big").unwrap().value, Value::Compressed(_)));
+
+        assert_eq!(db.append(b"big", b"tail"), Ok(516));
+        let mut expected = value;
+        expected.extend_from_slice(b"tail");
+        assert_eq!(db.get(b"big").unwrap().into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_compressed_strings_read_like_plain_ones() {
+        let value = b"abcdefgh".repeat(64);
+        let plain = Database::new();
+        let db = Database::new();
+        db.set_compression_threshold(100);
+
+        for db in [&plain, &db] {
+            db.set(b"big".to_vec(), Record::from_vec(value.clone()));
+            db.set(b"small".to_vec(), Record::from_vec(b"abcdefgh".to_vec()));
+        }
+        assert!(matches!(db.get(b"big").unwrap().value, Value::Compressed(_)));
+        assert!(matches!(db.get(b"small").unwrap().value, Value::String(_)));
+        assert_eq!(db.digest(), plain.digest());
+
+        assert_eq!(db.get(b"big").unwrap().into_string().unwrap(), value);
+        assert_eq!(db.strlen(b"big"), Ok(512));
+        assert_eq!(db.get_range(b"big", -3, -1), Ok(b"fgh".to_vec()));
+        assert_eq!(db.type_of(b"big"), Some("string"));
+
+        let memory = db.string_memory();
+        assert_eq!((memory.strings, memory.compressed, memory.logical_bytes), (2, 1, 520));
+        assert!(memory.stored_bytes < 100);
+
+        // changing it in place leaves it decompressed.
+        assert_eq!(db.append(b"big", b"!"), Ok(513));
+        assert!(matches!(db.get(b"big").unwrap().value, Value::String(_)));
+        assert_eq!(db.string_memory().compressed, 0);
+    }
+
+    #[test]
+    fn test_stream_ids_only_grow() {
+        let mut stream = Stream::default();
+        let fields = || vec![(b"f".to_vec(), b"v".to_vec())];
+
+        assert_eq!(stream.add(NewStreamId::Explicit(StreamId::MIN), fields(), 0), Err(StreamError::IdZero));
+        assert_eq!(stream.add(NewStreamId::AutoSeq(0), fields(), 0), Ok(StreamId::new(0, 1)));
+        assert_eq!(stream.add(NewStreamId::Explicit(StreamId::new(5, 0)), fields(), 0), Ok(StreamId::new(5, 0)));
+        assert_eq!(stream.add(NewStreamId::AutoSeq(5), fields(), 0), Ok(StreamId::new(5, 1)));
+        assert_eq!(stream.add(NewStreamId::AutoSeq(4), fields(), 0), Err(StreamError::IdNotGreater));
+        assert_eq!(stream.add(NewStreamId::Explicit(StreamId::new(5, 1)), fields(), 0), Err(StreamError::IdNotGreater));
+
+        // the clock is behind the last id, so it's carried on from instead.
+        assert_eq!(stream.add(NewStreamId::Auto, fields(), 3), Ok(StreamId::new(5, 2)));
+        assert_eq!(stream.add(NewStreamId::Auto, fields(), 9), Ok(StreamId::new(9, 0)));
+
+        stream.add(NewStreamId::Explicit(StreamId::MAX), fields(), 0).unwrap();
+        assert_eq!(stream.add(NewStreamId::Auto, fields(), 0), Err(StreamError::Exhausted));
+    }
+
+    #[test]
+    fn test_stream_add_range_and_trim() {
+        let db = Database::new();
+        let fields = |v: &str| vec![(b"f".to_vec(), v.as_bytes().to_vec())];
+        for seq in 1..=5 {
+            db.stream_add(b"s", NewStreamId::Explicit(StreamId::new(1, seq)), fields(&seq.to_string()), false, None).unwrap();
+        }
+        assert_eq!(db.stream_len(b"s"), Ok(5));
+
+        let ids = |entries: Vec<StreamEntry>| entries.into_iter().map(|(id, _)| id.seq).collect::<Vec<_>>();
+        assert_eq!(ids(db.stream_range(b"s", StreamId::new(1, 2), StreamId::new(1, 4), false, None).unwrap()), vec![2, 3, 4]);
+        assert_eq!(ids(db.stream_range(b"s", StreamId::MIN, StreamId::MAX, true, Some(2)).unwrap()), vec![5, 4]);
+        assert!(db.stream_range(b"s", StreamId::new(1, 4), StreamId::new(1, 2), false, None).unwrap().is_empty());
+        assert!(db.stream_range(b"missing", StreamId::MIN, StreamId::MAX, false, None).unwrap().is_empty());
+
+        let added = db.stream_add(b"s", NewStreamId::Auto, fields("6"), false, Some(StreamTrim::MaxLen(3))).unwrap().unwrap();
+        assert_eq!(ids(db.stream_range(b"s", StreamId::MIN, StreamId::MAX, false, None).unwrap()), vec![4, 5, added.seq]);
+        db.stream_add(b"s", NewStreamId::Auto, fields("7"), false, Some(StreamTrim::MinId(added))).unwrap();
+        assert_eq!(db.stream_len(b"s"), Ok(2));
+
+        // nothing is created when the add fails or isn't allowed to create the key.
+        assert_eq!(db.stream_add(b"new", NewStreamId::Auto, fields("x"), true, None), Ok(None));
+        assert_eq!(db.stream_add(b"new", NewStreamId::Explicit(StreamId::MIN), fields("x"), false, None), Err(StreamError::IdZero));
+        assert!(!db.exists(b"new"));
+
+        db.set(b"string".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.stream_add(b"string", NewStreamId::Auto, fields("x"), false, None), Err(StreamError::WrongType));
+        assert_eq!(db.stream_len(b"string"), Err(WrongType));
+    }
+
+    #[test]
+    fn test_stream_consumer_groups() {
+        let db = Database::new();
+        let fields = || vec![(b"f".to_vec(), b"v".to_vec())];
+        let add = |seq| db.stream_add(b"s", NewStreamId::Explicit(StreamId::new(1, seq)), fields(), false, None).unwrap();
+        let no_group = StreamError::NoGroup { key: b"s".to_vec(), group: b"g".to_vec() };
+
+        assert_eq!(db.stream_group_create(b"s", b"g", None, false), Err(StreamError::NoKey));
+        assert_eq!(db.stream_group_create(b"s", b"g", None, true), Ok(()));
+        assert_eq!(db.stream_group_create(b"s", b"g", None, false), Err(StreamError::GroupExists));
+        assert_eq!(db.stream_read_group(&[(b"s".to_vec(), None)], b"other", b"alice", None, false), Err(StreamError::NoGroup { key: b"s".to_vec(), group: b"other".to_vec() }));
+
+        for seq in 1..=3 {
+            add(seq);
+        }
+        let ids = |read: Option<Vec<KeyedGroupEntries>>| -> Vec<u64> {
+            read.unwrap_or_default().into_iter().flat_map(|(_, entries)| entries.into_iter().map(|(id, _)| id.seq)).collect()
+        };
+        let read = |consumer: &[u8], after: Option<StreamId>, count| db.stream_read_group(&[(b"s".to_vec(), after)], b"g", consumer, count, false).unwrap();
+
+        // > hands out each new entry once.
+        assert_eq!(ids(read(b"alice", None, Some(2))), vec![1, 2]);
+        assert_eq!(ids(read(b"bob", None, None)), vec![3]);
+        assert_eq!(read(b"bob", None, None), None);
+
+        // history only has the consumer's own pending entries.
+        assert_eq!(ids(read(b"alice", Some(StreamId::MIN), None)), vec![1, 2]);
+        assert_eq!(ids(read(b"alice", Some(StreamId::new(1, 1)), None)), vec![2]);
+        assert_eq!(ids(read(b"bob", Some(StreamId::MIN), None)), vec![3]);
+
+        assert_eq!(db.stream_ack(b"s", b"g", &[StreamId::new(1, 1), StreamId::new(1, 3), StreamId::new(9, 9)]), Ok(2));
+        assert_eq!(db.stream_ack(b"s", b"g", &[StreamId::new(1, 1)]), Ok(0));
+        assert_eq!(ids(read(b"alice", Some(StreamId::MIN), None)), vec![2]);
+        assert!(read(b"bob", Some(StreamId::MIN), None).unwrap()[0].1.is_empty());
+
+        // SETID moves the group back, entries delivered again change hands.
+        assert_eq!(db.stream_group_set_id(b"s", b"g", Some(StreamId::MIN)), Ok(()));
+        assert_eq!(ids(read(b"bob", None, Some(2))), vec![1, 2]);
+        assert_eq!(ids(read(b"alice", Some(StreamId::MIN), None)), Vec::<u64>::new());
+
+        assert_eq!(db.stream_group_create_consumer(b"s", b"g", b"bob"), Ok(false));
+        assert_eq!(db.stream_group_create_consumer(b"s", b"g", b"carol"), Ok(true));
+        assert_eq!(db.stream_group_delete_consumer(b"s", b"g", b"bob"), Ok(2));
+        assert_eq!(db.stream_group_delete_consumer(b"s", b"g", b"bob"), Ok(0));
+        assert_eq!(db.stream_group_destroy(b"s", b"g"), Ok(true));
+        assert_eq!(db.stream_group_destroy(b"s", b"g"), Ok(false));
+        assert_eq!(db.stream_group_create_consumer(b"s", b"g", b"bob"), Err(no_group));
+    }
+
+    #[tokio::test]
+    async fn test_stream_read_group_blocks_until_added() {
+        let db = Arc::new(Database::new());
+        db.stream_group_create(b"s", b"g", None, true).unwrap();
+
+        let waiting = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let streams = [(b"s".to_vec(), None)];
+                db.wait_for(&[b"s".to_vec()], None, |db| db.stream_read_group(&streams, b"g", b"c", None, false)).await
+            })
+        };
+        tokio::task::yield_now().await;
+        db.stream_add(b"s", NewStreamId::Explicit(StreamId::new(1, 1)), vec![(b"f".to_vec(), b"v".to_vec())], false, None).unwrap();
+
+        let read = waiting.await.unwrap().unwrap().unwrap();
+        assert_eq!(read, vec![(b"s".to_vec(), vec![(StreamId::new(1, 1), Some(vec![(b"f".to_vec(), b"v".to_vec())]))])]);
+    }
+
+    #[test]
+    fn test_hyperloglog_add_count_and_merge() {
+        let db = Database::new();
+        assert_eq!(db.hll_add(b"empty", &[]), Ok(true));
+        assert_eq!(db.hll_count(&[b"empty".to_vec()]), Ok(0));
+
+        let elements: Vec<Vec<u8>> = [&b"a"[..], b"b", b"c"].iter().map(|e| e.to_vec()).collect();
+        assert_eq!(db.hll_add(b"one", &elements), Ok(true));
+        assert_eq!(db.hll_add(b"one", &elements[..1]), Ok(false));
+        assert_eq!(db.hll_add(b"two", &[b"c".to_vec(), b"d".to_vec()]), Ok(true));
+        assert_eq!(db.hll_count(&[b"one".to_vec(), b"two".to_vec(), b"missing".to_vec()]), Ok(4));
+
+        assert_eq!(db.hll_merge(b"both", &[b"one".to_vec(), b"two".to_vec()]), Ok(()));
+        assert_eq!(db.hll_count(&[b"both".to_vec()]), Ok(4));
+        assert_eq!(db.type_of(b"both"), Some("string"));
+
+        // a plain string isn't a hyperloglog, and isn't changed by trying.
+        db.set(b"plain".to_vec(), Record::from_vec(b"HYLL".to_vec()));
+        assert_eq!(db.hll_add(b"plain", &elements), Err(HllError::Invalid));
+        assert_eq!(db.hll_merge(b"both", &[b"plain".to_vec()]), Err(HllError::Invalid));
+        assert_eq!(db.get(b"plain").unwrap().into_string().unwrap(), b"HYLL");
+        db.set(b"list".to_vec(), Record::new(Value::List(VecDeque::from([b"x".to_vec()]))));
+        assert_eq!(db.hll_count(&[b"list".to_vec()]), Err(HllError::WrongType));
+    }
+
+    #[test]
+    fn test_bit_op_store_and_position() {
+        let db = Database::new();
+        db.set(b"a".to_vec(), Record::from_vec(vec![0xf0, 0xff]));
+        db.set(b"b".to_vec(), Record::from_vec(vec![0x3c]));
+
+        assert_eq!(db.bit_op_store(b"and", BitOp::And, &[b"a".to_vec(), b"b".to_vec()]), Ok(2));
+        assert_eq!(db.get(b"and").unwrap().into_string().unwrap(), vec![0x30, 0x00]);
+        assert_eq!(db.bit_position(b"and", true, 0, None, BitUnit::Byte), Ok(Some(2)));
+        assert_eq!(db.bit_position(b"missing", true, 0, None, BitUnit::Byte), Ok(None));
+
+        // nothing to combine deletes the destination.
+        assert_eq!(db.bit_op_store(b"and", BitOp::Or, &[b"missing".to_vec()]), Ok(0));
+        assert!(!db.exists(b"and"));
+
+        db.set(b"list".to_vec(), Record::new(Value::List(VecDeque::from([b"x".to_vec()]))));
+        assert_eq!(db.bit_op_store(b"a", BitOp::Not, &[b"list".to_vec()]), Err(WrongType));
+        assert_eq!(db.get(b"a").unwrap().into_string().unwrap(), vec![0xf0, 0xff]);
+    }
+
+    #[test]
+    fn test_string_operations_reject_other_types() {
+        let db = Database::new();
+        db.set(b"list".to_vec(), Record::new(Value::List(VecDeque::from([b"a".to_vec()]))));
+
+        assert_eq!(db.type_of(b"list"), Some("list"));
+        assert_eq!(db.type_of(b"missing"), None);
+        assert_eq!(db.append(b"list", b"x"), Err(StringError::WrongType));
+        assert_eq!(db.set_range(b"list", 0, b"x"), Err(WrongType));
+        assert_eq!(db.get_range(b"list", 0, -1), Err(WrongType));
+        assert_eq!(db.strlen(b"list"), Err(WrongType));
+
+        // the failed writes left the list as it was.
+        assert_eq!(db.get(b"list").unwrap().value, Value::List(VecDeque::from([b"a".to_vec()])));
+    }
+
+    #[test]
+    fn test_collection_digest_ignores_iteration_order() {
+        let members: Vec<Vec<u8>> = (0..64).map(|i| format!("member:{}", i).into_bytes()).collect();
+        let forward = Record::new(Value::Set(members.iter().cloned().collect()));
+        let backward = Record::new(Value::Set(members.iter().rev().cloned().collect()));
+        assert_eq!(forward.digest(b"set"), backward.digest(b"set"));
+
+        // the same bytes under a different type are a different value.
+        let list = Record::new(Value::List(members.iter().cloned().collect()));
+        assert_ne!(forward.digest(b"set"), list.digest(b"set"));
+    }
+
+    #[test]
+    fn test_set_with_conditions() {
+        let db = Database::new();
+        let value = |v: &str| Record::from_vec(v.as_bytes().to_vec());
+        let nx = SetFlags { nx: true, ..SetFlags::default() };
+        let xx_get = SetFlags { xx: true, get: true, ..SetFlags::default() };
+
+        assert_eq!(db.set_with(b"foo".to_vec(), value("1"), xx_get), Ok(SetOutcome { written: false, previous: None }));
+        assert_eq!(db.set_with(b"foo".to_vec(), value("1"), nx), Ok(SetOutcome { written: true, previous: None }));
+        assert_eq!(db.set_with(b"foo".to_vec(), value("2"), nx), Ok(SetOutcome { written: false, previous: None }));
+        assert_eq!(db.set_with(b"foo".to_vec(), value("3"), xx_get), Ok(SetOutcome { written: true, previous: Some(b"1".to_vec()) }));
+
+        // the old deadline only carries over with keep_ttl.
+        let mut expiring = value("4");
+        expiring.set_expiry(Duration::from_secs(60));
+        db.set(b"foo".to_vec(), expiring);
+        let deadline = db.get(b"foo").unwrap().expires_at();
+        db.set_with(b"foo".to_vec(), value("5"), SetFlags { keep_ttl: true, ..SetFlags::default() }).unwrap();
+        assert_eq!(db.get(b"foo").unwrap().expires_at(), deadline);
+        db.set_with(b"foo".to_vec(), value("6"), SetFlags::default()).unwrap();
+        assert_eq!(db.get(b"foo").unwrap().expires_at(), None);
+        assert_eq!(db.expires(), 0);
+
+        // an expired key counts as missing.
+        let mut expired = value("old");
+        expired.set_expiry_at(SystemTime::now() - Duration::from_secs(1));
+        db.set(b"bar".to_vec(), expired);
+        let nx_get = SetFlags { nx: true, get: true, ..SetFlags::default() };
+        assert_eq!(db.set_with(b"bar".to_vec(), value("new"), nx_get), Ok(SetOutcome { written: true, previous: None }));
+
+        // SET GET leaves a key of another type alone, a plain SET overwrites it.
+        db.set(b"list".to_vec(), Record::new(Value::List(VecDeque::from([b"a".to_vec()]))));
+        let get = SetFlags { get: true, ..SetFlags::default() };
+        assert_eq!(db.set_with(b"list".to_vec(), value("1"), get), Err(WrongType));
+        assert_eq!(db.get(b"list").unwrap().type_name(), "list");
+        assert_eq!(db.set_with(b"list".to_vec(), value("1"), SetFlags::default()), Ok(SetOutcome { written: true, previous: None }));
+    }
+
+    #[test]
+    fn test_remove_if_expired_spares_fresh_values() {
+        let db = Database::new();
+        let mut expired = Record::from_vec(b"old".to_vec());
+        expired.set_expiry_at(SystemTime::now() - Duration::from_secs(1));
+
+        db.set(b"foo".to_vec(), expired.clone());
+        assert!(db.remove_if_expired(b"foo"));
+        assert!(db.get(b"foo").is_none());
+        assert!(!db.remove_if_expired(b"foo"));
+
+        // a value written after the expired one was read stays.
+        db.set(b"foo".to_vec(), expired);
+        db.set(b"foo".to_vec(), Record::from_vec(b"new".to_vec()));
+        assert!(!db.remove_if_expired(b"foo"));
+        assert_eq!(db.get(b"foo").unwrap().into_string().unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_set_many_nx_is_all_or_nothing() {
+        let db = Database::new();
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(Vec<u8>, Record)> {
+            pairs.iter().map(|(k, v)| (k.as_bytes().to_vec(), Record::from_vec(v.as_bytes().to_vec()))).collect()
+        };
+        let value = |key: &[u8]| db.get(key).map(|record| record.into_string().unwrap());
+
+        assert!(db.set_many_nx(pairs(&[("a", "1"), ("b", "1")])));
+
+        // one existing key blocks the whole batch.
+        assert!(!db.set_many_nx(pairs(&[("c", "2"), ("b", "2"), ("d", "2")])));
+        assert_eq!(value(b"b"), Some(b"1".to_vec()));
+        assert_eq!(value(b"c"), None);
+        assert_eq!(value(b"d"), None);
+
+        // an expired key doesn't count as existing.
+        let mut expired = Record::from_vec(b"old".to_vec());
+        expired.set_expiry_at(SystemTime::now() - Duration::from_secs(1));
+        db.set(b"e".to_vec(), expired);
+        assert!(db.set_many_nx(pairs(&[("e", "3"), ("f", "3")])));
+        assert_eq!(value(b"e"), Some(b"3".to_vec()));
+        assert_eq!(db.expires(), 0);
+
+        // a key repeated in the batch is written in order, the last value wins.
+        assert!(db.set_many_nx(pairs(&[("g", "1"), ("g", "2")])));
+        assert_eq!(value(b"g"), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn test_dataset_digest() {
+        let db = Database::new();
+        assert_eq!(db.digest(), [0u8; DIGEST_LEN]);
+        assert_eq!(db.digest_value(b"missing"), [0u8; DIGEST_LEN]);
+
+        // the order keys were written in doesn't matter.
+        let other = Database::new();
+        for i in 0..16 {
+            db.set(format!("key:{}", i).into_bytes(), Record::from_vec(i.to_string().into_bytes()));
+            other.set(format!("key:{}", 15 - i).into_bytes(), Record::from_vec((15 - i).to_string().into_bytes()));
+        }
+        assert_eq!(db.digest(), other.digest());
+        assert_ne!(db.digest(), [0u8; DIGEST_LEN]);
+
+        // a ttl changes the digest, but only its presence is hashed.
+        let before = db.digest();
+        assert!(db.expire(b"key:0", Duration::from_secs(60)));
+        let with_ttl = db.digest();
+        assert_ne!(with_ttl, before);
+        assert!(db.expire(b"key:0", Duration::from_secs(120)));
+        assert_eq!(db.digest(), with_ttl);
+        assert!(db.persist(b"key:0"));
+        assert_eq!(db.digest(), before);
+
+        // deleting every key brings it back to all zeros.
+        for i in 0..16 {
+            db.del(format!("key:{}", i).as_bytes());
+        }
+        assert_eq!(db.digest(), [0u8; DIGEST_LEN]);
+    }
+
+    #[test]
+    fn test_deadline_index_follows_ttl_changes() {
+        let db = Database::new();
+        let mut record = Record::from_vec(b"1".to_vec());
+        record.set_expiry(Duration::from_secs(60));
+        db.set(b"a".to_vec(), record.clone());
+        db.set(b"b".to_vec(), record);
+        db.set(b"c".to_vec(), Record::from_vec(b"1".to_vec()));
+        assert_eq!(db.expires(), 2);
+
+        // overwriting, persisting and deleting all drop the old deadline.
+        db.set(b"a".to_vec(), Record::from_vec(b"2".to_vec()));
+        assert!(db.expire(b"c", Duration::from_secs(60)));
+        assert!(db.persist(b"b"));
+        assert_eq!(db.expires(), 1);
+        assert!(db.del(b"c"));
+        assert_eq!(db.expires(), 0);
+    }
+
+    #[test]
+    fn test_remove_expired_only_takes_due_keys() {
+        let db = Database::new();
+        for i in 0..(EXPIRE_BATCH * 2 + 1) {
+            let mut record = Record::from_vec(b"1".to_vec());
+            record.set_expiry_at(SystemTime::now() - Duration::from_secs(1));
+            db.set(format!("old:{}", i).into_bytes(), record);
+        }
+
+        let mut later = Record::from_vec(b"1".to_vec());
+        later.set_expiry(Duration::from_secs(60));
+        db.set(b"later".to_vec(), later);
+        db.set(b"forever".to_vec(), Record::from_vec(b"1".to_vec()));
+
+        assert_eq!(db.remove_expired(), EXPIRE_BATCH * 2 + 1);
+        assert_eq!(db.expires(), 1);
+        assert!(db.exists(b"later") && db.exists(b"forever"));
+    }
+
+    #[tokio::test]
+    async fn test_active_expire_can_be_paused() {
+        let db = Arc::new(Database::new());
+        let mut record = Record::from_vec(b"1".to_vec());
+        record.set_expiry_at(SystemTime::now() - Duration::from_secs(1));
+        db.set(b"old".to_vec(), record);
+
+        db.set_active_expire(false);
+        tokio::spawn(active_expire(db.clone(), Duration::from_millis(1), |_| {}));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(db.expires(), 1);
+
+        db.set_active_expire(true);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(db.expires(), 0);
+    }
+
+    #[test]
+    fn test_object_info_counts_like_rdb() {
+        let db = Database::new();
+        let string = |s: &str| Record::new(Value::String(s.as_bytes().to_vec()));
+        db.set(b"small".to_vec(), string("12"));
+        db.set(b"wide".to_vec(), string("100000"));
+        db.set(b"text".to_vec(), string("hello"));
+        db.push(b"list", items(&["a", "7"]), ListEnd::Right, false).unwrap();
+
+        let serialized = |key: &[u8]| db.object_info(key).map(|info| info.serialized_len);
+        assert_eq!(serialized(b"small"), Some(2));
+        assert_eq!(serialized(b"wide"), Some(5));
+        assert_eq!(serialized(b"text"), Some(6));
+        // a length, then "a" with its length and 7 as an 8 bit integer.
+        assert_eq!(serialized(b"list"), Some(1 + 2 + 2));
+
+        let info = db.object_info(b"text").unwrap();
+        assert_eq!((info.kind, info.encoding), ("string", "embstr"));
+        assert_eq!(db.object_info(b"missing"), None);
+    }
+
+    fn items(values: &[&str]) -> Vec<Vec<u8>> {
+        values.iter().map(|v| v.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_push_order_and_existing_only() {
+        let db = Database::new();
+        assert_eq!(db.push(b"list", items(&["a"]), ListEnd::Left, true), Ok(0));
+        assert!(!db.exists(b"list"));
+
+        assert_eq!(db.push(b"list", items(&["a", "b", "c"]), ListEnd::Left, false), Ok(3));
+        assert_eq!(db.push(b"list", items(&["d"]), ListEnd::Right, true), Ok(4));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["c", "b", "a", "d"])));
+        assert_eq!(db.list_len(b"list"), Ok(4));
+
+        db.set(b"string".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.push(b"string", items(&["a"]), ListEnd::Left, false), Err(WrongType));
+        assert_eq!(db.list_len(b"string"), Err(WrongType));
+    }
+
+    #[test]
+    fn test_list_range_indexes() {
+        let db = Database::new();
+        db.push(b"list", items(&["a", "b", "c", "d", "e"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(db.list_range(b"list", 1, 2), Ok(items(&["b", "c"])));
+        assert_eq!(db.list_range(b"list", -2, -1), Ok(items(&["d", "e"])));
+        assert_eq!(db.list_range(b"list", -100, 1), Ok(items(&["a", "b"])));
+        assert_eq!(db.list_range(b"list", 3, 100), Ok(items(&["d", "e"])));
+        assert_eq!(db.list_range(b"list", 5, 10), Ok(Vec::new()));
+        assert_eq!(db.list_range(b"list", 2, 1), Ok(Vec::new()));
+        assert_eq!(db.list_range(b"list", 0, -6), Ok(Vec::new()));
+        assert_eq!(db.list_range(b"missing", 0, -1), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_pop_from_either_end() {
+        let db = Database::new();
+        assert_eq!(db.pop(b"list", ListEnd::Left, 1), Ok(None));
+
+        db.push(b"list", items(&["a", "b", "c", "d", "e"]), ListEnd::Right, false).unwrap();
+        assert_eq!(db.pop(b"list", ListEnd::Left, 2), Ok(Some(items(&["a", "b"]))));
+        assert_eq!(db.pop(b"list", ListEnd::Right, 2), Ok(Some(items(&["e", "d"]))));
+        assert_eq!(db.pop(b"list", ListEnd::Right, 0), Ok(Some(Vec::new())));
+
+        // popping the last value deletes the key.
+        assert_eq!(db.pop(b"list", ListEnd::Left, 10), Ok(Some(items(&["c"]))));
+        assert!(!db.exists(b"list"));
+    }
+
+    #[test]
+    fn test_list_move_between_and_within_lists() {
+        let db = Database::new();
+        assert_eq!(db.list_move(b"src", b"dst", ListEnd::Right, ListEnd::Left), Ok(None));
+        assert!(!db.exists(b"dst"));
+
+        db.push(b"src", items(&["a", "b", "c"]), ListEnd::Right, false).unwrap();
+        assert_eq!(db.list_move(b"src", b"dst", ListEnd::Right, ListEnd::Left), Ok(Some(b"c".to_vec())));
+        assert_eq!(db.list_move(b"src", b"dst", ListEnd::Left, ListEnd::Right), Ok(Some(b"a".to_vec())));
+        assert_eq!(db.list_range(b"dst", 0, -1), Ok(items(&["c", "a"])));
+
+        // the same key rotates the list, a single element list stays as it is.
+        assert_eq!(db.list_move(b"dst", b"dst", ListEnd::Right, ListEnd::Left), Ok(Some(b"a".to_vec())));
+        assert_eq!(db.list_range(b"dst", 0, -1), Ok(items(&["a", "c"])));
+        assert_eq!(db.list_move(b"src", b"src", ListEnd::Left, ListEnd::Right), Ok(Some(b"b".to_vec())));
+        assert_eq!(db.list_range(b"src", 0, -1), Ok(items(&["b"])));
+
+        // moving the last value deletes the source, a wrong typed destination moves nothing.
+        db.set(b"string".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.list_move(b"src", b"string", ListEnd::Left, ListEnd::Left), Err(WrongType));
+        assert_eq!(db.list_len(b"src"), Ok(1));
+        assert_eq!(db.list_move(b"src", b"dst", ListEnd::Left, ListEnd::Left), Ok(Some(b"b".to_vec())));
+        assert!(!db.exists(b"src"));
+    }
+
+    #[test]
+    fn test_pop_first_skips_empty_keys() {
+        let db = Database::new();
+        db.push(b"b", items(&["x", "y", "z"]), ListEnd::Right, false).unwrap();
+        db.push(b"c", items(&["w"]), ListEnd::Right, false).unwrap();
+
+        let keys = items(&["a", "b", "c"]);
+        assert_eq!(db.pop_first(&keys, ListEnd::Right, 2), Ok(Some((b"b".to_vec(), items(&["z", "y"])))));
+        assert_eq!(db.pop_first(&keys, ListEnd::Left, 5), Ok(Some((b"b".to_vec(), items(&["x"])))));
+        assert_eq!(db.pop_first(&keys, ListEnd::Left, 5), Ok(Some((b"c".to_vec(), items(&["w"])))));
+        assert_eq!(db.pop_first(&keys, ListEnd::Left, 5), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_wakes_on_push() {
+        let db = Arc::new(Database::new());
+        let keys = items(&["a", "b"]);
+        let waiter = {
+            let db = db.clone();
+            let keys = keys.clone();
+            tokio::spawn(async move { db.wait_for(&keys, None, |db| db.pop_first(&keys, ListEnd::Left, 1)).await })
+        };
+
+        while db.waiters.waiting(b"b") == 0 {
+            tokio::task::yield_now().await;
+        }
+        db.push(b"b", items(&["x"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(waiter.await.unwrap(), Ok(Some((b"b".to_vec(), items(&["x"])))));
+        assert!(!db.exists(b"b"));
+        assert_eq!(db.waiters.waiting(b"a"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_move_into_processing_list() {
+        let db = Arc::new(Database::new());
+        let waiter = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let keys = items(&["queue"]);
+                db.wait_for(&keys, None, |db| db.list_move(b"queue", b"processing", ListEnd::Right, ListEnd::Left)).await
+            })
+        };
+
+        while db.waiters.waiting(b"queue") == 0 {
+            tokio::task::yield_now().await;
+        }
+        db.push(b"queue", items(&["job"]), ListEnd::Left, false).unwrap();
+
+        assert_eq!(waiter.await.unwrap(), Ok(Some(b"job".to_vec())));
+        assert!(!db.exists(b"queue"));
+        assert_eq!(db.list_range(b"processing", 0, -1), Ok(items(&["job"])));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_times_out_or_fails_fast() {
+        let db = Database::new();
+        let keys = items(&["a"]);
+        let timeout = Some(Duration::from_millis(20));
+        assert_eq!(db.wait_for(&keys, timeout, |db| db.pop_first(&keys, ListEnd::Left, 1)).await, Ok(None));
+
+        // a value that is already there is taken without waiting, a wrong type fails straight away.
+        db.push(b"a", items(&["x", "y"]), ListEnd::Right, false).unwrap();
+        assert_eq!(db.wait_for(&keys, None, |db| db.pop_first(&keys, ListEnd::Right, 1)).await, Ok(Some((b"a".to_vec(), items(&["y"])))));
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        let keys = items(&["s", "a"]);
+        assert_eq!(db.wait_for(&keys, None, |db| db.pop_first(&keys, ListEnd::Left, 1)).await, Err(WrongType));
+    }
+
+    #[test]
+    fn test_set_intersections() {
+        let db = Database::new();
+        assert_eq!(db.set_add(b"a", items(&["x", "y", "z", "x"])), Ok(3));
+        assert_eq!(db.set_add(b"a", items(&["x", "w"])), Ok(1));
+        db.set_add(b"b", items(&["y", "z", "v"])).unwrap();
+
+        let mut common = db.set_intersect(&items(&["a", "b"]), None, false).unwrap();
+        common.sort();
+        assert_eq!(common, items(&["y", "z"]));
+        assert_eq!(db.set_intersect(&items(&["a", "b"]), Some(1), false).unwrap().len(), 1);
+
+        // a missing key empties the result but a wrong type anywhere is still an error.
+        assert_eq!(db.set_intersect(&items(&["a", "missing"]), None, false), Ok(Vec::new()));
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.set_intersect(&items(&["missing", "s"]), None, false), Err(WrongType));
+
+        // sorted sets only take part in the zset variant.
+        let mut zset = SortedSet::default();
+        zset.insert(b"z".to_vec(), 1.0);
+        db.set(b"zs".to_vec(), Record::new(Value::SortedSet(zset)));
+        assert_eq!(db.set_intersect(&items(&["a", "zs"]), None, false), Err(WrongType));
+        assert_eq!(db.set_intersect(&items(&["a", "zs"]), None, true), Ok(items(&["z"])));
+
+        assert_eq!(db.set_combine_store(b"dst", SetOp::Inter, &items(&["a", "b"])), Ok(2));
+        assert_eq!(db.type_of(b"dst"), Some("set"));
+        assert_eq!(db.set_combine_store(b"dst", SetOp::Inter, &items(&["a", "missing"])), Ok(0));
+        assert!(!db.exists(b"dst"));
+    }
+
+    #[test]
+    fn test_hash_fields() {
+        let db = Database::new();
+        let pairs = |p: &[(&str, &str)]| p.iter().map(|(f, v)| (f.as_bytes().to_vec(), v.as_bytes().to_vec())).collect();
+        assert_eq!(db.hash_set(b"h", pairs(&[("a", "1"), ("b", "2")])), Ok(2));
+        assert_eq!(db.hash_set(b"h", pairs(&[("a", "3"), ("c", "4")])), Ok(1));
+
+        assert_eq!(db.hash_get(b"h", b"a"), Ok(Some(b"3".to_vec())));
+        assert_eq!(db.hash_get(b"h", b"nope"), Ok(None));
+        assert_eq!(db.hash_get_many(b"h", &items(&["c", "nope"])), Ok(vec![Some(b"4".to_vec()), None]));
+        assert_eq!(db.hash_get_many(b"missing", &items(&["a", "b"])), Ok(vec![None, None]));
+        assert_eq!(db.hash_len(b"h"), Ok(3));
+
+        let mut all = db.hash_get_all(b"h").unwrap();
+        all.sort();
+        assert_eq!(all, pairs(&[("a", "3"), ("b", "2"), ("c", "4")]));
+
+        // the key goes away with its last field.
+        assert_eq!(db.hash_del(b"h", &items(&["a", "b", "nope"])), Ok(2));
+        assert_eq!(db.hash_del(b"h", &items(&["c"])), Ok(1));
+        assert!(!db.exists(b"h"));
+
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.hash_set(b"s", pairs(&[("a", "1")])), Err(WrongType));
+        assert_eq!(db.hash_get(b"s", b"a"), Err(WrongType));
+        assert_eq!(db.hash_del(b"s", &items(&["a"])), Err(WrongType));
+    }
+
+    #[test]
+    fn test_hash_random_fields() {
+        let db = Database::new();
+        let pairs = (0..10).map(|i| (format!("f{}", i).into_bytes(), i.to_string().into_bytes())).collect();
+        db.hash_set(b"h", pairs).unwrap();
+
+        let mut distinct = db.hash_random_fields(b"h", 4).unwrap();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 4);
+        assert_eq!(db.hash_random_fields(b"h", 100).unwrap().len(), 10);
+        assert!(db.hash_random_fields(b"h", 0).unwrap().is_empty());
+
+        // negative counts may repeat, so more can come back than the hash holds.
+        let repeated = db.hash_random_fields(b"h", -25).unwrap();
+        assert_eq!(repeated.len(), 25);
+        assert!(repeated.iter().all(|(field, value)| db.hash_get(b"h", field).unwrap().as_ref() == Some(value)));
+
+        assert_eq!(db.hash_random_fields(b"missing", -3), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_hash_field_ttls() {
+        let db = Database::new();
+        let pairs = |p: &[&str]| p.iter().map(|f| (f.as_bytes().to_vec(), b"v".to_vec())).collect();
+        db.hash_set(b"h", pairs(&["a", "b", "c"])).unwrap();
+        let soon = SystemTime::now() + Duration::from_secs(60);
+        let later = soon + Duration::from_secs(60);
+
+        assert_eq!(db.hash_expire(b"h", &items(&["a", "nope"]), soon, None), Ok(vec![FieldExpiry::Set, FieldExpiry::Missing]));
+        assert_eq!(db.hash_expire(b"missing", &items(&["a"]), soon, None), Ok(vec![FieldExpiry::Missing]));
+        // without a ttl a field counts as never expiring.
+        assert_eq!(db.hash_expire(b"h", &items(&["a", "b"]), later, Some(ExpireCondition::Gt)), Ok(vec![FieldExpiry::Set, FieldExpiry::Unchanged]));
+        assert_eq!(db.hash_expire(b"h", &items(&["a", "b"]), soon, Some(ExpireCondition::Lt)), Ok(vec![FieldExpiry::Set, FieldExpiry::Set]));
+        assert_eq!(db.hash_expire(b"h", &items(&["a", "c"]), later, Some(ExpireCondition::Nx)), Ok(vec![FieldExpiry::Unchanged, FieldExpiry::Set]));
+        assert_eq!(db.hash_expire(b"h", &items(&["a"]), later, Some(ExpireCondition::Xx)), Ok(vec![FieldExpiry::Set]));
+
+        let ttls = db.hash_ttl(b"h", &items(&["a", "nope"])).unwrap();
+        assert!(ttls[0].unwrap().unwrap() > Duration::from_secs(60));
+        assert_eq!(ttls[1], None);
+
+        // writing a field or persisting it drops its ttl.
+        db.hash_set(b"h", pairs(&["a"])).unwrap();
+        assert_eq!(db.hash_persist(b"h", &items(&["a", "b", "nope"])), Ok(vec![Some(false), Some(true), None]));
+        assert_eq!(db.hash_ttl(b"h", &items(&["a", "b"])), Ok(vec![Some(None), Some(None)]));
+
+        // a deadline in the past removes the field, and the key with its last one.
+        assert_eq!(db.hash_expire(b"h", &items(&["a", "b"]), SystemTime::now(), None), Ok(vec![FieldExpiry::Removed, FieldExpiry::Removed]));
+        assert_eq!(db.hash_len(b"h"), Ok(1));
+        db.hash_persist(b"h", &items(&["c"])).unwrap();
+        assert_eq!(db.hash_expire(b"h", &items(&["c"]), SystemTime::UNIX_EPOCH, None), Ok(vec![FieldExpiry::Removed]));
+        assert!(!db.exists(b"h"));
+
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.hash_expire(b"s", &items(&["a"]), soon, None), Err(WrongType));
+        assert_eq!(db.hash_ttl(b"s", &items(&["a"])), Err(WrongType));
+    }
+
+    #[tokio::test]
+    async fn test_expired_fields_are_removed() {
+        let db = Database::new();
+        let pairs = |p: &[&str]| p.iter().map(|f| (f.as_bytes().to_vec(), b"v".to_vec())).collect();
+        let index_len = |db: &Database| db.store.read().unwrap().field_deadlines.len();
+        db.hash_set(b"h", pairs(&["a", "b"])).unwrap();
+        db.hash_set(b"gone", pairs(&["a"])).unwrap();
+        let deadline = SystemTime::now() + Duration::from_millis(10);
+        db.hash_expire(b"h", &items(&["a"]), deadline, None).unwrap();
+        db.hash_expire(b"gone", &items(&["a"]), deadline, None).unwrap();
+        assert_eq!(index_len(&db), 2);
+
+        // an expired field reads as missing before anything removes it.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(db.hash_get(b"h", b"a"), Ok(None));
+        assert_eq!(db.hash_len(b"h"), Ok(1));
+        assert_eq!(db.hash_get_all(b"h"), Ok(pairs(&["b"])));
+
+        db.remove_expired();
+        assert_eq!(index_len(&db), 0);
+        assert_eq!(db.hash_get_all(b"h"), Ok(pairs(&["b"])));
+        assert!(!db.exists(b"gone"));
+
+        // replacing or deleting a hash takes its fields out of the index too.
+        db.hash_expire(b"h", &items(&["b"]), SystemTime::now() + Duration::from_secs(60), None).unwrap();
+        assert_eq!(index_len(&db), 1);
+        db.set(b"h".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(index_len(&db), 0);
+    }
+
+    #[test]
+    fn test_empty_aggregates_are_deleted() {
+        let db = Database::new();
+        let no_empty_aggregates = |db: &Database| db.store.read().unwrap().iter().all(|(_, record)| !record.value.is_empty_aggregate());
+
+        db.push(b"pop", items(&["a", "b"]), ListEnd::Right, false).unwrap();
+        db.pop(b"pop", ListEnd::Left, 1).unwrap();
+        db.pop(b"pop", ListEnd::Right, 1).unwrap();
+        db.push(b"rem", items(&["a", "a"]), ListEnd::Right, false).unwrap();
+        db.list_remove(b"rem", 0, b"a").unwrap();
+        db.push(b"trim", items(&["a"]), ListEnd::Right, false).unwrap();
+        db.list_trim(b"trim", 5, 10).unwrap();
+        db.push(b"src", items(&["a"]), ListEnd::Right, false).unwrap();
+        db.list_move(b"src", b"dst", ListEnd::Left, ListEnd::Left).unwrap();
+        db.hash_set(b"hash", vec![(b"f".to_vec(), b"v".to_vec())]).unwrap();
+        db.hash_del(b"hash", &items(&["f"])).unwrap();
+
+        for key in ["pop", "rem", "trim", "src", "hash"] {
+            assert!(!db.exists(key.as_bytes()), "{}", key);
+        }
+        assert!(db.exists(b"dst"));
+        assert!(no_empty_aggregates(&db));
+
+        // writing an empty collection over a key removes it.
+        db.set(b"dst".to_vec(), Record::new(Value::Set(HashSet::new())));
+        assert!(!db.exists(b"dst"));
+        db.set(b"stream".to_vec(), Record::new(Value::Stream(Stream::default())));
+        assert!(db.exists(b"stream"));
+    }
+
+    #[test]
+    fn test_layout_of_aggregates() {
+        let db = Database::new();
+        db.push(b"list", items(&["a", "bbb"]), ListEnd::Right, false).unwrap();
+        db.hash_set(b"hash", vec![(b"f".to_vec(), b"value".to_vec())]).unwrap();
+        db.set(b"string".to_vec(), Record::new(Value::String(b"x".to_vec())));
+
+        let list = db.layout(b"list").unwrap().unwrap();
+        assert_eq!((list.kind, list.repr, list.entries, list.bytes, list.largest), ("list", "vecdeque", 2, 4, 3));
+        assert!(list.capacity >= 2);
+        assert!(list.to_lines().starts_with("type:list\nrepr:vecdeque\nentries:2\n"));
+
+        let hash = db.layout(b"hash").unwrap().unwrap();
+        assert_eq!((hash.kind, hash.entries, hash.bytes), ("hash", 1, 6));
+
+        assert_eq!(db.layout(b"missing"), Ok(None));
+        assert_eq!(db.layout(b"string"), Err(WrongType));
+    }
+
+    #[test]
+    fn test_object_encoding_and_idle_time() {
+        let db = Database::new();
+        let string = |s: &str| Record::new(Value::String(s.as_bytes().to_vec()));
+        db.set(b"int".to_vec(), string("-42"));
+        db.set(b"padded".to_vec(), string("042"));
+        db.set(b"long".to_vec(), string(&"x".repeat(EMBSTR_MAX_LEN + 1)));
+        db.push(b"list", items(&["a"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(db.encoding(b"int"), Some("int"));
+        assert_eq!(db.encoding(b"padded"), Some("embstr"));
+        assert_eq!(db.encoding(b"long"), Some("raw"));
+        assert_eq!(db.encoding(b"list"), Some("quicklist"));
+        assert_eq!(db.encoding(b"missing"), None);
+
+        // an access starts the idle clock over, looking at it doesn't.
+        let store = db.store.read().unwrap();
+        store.peek(b"int").unwrap().accessed.0.store(unix_ms() - 5000, Ordering::Relaxed);
+        drop(store);
+        assert_eq!(db.idle_time(b"int").map(|idle| idle.as_secs()), Some(5));
+        assert_eq!(db.idle_time(b"int").map(|idle| idle.as_secs()), Some(5));
+        db.get(b"int");
+        assert_eq!(db.idle_time(b"int").map(|idle| idle.as_secs()), Some(0));
+        assert_eq!(db.idle_time(b"missing"), None);
+    }
+
+    #[test]
+    fn test_set_union_and_difference() {
+        let db = Database::new();
+        db.set_add(b"a", items(&["x", "y", "z"])).unwrap();
+        db.set_add(b"b", items(&["y", "w"])).unwrap();
+
+        let mut union = db.set_combine(SetOp::Union, &items(&["a", "missing", "b"])).unwrap();
+        union.sort();
+        assert_eq!(union, items(&["w", "x", "y", "z"]));
+
+        let mut diff = db.set_combine(SetOp::Diff, &items(&["a", "b", "missing"])).unwrap();
+        diff.sort();
+        assert_eq!(diff, items(&["x", "z"]));
+        assert_eq!(db.set_combine(SetOp::Diff, &items(&["missing", "a"])), Ok(Vec::new()));
+
+        // a destination that is also an input is read before it is replaced.
+        assert_eq!(db.set_combine_store(b"a", SetOp::Diff, &items(&["a", "b"])), Ok(2));
+        assert_eq!(db.set_combine_store(b"u", SetOp::Union, &items(&["a", "b"])), Ok(4));
+        assert_eq!(db.set_combine_store(b"u", SetOp::Diff, &items(&["b", "u"])), Ok(0));
+        assert!(!db.exists(b"u"));
+
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.set_combine(SetOp::Union, &items(&["a", "s"])), Err(WrongType));
+        assert_eq!(db.set_combine_store(b"d", SetOp::Diff, &items(&["missing", "s"])), Err(WrongType));
+    }
+
+    #[test]
+    fn test_zadd_flag_matrix() {
+        let db = Database::new();
+        let flags = |nx, xx, gt, lt| ZaddFlags { nx, xx, gt, lt };
+        let pair = |score: f64, member: &str| vec![(score, member.as_bytes().to_vec())];
+        db.zset_add(b"z", pair(5.0, "a"), ZaddFlags::default()).unwrap();
+
+        // (flags, score for the existing "a", whether it is updated).
+        let cases = [
+            (flags(false, true, false, false), 6.0, true),
+            (flags(false, true, true, false), 4.0, false),
+            (flags(false, true, true, false), 7.0, true),
+            (flags(false, true, false, true), 8.0, false),
+            (flags(false, true, false, true), 3.0, true),
+            (flags(false, false, true, false), 2.0, false),
+            (flags(false, false, false, true), 1.0, true),
+            (flags(true, false, false, false), 9.0, false),
+        ];
+        for (flags, score, updated) in cases {
+            let before = db.zset_score(b"z", b"a").unwrap().unwrap();
+            assert_eq!(db.zset_add(b"z", pair(score, "a"), flags), Ok((0, updated as usize)), "{:?} {}", flags, score);
+            let expected = if updated { score } else { before };
+            assert_eq!(db.zset_score(b"z", b"a"), Ok(Some(expected)), "{:?} {}", flags, score);
+        }
+
+        // gt and lt only look at existing members, a new one is added either way. xx never adds.
+        assert_eq!(db.zset_add(b"z", pair(100.0, "b"), flags(false, false, false, true)), Ok((1, 0)));
+        assert_eq!(db.zset_add(b"z", pair(-100.0, "c"), flags(false, false, true, false)), Ok((1, 0)));
+        assert_eq!(db.zset_add(b"z", pair(1.0, "d"), flags(false, true, true, false)), Ok((0, 0)));
+        assert_eq!(db.zset_score(b"z", b"d"), Ok(None));
+
+        // the INCR form, nil when a flag blocks it.
+        assert_eq!(db.zset_incr(b"z", 1.0, b"missing".to_vec(), flags(false, true, false, false)), Ok(None));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"a".to_vec(), flags(true, false, false, false)), Ok(None));
+        assert_eq!(db.zset_incr(b"z", -1.0, b"a".to_vec(), flags(false, false, true, false)), Ok(None));
+        assert_eq!(db.zset_incr(b"z", 0.0, b"a".to_vec(), flags(false, false, true, false)), Ok(None));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"a".to_vec(), flags(false, false, false, true)), Ok(None));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"a".to_vec(), flags(false, true, true, false)), Ok(Some(2.0)));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"e".to_vec(), flags(true, false, false, false)), Ok(Some(1.0)));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"f".to_vec(), flags(false, false, true, false)), Ok(Some(1.0)));
+        assert_eq!(db.zset_incr(b"none", 1.0, b"a".to_vec(), flags(false, true, false, false)), Ok(None));
+        assert!(!db.exists(b"none"));
+
+        // inf plus -inf is NaN, it is reported and the score stays as it was.
+        db.zset_add(b"z", pair(f64::INFINITY, "a"), ZaddFlags::default()).unwrap();
+        assert!(db.zset_incr(b"z", f64::NEG_INFINITY, b"a".to_vec(), ZaddFlags::default()).unwrap().unwrap().is_nan());
+        assert_eq!(db.zset_score(b"z", b"a"), Ok(Some(f64::INFINITY)));
+    }
+
+    #[test]
+    fn test_sorted_set_add_and_range() {
+        let db = Database::new();
+        let pairs = |pairs: &[(f64, &str)]| pairs.iter().map(|(score, member)| (*score, member.as_bytes().to_vec())).collect::<Vec<_>>();
+        let ranked = |range: Vec<MemberScore>| range.into_iter().map(|(member, score)| (String::from_utf8(member).unwrap(), score)).collect::<Vec<_>>();
+
+        assert_eq!(db.zset_add(b"z", pairs(&[(1.0, "a")]), ZaddFlags { xx: true, ..Default::default() }), Ok((0, 0)));
+        assert!(!db.exists(b"z"));
+
+        assert_eq!(db.zset_add(b"z", pairs(&[(2.0, "b"), (1.0, "a"), (1.0, "c")]), ZaddFlags::default()), Ok((3, 0)));
+        assert_eq!(db.zset_add(b"z", pairs(&[(0.5, "b"), (1.0, "a")]), ZaddFlags::default()), Ok((0, 1)));
+        assert_eq!(db.zset_add(b"z", pairs(&[(0.1, "b"), (5.0, "d")]), ZaddFlags { gt: true, ..Default::default() }), Ok((1, 0)));
+        assert_eq!(db.zset_add(b"z", pairs(&[(9.0, "a"), (9.0, "e")]), ZaddFlags { nx: true, ..Default::default() }), Ok((1, 0)));
+        assert_eq!(db.zset_card(b"z"), Ok(5));
+        assert_eq!(db.zset_score(b"z", b"b"), Ok(Some(0.5)));
+        assert_eq!(db.zset_score(b"z", b"nope"), Ok(None));
+
+        // ties are ordered by member.
+        let all = db.zset_range(b"z", &ZrangeBy::Rank(0, -1), false, None).unwrap();
+        assert_eq!(ranked(all), vec![("b".into(), 0.5), ("a".into(), 1.0), ("c".into(), 1.0), ("d".into(), 5.0), ("e".into(), 9.0)]);
+        let top = db.zset_range(b"z", &ZrangeBy::Rank(0, 1), true, None).unwrap();
+        assert_eq!(ranked(top), vec![("e".into(), 9.0), ("d".into(), 5.0)]);
+        assert_eq!(db.zset_range(b"z", &ZrangeBy::Rank(10, 20), false, None), Ok(Vec::new()));
+
+        // -0 is 0 and ( excludes the bound.
+        let by_score = |min, max| ZrangeBy::Score(min, max);
+        let within = db.zset_range(b"z", &by_score(ScoreBound::Inclusive(-0.0), ScoreBound::Exclusive(5.0)), false, None).unwrap();
+        assert_eq!(ranked(within), vec![("b".into(), 0.5), ("a".into(), 1.0), ("c".into(), 1.0)]);
+        let above = db.zset_range(b"z", &by_score(ScoreBound::Exclusive(1.0), ScoreBound::Inclusive(f64::INFINITY)), true, Some((1, 1))).unwrap();
+        assert_eq!(ranked(above), vec![("d".into(), 5.0)]);
+        assert_eq!(db.zset_range(b"z", &by_score(ScoreBound::Inclusive(5.0), ScoreBound::Inclusive(1.0)), false, None), Ok(Vec::new()));
+        assert_eq!(db.zset_range(b"z", &by_score(ScoreBound::Exclusive(f64::INFINITY), ScoreBound::Inclusive(f64::INFINITY)), false, None), Ok(Vec::new()));
+        assert_eq!(db.zset_range(b"z", &by_score(ScoreBound::Inclusive(0.0), ScoreBound::Inclusive(9.0)), false, Some((-1, 2))), Ok(Vec::new()));
+
+        db.zset_add(b"lex", pairs(&[(0.0, "a"), (0.0, "b"), (0.0, "c"), (0.0, "d")]), ZaddFlags::default()).unwrap();
+        let lex = db.zset_range(b"lex", &ZrangeBy::Lex(LexBound::Exclusive(b"a".to_vec()), LexBound::Inclusive(b"c".to_vec())), false, None).unwrap();
+        assert_eq!(ranked(lex), vec![("b".into(), 0.0), ("c".into(), 0.0)]);
+        let lex = db.zset_range(b"lex", &ZrangeBy::Lex(LexBound::Min, LexBound::Max), true, Some((0, 2))).unwrap();
+        assert_eq!(ranked(lex), vec![("d".into(), 0.0), ("c".into(), 0.0)]);
+
+        assert_eq!(db.zset_rank(b"z", b"c", false), Ok(Some((2, 1.0))));
+        assert_eq!(db.zset_rank(b"z", b"c", true), Ok(Some((2, 1.0))));
+        assert_eq!(db.zset_rank(b"z", b"e", true), Ok(Some((0, 9.0))));
+        assert_eq!(db.zset_rank(b"z", b"nope", false), Ok(None));
+        assert_eq!(db.zset_rank(b"missing", b"a", false), Ok(None));
+
+        assert_eq!(db.zset_incr(b"z", 2.5, b"a".to_vec(), ZaddFlags::default()), Ok(Some(3.5)));
+        assert_eq!(db.zset_incr(b"z", -1.0, b"a".to_vec(), ZaddFlags { gt: true, ..Default::default() }), Ok(None));
+        assert_eq!(db.zset_incr(b"z", 1.0, b"new".to_vec(), ZaddFlags { xx: true, ..Default::default() }), Ok(None));
+        assert_eq!(db.zset_incr(b"z", f64::INFINITY, b"a".to_vec(), ZaddFlags::default()), Ok(Some(f64::INFINITY)));
+        assert!(db.zset_incr(b"z", f64::NEG_INFINITY, b"a".to_vec(), ZaddFlags::default()).unwrap().unwrap().is_nan());
+        assert_eq!(db.zset_score(b"z", b"a"), Ok(Some(f64::INFINITY)));
+        assert_eq!(db.zset_incr(b"fresh", 2.0, b"a".to_vec(), ZaddFlags::default()), Ok(Some(2.0)));
+
+        // an empty result deletes the destination.
+        let keys = [b"z".to_vec(), b"lex".to_vec()];
+        assert_eq!(db.zset_combine_store(b"dest", SetOp::Inter, &keys, &[1.0, 1.0], Aggregate::Sum), Ok(4));
+        let combined = db.zset_combine(SetOp::Inter, &keys, &[1.0, 1.0], Aggregate::Sum).unwrap();
+        assert_eq!(ranked(combined), vec![("b".into(), 0.5), ("c".into(), 1.0), ("d".into(), 5.0), ("a".into(), f64::INFINITY)]);
+        assert_eq!(db.zset_combine_store(b"dest", SetOp::Inter, &[b"z".to_vec(), b"nope".to_vec()], &[1.0, 1.0], Aggregate::Sum), Ok(0));
+        assert!(!db.exists(b"dest"));
+
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.zset_add(b"s", pairs(&[(1.0, "a")]), ZaddFlags::default()), Err(WrongType));
+        assert_eq!(db.zset_incr(b"s", 1.0, b"a".to_vec(), ZaddFlags { xx: true, ..Default::default() }), Err(WrongType));
+        assert_eq!(db.zset_card(b"s"), Err(WrongType));
+    }
+
+    #[test]
+    fn test_sorted_set_score_and_lex_bounds() {
+        let db = Database::new();
+        let members = |range: Vec<MemberScore>| range.into_iter().map(|(member, _)| String::from_utf8(member).unwrap()).collect::<Vec<_>>();
+        let scores = ["a", "b", "c", "d", "e"].iter().enumerate().map(|(i, m)| (i as f64 + 1.0, m.as_bytes().to_vec())).collect();
+        db.zset_add(b"z", scores, ZaddFlags::default()).unwrap();
+        let lex = ["a", "b", "c", "d", "e"].iter().map(|m| (0.0, m.as_bytes().to_vec())).collect();
+        db.zset_add(b"lex", lex, ZaddFlags::default()).unwrap();
+
+        let by_score = |min, max, rev, limit| members(db.zset_range(b"z", &ZrangeBy::Score(min, max), rev, limit).unwrap());
+        use ScoreBound::{ Inclusive, Exclusive };
+        assert_eq!(by_score(Exclusive(1.0), Exclusive(4.0), false, None), vec!["b", "c"]);
+        assert_eq!(by_score(Exclusive(1.0), Exclusive(2.0), false, None), Vec::<String>::new());
+        assert_eq!(by_score(Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY), false, None), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(by_score(Exclusive(f64::NEG_INFINITY), Inclusive(2.0), false, None), vec!["a", "b"]);
+        assert_eq!(by_score(Inclusive(4.0), Exclusive(f64::INFINITY), false, None), vec!["d", "e"]);
+
+        // rev walks the same range from the top, limit applies after the direction.
+        assert_eq!(by_score(Inclusive(2.0), Inclusive(4.0), true, None), vec!["d", "c", "b"]);
+        assert_eq!(by_score(Exclusive(2.0), Inclusive(f64::INFINITY), true, Some((0, 2))), vec!["e", "d"]);
+
+        // a negative count takes everything past the offset, a negative offset nothing.
+        assert_eq!(by_score(Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY), false, Some((1, -1))), vec!["b", "c", "d", "e"]);
+        assert_eq!(by_score(Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY), false, Some((1, -5))), vec!["b", "c", "d", "e"]);
+        assert_eq!(by_score(Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY), false, Some((3, 0))), Vec::<String>::new());
+        assert_eq!(by_score(Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY), false, Some((9, -1))), Vec::<String>::new());
+
+        let by_lex = |min, max, rev, limit| members(db.zset_range(b"lex", &ZrangeBy::Lex(min, max), rev, limit).unwrap());
+        let inclusive = |member: &str| LexBound::Inclusive(member.as_bytes().to_vec());
+        let exclusive = |member: &str| LexBound::Exclusive(member.as_bytes().to_vec());
+        assert_eq!(by_lex(LexBound::Min, LexBound::Max, false, None), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(by_lex(LexBound::Min, exclusive("c"), false, None), vec!["a", "b"]);
+        assert_eq!(by_lex(exclusive("c"), LexBound::Max, false, None), vec!["d", "e"]);
+        assert_eq!(by_lex(inclusive("b"), inclusive("d"), true, None), vec!["d", "c", "b"]);
+        assert_eq!(by_lex(exclusive("b"), exclusive("c"), false, None), Vec::<String>::new());
+        assert_eq!(by_lex(LexBound::Max, LexBound::Min, false, None), Vec::<String>::new());
+        assert_eq!(by_lex(LexBound::Min, LexBound::Max, true, Some((1, -1))), vec!["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_set_contains_many() {
+        let db = Database::new();
+        db.set_add(b"set", items(&["a", "b"])).unwrap();
+        assert_eq!(db.set_contains_many(b"set", &items(&["b", "c", "a", "b"])), Ok(vec![true, false, true, true]));
+        assert_eq!(db.set_contains_many(b"missing", &items(&["a", "b"])), Ok(vec![false, false]));
+
+        db.set(b"s".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.set_contains_many(b"s", &items(&["a"])), Err(WrongType));
+    }
+
+    #[test]
+    fn test_list_index_set_and_insert() {
+        let db = Database::new();
+        db.push(b"list", items(&["a", "b", "c"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(db.list_index(b"list", -1), Ok(Some(b"c".to_vec())));
+        assert_eq!(db.list_index(b"list", 3), Ok(None));
+        assert_eq!(db.list_set(b"list", -3, b"x".to_vec()), Ok(Some(true)));
+        assert_eq!(db.list_set(b"list", 3, b"x".to_vec()), Ok(Some(false)));
+        assert_eq!(db.list_set(b"missing", 0, b"x".to_vec()), Ok(None));
+
+        assert_eq!(db.list_insert(b"list", true, b"b", b"y".to_vec()), Ok(4));
+        assert_eq!(db.list_insert(b"list", false, b"c", b"z".to_vec()), Ok(5));
+        assert_eq!(db.list_insert(b"list", false, b"nope", b"z".to_vec()), Ok(-1));
+        assert_eq!(db.list_insert(b"missing", false, b"a", b"z".to_vec()), Ok(0));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["x", "y", "b", "c", "z"])));
+    }
+
+    #[test]
+    fn test_list_remove_by_direction() {
+        let db = Database::new();
+        db.push(b"list", items(&["a", "b", "a", "c", "a"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(db.list_remove(b"list", -1, b"a"), Ok(1));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["a", "b", "a", "c"])));
+        assert_eq!(db.list_remove(b"list", 1, b"a"), Ok(1));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["b", "a", "c"])));
+
+        db.push(b"list", items(&["b"]), ListEnd::Right, false).unwrap();
+        assert_eq!(db.list_remove(b"list", 0, b"b"), Ok(2));
+        assert_eq!(db.list_remove(b"list", 0, b"a"), Ok(1));
+        assert_eq!(db.list_remove(b"list", 0, b"c"), Ok(1));
+        assert!(!db.exists(b"list"));
+    }
+
+    #[test]
+    fn test_list_trim() {
+        let db = Database::new();
+        db.push(b"list", items(&["a", "b", "c", "d", "e"]), ListEnd::Right, false).unwrap();
+
+        assert_eq!(db.list_trim(b"list", 0, -1), Ok(false));
+        assert_eq!(db.list_trim(b"list", 1, -2), Ok(true));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["b", "c", "d"])));
+
+        // a capped list keeps the newest entries at the head.
+        db.push(b"list", items(&["x"]), ListEnd::Left, false).unwrap();
+        assert_eq!(db.list_trim(b"list", 0, 2), Ok(true));
+        assert_eq!(db.list_range(b"list", 0, -1), Ok(items(&["x", "b", "c"])));
+
+        // an empty range deletes the key.
+        assert_eq!(db.list_trim(b"list", 5, 10), Ok(true));
+        assert!(!db.exists(b"list"));
+
+        db.set(b"string".to_vec(), Record::from_vec(b"x".to_vec()));
+        assert_eq!(db.list_trim(b"string", 0, 1), Err(WrongType));
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::Path;
+use std::time::{ Duration, Instant };
+use crate::config::DEFAULT_MAXCLIENTS;
+use crate::server::ServerArguments;
+
+// file descriptors redis keeps for itself on top of one per client, for logs, persistence and
+// listening sockets.
+const RESERVED_FDS: u64 = 32;
+
+// a clock coarser than this makes millisecond ttls and latency numbers unreliable.
+const MAX_CLOCK_RESOLUTION: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+    // the check doesn't apply to this platform or configuration.
+    Skip,
+}
+
+impl Status {
+    fn label(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+// the diagnostics --check-system prints, quick looks at the machine for what makes a server
+// misbehave later rather than fail to start. none of them change anything but a temp file.
+pub fn check_system(args: &ServerArguments) -> Vec<Check> {
+    let mut checks = vec![
+        clock_resolution(),
+        open_files(args.maxclients.unwrap_or(DEFAULT_MAXCLIENTS)),
+        overcommit(),
+        transparent_huge_pages(),
+        writable("working dir", Path::new(".")),
+    ];
+
+    if let Some(pidfile) = args.pidfile_path() {
+        let dir = Path::new(&pidfile).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        checks.push(writable("pidfile dir", dir));
+    }
+
+    checks.push(Check::new("persistence", Status::Skip, "no rdb or aof support, nothing to read"));
+    checks
+}
+
+// one line per check, then a summary.
+pub fn report(checks: &[Check]) -> String {
+    let mut report = String::new();
+    for check in checks {
+        report.push_str(&format!("[{:<4}] {:<24} {}\n", check.status.label(), check.name, check.detail));
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    report.push_str(&format!(
+        "{} ok, {} warnings, {} failed, {} skipped\n",
+        count(Status::Ok), count(Status::Warn), count(Status::Fail), count(Status::Skip)
+    ));
+    report
+}
+
+// warnings still pass, they are worth knowing about but a server runs with them.
+pub fn passed(checks: &[Check]) -> bool {
+    checks.iter().all(|check| check.status != Status::Fail)
+}
+
+// the smallest step the monotonic clock takes, over a few samples.
+fn clock_resolution() -> Check {
+    let resolution = (0..100)
+        .map(|_| {
+            let start = Instant::now();
+            loop {
+                let elapsed = start.elapsed();
+                if !elapsed.is_zero() {
+                    return elapsed;
+                }
+            }
+        })
+        .min()
+        .unwrap_or_default();
+
+    let status = if resolution > MAX_CLOCK_RESOLUTION { Status::Warn } else { Status::Ok };
+    Check::new("clock resolution", status, format!("{:?}", resolution))
+}
+
+fn open_files(maxclients: usize) -> Check {
+    let needed = maxclients as u64 + RESERVED_FDS;
+    match soft_open_files_limit() {
+        Some(limit) if limit >= needed => Check::new("open files limit", Status::Ok, format!("{} for maxclients {}", limit, maxclients)),
+        Some(limit) => Check::new(
+            "open files limit",
+            Status::Warn,
+            format!("{} but maxclients {} needs {}, raise it with ulimit -n", limit, maxclients, needed),
+        ),
+        None => Check::new("open files limit", Status::Skip, "can't read the limit on this platform"),
+    }
+}
+
+// the soft limit from /proc/self/limits, none where there is no procfs or it is unlimited.
+fn soft_open_files_limit() -> Option<u64> {
+    let limits = fs::read_to_string("/proc/self/limits").ok()?;
+    let line = limits.lines().find(|line| line.starts_with("Max open files"))?;
+    line["Max open files".len()..].split_whitespace().next()?.parse().ok()
+}
+
+// redis forks to save in the background, without overcommit that fork can fail on a busy box.
+fn overcommit() -> Check {
+    match fs::read_to_string("/proc/sys/vm/overcommit_memory").as_deref().map(str::trim) {
+        Ok("1") => Check::new("memory overcommit", Status::Ok, "vm.overcommit_memory = 1"),
+        Ok(mode) => Check::new(
+            "memory overcommit",
+            Status::Warn,
+            format!("vm.overcommit_memory = {}, set it to 1 so forking under memory pressure can't fail", mode),
+        ),
+        Err(_) => Check::new("memory overcommit", Status::Skip, "no /proc/sys/vm/overcommit_memory"),
+    }
+}
+
+// huge pages make copy on write after a fork copy far more than it needs to.
+fn transparent_huge_pages() -> Check {
+    match fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled") {
+        Ok(modes) if modes.contains("[always]") => Check::new(
+            "transparent huge pages",
+            Status::Warn,
+            "enabled always, set it to madvise or never to keep forks from copying memory",
+        ),
+        Ok(modes) => Check::new("transparent huge pages", Status::Ok, modes.trim().to_string()),
+        Err(_) => Check::new("transparent huge pages", Status::Skip, "no /sys/kernel/mm/transparent_hugepage"),
+    }
+}
+
+// creates and removes a file in dir, what writing a pidfile or dump there would need.
+fn writable(name: &'static str, dir: &Path) -> Check {
+    let probe = dir.join(format!(".check-system-{}.tmp", std::process::id()));
+    match fs::write(&probe, b"check").and_then(|_| fs::remove_file(&probe)) {
+        Ok(()) => Check::new(name, Status::Ok, format!("{} is writable", dir.display())),
+        Err(e) => Check::new(name, Status::Fail, format!("{} is not writable: {}", dir.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_and_pass() {
+        let checks = vec![
+            Check::new("clock resolution", Status::Ok, "40ns"),
+            Check::new("memory overcommit", Status::Warn, "vm.overcommit_memory = 0"),
+        ];
+        assert!(passed(&checks));
+        let report = report(&checks);
+        assert!(report.starts_with("[ok  ] clock resolution"));
+        assert!(report.ends_with("1 ok, 1 warnings, 0 failed, 0 skipped\n"));
+
+        let unwritable = writable("dir", Path::new("/definitely/not/a/dir"));
+        assert_eq!(unwritable.status, Status::Fail);
+        assert!(!passed(&[unwritable]));
+    }
+}
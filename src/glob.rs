@@ -0,0 +1,382 @@
+// glob style pattern matching with the same rules redis uses for KEYS, SCAN MATCH and PSUBSCRIBE.
+//   *       matches any sequence of bytes, including none
+//   ?       matches exactly one byte
+//   [abc]   matches one of the listed bytes, [^abc] matches one byte not listed, [a-z] a range
+//   \x      matches x literally
+use std::sync::Arc;
+
+// how many compiled patterns a connection keeps, a SCAN loop only ever uses one.
+const PATTERN_CACHE_SIZE: usize = 16;
+
+// matches the pattern against string as written, fine for a one off match. anything matching
+// the same pattern over and over should compile it into a Pattern once.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut p = 0;
+    let mut s = 0;
+
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                // collapse runs of stars, a trailing star matches everything left.
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+
+                for start in s..=string.len() {
+                    if glob_match(&pattern[p + 1..], &string[start..], nocase) {
+                        return true;
+                    }
+                }
+
+                return false;
+            },
+
+            b'?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+            },
+
+            b'[' => {
+                if s >= string.len() {
+                    return false;
+                }
+
+                p += 1;
+                let negate = p < pattern.len() && pattern[p] == b'^';
+                if negate {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    if p >= pattern.len() {
+                        // unterminated class, treat the end of the pattern as its end.
+                        p -= 1;
+                        break;
+                    }
+
+                    if pattern[p] == b']' {
+                        break;
+                    }
+
+                    if pattern[p] == b'\\' && p + 1 < pattern.len() {
+                        p += 1;
+                        if eq(pattern[p], string[s], nocase) {
+                            matched = true;
+                        }
+                    } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                        let (mut start, mut end) = (pattern[p], pattern[p + 2]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+
+                        let c = if nocase { string[s].to_ascii_lowercase() } else { string[s] };
+                        let (start, end) = if nocase {
+                            (start.to_ascii_lowercase(), end.to_ascii_lowercase())
+                        } else {
+                            (start, end)
+                        };
+
+                        if c >= start && c <= end {
+                            matched = true;
+                        }
+                        p += 2;
+                    } else if eq(pattern[p], string[s], nocase) {
+                        matched = true;
+                    }
+
+                    p += 1;
+                }
+
+                if matched == negate {
+                    return false;
+                }
+                s += 1;
+            },
+
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if s >= string.len() || !eq(pattern[p], string[s], nocase) {
+                    return false;
+                }
+                s += 1;
+            },
+
+            c => {
+                if s >= string.len() || !eq(c, string[s], nocase) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+
+        p += 1;
+    }
+
+    s == string.len()
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase { a.eq_ignore_ascii_case(&b) } else { a == b }
+}
+
+// a glob pattern parsed once, it matches exactly what glob_match does without going over
+// escapes and classes again for every string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    nocase: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Byte(u8),
+    One,
+    Star,
+    Class { negate: bool, items: Vec<ClassItem> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+    Byte(u8),
+    Range(u8, u8),
+}
+
+impl Pattern {
+    pub fn new(pattern: &[u8], nocase: bool) -> Self {
+        let mut tokens = Vec::new();
+        let mut p = 0;
+
+        while p < pattern.len() {
+            match pattern[p] {
+                // runs of stars are one star.
+                b'*' => {
+                    if tokens.last() != Some(&Token::Star) {
+                        tokens.push(Token::Star);
+                    }
+                },
+
+                b'?' => tokens.push(Token::One),
+
+                b'[' => {
+                    p += 1;
+                    let negate = p < pattern.len() && pattern[p] == b'^';
+                    if negate {
+                        p += 1;
+                    }
+
+                    let mut items = Vec::new();
+                    loop {
+                        if p >= pattern.len() {
+                            p -= 1;
+                            break;
+                        }
+
+                        if pattern[p] == b']' {
+                            break;
+                        }
+
+                        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+                            p += 1;
+                            items.push(ClassItem::Byte(pattern[p]));
+                        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                            let (start, end) = (pattern[p], pattern[p + 2]);
+                            items.push(ClassItem::Range(start.min(end), start.max(end)));
+                            p += 2;
+                        } else {
+                            items.push(ClassItem::Byte(pattern[p]));
+                        }
+                        p += 1;
+                    }
+                    tokens.push(Token::Class { negate, items });
+                },
+
+                b'\\' if p + 1 < pattern.len() => {
+                    p += 1;
+                    tokens.push(Token::Byte(pattern[p]));
+                },
+
+                c => tokens.push(Token::Byte(c)),
+            }
+            p += 1;
+        }
+
+        Pattern { tokens, nocase }
+    }
+
+    // walks the string once and only goes back to the last star on a mismatch, so a pattern
+    // with many stars doesn't blow up the way the recursion in glob_match can.
+    pub fn matches(&self, string: &[u8]) -> bool {
+        let (mut t, mut s) = (0, 0);
+        // the token after the last star seen and where in the string it was last tried.
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        loop {
+            match self.tokens.get(t) {
+                Some(Token::Star) => {
+                    backtrack = Some((t + 1, s));
+                    t += 1;
+                    continue;
+                },
+                Some(token) if s < string.len() && self.accepts(token, string[s]) => {
+                    t += 1;
+                    s += 1;
+                    continue;
+                },
+                None if s == string.len() => return true,
+                _ => {},
+            }
+
+            match backtrack {
+                Some((next, start)) if start < string.len() => {
+                    backtrack = Some((next, start + 1));
+                    t = next;
+                    s = start + 1;
+                },
+                _ => return false,
+            }
+        }
+    }
+
+    fn accepts(&self, token: &Token, c: u8) -> bool {
+        match token {
+            Token::Byte(b) => eq(*b, c, self.nocase),
+            Token::One => true,
+            Token::Star => false,
+            Token::Class { negate, items } => {
+                let matched = items.iter().any(|item| match item {
+                    ClassItem::Byte(b) => eq(*b, c, self.nocase),
+                    ClassItem::Range(start, end) if self.nocase => {
+                        (start.to_ascii_lowercase()..=end.to_ascii_lowercase()).contains(&c.to_ascii_lowercase())
+                    },
+                    ClassItem::Range(start, end) => (*start..=*end).contains(&c),
+                });
+                matched != *negate
+            },
+        }
+    }
+}
+
+// the patterns a connection matched with lately, so calling SCAN MATCH again with the next
+// cursor doesn't compile the same pattern again. it is only ever a handful, so they are
+// searched in order rather than hashed, and once full it simply starts over.
+#[derive(Debug, Default)]
+pub struct PatternCache {
+    patterns: Vec<(Vec<u8>, Arc<Pattern>)>,
+}
+
+impl PatternCache {
+    pub fn get(&mut self, pattern: &[u8], nocase: bool) -> Arc<Pattern> {
+        let cached = self.patterns.iter().find(|(source, compiled)| source == pattern && compiled.nocase == nocase);
+        if let Some((_, compiled)) = cached {
+            return compiled.clone();
+        }
+
+        if self.patterns.len() >= PATTERN_CACHE_SIZE {
+            self.patterns.clear();
+        }
+        let compiled = Arc::new(Pattern::new(pattern, nocase));
+        self.patterns.push((pattern.to_vec(), compiled.clone()));
+        compiled
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_literals_and_wildcards() {
+        assert!(glob_match(b"hello", b"hello", false));
+        assert!(!glob_match(b"hello", b"hell", false));
+        assert!(glob_match(b"*", b"", false));
+        assert!(glob_match(b"h*o", b"hello", false));
+        assert!(glob_match(b"h?llo", b"hallo", false));
+        assert!(!glob_match(b"h?llo", b"hllo", false));
+        assert!(glob_match(b"*llo", b"hello", false));
+        assert!(!glob_match(b"*x*", b"hello", false));
+    }
+
+    #[test]
+    fn test_glob_classes() {
+        assert!(glob_match(b"h[ae]llo", b"hello", false));
+        assert!(!glob_match(b"h[ae]llo", b"hillo", false));
+        assert!(glob_match(b"h[^e]llo", b"hallo", false));
+        assert!(!glob_match(b"h[^e]llo", b"hello", false));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo", false));
+        assert!(glob_match(b"h[b-a]llo", b"hbllo", false));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo", false));
+    }
+
+    #[test]
+    fn test_glob_escapes_and_case() {
+        assert!(glob_match(b"h\\*llo", b"h*llo", false));
+        assert!(!glob_match(b"h\\*llo", b"hello", false));
+        assert!(glob_match(b"HELLO", b"hello", true));
+        assert!(!glob_match(b"HELLO", b"hello", false));
+    }
+
+    #[test]
+    fn test_compiled_patterns_agree_with_glob_match() {
+        let patterns: &[&[u8]] = &[
+            b"", b"*", b"**", b"a*", b"*a", b"*a*b*", b"a?b", b"?*?", b"[ab]*", b"[^ab]?", b"[a-c]",
+            b"[c-a]*", b"[a-]", b"[\\]]*", b"[", b"[^", b"[ab", b"\\", b"a\\", b"\\*a", b"*[B-C]a*", b"[]a]",
+        ];
+        let alphabet = [b'a', b'b', b'c', b'A', b'-', b']', b'*', b'\\'];
+
+        // every string up to 3 bytes long over the alphabet, in both cases.
+        let mut strings: Vec<Vec<u8>> = vec![Vec::new()];
+        for len in 1..=3 {
+            let mut next = Vec::new();
+            for string in strings.iter().filter(|s| s.len() == len - 1) {
+                for c in alphabet {
+                    let mut longer = string.clone();
+                    longer.push(c);
+                    next.push(longer);
+                }
+            }
+            strings.extend(next);
+        }
+
+        for pattern in patterns {
+            for nocase in [false, true] {
+                let compiled = Pattern::new(pattern, nocase);
+                for string in &strings {
+                    assert_eq!(
+                        compiled.matches(string),
+                        glob_match(pattern, string, nocase),
+                        "{:?} against {:?} nocase {}", String::from_utf8_lossy(pattern), String::from_utf8_lossy(string), nocase
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pattern_cache_reuses_compiled_patterns() {
+        let mut cache = PatternCache::default();
+        let first = cache.get(b"user:*", false);
+        assert!(Arc::ptr_eq(&first, &cache.get(b"user:*", false)));
+        assert!(!Arc::ptr_eq(&first, &cache.get(b"user:*", true)));
+
+        for i in 0..PATTERN_CACHE_SIZE {
+            cache.get(format!("key:{}", i).as_bytes(), false);
+        }
+        assert!(cache.len() <= PATTERN_CACHE_SIZE);
+        assert!(first.matches(b"user:1"));
+    }
+}
@@ -0,0 +1,57 @@
+use tokio::net::{ TcpListener, TcpStream };
+use tokio::io::AsyncWriteExt;
+use std::io;
+
+// a bare bones liveness probe for l4 load balancers, every connection gets "+PONG\r\n" and is
+// closed right away. it never reads from the socket, so probes don't need to speak resp and
+// don't occupy a client slot on the main listener.
+#[derive(Debug)]
+pub struct HealthListener {
+    listener: TcpListener,
+}
+
+impl HealthListener {
+    pub async fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(Self::respond(stream));
+                },
+                // a failed probe is the load balancer's problem, keep serving the next one.
+                Err(_) => continue,
+            }
+        }
+    }
+
+    async fn respond(mut stream: TcpStream) {
+        let _ = stream.write_all(b"+PONG\r\n").await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_health_probe_gets_pong_and_eof() {
+        let health = HealthListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = health.local_addr().unwrap();
+        tokio::spawn(health.run());
+
+        let mut probe = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        probe.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"+PONG\r\n");
+    }
+}
@@ -1,76 +1,325 @@
 use tokio::sync::Mutex;
-use bytes::BytesMut;
+use bytes::{ Buf, BytesMut };
+use std::fmt::Debug;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::Instant;
+use crate::config::DEFAULT_REPL_BACKLOG_SIZE;
 use crate::resp::{Resp, RespEncoder};
 use crate::connection::Connection;
 
+// the i/o the history needs from a replica, a tcp connection in the server and an
+// in memory double in tests so propagation can be checked without sockets.
+pub trait ReplicaLink: Debug + Send {
+    // writes the bytes to the replica and flushes them.
+    fn send(&mut self, bytes: &[u8]) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+impl ReplicaLink for Connection {
+    async fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write(bytes);
+        Ok(self.flush().await?)
+    }
+}
+
 #[derive(Debug)]
-pub struct Replica {
-    pub stream: Connection,
+pub struct Replica<L: ReplicaLink = Connection> {
+    pub id: u64,
+    pub stream: L,
     // the point in histroy where the replica started receiving command forwards.
     pub start_offset: usize,
     // this is the last offset len we sent to this replica.
     pub last_offset: usize,
+    // the last offset the replica acknowledged having processed, it starts at the point
+    // the replica was attached since the full resync covers everything before it.
+    pub ack_offset: usize,
+    pub last_ack: Instant,
 }
 
-impl Replica {
-    pub fn new(stream: Connection, start_offset: usize, last_offset: usize) -> Self {
-        Replica { stream, start_offset, last_offset }
+impl<L: ReplicaLink> Replica<L> {
+    pub fn new(id: u64, stream: L, start_offset: usize, last_offset: usize) -> Self {
+        Replica { id, stream, start_offset, last_offset, ack_offset: start_offset, last_ack: Instant::now() }
     }
 
     pub fn update_offset(&mut self, offset: usize) {
         self.last_offset = offset;
     }
+
+    pub fn ack(&mut self, offset: usize) {
+        self.ack_offset = offset;
+        self.last_ack = Instant::now();
+    }
+}
+
+// a point in time view of a replica for INFO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaStatus {
+    pub id: u64,
+    pub ack_offset: usize,
+    // how many bytes of the history the replica has not acknowledged yet.
+    pub lag_bytes: usize,
+    // seconds since the replica last acknowledged anything.
+    pub lag_seconds: u64,
 }
 
 #[derive(Debug)]
-pub struct History {
-    inner: Mutex<HistoryInner>
+pub struct History<L: ReplicaLink = Connection> {
+    inner: Mutex<HistoryInner<L>>,
+    // the most bytes of past writes kept, repl-backlog-size. it is changed without taking the
+    // lock so CONFIG SET never waits on a slow replica, the next write trims to it.
+    backlog_size: AtomicUsize,
 }
 
-impl History {
+impl<L: ReplicaLink> Default for History<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: ReplicaLink> History<L> {
     pub fn new() -> Self {
         Self {
-            inner: Mutex::new(HistoryInner::new())
+            inner: Mutex::new(HistoryInner::new()),
+            backlog_size: AtomicUsize::new(DEFAULT_REPL_BACKLOG_SIZE as usize),
         }
     }
 
+    pub fn set_backlog_size(&self, bytes: usize) {
+        self.backlog_size.store(bytes, Ordering::Relaxed);
+    }
+
     // todo - handle poison errors here...
-    pub async fn add_replica(&self, stream: Connection) {
-       self.inner.lock().await.add_replica(stream);
+    // returns the id acks from this replica should be recorded under.
+    pub async fn add_replica(&self, stream: L) -> u64 {
+       self.inner.lock().await.add_replica(stream)
+    }
+
+    pub async fn remove_replica(&self, id: u64) {
+        self.inner.lock().await.repls.retain(|replica| replica.id != id);
+    }
+
+    // records a REPLCONF ACK from the replica.
+    pub async fn ack(&self, id: u64, offset: usize) {
+        if let Some(replica) = self.inner.lock().await.repls.iter_mut().find(|r| r.id == id) {
+            replica.ack(offset);
+        }
+    }
+
+    // returns the offset after the write.
+    pub async fn add_write(&self, resp: Resp) -> usize {
+        let backlog_size = self.backlog_size.load(Ordering::Relaxed);
+        self.inner.lock().await.add_write(resp, backlog_size).await
+    }
+
+    pub async fn replicas(&self) -> Vec<ReplicaStatus> {
+        self.inner.lock().await.replicas()
     }
 
-    pub async fn add_write(&self, resp: Resp) {
-        self.inner.lock().await.add_write(resp).await;
+    // the total number of bytes of writes recorded so far.
+    pub async fn offset(&self) -> usize {
+        self.inner.lock().await.offset()
+    }
+
+    // the offset of the oldest write still kept and how many bytes are kept from it on.
+    pub async fn backlog(&self) -> (usize, usize) {
+        let inner = self.inner.lock().await;
+        (inner.trimmed, inner.write_history.len())
     }
 }
 
 #[derive(Debug)]
-pub struct HistoryInner {
-    repls: Vec<Replica>,
+pub struct HistoryInner<L: ReplicaLink = Connection> {
+    repls: Vec<Replica<L>>,
+    // the latest writes, the ones before them were trimmed off to stay within the backlog size.
     write_history: BytesMut,
+    // how many bytes were trimmed, the offset write_history starts at. replica offsets count
+    // from the very first write.
+    trimmed: usize,
+    next_id: u64,
+}
+
+impl<L: ReplicaLink> Default for HistoryInner<L> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl HistoryInner {
+impl<L: ReplicaLink> HistoryInner<L> {
     pub fn new() -> Self {
         Self {
             repls: Vec::new(),
             write_history: BytesMut::new(),
+            trimmed: 0,
+            next_id: 0,
         }
     }
 
-    pub fn add_replica(&mut self, stream: Connection) {
-        let offset = self.write_history.len();
-        self.repls.push(Replica::new(stream, offset, offset));
+    pub fn offset(&self) -> usize {
+        self.trimmed + self.write_history.len()
+    }
+
+    pub fn add_replica(&mut self, stream: L) -> u64 {
+        let id = self.next_id;
+        let offset = self.offset();
+        self.next_id += 1;
+        self.repls.push(Replica::new(id, stream, offset, offset));
+        id
+    }
+
+    pub fn replicas(&self) -> Vec<ReplicaStatus> {
+        let offset = self.offset();
+        self.repls
+            .iter()
+            .map(|replica| ReplicaStatus {
+                id: replica.id,
+                ack_offset: replica.ack_offset,
+                lag_bytes: offset.saturating_sub(replica.ack_offset),
+                lag_seconds: replica.last_ack.elapsed().as_secs(),
+            })
+            .collect()
     }
 
-    pub async fn add_write(&mut self, resp: Resp) {
+    // every replica is sent what it hasn't seen yet, which is never more than the latest
+    // write, so the history can be trimmed to backlog_size right after.
+    pub async fn add_write(&mut self, resp: Resp, backlog_size: usize) -> usize {
         // encode the resp we just received into the write history.
         RespEncoder::encode_resp(&resp, &mut self.write_history);
+        let offset = self.offset();
         // send the write history to all replicas.
         for replica in self.repls.iter_mut() {
-            let _ = replica.stream.write(&self.write_history[replica.last_offset..]);
-            let _ = replica.stream.flush().await;
-            replica.update_offset(self.write_history.len());
+            let from = replica.last_offset.saturating_sub(self.trimmed);
+            let _ = replica.stream.send(&self.write_history[from..]).await;
+            replica.update_offset(offset);
         }
+
+        let excess = self.write_history.len().saturating_sub(backlog_size);
+        self.write_history.advance(excess);
+        self.trimmed += excess;
+        offset
+    }
+}
+
+// an in memory replica link for tests, everything sent to it is appended to a shared buffer.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReplica {
+    pub received: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    // when set every send fails, like a replica whose socket went away.
+    pub broken: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[cfg(test)]
+impl MemoryReplica {
+    pub fn received(&self) -> Vec<u8> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn set_broken(&self, broken: bool) {
+        self.broken.store(broken, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl ReplicaLink for MemoryReplica {
+    async fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.broken.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        self.received.lock().unwrap().extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(args: &[&str]) -> Vec<u8> {
+        RespEncoder::encode(&Resp::command(args)).to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_writes_propagate_to_every_replica() {
+        let history = History::new();
+        let (a, b) = (MemoryReplica::default(), MemoryReplica::default());
+        history.add_replica(a.clone()).await;
+        history.add_replica(b.clone()).await;
+
+        history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+        history.add_write(Resp::command(&["DEL", "foo"])).await;
+
+        let expected = [encoded(&["SET", "foo", "1"]), encoded(&["DEL", "foo"])].concat();
+        assert_eq!(a.received(), expected);
+        assert_eq!(b.received(), expected);
+        assert_eq!(history.offset().await, expected.len());
+    }
+
+    #[tokio::test]
+    async fn test_late_replica_starts_at_current_offset() {
+        let history = History::new();
+        history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+
+        let replica = MemoryReplica::default();
+        history.add_replica(replica.clone()).await;
+        history.add_write(Resp::command(&["SET", "bar", "2"])).await;
+
+        assert_eq!(replica.received(), encoded(&["SET", "bar", "2"]));
+    }
+
+    #[tokio::test]
+    async fn test_broken_replica_does_not_block_the_others() {
+        let history = History::new();
+        let (broken, healthy) = (MemoryReplica::default(), MemoryReplica::default());
+        history.add_replica(broken.clone()).await;
+        history.add_replica(healthy.clone()).await;
+
+        broken.set_broken(true);
+        history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+
+        assert!(broken.received().is_empty());
+        assert_eq!(healthy.received(), encoded(&["SET", "foo", "1"]));
+    }
+
+    #[tokio::test]
+    async fn test_acks_drive_replica_lag() {
+        let history = History::new();
+        let first = history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+        let id = history.add_replica(MemoryReplica::default()).await;
+
+        // nothing written since the replica attached, so it is fully caught up.
+        assert_eq!(history.replicas().await[0].lag_bytes, 0);
+
+        let second = history.add_write(Resp::command(&["SET", "bar", "2"])).await;
+        let status = &history.replicas().await[0];
+        assert_eq!((status.ack_offset, status.lag_bytes), (first, second - first));
+
+        history.ack(id, second).await;
+        assert_eq!(history.replicas().await[0].lag_bytes, 0);
+
+        history.remove_replica(id).await;
+        assert!(history.replicas().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backlog_keeps_only_the_latest_writes() {
+        let history = History::new();
+        let replica = MemoryReplica::default();
+        history.add_replica(replica.clone()).await;
+        let set = encoded(&["SET", "foo", "1"]);
+
+        history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+        history.set_backlog_size(set.len() + 1);
+        let offset = history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+
+        // offsets keep counting from the first write, only the kept bytes shrink.
+        assert_eq!(offset, set.len() * 2);
+        assert_eq!(history.backlog().await, (set.len() - 1, set.len() + 1));
+
+        history.set_backlog_size(4);
+        history.add_write(Resp::command(&["SET", "foo", "1"])).await;
+        assert_eq!(history.backlog().await, (set.len() * 3 - 4, 4));
+        assert_eq!(history.offset().await, set.len() * 3);
+        assert_eq!(replica.received(), set.repeat(3));
     }
 }
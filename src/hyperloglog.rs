@@ -0,0 +1,270 @@
+use crate::database::WrongType;
+
+// hyperloglogs are kept in strings laid out like redis' dense encoding, so they read the same
+// as one made by redis: "HYLL", an encoding byte, 3 unused bytes and a cached cardinality as
+// 8 little endian bytes whose top bit marks it stale, then 16384 registers of 6 bits packed
+// least significant bit first. the sparse encoding isn't supported, new ones start out dense.
+
+const P: u32 = 14;
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const HEADER_SIZE: usize = 16;
+pub const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+const MAGIC: &[u8; 4] = b"HYLL";
+const DENSE: u8 = 0;
+const CARDINALITY: usize = 8;
+const STALE: u8 = 1 << 7;
+const SEED: u64 = 0xadc83b19;
+// 1 / (2 ln 2), the alpha of hyperloglog as the number of registers goes to infinity.
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+// why a key can't be used as a hyperloglog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HllError {
+    WrongType,
+    // a string, but not one holding a hyperloglog.
+    Invalid,
+}
+
+impl From<WrongType> for HllError {
+    fn from(_: WrongType) -> Self {
+        HllError::WrongType
+    }
+}
+
+// an empty hyperloglog, its cached cardinality of 0 is valid.
+pub fn new() -> Vec<u8> {
+    let mut data = vec![0; DENSE_SIZE];
+    data[..MAGIC.len()].copy_from_slice(MAGIC);
+    data[4] = DENSE;
+    data
+}
+
+pub fn validate(data: &[u8]) -> Result<(), HllError> {
+    if data.len() == DENSE_SIZE && data.starts_with(MAGIC) && data[4] == DENSE {
+        Ok(())
+    } else {
+        Err(HllError::Invalid)
+    }
+}
+
+// counts the element in, true if a register changed so the cardinality may have too.
+pub fn add(data: &mut [u8], element: &[u8]) -> bool {
+    let (index, count) = pattern(element);
+    if register(data, index) >= count {
+        return false;
+    }
+    set_register(data, index, count);
+    invalidate(data);
+    true
+}
+
+// takes the larger of every pair of registers into data, true if any grew.
+pub fn merge(data: &mut [u8], other: &[u8]) -> bool {
+    let mut changed = false;
+    for index in 0..REGISTERS {
+        let theirs = register(other, index);
+        if theirs > register(data, index) {
+            set_register(data, index, theirs);
+            changed = true;
+        }
+    }
+    if changed {
+        invalidate(data);
+    }
+    changed
+}
+
+// the cached cardinality when it is still valid, estimated from the registers otherwise. only
+// an empty one or one made elsewhere has a valid cache, counting never stores one so a master
+// and its replicas keep the same bytes.
+pub fn count(data: &[u8]) -> u64 {
+    if data[CARDINALITY + 7] & STALE == 0 {
+        let mut cached = [0; 8];
+        cached.copy_from_slice(&data[CARDINALITY..CARDINALITY + 8]);
+        return u64::from_le_bytes(cached);
+    }
+    estimate(data)
+}
+
+fn invalidate(data: &mut [u8]) {
+    data[CARDINALITY + 7] |= STALE;
+}
+
+// the register an element goes to and the length of the run of zeros that follows in its
+// hash plus one, what the register is raised to.
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    let rest = hash >> P | 1 << Q;
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn register(data: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (HEADER_SIZE + bit / 8, bit % 8);
+    let low = data[byte] as u16;
+    let high = data.get(byte + 1).copied().unwrap_or(0) as u16;
+    ((low | high << 8) >> shift) as u8 & REGISTER_MAX
+}
+
+fn set_register(data: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (HEADER_SIZE + bit / 8, bit % 8);
+    let mask = (REGISTER_MAX as u16) << shift;
+    let value = (value as u16) << shift;
+
+    data[byte] = (data[byte] & !mask as u8) | value as u8;
+    if let Some(next) = data.get_mut(byte + 1) {
+        *next = (*next & !(mask >> 8) as u8) | (value >> 8) as u8;
+    }
+}
+
+// the estimator redis uses since 5.0, from Otmar Ertl's "New cardinality estimation
+// algorithms for HyperLogLog sketches". it needs no bias correction for small cardinalities.
+fn estimate(data: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut histogram = [0u32; Q as usize + 2];
+    for index in 0..REGISTERS {
+        histogram[register(data, index) as usize] += 1;
+    }
+
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for j in (1..=Q as usize).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+// MurmurHash64A by Austin Appleby, the hash redis feeds its hyperloglogs.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers_pack_into_six_bits() {
+        let mut data = new();
+        for index in [0, 1, 2, 3, 4, 5000, REGISTERS - 2, REGISTERS - 1] {
+            set_register(&mut data, index, REGISTER_MAX);
+            assert_eq!(register(&data, index), REGISTER_MAX);
+            set_register(&mut data, index, 17);
+            assert_eq!(register(&data, index), 17);
+        }
+        // neighbours were left alone.
+        assert_eq!(register(&data, 6), 0);
+        assert_eq!(register(&data, REGISTERS - 3), 0);
+        assert_eq!(&data[..4], b"HYLL");
+    }
+
+    #[test]
+    fn test_count_is_close() {
+        let mut data = new();
+        assert_eq!(count(&data), 0);
+
+        for i in 0..7 {
+            add(&mut data, format!("element:{}", i).as_bytes());
+        }
+        assert_eq!(count(&data), 7);
+
+        for i in 7..100_000 {
+            add(&mut data, format!("element:{}", i).as_bytes());
+        }
+        let estimate = count(&data) as f64;
+        assert!((estimate - 100_000.0).abs() / 100_000.0 < 0.02, "estimated {}", estimate);
+
+        // adding what is already there changes nothing.
+        assert!(!add(&mut data, b"element:5"));
+    }
+
+    #[test]
+    fn test_merge_counts_the_union() {
+        let (mut a, mut b) = (new(), new());
+        for i in 0..1000 {
+            add(&mut a, format!("{}", i).as_bytes());
+            add(&mut b, format!("{}", i + 500).as_bytes());
+        }
+        assert!(merge(&mut a, &b));
+        assert!(!merge(&mut a, &b));
+        let estimate = count(&a) as f64;
+        assert!((estimate - 1500.0).abs() / 1500.0 < 0.02, "estimated {}", estimate);
+    }
+
+    #[test]
+    fn test_cached_count() {
+        let mut data = new();
+        add(&mut data, b"a");
+        data[CARDINALITY..CARDINALITY + 8].copy_from_slice(&42u64.to_le_bytes());
+        assert_eq!(count(&data), 42);
+        add(&mut data, b"b");
+        assert_eq!(count(&data), 2);
+        assert_eq!(validate(&data), Ok(()));
+        assert_eq!(validate(b"HYLL"), Err(HllError::Invalid));
+    }
+}
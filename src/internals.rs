@@ -1,4 +1,5 @@
 use crate::resp::Resp;
+use crate::reply::Reply;
 use crate::command::{ Command, Transaction };
 use crate::arguments::Argument;
 use crate::protocol::ReplServerProtocol;
@@ -15,8 +16,11 @@ impl Command for ReplconfCommand {
         match self.0 {
             ReplconfArguments::ListeningPort(_port) => {
                 let mut protocol = ReplServerProtocol::new(stream, handle);
-                let _ = protocol.start().await;
-                return Transaction::Replicate;
+                match protocol.start().await {
+                    Ok(_) => Transaction::Replicate,
+                    // the handshake never completed, keep serving this connection as a normal client.
+                    Err(_) => Transaction::None,
+                }
             },
 
             ReplconfArguments::GetAck(_ack) => {
@@ -26,12 +30,12 @@ impl Command for ReplconfCommand {
                     .to_string();
 
                 let _ = client.repl_conf(&["ACK", &offset]).await;
-                return Transaction::None;
+                Transaction::None
             },
 
             _ => {
-                let _ = stream.write_err("ERR unsupported protocol sequence").await;
-                return Transaction::None;
+                let _ = stream.write_reply(Reply::error("ERR unsupported protocol sequence")).await;
+                Transaction::None
             }
         }
     }
@@ -42,6 +46,8 @@ pub enum ReplconfArguments {
     ListeningPort(String),
     Capa(String),
     GetAck(String),
+    // sent by a replica with the offset it has processed up to.
+    Ack(String),
 }
 
 impl Argument for ReplconfArguments {
@@ -69,6 +75,11 @@ impl Argument for ReplconfArguments {
                         return check_remaining(outcome, &mut args);
                     },
 
+                    "ACK" => {
+                        let outcome = ReplconfArguments::Ack(try_string(&mut args)?);
+                        return check_remaining(outcome, &mut args);
+                    },
+
                     _ => return Err("ERR unknown or unexpected argument".to_string()),
                 }
             }
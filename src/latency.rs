@@ -0,0 +1,133 @@
+use std::collections::{ BTreeMap, VecDeque };
+use std::sync::Mutex;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+// how many samples an event keeps, redis keeps the same.
+pub const HISTORY_LEN: usize = 160;
+
+// the events spikes are recorded under. redis also has fork, there is no fork here since
+// nothing is written to disk.
+pub const COMMAND: &str = "command";
+pub const FAST_COMMAND: &str = "fast-command";
+pub const EXPIRE_CYCLE: &str = "expire-cycle";
+
+// one spike, when it happened in seconds since the epoch and how long it took in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub time: u64,
+    pub latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct EventHistory {
+    samples: VecDeque<Sample>,
+    // the worst spike since the event was last reset, it outlives the samples.
+    max: u64,
+}
+
+// the spikes over latency-monitor-threshold, by event, for the LATENCY command.
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    events: Mutex<BTreeMap<&'static str, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // records took under event if it is at least threshold_ms, a threshold of 0 keeps the
+    // monitor off. spikes within the same second share a sample that keeps the worst of them.
+    pub fn record(&self, event: &'static str, took: Duration, threshold_ms: u64) {
+        let latency = took.as_millis() as u64;
+        if threshold_ms == 0 || latency < threshold_ms {
+            return;
+        }
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut events = self.events.lock().unwrap();
+        let history = events.entry(event).or_default();
+        history.max = history.max.max(latency);
+
+        match history.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                if history.samples.len() == HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+                history.samples.push_back(Sample { time, latency });
+            },
+        }
+    }
+
+    // LATENCY LATEST, every event with its newest sample and its worst spike.
+    pub fn latest(&self) -> Vec<(&'static str, Sample, u64)> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(event, history)| history.samples.back().map(|last| (*event, *last, history.max)))
+            .collect()
+    }
+
+    // LATENCY HISTORY, the samples of event oldest first. empty for events without spikes.
+    pub fn history(&self, event: &str) -> Vec<Sample> {
+        match self.events.lock().unwrap().get(event) {
+            Some(history) => history.samples.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // LATENCY RESET, forgets the events named or all of them when none are. returns how many
+    // events had anything to forget.
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut recorded = self.events.lock().unwrap();
+        if events.is_empty() {
+            let count = recorded.len();
+            recorded.clear();
+            return count;
+        }
+
+        let before = recorded.len();
+        recorded.retain(|event, _| !events.iter().any(|name| name.eq_ignore_ascii_case(event)));
+        before - recorded.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spikes_under_the_threshold_are_ignored() {
+        let monitor = LatencyMonitor::new();
+        monitor.record(COMMAND, Duration::from_millis(500), 0);
+        monitor.record(COMMAND, Duration::from_millis(5), 10);
+        assert!(monitor.latest().is_empty());
+
+        // the same second shares a sample, the max survives.
+        monitor.record(COMMAND, Duration::from_millis(20), 10);
+        monitor.record(COMMAND, Duration::from_millis(30), 10);
+        monitor.record(EXPIRE_CYCLE, Duration::from_millis(15), 10);
+        let history = monitor.history(COMMAND);
+        assert!(history.len() == 1 || history.len() == 2);
+        assert_eq!(history.iter().map(|s| s.latency).max(), Some(30));
+
+        let latest = monitor.latest();
+        assert_eq!(latest.iter().map(|(event, _, max)| (*event, *max)).collect::<Vec<_>>(), vec![(COMMAND, 30), (EXPIRE_CYCLE, 15)]);
+        assert!(monitor.history("nope").is_empty());
+    }
+
+    #[test]
+    fn test_reset_named_or_all() {
+        let monitor = LatencyMonitor::new();
+        monitor.record(COMMAND, Duration::from_millis(20), 1);
+        monitor.record(FAST_COMMAND, Duration::from_millis(20), 1);
+        monitor.record(EXPIRE_CYCLE, Duration::from_millis(20), 1);
+
+        assert_eq!(monitor.reset(&["COMMAND".to_string(), "nope".to_string()]), 1);
+        assert_eq!(monitor.latest().len(), 2);
+        assert_eq!(monitor.reset(&[]), 2);
+        assert!(monitor.latest().is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{ AtomicU32, Ordering };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+// the counter a new key starts with, so it isn't the first thing evicted before it had a
+// chance to be read.
+pub const LFU_INIT_VAL: u8 = 5;
+
+pub const DEFAULT_LOG_FACTOR: u32 = 10;
+pub const DEFAULT_DECAY_TIME: u32 = 1;
+
+// the knobs of the lfu counter, lfu-log-factor and lfu-decay-time in redis.
+#[derive(Debug)]
+pub struct LfuPolicy {
+    // how many hits it takes to saturate the counter, higher means slower growth.
+    log_factor: AtomicU32,
+    // minutes of idleness that take one off the counter, 0 disables decay.
+    decay_time: AtomicU32,
+}
+
+impl Default for LfuPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LfuPolicy {
+    pub fn new() -> Self {
+        Self {
+            log_factor: AtomicU32::new(DEFAULT_LOG_FACTOR),
+            decay_time: AtomicU32::new(DEFAULT_DECAY_TIME),
+        }
+    }
+
+    pub fn get_log_factor(&self) -> u32 {
+        self.log_factor.load(Ordering::Relaxed)
+    }
+
+    pub fn set_log_factor(&self, factor: u32) {
+        self.log_factor.store(factor, Ordering::Relaxed);
+    }
+
+    pub fn get_decay_time(&self) -> u32 {
+        self.decay_time.load(Ordering::Relaxed)
+    }
+
+    pub fn set_decay_time(&self, minutes: u32) {
+        self.decay_time.store(minutes, Ordering::Relaxed);
+    }
+}
+
+// the access frequency of a key packed like redis does it, the last decrement time in minutes
+// (16 bits) above a logarithmic counter (8 bits). it is atomic so a read under a shared lock
+// can still count as an access.
+#[derive(Debug)]
+pub struct LfuCounter(AtomicU32);
+
+impl Default for LfuCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for LfuCounter {
+    fn clone(&self) -> Self {
+        LfuCounter(AtomicU32::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl LfuCounter {
+    pub fn new() -> Self {
+        LfuCounter(AtomicU32::new(pack(now_minutes(), LFU_INIT_VAL)))
+    }
+
+    // the counter with any decay owed since the last access applied.
+    pub fn frequency(&self, policy: &LfuPolicy) -> u8 {
+        let (minutes, counter) = unpack(self.0.load(Ordering::Relaxed));
+        decay(counter, elapsed_minutes(minutes, now_minutes()), policy.get_decay_time())
+    }
+
+    // records an access, r is a uniform random number in [0, 1).
+    pub fn touch(&self, policy: &LfuPolicy, r: f64) {
+        let counter = self.frequency(policy);
+        let counter = log_incr(counter, policy.get_log_factor(), r);
+        self.0.store(pack(now_minutes(), counter), Ordering::Relaxed);
+    }
+}
+
+// increments the counter with a probability that shrinks as it grows, so 255 stands for
+// roughly a million hits at the default factor.
+pub fn log_incr(counter: u8, log_factor: u32, r: f64) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+
+    let baseval = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (baseval * log_factor as f64 + 1.0);
+    if r < p { counter + 1 } else { counter }
+}
+
+// takes one off the counter for every decay_time minutes that passed.
+pub fn decay(counter: u8, elapsed_minutes: u64, decay_time: u32) -> u8 {
+    if decay_time == 0 {
+        return counter;
+    }
+
+    let periods = elapsed_minutes / decay_time as u64;
+    counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+}
+
+fn pack(minutes: u16, counter: u8) -> u32 {
+    ((minutes as u32) << 8) | counter as u32
+}
+
+fn unpack(packed: u32) -> (u16, u8) {
+    ((packed >> 8) as u16, packed as u8)
+}
+
+// the current time in minutes, wrapping at 16 bits.
+fn now_minutes() -> u16 {
+    let minutes = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 60)
+        .unwrap_or(0);
+    (minutes & 0xFFFF) as u16
+}
+
+// minutes between then and now, allowing for the clock having wrapped once.
+fn elapsed_minutes(then: u16, now: u16) -> u64 {
+    if now >= then {
+        (now - then) as u64
+    } else {
+        (u16::MAX - then) as u64 + now as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // splitmix64, deterministic so the probabilistic tests always see the same draws.
+    struct SeededRng(u64);
+
+    impl SeededRng {
+        fn next_f64(&mut self) -> f64 {
+            self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^= z >> 31;
+            (z >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    fn hits(n: usize, log_factor: u32, seed: u64) -> u8 {
+        let mut rng = SeededRng(seed);
+        (0..n).fold(LFU_INIT_VAL, |counter, _| log_incr(counter, log_factor, rng.next_f64()))
+    }
+
+    #[test]
+    fn test_log_incr_is_deterministic_for_a_seed() {
+        assert_eq!(hits(10_000, DEFAULT_LOG_FACTOR, 42), hits(10_000, DEFAULT_LOG_FACTOR, 42));
+    }
+
+    #[test]
+    fn test_log_incr_growth_by_factor() {
+        // a factor of zero counts every hit until the counter saturates.
+        assert_eq!(hits(10, 0, 1), LFU_INIT_VAL + 10);
+        assert_eq!(hits(1_000, 0, 1), u8::MAX);
+
+        // the redis.conf table for factor 10: ~10 at 100 hits, ~18 at 1k, ~142 at 100k.
+        assert!((8..=13).contains(&hits(100, 10, 7)));
+        assert!((15..=22).contains(&hits(1_000, 10, 7)));
+        assert!((130..=155).contains(&hits(100_000, 10, 7)));
+
+        // a larger factor grows slower.
+        assert!(hits(100_000, 100, 7) < hits(100_000, 10, 7));
+    }
+
+    #[test]
+    fn test_decay() {
+        assert_eq!(decay(20, 3, 1), 17);
+        assert_eq!(decay(20, 3, 2), 19);
+        assert_eq!(decay(20, 1_000, 1), 0);
+        assert_eq!(decay(20, 1_000, 0), 20);
+        assert_eq!(elapsed_minutes(u16::MAX - 1, 2), 3);
+    }
+}
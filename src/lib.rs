@@ -2,6 +2,7 @@ pub mod database;
 pub mod command;
 pub mod resp;
 pub mod context;
+pub mod session;
 pub mod server; 
 pub mod arguments;
 pub mod client;
@@ -9,4 +10,32 @@ pub mod connection;
 pub mod listener;
 pub mod history;
 pub mod protocol;
-pub mod internals;
\ No newline at end of file
+pub mod internals;
+pub mod config;
+pub mod glob;
+pub mod reply;
+pub mod health;
+pub mod clients;
+pub mod stats;
+pub mod lfu;
+pub mod acl;
+pub mod blocking;
+pub mod setops;
+pub mod bitops;
+pub mod lzf;
+pub mod cluster;
+pub mod hyperloglog;
+pub mod doctor;
+pub mod daemon;
+pub mod proxy;
+pub mod pubsub;
+pub mod latency;
+pub mod skiplist;
+// the stable surface for code outside the server. the modules above are public so the binary
+// and tests can reach everything, but their paths keep moving and shouldn't be depended on.
+pub use resp::{ Resp, RespParser, RespEncoder, ParseError, Frame, StreamedType };
+pub use client::RedisClient;
+pub use connection::{ Connection, MemoryTransport };
+pub use server::{ RedisServer, ServerBuilder };
+pub use database::Database;
+pub use cluster::keyslot;
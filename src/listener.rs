@@ -1,64 +1,195 @@
 use tokio::net::{ TcpListener, TcpStream };
 use std::io;
+use std::net::SocketAddr;
 use std::time;
 use std::thread;
 use std::sync::Arc;
 use crate::context::{Context, Handle};
 use crate::history::History;
-use crate::connection::Connection;
-use crate::database::Database;
+use crate::connection::{ Connection, MemoryTransport };
+use crate::database::{ self, Database };
 use crate::server::ServerInfo;
+use crate::config::Config;
+use crate::clients::{ ClientRegistry, ClientKind };
+use crate::stats::Stats;
+use crate::pubsub::Broker;
+use crate::latency;
+use tokio::io::AsyncWriteExt;
 use crate::client::RedisClient;
 use crate::protocol::ReplicationProtocol;
 
-#[derive(Debug)]
+// connections accepted past maxclients so an operator can still get in and make room, they
+// only run admin commands.
+pub const RESERVED_ADMIN_CLIENTS: usize = 2;
+
+// how often keys past their deadline are swept, ten times a second like redis' default hz.
+const ACTIVE_EXPIRE_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+// what a connection may run depending on the port it came in on. without an internal port
+// every port is open, with one the public port refuses the commands config lists as internal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortPolicy {
+    Open,
+    Public,
+}
+
+#[derive(Debug, Clone)]
 pub struct Listener {
-    listener: TcpListener, // the socket we've bound to
+    // the sockets we've bound to, more than one when accepting is sharded with SO_REUSEPORT.
+    // each gets its own accept loop and the kernel spreads new connections over them.
+    sockets: Vec<Arc<TcpListener>>,
+    // the port operators connect to for the commands the public sockets refuse.
+    internal: Option<Arc<TcpListener>>,
     db: Arc<Database>, // the database we're running
     history: Arc<History>, // the server's connected replicas and transaction history
     info: Arc<ServerInfo>, // info about the server that is currently handling requests.
+    config: Arc<Config>, // runtime configuration shared by every connection.
+    clients: Arc<ClientRegistry>, // every open connection, including replicas.
+    stats: Arc<Stats>, // server wide counters.
+    broker: Arc<Broker>, // the pub/sub channels, shared by every connection.
 }
 
 
 impl Listener {
-    pub fn new(listener: TcpListener, db: Database, history: History, info: ServerInfo) -> Self {
+    // without sockets nothing is accepted and clients only come in through connect_in_memory.
+    pub fn new(sockets: Vec<TcpListener>, db: Database, history: History, info: ServerInfo, config: Config, clients: ClientRegistry, stats: Stats) -> Self {
+        let sockets = sockets.into_iter().map(Arc::new).collect();
         let db = Arc::new(db);
         let history = Arc::new(history);
         let info = Arc::new(info);
 
+        // the history is sized from the config, and resized whenever CONFIG SET changes it.
+        history.set_backlog_size(config.get_repl_backlog_size() as usize);
+        let backlog = history.clone();
+        config.observe("repl-backlog-size", Box::new(move |config| {
+            backlog.set_backlog_size(config.repl_backlog_size() as usize);
+            Ok(())
+        }));
+        let config = Arc::new(config);
+        let clients = Arc::new(clients);
+        let stats = Arc::new(stats);
+
         Self {
-            listener,
+            sockets,
+            internal: None,
             db,
             history,
-            info
+            info,
+            config,
+            clients,
+            stats,
+            broker: Arc::new(Broker::new()),
         }
     }
 
+    // also accepts on socket, where every command runs. the public sockets then refuse the
+    // internal commands.
+    pub fn with_internal(mut self, socket: TcpListener) -> Self {
+        self.internal = Some(Arc::new(socket));
+        self
+    }
+
     pub async fn run(&self) -> io::Result<()> {
+        if self.sockets.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "the listener has no sockets to accept on"));
+        }
+
+        let (stats, config) = (self.stats.clone(), self.config.clone());
+        tokio::spawn(database::active_expire(self.db.clone(), ACTIVE_EXPIRE_INTERVAL, move |took| {
+            stats.latency().record(latency::EXPIRE_CYCLE, took, config.get_latency_monitor_threshold());
+        }));
+
         // check if the server is a replica
         if self.info.is_replica() {
             // if it is, we need to connect to the master server and start listening for updates.
             // this will be implemented later.
             self.replicate_before_listen().await?;
-            loop {
-                let stream = self.accept().await?; 
-                let connection = Connection::new(stream);
-                self.listen_limited(connection);
-            }
-        } 
+        }
+
+        if self.internal.is_some() {
+            let listener = self.clone();
+            tokio::spawn(async move { listener.accept_loop(listener.sockets.len()).await });
+        }
+
+        for acceptor in 1..self.sockets.len() {
+            let listener = self.clone();
+            tokio::spawn(async move { listener.accept_loop(acceptor).await });
+        }
+        self.accept_loop(0).await
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self.sockets.first() {
+            Some(socket) => socket.local_addr(),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "the listener has no sockets")),
+        }
+    }
+
+    // a client served exactly like one accepted on the public port, over an in memory pipe
+    // instead of a socket. returns the client's end. maxclients doesn't apply, tests open as
+    // many as they need.
+    pub fn connect_in_memory(&self) -> Connection {
+        let (client, server) = MemoryTransport::pair();
+        let mut connection = Connection::new(server);
+        connection.set_client(self.clients.register("memory".to_string(), "memory".to_string(), ClientKind::Normal));
+        self.stats.incr_connections_received(self.sockets.len());
+
+        let ctx = self.context(connection);
+        if self.info.is_replica() {
+            self.listen_limited(ctx);
+        } else {
+            self.listen_all(ctx);
+        }
+        Connection::new(client)
+    }
+
+    pub fn internal_addr(&self) -> Option<io::Result<SocketAddr>> {
+        self.internal.as_ref().map(|socket| socket.local_addr())
+    }
+
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    // the acceptor past the public sockets is the internal port.
+    async fn accept_loop(&self, acceptor: usize) -> io::Result<()> {
+        let limited = self.info.is_replica();
+        let (socket, policy) = match self.sockets.get(acceptor) {
+            Some(socket) if self.internal.is_some() => (socket.clone(), PortPolicy::Public),
+            Some(socket) => (socket.clone(), PortPolicy::Open),
+            None => (self.internal.clone().expect("no internal socket to accept on"), PortPolicy::Open),
+        };
 
         loop {
-            let stream = self.accept().await?; 
-            let connection = Connection::new(stream);
-            self.listen_all(connection);
+            let (stream, reserved) = self.accept(&socket, acceptor).await?;
+            let connection = self.connection(stream, ClientKind::Normal);
+            let mut ctx = self.context(connection);
+            ctx.policy = policy;
+            if limited {
+                self.listen_limited(ctx);
+            } else if reserved {
+                self.listen_reserved(ctx);
+            } else {
+                self.listen_all(ctx);
+            }
         }
     }
 
-    async fn accept(&self) -> io::Result<TcpStream> {
+    // the next connection and whether it took one of the slots reserved past maxclients.
+    async fn accept(&self, socket: &TcpListener, acceptor: usize) -> io::Result<(TcpStream, bool)> {
         let mut backoff = 1;
         loop {
-            match self.listener.accept().await {
-                Ok((stream, _)) => return Ok(stream),
+            match socket.accept().await {
+                Ok((stream, _)) => {
+                    self.stats.incr_connections_received(acceptor);
+                    let maxclients = self.config.get_maxclients();
+                    let connected = self.clients.len();
+                    if connected >= maxclients + RESERVED_ADMIN_CLIENTS {
+                        self.reject(stream);
+                        continue;
+                    }
+                    return Ok((stream, connected >= maxclients))
+                },
                 Err(e) if backoff > 64 => return Err(e),
                 _ => {
                     thread::sleep(time::Duration::from_secs(backoff));
@@ -68,39 +199,64 @@ impl Listener {
         }
     }
 
-    // listen to connections with unlimited functionality.
-    fn listen_all(&self, stream: Connection) {
-        let ctx = Context::new(
+    // tells a client over the maxclients limit why it is being turned away, off the accept loop.
+    fn reject(&self, mut stream: TcpStream) {
+        self.stats.incr_rejected_connections();
+        tokio::spawn(async move {
+            let _ = stream.write_all(b"-ERR max number of clients reached\r\n").await;
+            let _ = stream.shutdown().await;
+        });
+    }
+
+    // wraps a socket in a connection that is registered as a client for as long as it lives.
+    fn connection(&self, stream: TcpStream, kind: ClientKind) -> Connection {
+        let addr = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        let laddr = stream.local_addr().map(|a| a.to_string()).unwrap_or_default();
+        let mut connection = Connection::new(stream);
+        connection.set_client(self.clients.register(addr, laddr, kind));
+        connection
+    }
+
+    fn context(&self, stream: Connection) -> Context {
+        Context::new(
             stream, 
             self.db.clone(), 
             self.history.clone(), 
-            self.info.clone()
-        );
-        
+            self.info.clone(),
+            self.config.clone(),
+            self.clients.clone(),
+            self.stats.clone()
+        )
+        .with_broker(self.broker.clone())
+    }
+
+    // listen to connections with unlimited functionality. whichever acceptor took the socket, a
+    // connection is served by this one task from start to end, so its commands run and reply in
+    // the order they were sent and each one sees the effects of the ones before it.
+    fn listen_all(&self, ctx: Context) {
         tokio::spawn(async move {
             ctx.handle_all().await
         });
     }
 
-    fn listen_limited(&self, stream: Connection) {
-        let ctx = Context::new(
-            stream, 
-            self.db.clone(), 
-            self.history.clone(), 
-            self.info.clone()
-        );
-        
+    fn listen_limited(&self, ctx: Context) {
         tokio::spawn(async move {
             ctx.handle_limited().await
         });
     }
 
+    fn listen_reserved(&self, ctx: Context) {
+        tokio::spawn(async move {
+            ctx.handle_reserved().await
+        });
+    }
+
     async fn replicate_before_listen(&self) -> io::Result<()> {
         println!("begin negotiation...");
         let tcp_socket = TcpStream::connect(self.info.get_master_host().unwrap()).await?;
-        let mut stream = Connection::new(tcp_socket);
+        let mut stream = self.connection(tcp_socket, ClientKind::Master);
         let client = RedisClient::from_stream(&mut stream);
-        let listening_port = self.listener
+        let listening_port = self
             .local_addr()?
             .port()
             .to_string();
@@ -108,7 +264,11 @@ impl Listener {
         let handle = Handle {
             database: self.db.clone(),
             history: self.history.clone(),
-            info: self.info.clone()
+            info: self.info.clone(),
+            config: self.config.clone(),
+            clients: self.clients.clone(),
+            stats: self.stats.clone(),
+            broker: self.broker.clone(),
         };
 
         let mut protocol = ReplicationProtocol::new(
@@ -120,7 +280,7 @@ impl Listener {
         protocol.start().await?;
 
         println!("begin listening...");
-        self.listen_all(stream);
+        self.listen_all(self.context(stream));
 
         Ok(())
     }
@@ -0,0 +1,171 @@
+// the lzf format redis compresses rdb strings with, a run of literals or a back reference per
+// control byte. a control byte below 32 is followed by that many literals plus one. otherwise
+// its top 3 bits are the reference length minus 2 (7 means a length byte follows) and its low
+// 5 bits the top of a 13 bit offset, whose low byte comes last.
+
+// bits of the hash of the next 3 bytes, the table remembers where each hash was last seen.
+const HASH_LOG: usize = 14;
+const MAX_LITERALS: usize = 1 << 5;
+const MAX_OFFSET: usize = 1 << 13;
+const MAX_REFERENCE: usize = (1 << 8) + (1 << 3);
+
+fn hash(bytes: &[u8]) -> usize {
+    let v = (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_LOG)) & ((1 << HASH_LOG) - 1)
+}
+
+// none when the input doesn't get any smaller, it should be kept as it is then.
+pub fn compress(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    // where each hash was last seen, plus one so 0 can mean never.
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    // the control byte of the literal run being written and how long the run is so far.
+    let mut run = 0;
+    let mut literals = 0;
+    out.push(0);
+
+    let mut i = 0;
+    while i < input.len() {
+        if i + 2 < input.len() {
+            let h = hash(&input[i..]);
+            let candidate = std::mem::replace(&mut table[h], i + 1);
+
+            if candidate > 0 && i - candidate < MAX_OFFSET && input[candidate - 1..candidate + 2] == input[i..i + 3] {
+                let from = candidate - 1;
+                let max = (input.len() - i).min(MAX_REFERENCE);
+                let mut len = 3;
+                while len < max && input[from + len] == input[i + len] {
+                    len += 1;
+                }
+
+                // close the literal run, or drop its control byte if it never got any.
+                if literals == 0 {
+                    out.pop();
+                } else {
+                    out[run] = (literals - 1) as u8;
+                }
+
+                let offset = i - from - 1;
+                let len_code = len - 2;
+                if len_code < 7 {
+                    out.push((len_code << 5) as u8 | (offset >> 8) as u8);
+                } else {
+                    out.push((7 << 5) | (offset >> 8) as u8);
+                    out.push((len_code - 7) as u8);
+                }
+                out.push(offset as u8);
+
+                i += len;
+                run = out.len();
+                literals = 0;
+                out.push(0);
+                if out.len() >= input.len() {
+                    return None;
+                }
+                continue;
+            }
+        }
+
+        out.push(input[i]);
+        literals += 1;
+        i += 1;
+        if literals == MAX_LITERALS {
+            out[run] = (literals - 1) as u8;
+            run = out.len();
+            literals = 0;
+            out.push(0);
+        }
+        if out.len() >= input.len() {
+            return None;
+        }
+    }
+
+    if literals == 0 {
+        out.pop();
+    } else {
+        out[run] = (literals - 1) as u8;
+    }
+    (out.len() < input.len()).then_some(out)
+}
+
+// none if input isn't lzf or doesn't decompress to exactly len bytes.
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+
+        if control < MAX_LITERALS {
+            let literals = input.get(i..i + control + 1)?;
+            out.extend_from_slice(literals);
+            i += control + 1;
+            continue;
+        }
+
+        let mut len_code = control >> 5;
+        if len_code == 7 {
+            len_code += *input.get(i)? as usize;
+            i += 1;
+        }
+        let offset = ((control & 0x1f) << 8) + *input.get(i)? as usize + 1;
+        i += 1;
+
+        // a reference may overlap what it produces, i.e., a run of one repeated byte.
+        let from = out.len().checked_sub(offset)?;
+        for k in 0..len_code + 2 {
+            out.push(out[from + k]);
+        }
+    }
+
+    (out.len() == len).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &[u8]) -> Option<usize> {
+        let compressed = compress(input)?;
+        assert_eq!(decompress(&compressed, input.len()).as_deref(), Some(input));
+        Some(compressed.len())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // a long run of one byte is all overlapping references past the longest one.
+        assert!(round_trip(&[b'a'; 10_000]).unwrap() < 200);
+
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(100);
+        assert!(round_trip(text.as_bytes()).unwrap() < text.len() / 4);
+
+        let mixed: Vec<u8> = (0..5000u32).flat_map(|i| format!("user:{}:{}", i % 50, i * 7919 % 101).into_bytes()).collect();
+        assert!(round_trip(&mixed).is_some());
+    }
+
+    #[test]
+    fn test_incompressible_input_is_refused() {
+        let mut state = 0x2545f491u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert_eq!(compress(&noise), None);
+        assert_eq!(compress(b"ab"), None);
+        assert_eq!(compress(b""), None);
+    }
+
+    #[test]
+    fn test_decompress_rejects_garbage() {
+        let compressed = compress(&[b'x'; 100]).unwrap();
+        assert_eq!(decompress(&compressed, 99), None);
+        // a reference before the start of the output.
+        assert_eq!(decompress(&[0x20, 0x05], 3), None);
+        // a literal run cut short.
+        assert_eq!(decompress(&[0x04, b'a'], 5), None);
+    }
+}
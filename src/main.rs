@@ -1,12 +1,53 @@
 // Uncomment this block to pass the first stage
 use std::io::{ self };
 use redis_starter_rust::server::{ RedisServer, ServerArguments };
+use redis_starter_rust::daemon::{ self, PidFile };
+use redis_starter_rust::proxy::{ self, Proxy };
+use redis_starter_rust::doctor;
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
+// the runtime is started by hand rather than with #[tokio::main], daemonizing forks and
+// that has to happen before tokio spawns its worker threads.
+fn main() -> io::Result<()> {
     let server_args = ServerArguments::parse();
-    let server = RedisServer::bind(server_args).await?;
-    server.listener.run().await?;
-    Ok(())
-}
+    if server_args.check_system {
+        let checks = doctor::check_system(&server_args);
+        print!("{}", doctor::report(&checks));
+        std::process::exit(if doctor::passed(&checks) { 0 } else { 1 });
+    }
+
+    if server_args.daemonize {
+        daemon::daemonize()?;
+    }
+
+    // like redis, failing to write the pidfile is reported but doesn't stop the server.
+    let _pidfile = server_args.pidfile_path().and_then(|path| match PidFile::create(&path) {
+        Ok(pidfile) => Some(pidfile),
+        Err(e) => {
+            println!("failed to write pidfile {}: {}", path, e);
+            None
+        },
+    });
 
+    tokio::runtime::Runtime::new()?.block_on(async {
+        if let Some(upstream) = server_args.proxy_to.clone() {
+            let addr = format!("{}:{}", server_args.host, server_args.port);
+            let mut proxy = Proxy::bind(&addr, upstream.clone()).await?;
+            for (from, to) in &server_args.proxy_renames {
+                proxy = proxy.with_rewrite(proxy::rename_command(from, to));
+            }
+            println!("Proxying {} to {}", addr, upstream);
+            tokio::select! {
+                result = proxy.run() => result?,
+                _ = daemon::shutdown_signal() => println!("received a shutdown signal, exiting"),
+            }
+            return Ok(());
+        }
+
+        let server = RedisServer::bind(server_args).await?;
+        tokio::select! {
+            result = server.listener.run() => result?,
+            _ = daemon::shutdown_signal() => println!("received a shutdown signal, exiting"),
+        }
+        Ok(())
+    })
+}
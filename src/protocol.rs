@@ -299,7 +299,7 @@ impl<'a> ReplServerProtocol<'a> {
 
                 _ => {
                     println!("Protocol Error {:?}", self.state);
-                    panic!("negotiation failed");
+                    return Err(io::Error::other("negotiation failed"));
                 }
             }
         }
@@ -309,7 +309,7 @@ impl<'a> ReplServerProtocol<'a> {
         match self.state {
             ReplServerState::RecievedListeningPort => { self.expect_capabilities().await },
             ReplServerState::RecievedCapabilities => { self.expect_psync().await },
-            _ => Err(io::Error::new(io::ErrorKind::Other, "Protocol Error"))
+            _ => Err(io::Error::other("Protocol Error"))
         }
     }
 
@@ -348,7 +348,7 @@ impl<'a> ReplServerProtocol<'a> {
 
     fn get_command_arg(&self, args: Vec<Resp>) -> io::Result<CommandArgument> {
         ArgumentParser::get_from(args.into_iter())
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            .map_err(io::Error::other)
     }
 }
 
@@ -379,4 +379,205 @@ fn get_empty_rdb_file() -> Vec<u8> {
     }
 
     res
-}
\ No newline at end of file
+}
+
+// simulation of the replication handshake over in memory pipes, the "network" is driven
+// by hand so reorderings, drops and garbage can be injected between the two state machines.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::duplex;
+    use crate::database::Database;
+    use crate::history::History;
+    use crate::server::ServerInfo;
+    use crate::config::Config;
+    use crate::clients::ClientRegistry;
+    use crate::stats::Stats;
+    use crate::pubsub::Broker;
+
+    fn master_handle() -> Handle {
+        Handle {
+            database: Arc::new(Database::new()),
+            history: Arc::new(History::new()),
+            config: Arc::new(Config::new()),
+            clients: Arc::new(ClientRegistry::new()),
+            stats: Arc::new(Stats::new()),
+            broker: Arc::new(Broker::new()),
+            info: Arc::new(ServerInfo::master()),
+        }
+    }
+
+    fn replica_handle() -> Handle {
+        Handle {
+            database: Arc::new(Database::new()),
+            history: Arc::new(History::new()),
+            config: Arc::new(Config::new()),
+            clients: Arc::new(ClientRegistry::new()),
+            stats: Arc::new(Stats::new()),
+            broker: Arc::new(Broker::new()),
+            info: Arc::new(ServerInfo::replica(("127.0.0.1".to_string(), "6379".to_string()))),
+        }
+    }
+
+    fn pipe() -> (Connection, Connection) {
+        let (a, b) = duplex(64 * 1024);
+        (Connection::new(a), Connection::new(b))
+    }
+
+    async fn run_replica(mut stream: Connection, handle: Handle) -> ReplClientState {
+        let client = RedisClient::from_stream(&mut stream);
+        let mut protocol = ReplicationProtocol::new(client, "6380".to_string(), handle);
+        protocol.start().await.unwrap();
+        protocol.state
+    }
+
+    #[test]
+    fn test_client_state_transitions() {
+        let state = ReplClientState::Initial
+            .handle_event(ReplicationEvents::Start)
+            .handle_event(ReplicationEvents::RecievedPong)
+            .handle_event(ReplicationEvents::ReceivedOk)
+            .handle_event(ReplicationEvents::ReceivedOk)
+            .handle_event(ReplicationEvents::ReceivedFullResync)
+            .handle_event(ReplicationEvents::ReceivedRDB);
+        assert_eq!(state, ReplClientState::ReplicationStart);
+
+        // acks keep an established session where it is.
+        let state = state.handle_event(ReplicationEvents::ReceivedAck);
+        assert_eq!(state, ReplClientState::ReplicationStart);
+    }
+
+    #[test]
+    fn test_client_state_rejects_out_of_order_events() {
+        let state = ReplClientState::Ping.handle_event(ReplicationEvents::ReceivedFullResync);
+        assert_eq!(state, ReplClientState::ProtocolError("Invalid State Transition"));
+
+        let state = ReplClientState::Initial.handle_event(ReplicationEvents::ReceivedAck);
+        assert_eq!(state, ReplClientState::ProtocolError("Invalid State Transition"));
+    }
+
+    #[test]
+    fn test_server_state_transitions() {
+        let state = ReplServerState::RecievedListeningPort
+            .handle_event(ReplServerEvents::NotifyCapabilities)
+            .handle_event(ReplServerEvents::NotifyPsync)
+            .handle_event(ReplServerEvents::SentResponse)
+            .handle_event(ReplServerEvents::SentResponse)
+            .handle_event(ReplServerEvents::Done);
+        assert_eq!(state, ReplServerState::ReplicationComplete);
+
+        let state = ReplServerState::RecievedListeningPort.handle_event(ReplServerEvents::NotifyPsync);
+        assert_eq!(state, ReplServerState::ProtocolError("Invalid State Transition"));
+    }
+
+    #[tokio::test]
+    async fn test_full_handshake() {
+        let (replica_end, mut master_end) = pipe();
+        let replica = replica_handle();
+        let replica_info = replica.info.clone();
+        let replica_task = tokio::spawn(run_replica(replica_end, replica));
+
+        // the master answers the ping and the listening port through the normal command path,
+        // then hands the connection to the server side of the protocol.
+        let (ping, _) = master_end.read_message().await.unwrap();
+        assert_eq!(ping, Resp::command(&["PING"]));
+        master_end.write_str("PONG").await.unwrap();
+
+        let (port, _) = master_end.read_message().await.unwrap();
+        assert_eq!(port, Resp::command(&["REPLCONF", "listening-port", "6380"]));
+
+        let master = master_handle();
+        let master_replid = master.info.get_master_replid();
+        let mut protocol = ReplServerProtocol::new(&mut master_end, master);
+        protocol.start().await.unwrap();
+        assert_eq!(protocol.state, ReplServerState::ReplicationComplete);
+
+        assert_eq!(replica_task.await.unwrap(), ReplClientState::ReplicationStart);
+        assert_eq!(replica_info.get_master_replid(), master_replid);
+        assert_eq!(replica_info.get_master_repl_offset(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_garbage_instead_of_pong() {
+        let (replica_end, mut master_end) = pipe();
+        let replica_task = tokio::spawn(run_replica(replica_end, replica_handle()));
+
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("NOT-PONG").await.unwrap();
+
+        assert!(matches!(replica_task.await.unwrap(), ReplClientState::ProtocolError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_client_reordered_fullresync() {
+        let (replica_end, mut master_end) = pipe();
+        let replica_task = tokio::spawn(run_replica(replica_end, replica_handle()));
+
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("PONG").await.unwrap();
+        let _ = master_end.read_message().await.unwrap();
+        // the full resync arrives before the listening port was acknowledged.
+        master_end.write_str("FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0").await.unwrap();
+
+        assert!(matches!(replica_task.await.unwrap(), ReplClientState::ProtocolError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_client_master_drops_connection() {
+        let (replica_end, mut master_end) = pipe();
+        let replica_task = tokio::spawn(run_replica(replica_end, replica_handle()));
+
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("PONG").await.unwrap();
+        let _ = master_end.read_message().await.unwrap();
+        drop(master_end);
+
+        assert!(matches!(replica_task.await.unwrap(), ReplClientState::ProtocolError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_client_malformed_fullresync() {
+        let (replica_end, mut master_end) = pipe();
+        let replica_task = tokio::spawn(run_replica(replica_end, replica_handle()));
+
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("PONG").await.unwrap();
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("OK").await.unwrap();
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("OK").await.unwrap();
+        let _ = master_end.read_message().await.unwrap();
+        master_end.write_str("FULLRESYNC only-two-parts").await.unwrap();
+
+        assert!(matches!(replica_task.await.unwrap(), ReplClientState::ProtocolError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_psync_before_capabilities() {
+        let (mut replica_end, mut master_end) = pipe();
+
+        let server_task = tokio::spawn(async move {
+            let mut protocol = ReplServerProtocol::new(&mut master_end, master_handle());
+            let outcome = protocol.start().await;
+            (outcome.is_ok(), protocol.state)
+        });
+
+        let (ok, _) = replica_end.read_message().await.unwrap();
+        assert_eq!(ok, Resp::SimpleString("OK".to_string()));
+        replica_end.write_message(&Resp::command(&["PSYNC", "?", "-1"])).await.unwrap();
+
+        let (completed, state) = server_task.await.unwrap();
+        assert!(!completed);
+        assert!(matches!(state, ReplServerState::ProtocolError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_server_replica_drops_connection() {
+        let (replica_end, mut master_end) = pipe();
+        drop(replica_end);
+
+        let mut protocol = ReplServerProtocol::new(&mut master_end, master_handle());
+        assert!(protocol.start().await.is_err());
+    }
+}
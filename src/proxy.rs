@@ -0,0 +1,133 @@
+use tokio::net::{ TcpListener, TcpStream };
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use crate::connection::Connection;
+use crate::resp::Resp;
+
+// changes a command on its way upstream, i.e., to see how a client copes when it behaves differently.
+pub type Rewrite = Arc<dyn Fn(Resp) -> Resp + Send + Sync>;
+
+// sits between clients and a real redis and logs every message going either way. everything
+// is parsed with the server's own parser, so traffic it can't parse shows up as a dropped
+// connection here before it would in the server. replies are read whole, a replica syncing
+// through the proxy won't get its rdb.
+pub struct Proxy {
+    listener: TcpListener,
+    upstream: String,
+    rewrite: Option<Rewrite>,
+}
+
+impl Proxy {
+    pub async fn bind(addr: &str, upstream: impl Into<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self { listener, upstream: upstream.into(), rewrite: None })
+    }
+
+    // rewrites run in the order they were added, each on what the one before returned.
+    pub fn with_rewrite(mut self, rewrite: Rewrite) -> Self {
+        self.rewrite = Some(match self.rewrite.take() {
+            Some(first) => Arc::new(move |message| rewrite(first(message))),
+            None => rewrite,
+        });
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn run(self) -> io::Result<()> {
+        loop {
+            let (client, addr) = self.listener.accept().await?;
+            let upstream = self.upstream.clone();
+            let rewrite = self.rewrite.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::relay(client, addr, &upstream, rewrite).await {
+                    println!("{} proxy error: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    // one upstream connection per client, both directions run until either side hangs up.
+    async fn relay(client: TcpStream, addr: SocketAddr, upstream: &str, rewrite: Option<Rewrite>) -> io::Result<()> {
+        let upstream = TcpStream::connect(upstream).await?;
+        println!("{} connected", addr);
+        let (mut client_reader, mut client_writer) = Connection::new(client).split();
+        let (mut upstream_reader, mut upstream_writer) = Connection::new(upstream).split();
+
+        let requests = async {
+            loop {
+                let (mut message, _) = client_reader.read_message().await?;
+                println!("{} > {:?}", addr, message);
+                if let Some(rewrite) = &rewrite {
+                    message = rewrite(message);
+                    println!("{} > rewritten {:?}", addr, message);
+                }
+                upstream_writer.write_message(&message).await?;
+            }
+        };
+
+        // pushes arrive here too, they aren't tied to a request.
+        let replies = async {
+            loop {
+                let (message, _) = upstream_reader.read_message().await?;
+                println!("{} < {:?}", addr, message);
+                client_writer.write_message(&message).await?;
+            }
+        };
+
+        let result: io::Result<()> = tokio::select! {
+            result = requests => result,
+            result = replies => result,
+        };
+        println!("{} disconnected", addr);
+        result
+    }
+}
+
+// a rewrite sending from as to, i.e., to try a client against a renamed command.
+pub fn rename_command(from: &str, to: &str) -> Rewrite {
+    let from = from.to_string();
+    let to = to.as_bytes().to_vec();
+    Arc::new(move |message| match message {
+        Resp::Array(mut args) => {
+            if let Some(Resp::BulkString(name)) = args.first_mut() {
+                if name.eq_ignore_ascii_case(from.as_bytes()) {
+                    *name = to.clone();
+                }
+            }
+            Resp::Array(args)
+        },
+        other => other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServerBuilder;
+
+    #[tokio::test]
+    async fn test_proxy_relays_and_rewrites() {
+        let server = ServerBuilder::new().port("0").bind().await.unwrap();
+        let upstream = server.local_addr().unwrap();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let proxy = Proxy::bind("127.0.0.1:0", upstream.to_string()).await.unwrap().with_rewrite(rename_command("fetch", "GET"));
+        let addr = proxy.local_addr().unwrap();
+        tokio::spawn(proxy.run());
+
+        let mut client = Connection::new(TcpStream::connect(addr).await.unwrap());
+        client.write_message(&Resp::command(&["SET", "foo", "bar"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        client.write_message(&Resp::command(&["FETCH", "foo"])).await.unwrap();
+        assert_eq!(client.read_message().await.unwrap().0, Resp::BulkString(b"bar".to_vec()));
+
+        // and the upstream saw the real thing.
+        let mut direct = Connection::new(TcpStream::connect(upstream).await.unwrap());
+        direct.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(direct.read_message().await.unwrap().0, Resp::BulkString(b"bar".to_vec()));
+    }
+}
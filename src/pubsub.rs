@@ -0,0 +1,359 @@
+use std::collections::{ BTreeSet, HashMap };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use tokio::sync::mpsc::{ self, UnboundedReceiver, UnboundedSender };
+use crate::reply::Reply;
+use crate::glob::Pattern;
+
+// the subscribers of a channel by id, each with the queue its messages go to.
+type Subscribers = HashMap<u64, UnboundedSender<Message>>;
+
+// what a connection can subscribe to, a channel by name or every channel matching a glob.
+// shard channels are the redis 7 kind SPUBLISH reaches, they live apart from the others so a
+// message published one way never reaches a subscriber of the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionKind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+impl SubscriptionKind {
+    // the name of the confirmation a client gets for subscribing and unsubscribing.
+    pub fn replies(&self) -> (&'static [u8], &'static [u8]) {
+        match self {
+            SubscriptionKind::Channel => (b"subscribe", b"unsubscribe"),
+            SubscriptionKind::Pattern => (b"psubscribe", b"punsubscribe"),
+            SubscriptionKind::Shard => (b"ssubscribe", b"sunsubscribe"),
+        }
+    }
+}
+
+// a message published to a channel, on its way to one subscriber. pattern is set when it
+// reached the subscriber through one, shard when it was published to a shard channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub shard: bool,
+    pub pattern: Option<Vec<u8>>,
+    pub channel: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    // what the subscriber is sent, a push in resp3.
+    pub fn into_reply(self) -> Reply {
+        if self.shard {
+            return Reply::Push(vec![Reply::bulk(b"smessage"), Reply::Bulk(self.channel), Reply::Bulk(self.payload)]);
+        }
+        match self.pattern {
+            Some(pattern) => Reply::Push(vec![Reply::bulk(b"pmessage"), Reply::Bulk(pattern), Reply::Bulk(self.channel), Reply::Bulk(self.payload)]),
+            None => Reply::Push(vec![Reply::bulk(b"message"), Reply::Bulk(self.channel), Reply::Bulk(self.payload)]),
+        }
+    }
+}
+
+// the queue the messages for one connection arrive on. the session drains it in between
+// commands and a blocking command drains it while it waits, both on the task serving the
+// connection, so the lock is never waited on.
+#[derive(Debug, Clone)]
+pub struct Mailbox(Arc<tokio::sync::Mutex<UnboundedReceiver<Message>>>);
+
+impl Mailbox {
+    pub fn new(messages: UnboundedReceiver<Message>) -> Self {
+        Mailbox(Arc::new(tokio::sync::Mutex::new(messages)))
+    }
+
+    // the next message, none once the subscriber it belongs to is gone.
+    pub async fn recv(&self) -> Option<Message> {
+        self.0.lock().await.recv().await
+    }
+}
+
+// the next message in mailbox, never resolves for a connection without one.
+pub async fn next_message(mailbox: Option<&Mailbox>) -> Option<Message> {
+    match mailbox {
+        Some(mailbox) => mailbox.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// a pattern compiled once when first subscribed to, and who subscribed to it.
+#[derive(Debug)]
+struct PatternSubscribers {
+    pattern: Pattern,
+    subscribers: Subscribers,
+}
+
+// the channels connections subscribed to, shared by every connection. each subscriber has a
+// queue of its own that its session drains in between the commands it reads, so publishing
+// never waits on a slow subscriber.
+#[derive(Debug, Default)]
+pub struct Broker {
+    channels: Mutex<HashMap<Vec<u8>, Subscribers>>,
+    patterns: Mutex<HashMap<Vec<u8>, PatternSubscribers>>,
+    shards: Mutex<HashMap<Vec<u8>, Subscribers>>,
+    next_id: AtomicU64,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a subscriber for one connection, subscribed to nothing yet, and the queue its messages
+    // arrive on.
+    pub fn subscriber(self: &Arc<Self>) -> (Subscriber, UnboundedReceiver<Message>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let subscriber = Subscriber {
+            broker: self.clone(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            sender,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            shards: BTreeSet::new(),
+        };
+        (subscriber, receiver)
+    }
+
+    // sends payload to everyone subscribed to channel and to every pattern it matches, returns
+    // how many deliveries that made. a client subscribed both ways gets it once for each.
+    pub fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let message = |pattern: Option<&[u8]>| Message { shard: false, pattern: pattern.map(<[u8]>::to_vec), channel: channel.to_vec(), payload: payload.to_vec() };
+        let mut receivers = 0;
+
+        if let Some(subscribers) = self.channels.lock().unwrap().get(channel) {
+            receivers += subscribers.values().filter(|sender| sender.send(message(None)).is_ok()).count();
+        }
+
+        for (pattern, entry) in self.patterns.lock().unwrap().iter() {
+            if entry.pattern.matches(channel) {
+                receivers += entry.subscribers.values().filter(|sender| sender.send(message(Some(pattern))).is_ok()).count();
+            }
+        }
+        receivers
+    }
+
+    // sends payload to everyone subscribed to the shard channel, patterns never match these.
+    pub fn publish_shard(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let message = || Message { shard: true, pattern: None, channel: channel.to_vec(), payload: payload.to_vec() };
+        match self.shards.lock().unwrap().get(channel) {
+            Some(subscribers) => subscribers.values().filter(|sender| sender.send(message()).is_ok()).count(),
+            None => 0,
+        }
+    }
+
+    // how many channels have at least one subscriber.
+    pub fn channels(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+
+    // how many patterns have at least one subscriber.
+    pub fn patterns(&self) -> usize {
+        self.patterns.lock().unwrap().len()
+    }
+
+    // how many shard channels have at least one subscriber.
+    pub fn shards(&self) -> usize {
+        self.shards.lock().unwrap().len()
+    }
+
+    fn add(&self, kind: SubscriptionKind, name: &[u8], id: u64, sender: &UnboundedSender<Message>) {
+        match kind {
+            SubscriptionKind::Channel | SubscriptionKind::Shard => {
+                let mut channels = self.registry(kind).lock().unwrap();
+                channels.entry(name.to_vec()).or_default().insert(id, sender.clone());
+            },
+            SubscriptionKind::Pattern => {
+                let mut patterns = self.patterns.lock().unwrap();
+                patterns
+                    .entry(name.to_vec())
+                    .or_insert_with(|| PatternSubscribers { pattern: Pattern::new(name, false), subscribers: Subscribers::new() })
+                    .subscribers
+                    .insert(id, sender.clone());
+            },
+        }
+    }
+
+    // a channel or pattern only has an entry while someone is subscribed to it.
+    fn remove(&self, kind: SubscriptionKind, name: &[u8], id: u64) {
+        match kind {
+            SubscriptionKind::Channel | SubscriptionKind::Shard => {
+                let mut channels = self.registry(kind).lock().unwrap();
+                if let Some(subscribers) = channels.get_mut(name) {
+                    subscribers.remove(&id);
+                    if subscribers.is_empty() {
+                        channels.remove(name);
+                    }
+                }
+            },
+            SubscriptionKind::Pattern => {
+                let mut patterns = self.patterns.lock().unwrap();
+                if let Some(entry) = patterns.get_mut(name) {
+                    entry.subscribers.remove(&id);
+                    if entry.subscribers.is_empty() {
+                        patterns.remove(name);
+                    }
+                }
+            },
+        }
+    }
+
+    // the subscribers by channel name for the kinds subscribed to by name.
+    fn registry(&self, kind: SubscriptionKind) -> &Mutex<HashMap<Vec<u8>, Subscribers>> {
+        match kind {
+            SubscriptionKind::Shard => &self.shards,
+            _ => &self.channels,
+        }
+    }
+}
+
+// the channels, patterns and shard channels one connection is subscribed to. dropping it
+// unsubscribes from all of them, so a connection going away never leaves anything behind in
+// the broker.
+#[derive(Debug)]
+pub struct Subscriber {
+    broker: Arc<Broker>,
+    id: u64,
+    sender: UnboundedSender<Message>,
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+    shards: BTreeSet<Vec<u8>>,
+}
+
+impl Subscriber {
+    // returns how many subscriptions of its kind this connection has afterwards, channels and
+    // patterns count together like in redis while shard channels count on their own.
+    pub fn subscribe(&mut self, kind: SubscriptionKind, name: &[u8]) -> usize {
+        if self.names_mut(kind).insert(name.to_vec()) {
+            self.broker.add(kind, name, self.id, &self.sender);
+        }
+        self.count(kind)
+    }
+
+    // returns how many subscriptions of its kind this connection has afterwards.
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, name: &[u8]) -> usize {
+        if self.names_mut(kind).remove(name) {
+            self.broker.remove(kind, name, self.id);
+        }
+        self.count(kind)
+    }
+
+    // the channels or patterns subscribed to, in order.
+    pub fn subscriptions(&self, kind: SubscriptionKind) -> Vec<Vec<u8>> {
+        let names = match kind {
+            SubscriptionKind::Channel => &self.channels,
+            SubscriptionKind::Pattern => &self.patterns,
+            SubscriptionKind::Shard => &self.shards,
+        };
+        names.iter().cloned().collect()
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty() || !self.shards.is_empty()
+    }
+
+    fn count(&self, kind: SubscriptionKind) -> usize {
+        match kind {
+            SubscriptionKind::Shard => self.shards.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        }
+    }
+
+    fn names_mut(&mut self, kind: SubscriptionKind) -> &mut BTreeSet<Vec<u8>> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::Shard => &mut self.shards,
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for channel in &self.channels {
+            self.broker.remove(SubscriptionKind::Channel, channel, self.id);
+        }
+        for pattern in &self.patterns {
+            self.broker.remove(SubscriptionKind::Pattern, pattern, self.id);
+        }
+        for shard in &self.shards {
+            self.broker.remove(SubscriptionKind::Shard, shard, self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_reaches_subscribers() {
+        let broker = Arc::new(Broker::new());
+        let (mut a, mut a_messages) = broker.subscriber();
+        let (mut b, mut b_messages) = broker.subscriber();
+
+        assert_eq!(a.subscribe(SubscriptionKind::Channel, b"news"), 1);
+        assert_eq!(a.subscribe(SubscriptionKind::Channel, b"news"), 1);
+        assert_eq!(a.subscribe(SubscriptionKind::Channel, b"sport"), 2);
+        assert_eq!(b.subscribe(SubscriptionKind::Channel, b"news"), 1);
+
+        assert_eq!(broker.publish(b"news", b"hello"), 2);
+        assert_eq!(broker.publish(b"weather", b"rain"), 0);
+        let message = Message { shard: false, pattern: None, channel: b"news".to_vec(), payload: b"hello".to_vec() };
+        assert_eq!(a_messages.try_recv(), Ok(message.clone()));
+        assert_eq!(b_messages.try_recv(), Ok(message));
+        assert!(a_messages.try_recv().is_err());
+
+        assert_eq!(a.unsubscribe(SubscriptionKind::Channel, b"news"), 1);
+        assert_eq!(broker.publish(b"news", b"again"), 1);
+        assert_eq!(broker.channels(), 2);
+
+        // dropping a subscriber takes its channels with it.
+        drop(a);
+        drop(b);
+        assert_eq!(broker.channels(), 0);
+        assert_eq!(broker.publish(b"news", b"anyone?"), 0);
+    }
+
+    #[test]
+    fn test_patterns_match_channels() {
+        let broker = Arc::new(Broker::new());
+        let (mut subscriber, mut messages) = broker.subscriber();
+
+        assert_eq!(subscriber.subscribe(SubscriptionKind::Pattern, b"news.*"), 1);
+        assert_eq!(subscriber.subscribe(SubscriptionKind::Channel, b"news.tech"), 2);
+        assert_eq!(broker.publish(b"news.tech", b"rust"), 2);
+        assert_eq!(broker.publish(b"news.art", b"paint"), 1);
+        assert_eq!(broker.publish(b"sport", b"ball"), 0);
+
+        let received: Vec<Message> = std::iter::from_fn(|| messages.try_recv().ok()).collect();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].pattern, None);
+        assert_eq!(received[1].pattern.as_deref(), Some(&b"news.*"[..]));
+        assert_eq!(received[2].channel, b"news.art");
+
+        assert_eq!(subscriber.unsubscribe(SubscriptionKind::Pattern, b"news.*"), 1);
+        assert_eq!(broker.patterns(), 0);
+        assert_eq!(broker.publish(b"news.art", b"paint"), 0);
+    }
+
+    #[test]
+    fn test_shard_channels_are_separate() {
+        let broker = Arc::new(Broker::new());
+        let (mut subscriber, mut messages) = broker.subscriber();
+
+        assert_eq!(subscriber.subscribe(SubscriptionKind::Channel, b"orders"), 1);
+        assert_eq!(subscriber.subscribe(SubscriptionKind::Pattern, b"*"), 2);
+        assert_eq!(subscriber.subscribe(SubscriptionKind::Shard, b"orders"), 1);
+
+        assert_eq!(broker.publish_shard(b"orders", b"42"), 1);
+        assert_eq!(messages.try_recv(), Ok(Message { shard: true, pattern: None, channel: b"orders".to_vec(), payload: b"42".to_vec() }));
+        assert!(messages.try_recv().is_err());
+        assert_eq!(broker.publish(b"orders", b"43"), 2);
+        assert_eq!(broker.publish_shard(b"other", b"44"), 0);
+
+        assert_eq!(subscriber.unsubscribe(SubscriptionKind::Shard, b"orders"), 0);
+        assert_eq!(broker.shards(), 0);
+        assert!(subscriber.is_subscribed());
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::Display;
+use crate::resp::{ Resp, format_double };
+use crate::database::{ StreamError, StringError, WrongType };
+use crate::hyperloglog::HllError;
+
+// the wire protocol a client speaks, decides how a reply is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    // the protocol for a HELLO protover, none for versions the server doesn't speak.
+    pub fn from_version(version: i64) -> Option<Protocol> {
+        match version {
+            2 => Some(Protocol::Resp2),
+            3 => Some(Protocol::Resp3),
+            _ => None,
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+// errors a command can reply with, rendered as the full error line including its prefix.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerError {
+    Syntax,
+    NotAnInteger,
+    WrongType,
+    WrongArity(String),
+    // a fully formed error line, i.e., "ERR something went wrong"
+    Custom(String),
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ServerError::Syntax => write!(f, "ERR syntax error"),
+            ServerError::NotAnInteger => write!(f, "ERR value is not an integer or out of range"),
+            ServerError::WrongType => write!(f, "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            ServerError::WrongArity(cmd) => write!(f, "ERR wrong number of arguments for '{}' command", cmd),
+            ServerError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<WrongType> for ServerError {
+    fn from(_: WrongType) -> Self {
+        ServerError::WrongType
+    }
+}
+
+impl From<StringError> for ServerError {
+    fn from(err: StringError) -> Self {
+        match err {
+            StringError::WrongType => ServerError::WrongType,
+            StringError::TooLarge => ServerError::Custom("ERR string exceeds maximum allowed size (proto-max-bulk-len)".to_string()),
+        }
+    }
+}
+
+impl From<HllError> for ServerError {
+    fn from(err: HllError) -> Self {
+        match err {
+            HllError::WrongType => ServerError::WrongType,
+            HllError::Invalid => ServerError::Custom("WRONGTYPE Key is not a valid HyperLogLog string value.".to_string()),
+        }
+    }
+}
+
+impl From<StreamError> for ServerError {
+    fn from(err: StreamError) -> Self {
+        let msg = match err {
+            StreamError::WrongType => return ServerError::WrongType,
+            StreamError::IdNotGreater => "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+            StreamError::IdZero => "ERR The ID specified in XADD must be greater than 0-0",
+            StreamError::Exhausted => "ERR The stream has exhausted the last possible ID, unable to add more items",
+            StreamError::NoKey => "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+            StreamError::GroupExists => "BUSYGROUP Consumer Group name already exists",
+            StreamError::NoGroup { key, group } => {
+                return ServerError::Custom(format!(
+                    "NOGROUP No such consumer group '{}' for key name '{}'",
+                    String::from_utf8_lossy(&group),
+                    String::from_utf8_lossy(&key),
+                ));
+            },
+        };
+        ServerError::Custom(msg.to_string())
+    }
+}
+
+impl From<String> for ServerError {
+    fn from(msg: String) -> Self {
+        ServerError::Custom(msg)
+    }
+}
+
+impl From<&str> for ServerError {
+    fn from(msg: &str) -> Self {
+        ServerError::Custom(msg.to_string())
+    }
+}
+
+// what a command answers with, independent of the protocol version of the client.
+// commands build these and the connection converts them into resp on the way out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Ok,
+    // a missing value, null bulk string in resp2.
+    Nil,
+    // a missing aggregate, null array in resp2.
+    NilArray,
+    Status(String),
+    Int(i64),
+    Bulk(Vec<u8>),
+    // free form text such as INFO output, a verbatim string in resp3.
+    Verbatim(String),
+    // a bulk string in resp2.
+    Double(f64),
+    // an integer in resp2.
+    Bool(bool),
+    Array(Vec<Reply>),
+    // a flat array of alternating keys and values in resp2.
+    Map(Vec<(Reply, Reply)>),
+    // pairs that may repeat, such as fields with their values. an array of two element arrays
+    // in resp3 and flat like a map in resp2.
+    Pairs(Vec<(Reply, Reply)>),
+    // an array in resp2.
+    Set(Vec<Reply>),
+    // out of band data the server sends on its own, such as pub/sub messages. an array in resp2.
+    Push(Vec<Reply>),
+    Error(ServerError),
+}
+
+impl Reply {
+    pub fn error(err: impl Into<ServerError>) -> Reply {
+        Reply::Error(err.into())
+    }
+
+    pub fn bulk(bytes: &[u8]) -> Reply {
+        Reply::Bulk(bytes.to_vec())
+    }
+
+    pub fn into_resp(self, protocol: Protocol) -> Resp {
+        match (self, protocol) {
+            (Reply::Ok, _) => Resp::SimpleString("OK".to_string()),
+            (Reply::Nil, Protocol::Resp2) => Resp::BulkStringNull,
+            (Reply::NilArray, Protocol::Resp2) => Resp::ArrayNull,
+            (Reply::Nil, Protocol::Resp3) | (Reply::NilArray, Protocol::Resp3) => Resp::Null,
+            (Reply::Status(s), _) => Resp::SimpleString(s),
+            (Reply::Int(i), _) => Resp::Integer(i),
+            (Reply::Bulk(b), _) => Resp::BulkString(b),
+            (Reply::Verbatim(s), Protocol::Resp2) => Resp::BulkString(s.into_bytes()),
+            (Reply::Verbatim(s), Protocol::Resp3) => Resp::VerbatimString(format!("txt:{}", s).into_bytes()),
+            (Reply::Double(f), Protocol::Resp2) => Resp::BulkString(format_double(f).into_bytes()),
+            (Reply::Double(f), Protocol::Resp3) => Resp::Double(f),
+            (Reply::Bool(b), Protocol::Resp2) => Resp::Integer(b as i64),
+            (Reply::Bool(b), Protocol::Resp3) => Resp::Boolean(b),
+            (Reply::Array(items), protocol) => Resp::Array(Self::convert_all(items, protocol)),
+            (Reply::Map(pairs), Protocol::Resp2) => Resp::Array(
+                pairs
+                    .into_iter()
+                    .flat_map(|(k, v)| [k.into_resp(Protocol::Resp2), v.into_resp(Protocol::Resp2)])
+                    .collect()
+            ),
+            (Reply::Map(pairs), Protocol::Resp3) => Resp::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.into_resp(Protocol::Resp3), v.into_resp(Protocol::Resp3)))
+                    .collect()
+            ),
+            (Reply::Pairs(pairs), Protocol::Resp2) => Reply::Map(pairs).into_resp(Protocol::Resp2),
+            (Reply::Pairs(pairs), Protocol::Resp3) => Resp::Array(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| Resp::Array(vec![k.into_resp(Protocol::Resp3), v.into_resp(Protocol::Resp3)]))
+                    .collect()
+            ),
+            (Reply::Set(items), Protocol::Resp2) => Resp::Array(Self::convert_all(items, Protocol::Resp2)),
+            (Reply::Set(items), Protocol::Resp3) => Resp::Set(Self::convert_all(items, Protocol::Resp3)),
+            (Reply::Push(items), Protocol::Resp2) => Resp::Array(Self::convert_all(items, Protocol::Resp2)),
+            (Reply::Push(items), Protocol::Resp3) => Resp::Push(Self::convert_all(items, Protocol::Resp3)),
+            (Reply::Error(e), _) => Resp::SimpleError(e.to_string()),
+        }
+    }
+
+    fn convert_all(items: Vec<Reply>, protocol: Protocol) -> Vec<Resp> {
+        items.into_iter().map(|item| item.into_resp(protocol)).collect()
+    }
+}
+
+impl From<ServerError> for Reply {
+    fn from(err: ServerError) -> Self {
+        Reply::Error(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nil_by_protocol() {
+        assert_eq!(Reply::Nil.into_resp(Protocol::Resp2), Resp::BulkStringNull);
+        assert_eq!(Reply::NilArray.into_resp(Protocol::Resp2), Resp::ArrayNull);
+        assert_eq!(Reply::Nil.into_resp(Protocol::Resp3), Resp::Null);
+    }
+
+    #[test]
+    fn test_map_flattens_in_resp2() {
+        let reply = Reply::Map(vec![(Reply::bulk(b"len"), Reply::Int(3))]);
+        assert_eq!(
+            reply.clone().into_resp(Protocol::Resp2),
+            Resp::Array(vec![Resp::BulkString(b"len".to_vec()), Resp::Integer(3)])
+        );
+        assert_eq!(
+            reply.into_resp(Protocol::Resp3),
+            Resp::Map(vec![(Resp::BulkString(b"len".to_vec()), Resp::Integer(3))])
+        );
+    }
+
+    #[test]
+    fn test_pairs_nest_in_resp3() {
+        let reply = Reply::Pairs(vec![(Reply::bulk(b"f"), Reply::bulk(b"v")), (Reply::bulk(b"f"), Reply::bulk(b"v"))]);
+        let pair = Resp::Array(vec![Resp::BulkString(b"f".to_vec()), Resp::BulkString(b"v".to_vec())]);
+        assert_eq!(reply.clone().into_resp(Protocol::Resp3), Resp::Array(vec![pair.clone(), pair]));
+        assert_eq!(reply.into_resp(Protocol::Resp2).as_slice().map(|items| items.len()), Some(4));
+    }
+
+    #[test]
+    fn test_errors_render_prefix() {
+        assert_eq!(
+            Reply::Error(ServerError::WrongArity("get".to_string())).into_resp(Protocol::Resp2),
+            Resp::SimpleError("ERR wrong number of arguments for 'get' command".to_string())
+        );
+        assert_eq!(
+            Reply::Error(ServerError::WrongType).into_resp(Protocol::Resp3),
+            Resp::SimpleError("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+        );
+    }
+}
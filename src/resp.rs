@@ -19,6 +19,7 @@ use std::error::Error;
 // Sets	            RESP3	                    Aggregate	~
 // Pushes	        RESP3	                    Aggregate	>
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum ParseError {
     UnexpectedEndOfInput,
     InvalidByte, 
@@ -28,7 +29,23 @@ pub enum ParseError {
     InvalidFloat(std::num::ParseFloatError),
     InvalidFloatConversion,
     InvalidLength,
-    InvalidStringConversion
+    InvalidStringConversion,
+    // a line was not terminated by \r\n, or contained a bare \r or \n.
+    InvalidCrlf,
+    // the first byte of a value is not a known resp type.
+    UnknownType(u8),
+}
+
+impl ParseError {
+    // true when more input may still complete the value, every other error means the
+    // peer sent something that can never become valid resp.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, ParseError::UnexpectedEndOfInput)
+    }
+
+    pub fn is_protocol_error(&self) -> bool {
+        !self.is_incomplete()
+    }
 }
 
 impl From<std::string::FromUtf8Error> for ParseError {
@@ -67,6 +84,8 @@ impl Display for ParseError {
             ParseError::InvalidFloatConversion => write!(f, "Invalid float conversion"),
             ParseError::InvalidLength => write!(f, "Invalid length"),
             ParseError::InvalidStringConversion => write!(f, "Invalid string conversion"),
+            ParseError::InvalidCrlf => write!(f, "expected CRLF line terminator"),
+            ParseError::UnknownType(b) => write!(f, "unexpected type byte '{}'", b.escape_ascii()),
         }
     }
 }
@@ -74,6 +93,7 @@ impl Display for ParseError {
 impl Error for ParseError {}
 // to-do - can we change this to be a copyless parser?
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Resp {
     // these are required to be utf8 encoded, hence they are strings instead of u8s
     SimpleString(String),
@@ -93,6 +113,8 @@ pub enum Resp {
     Map(Vec<(Resp, Resp)>), // todo - could we implement hash for these types?
     Set(Vec<Resp>), // todo - could we implement hash for these types?
     Push(Vec<Resp>),
+    // out of band metadata (attributes) about the value that follows it on the wire.
+    Attribute(Vec<(Resp, Resp)>, Box<Resp>),
 }
 
 impl TryInto<String> for Resp {
@@ -116,6 +138,12 @@ impl TryInto<String> for Resp {
 }
 
 impl Resp {
+    // a command the way a client sends it, an array of bulk strings.
+    #[cfg(test)]
+    pub(crate) fn command(args: &[&str]) -> Resp {
+        Resp::Array(args.iter().map(|a| Resp::BulkString(a.as_bytes().to_vec())).collect())
+    }
+
     pub fn as_str(&self) -> Option<&str> {
         match self {
             Resp::SimpleString(s) | Resp::SimpleError(s) => Some(s),
@@ -229,6 +257,24 @@ impl Resp {
         matches!(self, Resp::Push(_))
     }
 
+    pub fn is_attribute(&self) -> bool {
+        matches!(self, Resp::Attribute(_, _))
+    }
+
+    // separates the attributes attached to a value from the value itself,
+    // consecutive attribute frames are merged in the order they were received.
+    pub fn split_attributes(self) -> (Resp, Vec<(Resp, Resp)>) {
+        let mut attributes = Vec::new();
+        let mut value = self;
+
+        while let Resp::Attribute(mut attrs, inner) = value {
+            attributes.append(&mut attrs);
+            value = *inner;
+        }
+
+        (value, attributes)
+    }
+
     pub fn is_bulk_string_null(&self) -> bool {
         matches!(self, Resp::BulkStringNull)
     }
@@ -253,18 +299,77 @@ impl Resp {
     }
 }
 
+// the kinds of value resp3 lets a server stream without announcing its size up front, i.e.,
+// $? followed by chunks or *? followed by elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamedType {
+    String,
+    Array,
+    Set,
+    Map,
+    Push,
+}
+
+impl StreamedType {
+    fn from_byte(byte: u8) -> Option<StreamedType> {
+        match byte {
+            b'$' => Some(StreamedType::String),
+            b'*' => Some(StreamedType::Array),
+            b'~' => Some(StreamedType::Set),
+            b'%' => Some(StreamedType::Map),
+            b'>' => Some(StreamedType::Push),
+            _ => None,
+        }
+    }
+
+    fn type_byte(&self) -> u8 {
+        match self {
+            StreamedType::String => b'$',
+            StreamedType::Array => b'*',
+            StreamedType::Set => b'~',
+            StreamedType::Map => b'%',
+            StreamedType::Push => b'>',
+        }
+    }
+}
+
+// one piece of a reply as it comes off the wire. a streamed value is handed out as its
+// header, each chunk or element, and its end marker, so it can be consumed before the
+// server finished sending it.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Frame {
+    StreamStart(StreamedType),
+    // one chunk of a streamed string.
+    Chunk(Vec<u8>),
+    // the empty chunk ending a string or the . ending an aggregate.
+    StreamEnd,
+    // a complete value, either a reply of its own or an element of a streamed aggregate.
+    Value(Resp),
+}
+
+// the most elements an aggregate may declare, the limit redis puts on multibulk requests.
+pub const MAX_AGGREGATE_LEN: i64 = 1024 * 1024;
+
+// the longest bulk string, redis' default proto-max-bulk-len.
+pub const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+// an aggregate only reserves room for this many elements up front, the rest grows as they
+// arrive. the declared length comes from the peer and a value is checked on every partial read.
+const MAX_PREALLOCATED: usize = 1024;
+
 #[derive(Debug)]
 pub struct RespParser<'a> {
     data: &'a mut Cursor<BytesMut>,
 }
 
 impl<'a> RespParser<'a> { 
-    pub fn new(data: &'a mut Cursor<BytesMut>) -> RespParser {
+    pub fn new(data: &'a mut Cursor<BytesMut>) -> RespParser<'a> {
         RespParser { data }
     }
 
     pub fn is_eof(&self) -> bool {
-        self.data.remaining() <= 0
+        !self.data.has_remaining()
     }
 
     pub fn check(&mut self) -> Result<Resp, ParseError> {
@@ -278,6 +383,42 @@ impl<'a> RespParser<'a> {
         self.parse_resp()
     }
 
+    pub fn check_frame(&mut self) -> Result<Frame, ParseError> {
+        let start_pos = self.data.position();
+        let res = self.parse_frame();
+        self.data.set_position(start_pos);
+        res
+    }
+
+    // the next piece of the input without assembling streamed values, nested streamed values
+    // inside an element are still parsed whole.
+    pub fn parse_frame(&mut self) -> Result<Frame, ParseError> {
+        let header = self.data.chunk();
+        if header.len() >= 2 && header[1] == b'?' {
+            if let Some(kind) = StreamedType::from_byte(header[0]) {
+                self.data.advance(2);
+                self.expect_crlf()?;
+                return Ok(Frame::StreamStart(kind));
+            }
+        }
+
+        match header.first() {
+            Some(b';') => {
+                self.data.advance(1);
+                Ok(match self.parse_chunk()? {
+                    Some(chunk) => Frame::Chunk(chunk),
+                    None => Frame::StreamEnd,
+                })
+            },
+            Some(b'.') => {
+                self.data.advance(1);
+                self.expect_crlf()?;
+                Ok(Frame::StreamEnd)
+            },
+            _ => Ok(Frame::Value(self.parse()?)),
+        }
+    }
+
     fn parse_resp(&mut self) -> Result<Resp, ParseError> {
         let first_byte = self.next_byte()?;
 
@@ -296,7 +437,8 @@ impl<'a> RespParser<'a> {
             b'%' => Ok(self.parse_map()?),
             b'~' => Ok(self.parse_set()?),
             b'>' => Ok(self.parse_push()?),
-            _ => panic!("unsupported decoding type"),
+            b'|' => Ok(self.parse_attribute()?),
+            other => Err(ParseError::UnknownType(other)),
         }
     }
 
@@ -321,34 +463,46 @@ impl<'a> RespParser<'a> {
 
     fn parse_bulk_string(&mut self) -> Result<Resp, ParseError> {
         let len_bytes = self.parse_until_crlf()?;
+        if len_bytes == b"?" {
+            return self.parse_streamed_string();
+        }
         let len = self.bytes_to_len(&len_bytes)?;
         
-        if len < 0 {
+        if len == -1 {
             return Ok(Resp::BulkStringNull);
         }
 
+        if !(0..=MAX_BULK_LEN).contains(&len) {
+            return Err(ParseError::InvalidLength);
+        }
+
         let result = self.vec_from_slice(len as usize)?;
         // note this is the only case were we need to manually advance
         // because we slice based on the len for effiency...
-        self.expect_byte(b'\r')?;
-        self.expect_byte(b'\n')?;
-        // todo handle error case
+        self.expect_crlf()?;
         Ok(Resp::BulkString(result))
     }
 
     fn parse_array(&mut self) -> Result<Resp, ParseError> {
         let len_bytes = self.parse_until_crlf()?;
+        if len_bytes == b"?" {
+            return Ok(Resp::Array(self.parse_streamed_items()?));
+        }
         let len = self.bytes_to_len(&len_bytes)?;
         
-        if len < 0 {
+        if len == -1 {
             return Ok(Resp::ArrayNull);
         }
 
+        if !(0..=MAX_AGGREGATE_LEN).contains(&len) {
+            return Err(ParseError::InvalidLength);
+        }
+
         if len == 0 {
             return Ok(Resp::Array(vec![]))
         }
 
-        let mut res: Vec<Resp> = Vec::with_capacity(len as usize);
+        let mut res: Vec<Resp> = Vec::with_capacity(preallocated(len));
 
         for _ in 0..len {
             res.push(self.parse()?);
@@ -358,16 +512,18 @@ impl<'a> RespParser<'a> {
     }
 
     fn parse_null(&mut self) -> Result<Resp, ParseError> {
-        let _ = self.parse_until_crlf()?;
+        self.expect_crlf()?;
         Ok(Resp::Null)
     }
     
     fn parse_boolean(&mut self) -> Result<Resp, ParseError> {
-        match self.next_byte()? {
-            b't' => Ok(Resp::Boolean(true)),
-            b'f' => Ok(Resp::Boolean(false)),
-            _ => Err(ParseError::InvalidByte),
-        }
+        let value = match self.next_byte()? {
+            b't' => true,
+            b'f' => false,
+            _ => return Err(ParseError::InvalidByte),
+        };
+        self.expect_crlf()?;
+        Ok(Resp::Boolean(value))
     }
 
     fn parse_float(&mut self) -> Result<Resp, ParseError> {
@@ -399,7 +555,7 @@ impl<'a> RespParser<'a> {
         let bulk_str = self.parse_bulk_string()?; 
         match bulk_str {
             Resp::BulkString(s) => Ok(Resp::BulkError(s)),
-            other => panic!("bulk error could not transform from {:#?}", other)
+            _ => Err(ParseError::InvalidLength),
         }
     }
 
@@ -408,18 +564,27 @@ impl<'a> RespParser<'a> {
         let bulk_str = self.parse_bulk_string()?; 
         match bulk_str {
             Resp::BulkString(s) => Ok(Resp::VerbatimString(s)),
-            _ => panic!("verbatim string could not transform from {:#?}", bulk_str)
+            _ => Err(ParseError::InvalidLength),
         }
     }
 
     fn parse_map(&mut self) -> Result<Resp, ParseError> {
         let len_bytes = self.parse_until_crlf()?;
+        if len_bytes == b"?" {
+            let mut result = Vec::new();
+            while !self.at_stream_end()? {
+                let key = self.parse()?;
+                let value = self.parse()?;
+                result.push((key, value));
+            }
+            return Ok(Resp::Map(result));
+        }
         let len = self.bytes_to_len(&len_bytes)?;
-        if len < 0 {
+        if !(0..=MAX_AGGREGATE_LEN).contains(&len) {
             return Err(ParseError::InvalidLength);
         }
 
-        let mut result: Vec<(Resp, Resp)> = Vec::with_capacity(len as usize);
+        let mut result: Vec<(Resp, Resp)> = Vec::with_capacity(preallocated(len));
 
         // todo - should we be validating these as we go?
         for _ in 0..len {
@@ -431,15 +596,29 @@ impl<'a> RespParser<'a> {
         Ok(Resp::Map(result))
     }
 
+    // an attribute is a map followed by the value it describes, the two are kept together
+    // so aggregates still count it as a single element.
+    fn parse_attribute(&mut self) -> Result<Resp, ParseError> {
+        let attributes = match self.parse_map()? {
+            Resp::Map(m) => m,
+            _ => unreachable!(),
+        };
+        let value = self.parse()?;
+        Ok(Resp::Attribute(attributes, Box::new(value)))
+    }
+
     fn parse_set(&mut self) -> Result<Resp, ParseError> {
         let len_bytes = self.parse_until_crlf()?;
+        if len_bytes == b"?" {
+            return Ok(Resp::Set(self.parse_streamed_items()?));
+        }
         let len = self.bytes_to_len(&len_bytes)?;
 
-        if len < 0 {
+        if !(0..=MAX_AGGREGATE_LEN).contains(&len) {
             return Err(ParseError::InvalidLength);
         }
 
-        let mut result: Vec<Resp> = Vec::with_capacity(len as usize);
+        let mut result: Vec<Resp> = Vec::with_capacity(preallocated(len));
 
         // todo - should we be validating these as we go?
         for _ in 0..len {
@@ -459,19 +638,83 @@ impl<'a> RespParser<'a> {
         unreachable!();
     }
     
+    // the chunks of a $? string joined together, the string ends with an empty chunk.
+    fn parse_streamed_string(&mut self) -> Result<Resp, ParseError> {
+        let mut result = Vec::new();
+        loop {
+            if self.next_byte()? != b';' {
+                return Err(ParseError::InvalidByte);
+            }
+            match self.parse_chunk()? {
+                Some(chunk) => result.extend_from_slice(&chunk),
+                None => return Ok(Resp::BulkString(result)),
+            }
+        }
+    }
+
+    // a ;len chunk after its type byte, none for the empty chunk that ends the string.
+    fn parse_chunk(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        let len_bytes = self.parse_until_crlf()?;
+        let len = self.bytes_to_len(&len_bytes)?;
+        if len < 0 {
+            return Err(ParseError::InvalidLength);
+        }
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let chunk = self.vec_from_slice(len as usize)?;
+        self.expect_crlf()?;
+        Ok(Some(chunk))
+    }
+
+    // the elements of an aggregate sent with a ? length, up to the . that ends it.
+    fn parse_streamed_items(&mut self) -> Result<Vec<Resp>, ParseError> {
+        let mut result = Vec::new();
+        while !self.at_stream_end()? {
+            result.push(self.parse()?);
+        }
+        Ok(result)
+    }
+
+    // consumes the . ending a streamed aggregate if it is next.
+    fn at_stream_end(&mut self) -> Result<bool, ParseError> {
+        match self.data.chunk().first() {
+            None => Err(ParseError::UnexpectedEndOfInput),
+            Some(b'.') => {
+                self.data.advance(1);
+                self.expect_crlf()?;
+                Ok(true)
+            },
+            Some(_) => Ok(false),
+        }
+    }
+
     // note: this consumes the crlf character as well, so no need to check for it.
+    // a bare \r or \n inside the line is a protocol error.
     fn parse_until_crlf(&mut self) -> Result<Vec<u8>, ParseError> {
         let mut result = Vec::new();
         loop {
-            let byte = self.next_byte()?;
-            if byte == b'\r' {
-                self.expect_byte(b'\n')?;
-                return Ok(result);
+            match self.next_byte()? {
+                b'\r' => {
+                    if self.next_byte()? != b'\n' {
+                        return Err(ParseError::InvalidCrlf);
+                    }
+                    return Ok(result);
+                },
+                b'\n' => return Err(ParseError::InvalidCrlf),
+                byte => result.push(byte),
             }
-            result.push(byte);
         }
     }
 
+    fn expect_crlf(&mut self) -> Result<(), ParseError> {
+        if self.next_byte()? != b'\r' || self.next_byte()? != b'\n' {
+            return Err(ParseError::InvalidCrlf);
+        }
+        Ok(())
+    }
+
     fn bytes_to_len(&self, input: &[u8]) -> Result<i64, ParseError> {
         let len_str = std::str::from_utf8(input)?;
         let len = len_str.parse::<i64>()?;
@@ -486,16 +729,6 @@ impl<'a> RespParser<'a> {
         Ok(self.data.get_u8())
     }
 
-    fn expect_byte(&mut self, expected: u8) -> Result<(), ParseError> {
-        let byte = self.next_byte()?;
-
-        if byte != expected {
-            return Err(ParseError::InvalidByte);
-        }
-
-        Ok(())
-    }
-
     fn get_slice(&mut self, to: usize) -> Result<&[u8], ParseError> {
         if self.data.remaining() < to {
             return Err(ParseError::UnexpectedEndOfInput)
@@ -505,15 +738,74 @@ impl<'a> RespParser<'a> {
         Ok(&self.data.get_ref()[curr_pos..curr_pos + to])
     }
 
+    // only allocates once all to bytes have arrived.
     fn vec_from_slice(&mut self, to: usize) -> Result<Vec<u8>, ParseError> {
         let curr_pos = self.data.position();
-        let mut result = Vec::with_capacity(to);
-        result.extend_from_slice(self.get_slice(to)?);
+        let result = self.get_slice(to)?.to_vec();
         self.data.set_position(curr_pos + to as u64);
         Ok(result)
     }
 }
 
+fn preallocated(len: i64) -> usize {
+    (len as usize).min(MAX_PREALLOCATED)
+}
+
+// formats a double the way redis does with fpconv_dtoa: the shortest digits that round trip,
+// written out plainly for everyday magnitudes and in scientific notation with a signed exponent
+// otherwise, i.e., 3 -> "3", 0.1 -> "0.1", 1e21 -> "1e+21", 1.23e-7 -> "1.23e-7".
+pub fn format_double(f: f64) -> String {
+    if f.is_nan() {
+        return "nan".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if f == 0.0 {
+        return if f.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    // rust's {:e} already gives the shortest round trip digits, only the layout differs.
+    let scientific = format!("{:e}", f.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let ndigits = digits.len() as i32;
+    // the value is digits * 10^k.
+    let k = exponent - (ndigits - 1);
+
+    let mut out = String::new();
+    if f < 0.0 {
+        out.push('-');
+    }
+
+    if k >= 0 && exponent.abs() < ndigits + 7 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat(k as usize));
+    } else if k < 0 && (k > -7 || exponent.abs() < 4) {
+        let offset = ndigits + k;
+        if offset <= 0 {
+            out.push_str("0.");
+            out.push_str(&"0".repeat(-offset as usize));
+            out.push_str(&digits);
+        } else {
+            out.push_str(&digits[..offset as usize]);
+            out.push('.');
+            out.push_str(&digits[offset as usize..]);
+        }
+    } else {
+        out.push_str(&digits[..1]);
+        if ndigits > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push('e');
+        out.push(if exponent < 0 { '-' } else { '+' });
+        out.push_str(&exponent.abs().to_string());
+    }
+    out
+}
+
 pub struct RespEncoder;
 
 impl RespEncoder {
@@ -541,6 +833,7 @@ impl RespEncoder {
             Resp::Map(m) => Self::encode_map(m, buffer),
             Resp::Set(s) => Self::encode_set(s, buffer),
             Resp::Push(p) => Self::encode_push(p, buffer),
+            Resp::Attribute(a, value) => Self::encode_attribute(a, value, buffer),
         }
     }
 
@@ -576,14 +869,45 @@ impl RespEncoder {
     }
 
     pub fn encode_array(arr: &[Resp], buffer: &mut BytesMut) {
-        buffer.put_u8(b'*');
-        buffer.extend_from_slice(arr.len().to_string().as_bytes());
-        buffer.extend_from_slice(b"\r\n");
+        Self::encode_array_len(arr.len(), buffer);
         for item in arr {
             Self::encode_resp(item, buffer);
         }
     }
 
+    // writes only the header of an array, the caller is responsible for encoding len items after it.
+    pub fn encode_array_len(len: usize, buffer: &mut BytesMut) {
+        buffer.put_u8(b'*');
+        buffer.extend_from_slice(len.to_string().as_bytes());
+        buffer.extend_from_slice(b"\r\n");
+    }
+
+    // writes the header of a value whose size isn't known yet. a string is followed by
+    // encode_chunk calls and an aggregate by its elements, both are closed by encode_stream_end.
+    pub fn encode_stream_start(kind: StreamedType, buffer: &mut BytesMut) {
+        buffer.put_u8(kind.type_byte());
+        buffer.extend_from_slice(b"?\r\n");
+    }
+
+    // an empty chunk would end the string early, so there is nothing to write for one.
+    pub fn encode_chunk(bytes: &[u8], buffer: &mut BytesMut) {
+        if bytes.is_empty() {
+            return;
+        }
+        buffer.put_u8(b';');
+        buffer.extend_from_slice(bytes.len().to_string().as_bytes());
+        buffer.extend_from_slice(b"\r\n");
+        buffer.extend_from_slice(bytes);
+        buffer.extend_from_slice(b"\r\n");
+    }
+
+    pub fn encode_stream_end(kind: StreamedType, buffer: &mut BytesMut) {
+        match kind {
+            StreamedType::String => buffer.extend_from_slice(b";0\r\n"),
+            _ => buffer.extend_from_slice(b".\r\n"),
+        }
+    }
+
     pub fn encode_array_null(buffer: &mut BytesMut) {
         buffer.put_u8(b'*');
         buffer.extend_from_slice(b"-1\r\n");
@@ -602,19 +926,7 @@ impl RespEncoder {
 
     pub fn encode_double(f: f64, buffer: &mut BytesMut) {
         buffer.put_u8(b',');
-
-        if f.is_infinite() {
-            if f.is_sign_positive() {
-                buffer.extend_from_slice(b"inf");
-            } else {
-                buffer.extend_from_slice(b"-inf");
-            }
-        } else if f.is_nan() {
-            buffer.extend_from_slice(b"nan");
-        } else {
-            buffer.extend_from_slice(format!("{:e}", f).as_bytes());
-        }
-
+        buffer.extend_from_slice(format_double(f).as_bytes());
         buffer.extend_from_slice(b"\r\n");
     }
 
@@ -659,6 +971,17 @@ impl RespEncoder {
         }
     }
 
+    pub fn encode_attribute(a: &[(Resp, Resp)], value: &Resp, buffer: &mut BytesMut) {
+        buffer.put_u8(b'|');
+        buffer.extend_from_slice(a.len().to_string().as_bytes());
+        buffer.extend_from_slice(b"\r\n");
+        for (key, attr) in a {
+            Self::encode_resp(key, buffer);
+            Self::encode_resp(attr, buffer);
+        }
+        Self::encode_resp(value, buffer);
+    }
+
     pub fn encode_push(p: &[Resp], buffer: &mut BytesMut) {
         buffer.put_u8(b'>');
         buffer.extend_from_slice(p.len().to_string().as_bytes());
@@ -882,7 +1205,7 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_double_with_exponent_bigE() {
+    fn test_parse_double_with_exponent_big_e() {
         let data = BytesMut::from(&b",1.23E-5\r\n"[..]);
         let mut cursor = Cursor::new(data);
         let mut parser = RespParser::new(&mut cursor);
@@ -1012,7 +1335,7 @@ mod tests {
         assert_eq!(result1.unwrap(), Resp::SimpleString("hello".to_string()));
         assert_eq!(result2.unwrap(), Resp::SimpleString("world".to_string()));
         assert_eq!(result3, Err(ParseError::UnexpectedEndOfInput));
-        assert_eq!(result3, Err(ParseError::UnexpectedEndOfInput));
+        assert_eq!(result4, Err(ParseError::UnexpectedEndOfInput));
     }
 
     #[test]
@@ -1025,6 +1348,216 @@ mod tests {
         assert_eq!(parser.data.position(), 0);
     }
 
+    #[test]
+    fn test_parse_streamed_string() {
+        let data = BytesMut::from(&b"$?\r\n;4\r\nHell\r\n;5\r\no wor\r\n;1\r\nd\r\n;0\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), Resp::BulkString(b"Hello word".to_vec()));
+        assert!(parser.is_eof());
+
+        // without the empty chunk the string isn't finished yet.
+        let data = BytesMut::from(&b"$?\r\n;4\r\nHell\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        assert_eq!(RespParser::new(&mut cursor).parse(), Err(ParseError::UnexpectedEndOfInput));
+    }
+
+    #[test]
+    fn test_parse_streamed_aggregates() {
+        let data = BytesMut::from(&b"*?\r\n:1\r\n*?\r\n+a\r\n.\r\n$?\r\n;2\r\nhi\r\n;0\r\n.\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), Resp::Array(vec![
+            Resp::Integer(1),
+            Resp::Array(vec![Resp::SimpleString("a".to_string())]),
+            Resp::BulkString(b"hi".to_vec()),
+        ]));
+        assert!(parser.is_eof());
+
+        let data = BytesMut::from(&b"%?\r\n+a\r\n:1\r\n+b\r\n:2\r\n.\r\n~?\r\n.\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), Resp::Map(vec![
+            (Resp::SimpleString("a".to_string()), Resp::Integer(1)),
+            (Resp::SimpleString("b".to_string()), Resp::Integer(2)),
+        ]));
+        assert_eq!(parser.parse().unwrap(), Resp::Set(vec![]));
+
+        let data = BytesMut::from(&b"*?\r\n:1\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        assert_eq!(RespParser::new(&mut cursor).parse(), Err(ParseError::UnexpectedEndOfInput));
+    }
+
+    #[test]
+    fn test_streamed_frames_round_trip() {
+        let mut buffer = BytesMut::new();
+        RespEncoder::encode_stream_start(StreamedType::Array, &mut buffer);
+        RespEncoder::encode_resp(&Resp::Integer(1), &mut buffer);
+        RespEncoder::encode_stream_start(StreamedType::String, &mut buffer);
+        RespEncoder::encode_chunk(b"ab", &mut buffer);
+        RespEncoder::encode_chunk(b"", &mut buffer);
+        RespEncoder::encode_chunk(b"c", &mut buffer);
+        RespEncoder::encode_stream_end(StreamedType::String, &mut buffer);
+        RespEncoder::encode_stream_end(StreamedType::Array, &mut buffer);
+        assert_eq!(buffer.to_vec(), b"*?\r\n:1\r\n$?\r\n;2\r\nab\r\n;1\r\nc\r\n;0\r\n.\r\n");
+
+        // frame by frame the nesting is left to the caller.
+        let mut cursor = Cursor::new(buffer.clone());
+        let mut parser = RespParser::new(&mut cursor);
+        let mut frames = Vec::new();
+        while !parser.is_eof() {
+            frames.push(parser.parse_frame().unwrap());
+        }
+        assert_eq!(frames, vec![
+            Frame::StreamStart(StreamedType::Array),
+            Frame::Value(Resp::Integer(1)),
+            Frame::StreamStart(StreamedType::String),
+            Frame::Chunk(b"ab".to_vec()),
+            Frame::Chunk(b"c".to_vec()),
+            Frame::StreamEnd,
+            Frame::StreamEnd,
+        ]);
+
+        // and assembled it is a plain value.
+        let mut cursor = Cursor::new(buffer);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), Resp::Array(vec![Resp::Integer(1), Resp::BulkString(b"abc".to_vec())]));
+    }
+
+    #[test]
+    fn test_parse_attribute() {
+        let data = BytesMut::from(&b"|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.5\r\n*2\r\n:2039123\r\n:9543892\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        let result = parser.parse().unwrap();
+        assert!(parser.is_eof());
+
+        let (value, attributes) = result.split_attributes();
+        assert_eq!(value, Resp::Array(vec![Resp::Integer(2039123), Resp::Integer(9543892)]));
+        assert_eq!(attributes, vec![(
+            Resp::SimpleString("key-popularity".to_string()),
+            Resp::Map(vec![(Resp::BulkString(b"a".to_vec()), Resp::Double(0.5))])
+        )]);
+    }
+
+    #[test]
+    fn test_parse_attribute_inside_array() {
+        let data = BytesMut::from(&b"*2\r\n:1\r\n|1\r\n+ttl\r\n:3600\r\n:2\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        let result = parser.parse().unwrap();
+        assert_eq!(result, Resp::Array(vec![
+            Resp::Integer(1),
+            Resp::Attribute(
+                vec![(Resp::SimpleString("ttl".to_string()), Resp::Integer(3600))],
+                Box::new(Resp::Integer(2))
+            ),
+        ]));
+    }
+
+    #[test]
+    fn test_encode_attribute_round_trip() {
+        let data = Resp::Attribute(
+            vec![(Resp::SimpleString("ttl".to_string()), Resp::Integer(3600))],
+            Box::new(Resp::SimpleString("OK".to_string()))
+        );
+        let encoded = RespEncoder::encode(&data);
+        assert_eq!(&encoded[..], b"|1\r\n+ttl\r\n:3600\r\n+OK\r\n");
+
+        let mut cursor = Cursor::new(encoded);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), data);
+    }
+
+    #[test]
+    fn test_parse_boolean_consumes_crlf() {
+        let data = BytesMut::from(&b"#t\r\n+ok\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse().unwrap(), Resp::Boolean(true));
+        assert_eq!(parser.parse().unwrap(), Resp::SimpleString("ok".to_string()));
+    }
+
+    #[test]
+    fn test_parse_rejects_bare_lf() {
+        let data = BytesMut::from(&b"+hel\nlo\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse(), Err(ParseError::InvalidCrlf));
+    }
+
+    #[test]
+    fn test_parse_rejects_cr_without_lf() {
+        let data = BytesMut::from(&b"+hello\rx"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse(), Err(ParseError::InvalidCrlf));
+    }
+
+    #[test]
+    fn test_parse_bulk_string_missing_crlf() {
+        let data = BytesMut::from(&b"$5\r\nhelloXX"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse(), Err(ParseError::InvalidCrlf));
+    }
+
+    #[test]
+    fn test_parse_invalid_negative_length() {
+        let data = BytesMut::from(&b"*-2\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.parse(), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_lengths() {
+        let cases: [&[u8]; 6] = [
+            b"*9223372036854775807\r\n",
+            b"*1048577\r\n",
+            b"%1048577\r\n",
+            b"~1048577\r\n",
+            b"$536870913\r\n",
+            b"*1\r\n$9223372036854775807\r\n",
+        ];
+
+        for case in cases {
+            let mut cursor = Cursor::new(BytesMut::from(case));
+            let mut parser = RespParser::new(&mut cursor);
+            let result = parser.check();
+            assert_eq!(result, Err(ParseError::InvalidLength), "{:?}", String::from_utf8_lossy(case));
+            assert!(result.unwrap_err().is_protocol_error());
+        }
+
+        // a length at the limit is fine, it just waits for the elements.
+        let mut cursor = Cursor::new(BytesMut::from(&b"*1048576\r\n$3\r\nfoo\r\n"[..]));
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.check(), Err(ParseError::UnexpectedEndOfInput));
+        let mut cursor = Cursor::new(BytesMut::from(&b"$536870912\r\nfoo"[..]));
+        let mut parser = RespParser::new(&mut cursor);
+        assert_eq!(parser.check(), Err(ParseError::UnexpectedEndOfInput));
+    }
+
+    #[test]
+    fn test_parse_unknown_type() {
+        let data = BytesMut::from(&b"PING\r\n"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        let result = parser.parse();
+        assert_eq!(result, Err(ParseError::UnknownType(b'P')));
+        assert!(result.unwrap_err().is_protocol_error());
+    }
+
+    #[test]
+    fn test_parse_incomplete_is_not_protocol_error() {
+        let data = BytesMut::from(&b"$5\r\nhel"[..]);
+        let mut cursor = Cursor::new(data);
+        let mut parser = RespParser::new(&mut cursor);
+        let result = parser.parse().unwrap_err();
+        assert!(result.is_incomplete());
+        assert!(!result.is_protocol_error());
+    }
+
     #[test]
     fn test_encode_simple_string() {
         let data = Resp::SimpleString("hello".to_string());
@@ -1106,7 +1639,7 @@ mod tests {
     fn test_encode_double() {
         let data = Resp::Double(1.23);
         let result = RespEncoder::encode(&data);
-        assert_eq!(result.to_vec(), b",1.23e0\r\n");
+        assert_eq!(result.to_vec(), b",1.23\r\n");
     }
 
     #[test]
@@ -1130,6 +1663,34 @@ mod tests {
         assert_eq!(result.to_vec(), b",-inf\r\n");
     }
 
+    #[test]
+    fn test_format_double_matches_redis() {
+        // what redis 7.2 replies for the same values, i.e., ZSCORE after ZADD with that score.
+        let golden = [
+            (3.0, "3"),
+            (1.5, "1.5"),
+            (0.1, "0.1"),
+            (0.001, "0.001"),
+            (123.456, "123.456"),
+            (-2.5, "-2.5"),
+            (1e7, "10000000"),
+            (1e8, "1e+8"),
+            (123456789012.0, "123456789012"),
+            (1e21, "1e+21"),
+            (1e-6, "0.000001"),
+            (1e-7, "1e-7"),
+            (1.23e-7, "1.23e-7"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (5e-324, "5e-324"),
+            (-0.0, "-0"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+        ];
+        for (value, expected) in golden {
+            assert_eq!(format_double(value), expected, "{:?}", value);
+        }
+    }
+
     #[test]
     fn test_encode_double_nan() {
         let data = Resp::Double(f64::NAN);
@@ -1,11 +1,22 @@
 // Uncomment this block to pass the first stage
-use tokio::net::{ TcpListener };
+use tokio::net::{ TcpListener, TcpSocket, lookup_host };
 use std::sync::Mutex;
 use std::io;
 use std::env;
+use std::net::SocketAddr;
 use crate::database::{ Database };
 use crate::listener::{ Listener };
+use crate::connection::Connection;
 use crate::history::History;
+use crate::config::{ Config, CompatVersion, DEFAULT_INTERNAL_COMMANDS, DEFAULT_DBFILENAME };
+use crate::health::HealthListener;
+use crate::clients::ClientRegistry;
+use crate::stats::Stats;
+use crate::lfu;
+use crate::daemon;
+
+// the accept queue of each socket, the same as redis' default tcp-backlog.
+const LISTEN_BACKLOG: u32 = 511;
 
 #[derive(Debug)]
 pub struct ServerInfo {
@@ -37,7 +48,7 @@ impl ServerInfo {
     }
 
     pub fn get_master_repl_offset(&self) -> i64 {
-        self.inner.lock().unwrap().master_repl_offset.clone()
+        self.inner.lock().unwrap().master_repl_offset
     }
 
     pub fn get_master_host(&self) -> Option<String> {
@@ -45,7 +56,7 @@ impl ServerInfo {
     }
 
     pub fn is_replica(&self) -> bool {
-        if self.get_role() == "master" { false } else { true }
+        self.get_role() != "master"
     }
 
     pub fn set_master_replid(&self, replid: String) {
@@ -59,6 +70,14 @@ impl ServerInfo {
     pub fn incr_master_repl_offset(&self, offset: i64) {
         self.inner.lock().unwrap().incr_master_repl_offset(offset);
     }
+
+    pub fn is_master_link_up(&self) -> bool {
+        self.inner.lock().unwrap().master_link_up
+    }
+
+    pub fn set_master_link_up(&self, up: bool) {
+        self.inner.lock().unwrap().master_link_up = up;
+    }
 }
 
 #[derive(Debug)]
@@ -67,14 +86,15 @@ pub struct ServerInfoInner {
     master_replid: String,
     master_repl_offset: i64,
     master_host: Option<String>,
+    // whether a replica's link to its master is serving, always false on a master.
+    master_link_up: bool,
 }
 
 impl ServerInfoInner {
     pub fn new(master_host: Option<(String, String)>) -> Self {
-        if master_host.is_some() {
-            Self::replica(master_host.unwrap())
-        } else {
-            Self::master()
+        match master_host {
+            Some(host) => Self::replica(host),
+            None => Self::master(),
         }
     }
 
@@ -84,7 +104,8 @@ impl ServerInfoInner {
             // this will be generated eventually...
             master_replid: "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb".to_string(),
             master_repl_offset: 0,
-            master_host: None
+            master_host: None,
+            master_link_up: false,
         }
     }
 
@@ -93,7 +114,8 @@ impl ServerInfoInner {
             role: "slave".to_string(),
             master_replid: "?".to_string(),
             master_repl_offset: -1,
-            master_host: Some(format!("{}:{}", master_host.0, master_host.1))
+            master_host: Some(format!("{}:{}", master_host.0, master_host.1)),
+            master_link_up: false,
         }
     }
 
@@ -106,7 +128,7 @@ impl ServerInfoInner {
     }
 
     pub fn get_master_repl_offset(&self) -> i64 {
-        self.master_repl_offset.clone()
+        self.master_repl_offset
     }
 
     pub fn get_master_host(&self) -> Option<String> {
@@ -114,7 +136,7 @@ impl ServerInfoInner {
     }
 
     pub fn is_replica(&self) -> bool {
-        if self.role == "master" { false } else { true }
+        self.role != "master"
     }
 
     pub fn set_master_replid(&mut self, replid: String) {
@@ -134,33 +156,342 @@ pub struct RedisServer {
     pub listener: Listener,
 }
 
+impl RedisServer {
+    // the address actually bound, useful when the port was 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // the internal port actually bound, none if there is none.
+    pub fn internal_addr(&self) -> Option<io::Result<SocketAddr>> {
+        self.listener.internal_addr()
+    }
+}
+
 impl RedisServer {
     pub async fn bind(args: ServerArguments) -> io::Result<Self> {
         let addr = format!("{}:{}", args.host, args.port);
-        let database = Database::new(); 
-        let history = History::new();
-        let info = ServerInfo::new(args.replica_of);
-        
-        let tcp_socket = TcpListener::bind(addr.clone()).await?;
-        println!("Listening on: {}", addr);
-
-        let listener = Listener::new(tcp_socket, database, history, info);
+        let (database, info, config) = Self::configure(&args);
+
+        let acceptors = args.acceptors.unwrap_or(1).max(1);
+        let sockets = bind_sockets(&addr, acceptors).await?;
+        println!("Listening on: {} with {} acceptor(s)", addr, sockets.len());
+
+        if let Some(health_port) = args.health_port {
+            let health_addr = format!("{}:{}", args.host, health_port);
+            let health = HealthListener::bind(&health_addr).await?;
+            println!("Health checks on: {}", health_addr);
+            tokio::spawn(health.run());
+        }
+
+        let stats = Stats::with_acceptors(sockets.len());
+        let mut listener = Listener::new(sockets, database, History::new(), info, config, ClientRegistry::new(), stats);
+
+        if let Some(internal_port) = args.internal_port {
+            let internal_addr = format!("{}:{}", args.host, internal_port);
+            listener = listener.with_internal(TcpListener::bind(&internal_addr).await?);
+            println!("Internal commands on: {}", internal_addr);
+        }
         Ok(RedisServer { listener })
     }
+
+    // a server with nothing bound, clients only come in through connect_in_memory. keys only
+    // expire when they are looked up since nothing sweeps them in the background, which keeps
+    // tests deterministic. a replica never connects to its master.
+    pub fn in_memory(args: ServerArguments) -> Self {
+        let (database, info, config) = Self::configure(&args);
+        let listener = Listener::new(Vec::new(), database, History::new(), info, config, ClientRegistry::new(), Stats::with_acceptors(0));
+        RedisServer { listener }
+    }
+
+    // a client of this server on the other end of an in memory pipe.
+    pub fn connect_in_memory(&self) -> Connection {
+        self.listener.connect_in_memory()
+    }
+
+    // the state the arguments describe, before anything is bound.
+    fn configure(args: &ServerArguments) -> (Database, ServerInfo, Config) {
+        let database = Database::new();
+        let info = ServerInfo::new(args.replica_of.clone());
+        let config = Config::new();
+
+        if let Some(version) = args.compat_version {
+            config.set_compat_version(version);
+        }
+
+        if let Some(max) = args.keys_max_results {
+            config.set_keys_max_results(max);
+        }
+
+        if let Some(max) = args.maxclients {
+            config.set_maxclients(max);
+        }
+
+        if let Some(factor) = args.lfu_log_factor {
+            database.set_lfu_log_factor(factor);
+        }
+
+        if let Some(minutes) = args.lfu_decay_time {
+            database.set_lfu_decay_time(minutes);
+        }
+
+        if let Some(bytes) = args.compression_threshold {
+            database.set_compression_threshold(bytes);
+        }
+
+        if args.requirepass.is_some() {
+            config.set_requirepass(args.requirepass.clone());
+        }
+
+        if let Some(serve) = args.replica_serve_stale_data {
+            config.set_replica_serve_stale_data(serve);
+        }
+
+        if let Some(commands) = args.internal_commands.clone() {
+            config.set_internal_commands(commands);
+        }
+
+        if let Some(dir) = args.dir.clone() {
+            config.set_dir(dir);
+        }
+
+        if let Some(name) = args.dbfilename.clone() {
+            config.set_dbfilename(name);
+        }
+
+        (database, info, config)
+    }
+}
+
+// only linux spreads connections over sockets sharing a port, macOS and the BSDs accept the
+// option but hand every connection to one socket, and windows has no SO_REUSEPORT at all.
+pub const SHARDED_ACCEPT: bool = cfg!(any(target_os = "linux", target_os = "android"));
+
+// binds acceptors sockets to addr. with more than one they all share the port through
+// SO_REUSEPORT and the kernel spreads incoming connections over them.
+async fn bind_sockets(addr: &str, acceptors: usize) -> io::Result<Vec<TcpListener>> {
+    if acceptors == 1 || !SHARDED_ACCEPT {
+        if acceptors > 1 {
+            println!("accepting can only be sharded on linux, accepting on a single socket");
+        }
+        return Ok(vec![TcpListener::bind(addr).await?]);
+    }
+
+    let mut local = lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("could not resolve {}", addr)))?;
+
+    let mut sockets = Vec::with_capacity(acceptors);
+    for _ in 0..acceptors {
+        let socket = match reuseport_socket(local) {
+            Ok(socket) => socket,
+            // a kernel built without SO_REUSEPORT says so on the first socket, which is only
+            // known at runtime.
+            Err(e) if sockets.is_empty() => {
+                println!("SO_REUSEPORT is not available ({}), accepting on a single socket", e);
+                return Ok(vec![TcpListener::bind(addr).await?]);
+            },
+            Err(e) => return Err(e),
+        };
+        // a port of 0 is picked by the first socket, the rest join it.
+        local = socket.local_addr()?;
+        sockets.push(socket);
+    }
+    Ok(sockets)
+}
+
+#[cfg(unix)]
+fn reuseport_socket(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_reuseaddr(true)?;
+    socket.set_reuseport(true)?;
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+#[cfg(not(unix))]
+fn reuseport_socket(_addr: SocketAddr) -> io::Result<TcpListener> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "SO_REUSEPORT is only available on unix"))
+}
+
+// builds a server without going through the command line, i.e., to embed one in a test.
+// everything not set keeps the default the command line would use.
+#[derive(Default)]
+pub struct ServerBuilder {
+    args: ServerArguments,
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.args.host = host.into();
+        self
+    }
+
+    // "0" binds an ephemeral port.
+    pub fn port(mut self, port: impl Into<String>) -> Self {
+        self.args.port = port.into();
+        self
+    }
+
+    pub fn replica_of(mut self, host: impl Into<String>, port: impl Into<String>) -> Self {
+        self.args.replica_of = Some((host.into(), port.into()));
+        self
+    }
+
+    pub fn compat_version(mut self, version: CompatVersion) -> Self {
+        self.args.compat_version = Some(version);
+        self
+    }
+
+    pub fn maxclients(mut self, max: usize) -> Self {
+        self.args.maxclients = Some(max);
+        self
+    }
+
+    pub fn acceptors(mut self, acceptors: usize) -> Self {
+        self.args.acceptors = Some(acceptors);
+        self
+    }
+
+    pub fn requirepass(mut self, password: impl Into<String>) -> Self {
+        self.args.requirepass = Some(password.into());
+        self
+    }
+
+    pub fn replica_serve_stale_data(mut self, serve: bool) -> Self {
+        self.args.replica_serve_stale_data = Some(serve);
+        self
+    }
+
+    // "0" binds an ephemeral port.
+    pub fn internal_port(mut self, port: impl Into<String>) -> Self {
+        self.args.internal_port = Some(port.into());
+        self
+    }
+
+    pub fn internal_commands(mut self, commands: &[&str]) -> Self {
+        self.args.internal_commands = Some(commands.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    // 0, the default, never compresses.
+    pub fn compression_threshold(mut self, bytes: usize) -> Self {
+        self.args.compression_threshold = Some(bytes);
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<String>) -> Self {
+        self.args.dir = Some(dir.into());
+        self
+    }
+
+    pub fn dbfilename(mut self, name: impl Into<String>) -> Self {
+        self.args.dbfilename = Some(name.into());
+        self
+    }
+
+    pub async fn bind(self) -> io::Result<RedisServer> {
+        RedisServer::bind(self.args).await
+    }
+
+    // the whole server without a socket, for tests that want no ports and no timing.
+    pub fn in_memory(self) -> RedisServer {
+        RedisServer::in_memory(self.args)
+    }
 }
 
 // These arguments do not require a name and do not conform to the general argument parser trait...
+#[non_exhaustive]
 pub struct ServerArguments {
     pub host: String,
     pub port: String,
     pub replica_of: Option<(String, String)>,
+    pub compat_version: Option<CompatVersion>,
+    pub keys_max_results: Option<usize>,
+    pub health_port: Option<String>,
+    pub maxclients: Option<usize>,
+    pub lfu_log_factor: Option<u32>,
+    pub lfu_decay_time: Option<u32>,
+    pub requirepass: Option<String>,
+    // detach from the terminal before serving, unix only.
+    pub daemonize: bool,
+    pub pidfile: Option<String>,
+    // how many sockets accept connections, each with its own accept loop.
+    pub acceptors: Option<usize>,
+    pub replica_serve_stale_data: Option<bool>,
+    // a second port where the internal commands run, the public one refuses them once it's bound.
+    pub internal_port: Option<String>,
+    pub internal_commands: Option<Vec<String>>,
+    // relay to this redis and log the traffic instead of serving, with the renames applied.
+    pub proxy_to: Option<String>,
+    pub proxy_renames: Vec<(String, String)>,
+    // strings of at least this many bytes are stored lzf compressed.
+    pub compression_threshold: Option<usize>,
+    // print a report of the machine's fitness to run a server and exit.
+    pub check_system: bool,
+    // where snapshots are written and their file name.
+    pub dir: Option<String>,
+    pub dbfilename: Option<String>,
   }
   
+  impl Default for ServerArguments {
+      fn default() -> Self {
+          Self {
+              host: "127.0.0.1".to_string(),
+              port: "6379".to_string(),
+              replica_of: None,
+              compat_version: None,
+              keys_max_results: None,
+              health_port: None,
+              maxclients: None,
+              lfu_log_factor: None,
+              lfu_decay_time: None,
+              requirepass: None,
+              daemonize: false,
+              pidfile: None,
+              acceptors: None,
+              replica_serve_stale_data: None,
+              internal_port: None,
+              internal_commands: None,
+              proxy_to: None,
+              proxy_renames: Vec::new(),
+              compression_threshold: None,
+              check_system: false,
+              dir: None,
+              dbfilename: None,
+          }
+      }
+  }
+
   impl ServerArguments {
       pub fn parse() -> ServerArguments {
           let mut env = env::args();
           let mut port = "6379".to_string();
           let mut replica_of = None;
+          let mut compat_version = None;
+          let mut keys_max_results = None;
+          let mut health_port = None;
+          let mut maxclients = None;
+          let mut lfu_log_factor = None;
+          let mut lfu_decay_time = None;
+          let mut requirepass = None;
+          let mut daemonize = false;
+          let mut pidfile = None;
+          let mut acceptors = None;
+          let mut replica_serve_stale_data = None;
+          let mut internal_port = None;
+          let mut internal_commands = None;
+          let mut proxy_to = None;
+          let mut proxy_renames = Vec::new();
+          let mut compression_threshold = None;
+          let mut check_system = false;
+          let mut dir = None;
+          let mut dbfilename = None;
   
           env.next(); // skip executable path...
   
@@ -180,17 +511,391 @@ pub struct ServerArguments {
                               replica_of = Some((repl_host, repl_port));
                           }
                       };
-                  }
+                  },
+
+                  "--compat-version" => {
+                      match env.next().as_deref().map(CompatVersion::parse) {
+                          Some(Some(version)) => compat_version = Some(version),
+                          _ => println!("invalid compat version, defaulting to {}", CompatVersion::LATEST),
+                      }
+                  },
+
+                  "--keys-max-results" => {
+                      match env.next().map(|n| n.parse::<usize>()) {
+                          Some(Ok(max)) => keys_max_results = Some(max),
+                          _ => println!("invalid keys-max-results, defaulting to unlimited"),
+                      }
+                  },
+
+                  "--health-port" => {
+                      if let Some(n) = env.next() {
+                          health_port = Some(n);
+                      } else {
+                          println!("no health port passed, health checks disabled");
+                      }
+                  },
+
+                  "--maxclients" => {
+                      match env.next().map(|n| n.parse::<usize>()) {
+                          Some(Ok(max)) => maxclients = Some(max),
+                          _ => println!("invalid maxclients, defaulting to 10000"),
+                      }
+                  },
+
+                  "--lfu-log-factor" => {
+                      match env.next().map(|n| n.parse::<u32>()) {
+                          Some(Ok(factor)) => lfu_log_factor = Some(factor),
+                          _ => println!("invalid lfu-log-factor, defaulting to {}", lfu::DEFAULT_LOG_FACTOR),
+                      }
+                  },
+
+                  "--check-system" => check_system = true,
+
+                  "--dir" => {
+                      match env.next() {
+                          Some(path) if !path.is_empty() => dir = Some(path),
+                          _ => println!("no dir passed, defaulting to the working directory"),
+                      }
+                  },
+
+                  "--dbfilename" => {
+                      match env.next() {
+                          Some(name) if !name.is_empty() => dbfilename = Some(name),
+                          _ => println!("no dbfilename passed, defaulting to {}", DEFAULT_DBFILENAME),
+                      }
+                  },
+
+                  "--compression-threshold" => {
+                      match env.next().map(|n| n.parse::<usize>()) {
+                          Some(Ok(bytes)) => compression_threshold = Some(bytes),
+                          _ => println!("invalid compression-threshold, strings won't be compressed"),
+                      }
+                  },
+
+                  "--lfu-decay-time" => {
+                      match env.next().map(|n| n.parse::<u32>()) {
+                          Some(Ok(minutes)) => lfu_decay_time = Some(minutes),
+                          _ => println!("invalid lfu-decay-time, defaulting to {}", lfu::DEFAULT_DECAY_TIME),
+                      }
+                  },
+
+                  // an empty password leaves the default user nopass, like redis.
+                  "--requirepass" => {
+                      match env.next() {
+                          Some(password) if !password.is_empty() => requirepass = Some(password),
+                          Some(_) => {},
+                          None => println!("no requirepass passed, authentication disabled"),
+                      }
+                  },
+
+                  "--daemonize" => {
+                      match env.next().map(|v| v.to_lowercase()).as_deref() {
+                          Some("yes") => daemonize = true,
+                          Some("no") => daemonize = false,
+                          _ => println!("invalid daemonize, expected yes or no, running in the foreground"),
+                      }
+                  },
+
+                  "--pidfile" => {
+                      match env.next() {
+                          Some(path) if !path.is_empty() => pidfile = Some(path),
+                          _ => println!("no pidfile passed, none will be written"),
+                      }
+                  },
+
+                  "--acceptors" => {
+                      match env.next().map(|n| n.parse::<usize>()) {
+                          Some(Ok(n)) if n > 0 => acceptors = Some(n),
+                          _ => println!("invalid acceptors, defaulting to 1"),
+                      }
+                  },
+
+                  "--replica-serve-stale-data" => {
+                      match env.next().map(|v| v.to_lowercase()).as_deref() {
+                          Some("yes") => replica_serve_stale_data = Some(true),
+                          Some("no") => replica_serve_stale_data = Some(false),
+                          _ => println!("invalid replica-serve-stale-data, expected yes or no, defaulting to yes"),
+                      }
+                  },
+
+                  "--internal-port" => {
+                      if let Some(n) = env.next() {
+                          internal_port = Some(n);
+                      } else {
+                          println!("no internal port passed, every command runs on the public port");
+                      }
+                  },
+
+                  // a comma separated list, i.e., "debug,config". an empty one refuses nothing.
+                  "--internal-commands" => {
+                      match env.next() {
+                          Some(list) => internal_commands = Some(list.split(',').map(str::trim).filter(|c| !c.is_empty()).map(String::from).collect()),
+                          None => println!("no internal commands passed, defaulting to {}", DEFAULT_INTERNAL_COMMANDS.join(",")),
+                      }
+                  },
+
+                  "--proxy-to" => {
+                      if let Some(upstream) = env.next() {
+                          proxy_to = Some(upstream);
+                      } else {
+                          println!("no upstream passed, serving instead of proxying");
+                      }
+                  },
+
+                  "--proxy-rename" => {
+                      match (env.next(), env.next()) {
+                          (Some(from), Some(to)) => proxy_renames.push((from, to)),
+                          _ => println!("proxy-rename takes the command and its new name"),
+                      }
+                  },
                   _ => println!("recevied unsupported arg {}", arg)
               }
           }
           
           // default to local host for now.
-          Self { host: "127.0.0.1".to_string(), port, replica_of }
+          Self { host: "127.0.0.1".to_string(), port, replica_of, compat_version, keys_max_results, health_port, maxclients, lfu_log_factor, lfu_decay_time, requirepass, daemonize, pidfile, acceptors, replica_serve_stale_data, internal_port, internal_commands, proxy_to, proxy_renames, compression_threshold, check_system, dir, dbfilename }
       }
   
       pub fn is_replica(&self) -> bool {
           self.replica_of.is_some()
       }
+
+      // where the pid should be written, a daemon always gets a pidfile like in redis.
+      pub fn pidfile_path(&self) -> Option<String> {
+          match (&self.pidfile, self.daemonize) {
+              (Some(path), _) => Some(path.clone()),
+              (None, true) => Some(daemon::DEFAULT_PIDFILE.to_string()),
+              (None, false) => None,
+          }
+      }
   }
-    
\ No newline at end of file
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ Connection, RedisClient, Resp, ServerBuilder };
+    use crate::listener::RESERVED_ADMIN_CLIENTS;
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn test_daemon_always_gets_a_pidfile() {
+        let mut args = ServerArguments::default();
+        assert_eq!(args.pidfile_path(), None);
+
+        args.daemonize = true;
+        assert_eq!(args.pidfile_path().as_deref(), Some(daemon::DEFAULT_PIDFILE));
+
+        args.pidfile = Some("/tmp/custom.pid".to_string());
+        assert_eq!(args.pidfile_path().as_deref(), Some("/tmp/custom.pid"));
+    }
+
+    #[tokio::test]
+    async fn test_sharded_acceptors_share_the_port() {
+        let server = ServerBuilder::new().port("0").acceptors(4).bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let stats = server.listener.stats();
+        tokio::spawn(async move { server.listener.run().await });
+
+        for _ in 0..16 {
+            let mut stream = Connection::new(TcpStream::connect(addr).await.unwrap());
+            let mut client = RedisClient::from_stream(&mut stream);
+            client.ping().await.unwrap();
+            assert_eq!(client.read_message().await.unwrap(), Resp::SimpleString("PONG".to_string()));
+        }
+
+        // elsewhere the server falls back to a single socket rather than failing to start.
+        let accepted = stats.get_accepted_by_acceptor();
+        assert_eq!(accepted.len(), if SHARDED_ACCEPT { 4 } else { 1 });
+        assert_eq!(accepted.iter().sum::<u64>(), 16);
+    }
+
+    #[tokio::test]
+    async fn test_pipelines_keep_their_order_across_acceptors() {
+        let server = ServerBuilder::new().port("0").acceptors(4).bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let bulk = |s: &str| Resp::BulkString(s.as_bytes().to_vec());
+
+        let mut clients = Vec::new();
+        for client in 0..16 {
+            clients.push(tokio::spawn(async move {
+                let key = format!("pipeline:{}", client);
+                let mut stream = Connection::new(TcpStream::connect(addr).await.unwrap());
+
+                // the whole pipeline goes out before any reply is read, every GET has to see the
+                // writes queued ahead of it on the same connection.
+                for round in 0..50 {
+                    let value = round.to_string();
+                    stream.write_message(&Resp::command(&["SET", &key, &value])).await.unwrap();
+                    stream.write_message(&Resp::command(&["APPEND", &key, "!"])).await.unwrap();
+                    stream.write_message(&Resp::command(&["GET", &key])).await.unwrap();
+                    stream.write_message(&Resp::command(&["DEL", &key])).await.unwrap();
+                    stream.write_message(&Resp::command(&["GET", &key])).await.unwrap();
+                }
+
+                for round in 0..50 {
+                    let expected = format!("{}!", round);
+                    assert_eq!(stream.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+                    assert_eq!(stream.read_message().await.unwrap().0, Resp::Integer(expected.len() as i64));
+                    assert_eq!(stream.read_message().await.unwrap().0, bulk(&expected));
+                    assert_eq!(stream.read_message().await.unwrap().0, Resp::Integer(1));
+                    assert_eq!(stream.read_message().await.unwrap().0, Resp::BulkStringNull);
+                }
+            }));
+        }
+
+        for client in clients {
+            client.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_operators_get_in_past_maxclients() {
+        let server = ServerBuilder::new().port("0").maxclients(1).bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let mut client = Connection::new(TcpStream::connect(addr).await.unwrap());
+        client.write_message(&Resp::command(&["CLIENT", "ID"])).await.unwrap();
+        let id = match client.read_message().await.unwrap().0 {
+            Resp::Integer(id) => id,
+            other => panic!("unexpected CLIENT ID reply {:?}", other),
+        };
+
+        // a reserved connection can't be used for data.
+        let mut reserved = Connection::new(TcpStream::connect(addr).await.unwrap());
+        reserved.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(reserved.read_message().await.unwrap().0, Resp::SimpleError("ERR max number of clients reached".to_string()));
+        assert!(reserved.read_message().await.is_err());
+
+        // but it can make room.
+        let mut operator = Connection::new(TcpStream::connect(addr).await.unwrap());
+        operator.write_message(&Resp::command(&["CLIENT", "KILL", "ID", &id.to_string()])).await.unwrap();
+        assert_eq!(operator.read_message().await.unwrap().0, Resp::Integer(1));
+        assert!(client.read_message().await.is_err());
+
+        // killing itself still gets the reply out before the connection closes.
+        operator.write_message(&Resp::command(&["CLIENT", "KILL", "TYPE", "normal", "SKIPME", "no"])).await.unwrap();
+        assert_eq!(operator.read_message().await.unwrap().0, Resp::Integer(1));
+        assert!(operator.read_message().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connections_past_the_reserve_are_rejected() {
+        let server = ServerBuilder::new().port("0").maxclients(1).bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let stats = server.listener.stats();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let mut connected = Vec::new();
+        // the client maxclients allows and the ones kept for operators, each one is registered
+        // once it answered.
+        for _ in 0..(1 + RESERVED_ADMIN_CLIENTS) {
+            let mut conn = Connection::new(TcpStream::connect(addr).await.unwrap());
+            conn.write_message(&Resp::command(&["PING"])).await.unwrap();
+            assert_eq!(conn.read_message().await.unwrap().0, Resp::SimpleString("PONG".to_string()));
+            connected.push(conn);
+        }
+
+        let mut rejected = Connection::new(TcpStream::connect(addr).await.unwrap());
+        assert_eq!(rejected.read_message().await.unwrap().0, Resp::SimpleError("ERR max number of clients reached".to_string()));
+        assert!(rejected.read_message().await.is_err());
+
+        assert_eq!(stats.get_connections_received(), 2 + RESERVED_ADMIN_CLIENTS as u64);
+        assert_eq!(stats.get_rejected_connections(), 1);
+
+        connected[0].write_message(&Resp::command(&["INFO", "stats"])).await.unwrap();
+        let info = connected[0].read_message().await.unwrap().0;
+        let info = String::from_utf8_lossy(info.as_bytes().unwrap()).to_string();
+        assert!(info.contains(&format!("total_connections_received:{}\r\n", 2 + RESERVED_ADMIN_CLIENTS)), "{}", info);
+        assert!(info.contains("rejected_connections:1\r\n"), "{}", info);
+    }
+
+    #[tokio::test]
+    async fn test_killing_a_blocked_client() {
+        let server = ServerBuilder::new().in_memory();
+        let mut blocked = server.connect_in_memory();
+        let mut operator = server.connect_in_memory();
+
+        blocked.write_message(&Resp::command(&["CLIENT", "ID"])).await.unwrap();
+        let id = match blocked.read_message().await.unwrap().0 {
+            Resp::Integer(id) => id,
+            other => panic!("unexpected CLIENT ID reply {:?}", other),
+        };
+        blocked.write_message(&Resp::command(&["BLPOP", "queue", "0"])).await.unwrap();
+
+        operator.write_message(&Resp::command(&["CLIENT", "KILL", "ID", &id.to_string()])).await.unwrap();
+        assert_eq!(operator.read_message().await.unwrap().0, Resp::Integer(1));
+        assert!(blocked.read_message().await.is_err());
+
+        // the killed client gave up its wait, the push stays in the list.
+        operator.write_message(&Resp::command(&["RPUSH", "queue", "job"])).await.unwrap();
+        assert_eq!(operator.read_message().await.unwrap().0, Resp::Integer(1));
+        operator.write_message(&Resp::command(&["LLEN", "queue"])).await.unwrap();
+        assert_eq!(operator.read_message().await.unwrap().0, Resp::Integer(1));
+    }
+
+    #[tokio::test]
+    async fn test_internal_commands_only_run_on_the_internal_port() {
+        let server = ServerBuilder::new().port("0").internal_port("0").requirepass("secret").bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let internal_addr = server.internal_addr().unwrap().unwrap();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let mut public = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut internal = Connection::new(TcpStream::connect(internal_addr).await.unwrap());
+        for conn in [&mut public, &mut internal] {
+            conn.write_message(&Resp::command(&["AUTH", "secret"])).await.unwrap();
+            assert_eq!(conn.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+            conn.write_message(&Resp::command(&["SET", "foo", "bar"])).await.unwrap();
+            assert_eq!(conn.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        }
+
+        // refused even for an authenticated client, and the connection stays usable.
+        public.write_message(&Resp::command(&["debug", "digest-value", "foo"])).await.unwrap();
+        assert_eq!(public.read_message().await.unwrap().0, Resp::SimpleError("ERR DEBUG is only allowed on the internal port".to_string()));
+        public.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(public.read_message().await.unwrap().0, Resp::BulkString(b"bar".to_vec()));
+
+        internal.write_message(&Resp::command(&["DEBUG", "DIGEST-VALUE", "foo"])).await.unwrap();
+        assert!(matches!(internal.read_message().await.unwrap().0, Resp::Array(_)));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_server_runs_the_full_stack() {
+        let server = ServerBuilder::new().requirepass("secret").in_memory();
+        assert!(server.local_addr().is_err());
+        assert!(server.listener.run().await.is_err());
+
+        let mut writer = server.connect_in_memory();
+        let mut reader = server.connect_in_memory();
+
+        // the session still applies auth, then both clients share the one keyspace.
+        writer.write_message(&Resp::command(&["SET", "foo", "bar"])).await.unwrap();
+        assert_eq!(writer.read_message().await.unwrap().0, Resp::SimpleError("NOAUTH Authentication required.".to_string()));
+        for client in [&mut writer, &mut reader] {
+            client.write_message(&Resp::command(&["AUTH", "secret"])).await.unwrap();
+            assert_eq!(client.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        }
+        writer.write_message(&Resp::command(&["SET", "foo", "bar"])).await.unwrap();
+        assert_eq!(writer.read_message().await.unwrap().0, Resp::SimpleString("OK".to_string()));
+        reader.write_message(&Resp::command(&["GET", "foo"])).await.unwrap();
+        assert_eq!(reader.read_message().await.unwrap().0, Resp::BulkString(b"bar".to_vec()));
+
+        reader.write_message(&Resp::command(&["CLIENT", "ID"])).await.unwrap();
+        assert_eq!(reader.read_message().await.unwrap().0, Resp::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_builder_serves_through_the_facade() {
+        let server = ServerBuilder::new().port("0").bind().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move { server.listener.run().await });
+
+        let mut stream = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut client = RedisClient::from_stream(&mut stream);
+        client.ping().await.unwrap();
+        assert_eq!(client.read_message().await.unwrap(), Resp::SimpleString("PONG".to_string()));
+    }
+}
@@ -0,0 +1,340 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::context::{ self, Context };
+use crate::connection::Connection;
+use crate::resp::Resp;
+use crate::clients::{ self, ClientKind, KillSwitch };
+use crate::reply::Reply;
+use crate::command::{ CmdParser, Cmd, Command, Transaction };
+use crate::internals::{ ReplconfCommand, ReplconfArguments };
+use crate::pubsub::Mailbox;
+
+const MAXCLIENTS_REACHED: &str = "ERR max number of clients reached";
+
+const MASTERDOWN: &str = "MASTERDOWN Link with MASTER is down and replica-serve-stale-data is set to 'no'.";
+
+// the policy a session applies to each command it reads, one for each kind of connection
+// the server serves. adding a new mode means adding a variant here, not another loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFilter {
+    // a client of a master, everything runs and writes are propagated.
+    Master,
+    // a client connected directly to a replica, only reads and introspection are allowed.
+    ReplicaClient,
+    // the link from a replica to its master, nothing is replied to except GETACK and
+    // every byte counts towards the replication offset.
+    MasterLink,
+    // a replica of this master after the handshake, only its acks are read.
+    ReplicaAcks(u64),
+    // a connection let in past maxclients from the few kept for operators, it can look around
+    // and kill clients to make room but nothing else.
+    Reserved,
+}
+
+// what the session should do with a command once the filter looked at it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    // run the command and reply to the client.
+    Run,
+    // run the command without writing a reply.
+    RunSilently,
+    // don't run it, reply with the error and keep going.
+    Reject(String),
+    // don't run it, reply with the error and hang up.
+    RejectAndClose(String),
+    // record the offset a replica acknowledged.
+    Acknowledge(usize),
+    // drop it without a reply.
+    Ignore,
+}
+
+impl CommandFilter {
+    // message is what cmd was parsed from, its name says whether the command is a read.
+    pub fn admit(&self, cmd: &Cmd, message: &Resp) -> Admission {
+        match self {
+            CommandFilter::Master => match cmd {
+                Cmd::Unexpected(err) => Admission::Reject(err.clone()),
+                _ => Admission::Run,
+            },
+
+            CommandFilter::ReplicaClient => match cmd {
+                Cmd::Info(_) | Cmd::Debug(_) | Cmd::Client(_) | Cmd::Hello(_) | Cmd::Auth(_) | Cmd::Acl(_) => Admission::Run,
+                Cmd::Subscribe(_) | Cmd::Unsubscribe(_) => Admission::Run,
+                // a read with bad arguments gets its error like it would on the master.
+                Cmd::Unexpected(err) if context::is_read(message) => Admission::Reject(err.clone()),
+                _ if context::is_read(message) => Admission::Run,
+                _ => Admission::RejectAndClose("ERR direct messaging to replica not allowed".to_string()),
+            },
+
+            CommandFilter::MasterLink => match cmd {
+                // the master never reads replies other than acks, so errors are dropped.
+                Cmd::Unexpected(_) => Admission::Ignore,
+                Cmd::ReplConf(_) => Admission::Run,
+                _ => Admission::RunSilently,
+            },
+
+            CommandFilter::Reserved => match cmd {
+                Cmd::Auth(_) | Cmd::Hello(_) | Cmd::Ping(_) | Cmd::Client(_) | Cmd::Info(_) | Cmd::Acl(_) | Cmd::Debug(_) => Admission::Run,
+                Cmd::Unexpected(err) => Admission::Reject(err.clone()),
+                _ => Admission::RejectAndClose(MAXCLIENTS_REACHED.to_string()),
+            },
+
+            // a replica only ever sends REPLCONF ACK back, anything else is ignored like redis does.
+            CommandFilter::ReplicaAcks(_) => match cmd {
+                Cmd::ReplConf(ReplconfCommand(ReplconfArguments::Ack(offset))) => offset
+                    .parse::<usize>()
+                    .map(Admission::Acknowledge)
+                    .unwrap_or(Admission::Ignore),
+                _ => Admission::Ignore,
+            },
+        }
+    }
+
+    // whether writes run through this session are sent on to replicas.
+    pub fn propagates(&self) -> bool {
+        matches!(self, CommandFilter::Master)
+    }
+
+    // whether clients on this session have to authenticate when requirepass is set, the
+    // replication links are trusted.
+    pub fn checks_auth(&self) -> bool {
+        matches!(self, CommandFilter::Master | CommandFilter::ReplicaClient | CommandFilter::Reserved)
+    }
+
+    // whether reads on this session come from a replica's copy of the data.
+    pub fn serves_stale_data(&self) -> bool {
+        matches!(self, CommandFilter::ReplicaClient)
+    }
+
+    // whether the bytes read count towards this server's replication offset.
+    pub fn counts_offset(&self) -> bool {
+        matches!(self, CommandFilter::MasterLink)
+    }
+}
+
+// the read, parse, execute loop shared by every connection.
+pub struct Session {
+    context: Context,
+    filter: CommandFilter,
+    // taken up front, a replica's registration moves to the write half of its connection.
+    kill_switch: Option<Arc<KillSwitch>>,
+    // what was published to the channels the client subscribed to, written out as it comes.
+    messages: Mailbox,
+}
+
+impl Session {
+    pub fn new(mut context: Context, filter: CommandFilter) -> Self {
+        let kill_switch = context.stream.kill_switch();
+        let (subscriber, messages) = context.broker.subscriber();
+        let messages = Mailbox::new(messages);
+        context.stream.set_subscriber(subscriber, messages.clone());
+        Session { context, filter, kill_switch, messages }
+    }
+
+    pub async fn run(mut self) -> io::Result<()> {
+        // the master link is only up for as long as its session serves it.
+        let link = self.filter == CommandFilter::MasterLink;
+        if link {
+            self.context.info.set_master_link_up(true);
+        }
+
+        let result = self.serve().await;
+
+        if link {
+            self.context.info.set_master_link_up(false);
+        }
+
+        if let CommandFilter::ReplicaAcks(id) = self.filter {
+            self.context.history.remove_replica(id).await;
+        }
+
+        result
+    }
+
+    async fn serve(&mut self) -> io::Result<()> {
+        loop {
+            let (message, msg_len) = match self.kill_switch.clone() {
+                Some(kill_switch) if kill_switch.is_killed() => return Ok(()),
+                kill_switch => tokio::select! {
+                    read = self.context.stream.read_message() => read?,
+                    Some(published) = self.messages.recv() => {
+                        self.context.stream.write_push(published.into_reply()).await?;
+                        continue;
+                    },
+                    _ = clients::killed(kill_switch) => return Ok(()),
+                },
+            };
+            self.context.stream.start_command();
+            self.context.touch_client(&message);
+            let cmd = CmdParser::parse(message.clone());
+
+            if self.needs_auth(&cmd) {
+                self.context.stream.write_reply(Reply::error("NOAUTH Authentication required.")).await?;
+                continue;
+            }
+
+            if let Some(err) = self.context.refuses(&message) {
+                self.context.stream.write_reply(Reply::error(err)).await?;
+                continue;
+            }
+
+            if let Some(err) = self.context.refuses_while_subscribed(&message) {
+                self.context.stream.write_reply(Reply::error(err)).await?;
+                continue;
+            }
+
+            if self.is_stale_read(&message) {
+                self.context.stream.write_reply(Reply::error(MASTERDOWN)).await?;
+                continue;
+            }
+
+            match self.filter.admit(&cmd, &message) {
+                Admission::Run => self.execute(cmd, message).await?,
+
+                Admission::RunSilently => {
+                    self.context.stream.close_write();
+                    self.execute(cmd, message).await?;
+                    self.context.stream.open_write();
+                },
+
+                Admission::Reject(err) => {
+                    self.context.stream.write_reply(Reply::error(err)).await?;
+                },
+
+                Admission::RejectAndClose(err) => {
+                    self.context.stream.write_reply(Reply::error(err)).await?;
+                    return Ok(());
+                },
+
+                Admission::Acknowledge(offset) => {
+                    if let CommandFilter::ReplicaAcks(id) = self.filter {
+                        self.context.history.ack(id, offset).await;
+                    }
+                },
+
+                Admission::Ignore => {},
+            }
+
+            // every byte from the master counts towards the offset once it is processed, including
+            // commands that failed. a GETACK is only counted after its ACK went out, so the ack
+            // covers everything before it and the next ack includes it, which is what the master expects.
+            if self.filter.counts_offset() {
+                self.context.info.incr_master_repl_offset(msg_len as i64);
+            }
+        }
+    }
+
+    // AUTH and HELLO are how a client authenticates, so they always run. malformed commands
+    // get their own error.
+    fn needs_auth(&self, cmd: &Cmd) -> bool {
+        self.filter.checks_auth()
+            && !matches!(cmd, Cmd::Auth(_) | Cmd::Hello(_) | Cmd::Unexpected(_))
+            && !self.context.stream.is_authenticated()
+            && self.context.config.get_requirepass().is_some()
+    }
+
+    // a read on a replica that lost its master, refused when replica-serve-stale-data is no.
+    // introspection still runs so the link state can be looked at.
+    fn is_stale_read(&self, message: &Resp) -> bool {
+        self.filter.serves_stale_data()
+            && context::is_read(message)
+            && !self.context.info.is_master_link_up()
+            && !self.context.config.get_replica_serve_stale_data()
+    }
+
+    async fn execute(&mut self, cmd: Cmd, message: Resp) -> io::Result<()> {
+        let handle = self.context.handle();
+        let propagation = cmd.propagation();
+        let started = Instant::now();
+        let transaction = cmd.execute(&mut self.context.stream, handle).await;
+
+        if let Some(event) = context::latency_event(&message) {
+            let threshold = self.context.config.get_latency_monitor_threshold();
+            self.context.stats.latency().record(event, started.elapsed(), threshold);
+        }
+
+        match transaction {
+            Transaction::Replicate => self.start_replica().await,
+
+            Transaction::Write if self.filter.propagates() && propagation.to_replicas() => {
+                let offset = self.context.history.add_write(message).await;
+                self.context.info.set_master_repl_offset(offset as i64);
+            },
+
+            Transaction::WriteAs(rewritten) if self.filter.propagates() && propagation.to_replicas() => {
+                let offset = self.context.history.add_write(rewritten).await;
+                self.context.info.set_master_repl_offset(offset as i64);
+            },
+
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    // hands the write half of this connection to the history, from here on the session
+    // only reads the acks the replica sends back.
+    async fn start_replica(&mut self) {
+        if let Some(client) = self.context.stream.client() {
+            client.set_kind(ClientKind::Replica);
+        }
+
+        let stream = std::mem::replace(&mut self.context.stream, Connection::detached());
+        let (reader, writer) = stream.split();
+        let id = self.context.history.add_replica(writer).await;
+        self.context.stream = reader;
+        self.filter = CommandFilter::ReplicaAcks(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admit(filter: CommandFilter, args: &[&str]) -> Admission {
+        let message = Resp::command(args);
+        filter.admit(&CmdParser::parse(message.clone()), &message)
+    }
+
+    #[test]
+    fn test_replica_client_only_runs_reads() {
+        let filter = CommandFilter::ReplicaClient;
+        assert_eq!(admit(filter, &["GET", "foo"]), Admission::Run);
+        assert!(matches!(admit(filter, &["SET", "foo", "1"]), Admission::RejectAndClose(_)));
+
+        // every readonly command in the table runs, not just GET.
+        for read in [&["MGET", "a", "b"][..], &["GETRANGE", "a", "0", "1"], &["LRANGE", "l", "0", "-1"], &["HGET", "h", "f"], &["ZRANGE", "z", "0", "-1"], &["SCAN", "0"], &["TYPE", "a"]] {
+            assert_eq!(admit(filter, read), Admission::Run, "{:?}", read);
+        }
+        assert!(matches!(admit(filter, &["GET"]), Admission::Reject(_)));
+        assert!(matches!(admit(filter, &["LPUSH", "l", "a"]), Admission::RejectAndClose(_)));
+        assert!(matches!(admit(filter, &["NOPE"]), Admission::RejectAndClose(_)));
+    }
+
+    #[test]
+    fn test_master_link_is_silent() {
+        let filter = CommandFilter::MasterLink;
+        assert_eq!(admit(filter, &["SET", "foo", "1"]), Admission::RunSilently);
+        assert_eq!(admit(filter, &["REPLCONF", "GETACK", "*"]), Admission::Run);
+        assert_eq!(admit(filter, &["NOPE"]), Admission::Ignore);
+        assert!(filter.counts_offset() && !filter.propagates());
+    }
+
+    #[test]
+    fn test_reserved_connections_only_admin() {
+        let filter = CommandFilter::Reserved;
+        assert_eq!(admit(filter, &["CLIENT", "KILL", "ID", "1"]), Admission::Run);
+        assert_eq!(admit(filter, &["INFO"]), Admission::Run);
+        assert_eq!(admit(filter, &["GET", "foo"]), Admission::RejectAndClose(MAXCLIENTS_REACHED.to_string()));
+        assert!(filter.checks_auth() && !filter.propagates());
+    }
+
+    #[test]
+    fn test_replica_acks_only_admit_acks() {
+        let filter = CommandFilter::ReplicaAcks(0);
+        assert_eq!(admit(filter, &["REPLCONF", "ACK", "42"]), Admission::Acknowledge(42));
+        assert_eq!(admit(filter, &["REPLCONF", "ACK", "x"]), Admission::Ignore);
+        assert_eq!(admit(filter, &["SET", "foo", "1"]), Admission::Ignore);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use crate::database::SortedSet;
+
+// a collection the set algebra can run over, sets and sorted sets both are.
+pub trait Members {
+    fn member_count(&self) -> usize;
+    fn has_member(&self, member: &[u8]) -> bool;
+    fn members(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_>;
+    // the score member has as an input to the zset commands, a plain set scores everything 1.
+    fn score_of(&self, member: &[u8]) -> Option<f64>;
+}
+
+impl Members for HashSet<Vec<u8>> {
+    fn member_count(&self) -> usize {
+        self.len()
+    }
+
+    fn has_member(&self, member: &[u8]) -> bool {
+        self.contains(member)
+    }
+
+    fn members(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        Box::new(self.iter())
+    }
+
+    fn score_of(&self, member: &[u8]) -> Option<f64> {
+        self.contains(member).then_some(1.0)
+    }
+}
+
+impl Members for SortedSet {
+    fn member_count(&self) -> usize {
+        self.len()
+    }
+
+    fn has_member(&self, member: &[u8]) -> bool {
+        self.contains(member)
+    }
+
+    fn members(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        Box::new(self.iter().map(|(member, _)| member))
+    }
+
+    fn score_of(&self, member: &[u8]) -> Option<f64> {
+        self.score(member)
+    }
+}
+
+// the set algebra behind SINTER, SUNION and SDIFF and their STORE variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+// applies op to the inputs in order, none stands for a key that doesn't exist and counts as an
+// empty set: it empties an intersection, adds nothing to a union and removes nothing from the
+// first input of a difference.
+pub fn combine(op: SetOp, inputs: Vec<Option<&dyn Members>>) -> Vec<&Vec<u8>> {
+    match op {
+        SetOp::Inter => match inputs.into_iter().collect::<Option<Vec<_>>>() {
+            Some(inputs) => intersect(inputs, None),
+            None => Vec::new(),
+        },
+        SetOp::Union => union(inputs.into_iter().flatten().collect()),
+        SetOp::Diff => {
+            let mut inputs = inputs.into_iter();
+            match inputs.next().flatten() {
+                Some(first) => difference(first, inputs.flatten().collect()),
+                None => Vec::new(),
+            }
+        },
+    }
+}
+
+// how ZUNION and ZINTER merge the scores a member has in several inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+// like combine but every member comes with a score. a union or intersection multiplies each
+// input's score by its weight and aggregates them, a difference keeps the first input's score.
+// inf * 0 and inf - inf give 0 rather than nan, the same as redis.
+pub fn combine_scored<'a>(op: SetOp, inputs: Vec<Option<&'a dyn Members>>, weights: &[f64], aggregate: Aggregate) -> Vec<(&'a Vec<u8>, f64)> {
+    let members = combine(op, inputs.clone());
+    if op == SetOp::Diff {
+        let first = match inputs.first().copied().flatten() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+        return members.into_iter().map(|member| (member, first.score_of(member).unwrap())).collect();
+    }
+
+    let not_nan = |score: f64| if score.is_nan() { 0.0 } else { score };
+    members
+        .into_iter()
+        .map(|member| {
+            let score = inputs
+                .iter()
+                .zip(weights)
+                .filter_map(|(input, weight)| input.and_then(|input| input.score_of(member)).map(|score| not_nan(score * weight)))
+                .reduce(|acc, score| match aggregate {
+                    Aggregate::Sum => not_nan(acc + score),
+                    Aggregate::Min => acc.min(score),
+                    Aggregate::Max => acc.max(score),
+                })
+                .unwrap();
+            (member, score)
+        })
+        .collect()
+}
+
+// every member of any input, each once.
+pub fn union(inputs: Vec<&dyn Members>) -> Vec<&Vec<u8>> {
+    let mut seen = HashSet::new();
+    let mut all = Vec::new();
+    for input in inputs {
+        for member in input.members() {
+            if seen.insert(member) {
+                all.push(member);
+            }
+        }
+    }
+    all
+}
+
+// the members of first none of the others have.
+pub fn difference<'a>(first: &'a dyn Members, others: Vec<&dyn Members>) -> Vec<&'a Vec<u8>> {
+    first
+        .members()
+        .filter(|member| !others.iter().any(|other| other.has_member(member)))
+        .collect()
+}
+
+// the members every input has in common. the smallest input drives the iteration and every
+// other one is only probed, so the cost follows the smallest input however skewed the sizes
+// are. with a limit it stops as soon as that many members were found, which is all
+// SINTERCARD LIMIT needs.
+pub fn intersect(mut inputs: Vec<&dyn Members>, limit: Option<usize>) -> Vec<&Vec<u8>> {
+    if inputs.is_empty() {
+        return Vec::new();
+    }
+
+    // probing the smaller sets first rules out most candidates with the fewest lookups.
+    inputs.sort_by_key(|input| input.member_count());
+    let (smallest, rest) = inputs.split_first().unwrap();
+    let limit = limit.unwrap_or(usize::MAX);
+
+    let mut common = Vec::new();
+    for member in smallest.members() {
+        if common.len() >= limit {
+            break;
+        }
+        if rest.iter().all(|input| input.has_member(member)) {
+            common.push(member);
+        }
+    }
+    common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(members: &[&str]) -> HashSet<Vec<u8>> {
+        members.iter().map(|m| m.as_bytes().to_vec()).collect()
+    }
+
+    fn sorted(common: Vec<&Vec<u8>>) -> Vec<Vec<u8>> {
+        let mut common: Vec<Vec<u8>> = common.into_iter().cloned().collect();
+        common.sort();
+        common
+    }
+
+    #[test]
+    fn test_intersect() {
+        let a = set(&["a", "b", "c", "d"]);
+        let b = set(&["b", "c", "d", "e"]);
+        let c = set(&["c", "d"]);
+        assert_eq!(sorted(intersect(vec![&a, &b, &c], None)), vec![b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(sorted(intersect(vec![&a], None)).len(), 4);
+        assert!(intersect(vec![&a, &set(&[])], None).is_empty());
+        assert!(intersect(Vec::new(), None).is_empty());
+    }
+
+    #[test]
+    fn test_union_and_difference() {
+        let a = set(&["a", "b", "c"]);
+        let b = set(&["b", "d"]);
+        let c = set(&["c"]);
+        assert_eq!(sorted(union(vec![&a, &b, &c])), sorted(vec![&b"a".to_vec(), &b"b".to_vec(), &b"c".to_vec(), &b"d".to_vec()]));
+        assert_eq!(sorted(difference(&a, vec![&b, &c])), vec![b"a".to_vec()]);
+        assert!(difference(&c, vec![&a]).is_empty());
+
+        // a missing input is an empty set.
+        assert_eq!(combine(SetOp::Union, vec![None, Some(&c)]).len(), 1);
+        assert_eq!(combine(SetOp::Diff, vec![Some(&a), None]).len(), 3);
+        assert!(combine(SetOp::Diff, vec![None, Some(&a)]).is_empty());
+        assert!(combine(SetOp::Inter, vec![Some(&a), None]).is_empty());
+    }
+
+    #[test]
+    fn test_combine_scored() {
+        let mut a = SortedSet::default();
+        a.insert(b"x".to_vec(), 1.0);
+        a.insert(b"y".to_vec(), f64::INFINITY);
+        let b = set(&["x", "y", "z"]);
+        let scores = |mut scored: Vec<(&Vec<u8>, f64)>| {
+            scored.sort_by(|l, r| l.0.cmp(r.0));
+            scored.into_iter().map(|(member, score)| (String::from_utf8(member.clone()).unwrap(), score)).collect::<Vec<_>>()
+        };
+
+        let union = combine_scored(SetOp::Union, vec![Some(&a), Some(&b)], &[2.0, 3.0], Aggregate::Sum);
+        assert_eq!(scores(union), vec![("x".into(), 5.0), ("y".into(), f64::INFINITY), ("z".into(), 3.0)]);
+        let inter = combine_scored(SetOp::Inter, vec![Some(&a), Some(&b)], &[1.0, 1.0], Aggregate::Min);
+        assert_eq!(scores(inter), vec![("x".into(), 1.0), ("y".into(), 1.0)]);
+
+        // a zero weight on an infinite score counts as 0, not nan.
+        let inter = combine_scored(SetOp::Inter, vec![Some(&a), Some(&b)], &[0.0, 1.0], Aggregate::Max);
+        assert_eq!(scores(inter), vec![("x".into(), 1.0), ("y".into(), 1.0)]);
+        let diff = combine_scored(SetOp::Diff, vec![Some(&b), Some(&a)], &[1.0, 1.0], Aggregate::Sum);
+        assert_eq!(scores(diff), vec![("z".into(), 1.0)]);
+        assert!(combine_scored(SetOp::Diff, vec![None, Some(&a)], &[1.0, 1.0], Aggregate::Sum).is_empty());
+    }
+
+    #[test]
+    fn test_intersect_limit_and_mixed_inputs() {
+        let big: HashSet<Vec<u8>> = (0..10_000).map(|i| i.to_string().into_bytes()).collect();
+        let small = set(&["1", "2", "3", "x"]);
+        assert_eq!(intersect(vec![&big, &small], Some(2)).len(), 2);
+        assert_eq!(intersect(vec![&big, &small], Some(10)).len(), 3);
+
+        let mut zset = SortedSet::default();
+        zset.insert(b"2".to_vec(), 1.0);
+        zset.insert(b"x".to_vec(), 2.0);
+        assert_eq!(sorted(intersect(vec![&big, &small, &zset], None)), vec![b"2".to_vec()]);
+    }
+}
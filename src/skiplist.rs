@@ -0,0 +1,348 @@
+use std::hash::{ BuildHasher, RandomState };
+use crate::database::Score;
+
+// how tall a node can grow. like redis a node climbs each level with a chance of 1 in 4, which
+// keeps 32 levels enough for any set that fits in memory.
+const MAX_LEVEL: usize = 32;
+const CLIMB: u64 = 4;
+
+// the slot of the header, it holds no member and starts every level.
+const HEAD: usize = 0;
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: Option<usize>,
+    // how many nodes forward skips over, the node it lands on included.
+    span: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+// the members of a sorted set ordered by score and then member, the zskiplist of redis. every
+// link counts the nodes it skips, so the rank of a member and the member at a rank are found
+// in O(log n) instead of walking everything ranked before them. nodes live in a vector and
+// link to each other by index, removed slots are reused by the next insert.
+#[derive(Clone, Debug)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    len: usize,
+    level: usize,
+    // xorshift state for the level of new nodes, never zero.
+    seed: u64,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Vec::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![Level { forward: None, span: 0 }; MAX_LEVEL],
+        };
+        let seed = RandomState::new().hash_one(0u8) | 1;
+        Self { nodes: vec![head], free: Vec::new(), len: 0, level: 1, seed }
+    }
+}
+
+impl SkipList {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // adds member with score, the caller makes sure the member isn't in the list already.
+    pub fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.before(next, score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = self.alloc(Node { member, score, backward: None, levels: vec![Level { forward: None, span: 0 }; level] });
+        for i in 0..level {
+            let prev = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[node].levels[i] = Level {
+                forward: self.nodes[prev].levels[i].forward,
+                span: self.nodes[prev].levels[i].span - skipped,
+            };
+            self.nodes[prev].levels[i] = Level { forward: Some(node), span: skipped + 1 };
+        }
+        // the levels above the new node now skip over one more node.
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+
+        self.nodes[node].backward = if update[0] == HEAD { None } else { Some(update[0]) };
+        if let Some(next) = self.nodes[node].levels[0].forward {
+            self.nodes[next].backward = Some(node);
+        }
+        self.len += 1;
+    }
+
+    // removes member with score, returns whether it was there.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.before(next, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let target = match self.nodes[x].levels[0].forward {
+            Some(next) if self.nodes[next].member == member && Score(self.nodes[next].score) == Score(score) => next,
+            _ => return false,
+        };
+
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[*prev].levels[i].forward == Some(target) {
+                let skipped = self.nodes[target].levels[i];
+                let link = &mut self.nodes[*prev].levels[i];
+                link.span = link.span + skipped.span - 1;
+                link.forward = skipped.forward;
+            } else {
+                self.nodes[*prev].levels[i].span -= 1;
+            }
+        }
+
+        if let Some(next) = self.nodes[target].levels[0].forward {
+            self.nodes[next].backward = self.nodes[target].backward;
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+
+        let node = &mut self.nodes[target];
+        node.member = Vec::new();
+        node.levels = Vec::new();
+        self.free.push(target);
+        self.len -= 1;
+        true
+    }
+
+    // how many members sort before score and member, which is the rank member has when it is
+    // in the list.
+    pub fn count_before(&self, score: f64, member: &[u8]) -> usize {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.before(next, score, member) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    // the members ranked from start up to but not including end, lowest first.
+    pub fn slice(&self, start: usize, end: usize) -> Iter<'_> {
+        let end = end.min(self.len);
+        if start >= end {
+            return Iter { list: self, front: None, back: None, remaining: 0 };
+        }
+        Iter { list: self, front: self.at(start), back: self.at(end - 1), remaining: end - start }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.slice(0, self.len)
+    }
+
+    // the node at rank, found by following the spans down from the top level.
+    fn at(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    // whether the node sorts before score and member.
+    fn before(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[node];
+        (Score(node.score), node.member.as_slice()) < (Score(score), member)
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && self.next_random().is_multiple_of(CLIMB) {
+            level += 1;
+        }
+        level
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+// walks a run of consecutive ranks from either end.
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Vec<u8>, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.list.nodes[self.front?];
+        self.front = node.levels[0].forward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.list.nodes[self.back?];
+        self.back = node.backward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members<'a>(iter: impl Iterator<Item = (&'a Vec<u8>, f64)>) -> Vec<String> {
+        iter.map(|(member, _)| String::from_utf8(member.clone()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ranks_follow_inserts_and_removes() {
+        let mut list = SkipList::default();
+        // inserted out of order, with a tie on score broken by member.
+        for (score, member) in [(3.0, "c"), (1.0, "a"), (2.0, "b2"), (2.0, "b1"), (5.0, "e")] {
+            list.insert(score, member.as_bytes().to_vec());
+        }
+        assert_eq!(members(list.iter()), vec!["a", "b1", "b2", "c", "e"]);
+        assert_eq!(members(list.iter().rev()), vec!["e", "c", "b2", "b1", "a"]);
+        assert_eq!(list.count_before(2.0, b"b2"), 2);
+        assert_eq!(list.count_before(4.0, b""), 4);
+        assert_eq!(members(list.slice(1, 3)), vec!["b1", "b2"]);
+        assert_eq!(members(list.slice(3, 9)), vec!["c", "e"]);
+        assert_eq!(list.slice(4, 2).count(), 0);
+
+        assert!(list.remove(2.0, b"b1"));
+        assert!(!list.remove(2.0, b"b1"));
+        assert!(!list.remove(9.0, b"c"));
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.count_before(3.0, b"c"), 2);
+        assert_eq!(members(list.slice(1, 4).rev()), vec!["e", "c", "b2"]);
+
+        // the freed slot is reused.
+        list.insert(0.0, b"z".to_vec());
+        assert_eq!(list.nodes.len(), 6);
+        assert_eq!(members(list.iter()), vec!["z", "a", "b2", "c", "e"]);
+    }
+
+    #[test]
+    fn test_ranks_match_a_sorted_vector() {
+        let mut list = SkipList::default();
+        let mut expected: Vec<(i64, Vec<u8>)> = Vec::new();
+        // a deterministic mix of inserts and removes, big enough to grow several levels.
+        let mut x: i64 = 7;
+        for step in 0..3000 {
+            x = (x * 1_103_515_245 + 12_345) % 2_147_483_648;
+            let score = x % 200;
+            let member = format!("m{}", x % 1000).into_bytes();
+            match expected.iter().position(|(_, m)| *m == member) {
+                Some(i) if step % 3 == 0 => {
+                    let (old, _) = expected.remove(i);
+                    assert!(list.remove(old as f64, &member));
+                },
+                Some(_) => {},
+                None => {
+                    list.insert(score as f64, member.clone());
+                    expected.push((score, member));
+                },
+            }
+        }
+        expected.sort();
+
+        assert_eq!(list.len(), expected.len());
+        let walked: Vec<(i64, Vec<u8>)> = list.iter().map(|(m, s)| (s as i64, m.clone())).collect();
+        assert_eq!(walked, expected);
+        for (rank, (score, member)) in expected.iter().enumerate() {
+            assert_eq!(list.count_before(*score as f64, member), rank);
+            assert_eq!(list.slice(rank, rank + 1).next().map(|(m, _)| m), Some(member));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::atomic::{ AtomicU64, Ordering };
+use crate::latency::LatencyMonitor;
+
+// the kinds of reads keyspace hits and misses are broken down by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    String,
+    List,
+    Hash,
+}
+
+impl Family {
+    pub const ALL: [Family; 3] = [Family::String, Family::List, Family::Hash];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Family::String => "string",
+            Family::List => "list",
+            Family::Hash => "hash",
+        }
+    }
+}
+
+// server wide counters reported by INFO, plain atomics so hot paths never take a lock.
+#[derive(Debug, Default)]
+pub struct Stats {
+    total_connections_received: AtomicU64,
+    rejected_connections: AtomicU64,
+    // the connections each acceptor took, when the listening socket is sharded.
+    accepted: Vec<AtomicU64>,
+    // reads that found something to return and reads that came back empty, by family.
+    keyspace_hits: [AtomicU64; 3],
+    keyspace_misses: [AtomicU64; 3],
+    // the spikes LATENCY reports, it takes a lock but only once something was slow.
+    latency: LatencyMonitor,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_acceptors(acceptors: usize) -> Self {
+        Self { accepted: (0..acceptors).map(|_| AtomicU64::new(0)).collect(), ..Self::default() }
+    }
+
+    pub fn incr_connections_received(&self, acceptor: usize) {
+        self.total_connections_received.fetch_add(1, Ordering::Relaxed);
+        if let Some(accepted) = self.accepted.get(acceptor) {
+            accepted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn incr_rejected_connections(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_connections_received(&self) -> u64 {
+        self.total_connections_received.load(Ordering::Relaxed)
+    }
+
+    pub fn get_rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn get_accepted_by_acceptor(&self) -> Vec<u64> {
+        self.accepted.iter().map(|accepted| accepted.load(Ordering::Relaxed)).collect()
+    }
+
+    pub fn incr_keyspace_lookup(&self, family: Family, hit: bool) {
+        let counters = if hit { &self.keyspace_hits } else { &self.keyspace_misses };
+        counters[family as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_keyspace_hits(&self, family: Family) -> u64 {
+        self.keyspace_hits[family as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn get_keyspace_misses(&self, family: Family) -> u64 {
+        self.keyspace_misses[family as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn get_total_keyspace_hits(&self) -> u64 {
+        Family::ALL.iter().map(|family| self.get_keyspace_hits(*family)).sum()
+    }
+
+    pub fn get_total_keyspace_misses(&self) -> u64 {
+        Family::ALL.iter().map(|family| self.get_keyspace_misses(*family)).sum()
+    }
+
+    pub fn latency(&self) -> &LatencyMonitor {
+        &self.latency
+    }
+}
+
+// the share of lookups that hit, 0 before there were any.
+pub fn hit_ratio(hits: u64, misses: u64) -> f64 {
+    match hits + misses {
+        0 => 0.0,
+        total => hits as f64 / total as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_by_acceptor() {
+        let stats = Stats::with_acceptors(2);
+        stats.incr_connections_received(0);
+        stats.incr_connections_received(1);
+        stats.incr_connections_received(1);
+        // the internal port and in memory clients count without an acceptor of their own.
+        stats.incr_connections_received(2);
+        stats.incr_rejected_connections();
+
+        assert_eq!(stats.get_connections_received(), 4);
+        assert_eq!(stats.get_accepted_by_acceptor(), vec![1, 2]);
+        assert_eq!(stats.get_rejected_connections(), 1);
+        assert!(Stats::new().get_accepted_by_acceptor().is_empty());
+    }
+
+    #[test]
+    fn test_keyspace_lookups_by_family() {
+        let stats = Stats::new();
+        stats.incr_keyspace_lookup(Family::String, true);
+        stats.incr_keyspace_lookup(Family::String, false);
+        stats.incr_keyspace_lookup(Family::Hash, true);
+        stats.incr_keyspace_lookup(Family::Hash, true);
+
+        assert_eq!((stats.get_keyspace_hits(Family::String), stats.get_keyspace_misses(Family::String)), (1, 1));
+        assert_eq!((stats.get_keyspace_hits(Family::List), stats.get_keyspace_misses(Family::List)), (0, 0));
+        assert_eq!((stats.get_total_keyspace_hits(), stats.get_total_keyspace_misses()), (3, 1));
+        assert_eq!(hit_ratio(3, 1), 0.75);
+        assert_eq!(hit_ratio(0, 0), 0.0);
+    }
+}
@@ -0,0 +1,119 @@
+// runs the same random command sequences against a real redis and this server and compares
+// every reply, then the value of every key the sequence touched. it only runs when
+// REDIS_DIFF_ADDR points at a redis to compare with, i.e.,
+//
+//     REDIS_DIFF_ADDR=127.0.0.1:6379 cargo test --test differential
+//
+// REDIS_DIFF_SEED replays a failing sequence, REDIS_DIFF_STEPS makes the sequences longer.
+// only keys under the diff: prefix are touched, the rest of that redis is left alone.
+use std::env;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use redis_starter_rust::{ Connection, Resp, ServerBuilder };
+use tokio::net::TcpStream;
+
+const KEYS: [&str; 4] = ["diff:a", "diff:b", "diff:c", "diff:d"];
+const VALUES: [&str; 5] = ["", "x", "hello", "0", "\x00\r\n"];
+const DEFAULT_STEPS: usize = 500;
+
+// xorshift64, good enough to pick commands and reproducible from the seed alone.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    // mostly small offsets and indexes, sometimes negative or out of range ones.
+    fn index(&mut self) -> String {
+        match self.below(10) {
+            0 => format!("-{}", self.below(20)),
+            1 => (self.below(1000) + 100).to_string(),
+            _ => self.below(16).to_string(),
+        }
+    }
+}
+
+// one random command from the string commands both servers share.
+fn random_command(rng: &mut Rng) -> Vec<String> {
+    let key = rng.pick(&KEYS).to_string();
+    let value = rng.pick(&VALUES).to_string();
+    match rng.below(7) {
+        0 => vec!["SET".into(), key, value],
+        1 => vec!["GET".into(), key],
+        2 => vec!["SETRANGE".into(), key, rng.index(), value],
+        3 => vec!["GETRANGE".into(), key, rng.index(), rng.index()],
+        4 => vec!["APPEND".into(), key, value],
+        5 => vec!["DEL".into(), key],
+        _ => vec!["MGET".into(), key, rng.pick(&KEYS).to_string()],
+    }
+}
+
+async fn send(conn: &mut Connection, args: &[String]) -> Resp {
+    let command = Resp::Array(args.iter().map(|a| Resp::BulkString(a.as_bytes().to_vec())).collect());
+    conn.write_message(&command).await.unwrap();
+    conn.read_message().await.unwrap().0
+}
+
+fn command(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[tokio::test]
+async fn test_string_commands_match_redis() {
+    let redis_addr = match env::var("REDIS_DIFF_ADDR") {
+        Ok(addr) => addr,
+        Err(_) => {
+            eprintln!("REDIS_DIFF_ADDR is not set, skipping the differential test");
+            return;
+        },
+    };
+    let seed = env::var("REDIS_DIFF_SEED")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64 | 1);
+    let steps = env::var("REDIS_DIFF_STEPS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_STEPS);
+
+    let server = ServerBuilder::new().port("0").bind().await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(async move { server.listener.run().await });
+
+    let mut ours = Connection::new(TcpStream::connect(addr).await.unwrap());
+    let mut redis = Connection::new(TcpStream::connect(&redis_addr).await.unwrap());
+
+    let mut cleanup = command(&["DEL"]);
+    cleanup.extend(KEYS.iter().map(|k| k.to_string()));
+    send(&mut redis, &cleanup).await;
+
+    let mut rng = Rng(seed);
+    for step in 0..steps {
+        let args = random_command(&mut rng);
+        let expected = send(&mut redis, &args).await;
+        let actual = send(&mut ours, &args).await;
+        assert_eq!(actual, expected, "seed {} step {}: {:?}", seed, step, args);
+    }
+
+    // the digests of the two servers are computed differently, so the final state is compared
+    // key by key instead.
+    for key in KEYS {
+        let args = command(&["GET", key]);
+        let expected = send(&mut redis, &args).await;
+        let actual = send(&mut ours, &args).await;
+        assert_eq!(actual, expected, "seed {}: final value of {}", seed, key);
+    }
+
+    send(&mut redis, &cleanup).await;
+}